@@ -1,7 +1,9 @@
-/// HostCallback is a type that references a pointer to a function
+use std::sync::Arc;
+
+/// HostCallback is a type that references a function, or a closure,
 /// that can be stored and then invoked by burrego when the Open
 /// Policy Agent Wasm target invokes certain Wasm imports.
-pub type HostCallback = fn(&str);
+pub type HostCallback = Arc<dyn Fn(&str) + Send + Sync>;
 
 /// HostCallbacks defines a set of pluggable host implementations of
 /// OPA documented imports:
@@ -15,8 +17,8 @@ pub struct HostCallbacks {
 impl Default for HostCallbacks {
     fn default() -> HostCallbacks {
         HostCallbacks {
-            opa_abort: default_opa_abort,
-            opa_println: default_opa_println,
+            opa_abort: Arc::new(default_opa_abort),
+            opa_println: Arc::new(default_opa_println),
         }
     }
 }
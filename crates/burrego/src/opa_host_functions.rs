@@ -28,7 +28,7 @@ fn register_opa_abort_func(
             "opa_abort",
             |mut caller: Caller<'_, Option<StackHelper>>, addr: i32| {
                 let stack_helper = caller.data().as_ref().unwrap();
-                let opa_abort_host_callback = stack_helper.opa_abort_host_callback;
+                let opa_abort_host_callback = stack_helper.opa_abort_host_callback.clone();
 
                 let memory_export = caller.get_export("memory").ok_or_else(|| BurregoError::RegoWasmError("cannot find 'memory' export".to_string()))?;
                 let memory = memory_export.into_memory().ok_or_else(|| BurregoError::RegoWasmError("'memory' export cannot be converted into a memory object".to_string()))?;
@@ -56,7 +56,7 @@ fn register_opa_println_func(
         "opa_println",
         |mut caller: Caller<'_, Option<StackHelper>>, addr: i32| {
             let stack_helper = caller.data().as_ref().unwrap();
-            let opa_println_host_callback = stack_helper.opa_println_host_callback;
+            let opa_println_host_callback = stack_helper.opa_println_host_callback.clone();
 
             let memory_export = caller.get_export("memory").ok_or_else(|| BurregoError::RegoWasmError("cannot find 'memory' export".to_string()))?;
             let memory = memory_export.into_memory().ok_or_else(|| BurregoError::RegoWasmError("'memory' export cannot be converted into a memory object".to_string()))?;
@@ -6,8 +6,13 @@ use tracing::info;
 pub mod errors;
 pub mod policy_id;
 pub mod policy_mode;
+pub mod shadow_decision;
 
-use crate::admission_response_handler::{policy_id::PolicyID, policy_mode::PolicyMode};
+use crate::admission_response_handler::{
+    policy_id::PolicyID,
+    policy_mode::PolicyMode,
+    shadow_decision::{ResponseConstraint, ShadowDecision},
+};
 
 /// Applies a series of mutation constrains to the admission response.
 ///
@@ -41,18 +46,57 @@ impl<'a> AdmissionResponseHandler<'a> {
     }
 
     pub fn process_response(&'a self, admission_response: AdmissionResponse) -> AdmissionResponse {
-        let admission_response = self.apply_monitor_mode(admission_response);
-        let admission_response = self.apply_mutation_constraint(admission_response);
+        self.process_response_with_shadow_decision(admission_response)
+            .0
+    }
+
+    /// Process the admission response like [`process_response`](Self::process_response) does,
+    /// but also return the [`ShadowDecision`] taken by the policy before any constraint
+    /// was applied.
+    pub fn process_response_with_shadow_decision(
+        &'a self,
+        admission_response: AdmissionResponse,
+    ) -> (AdmissionResponse, ShadowDecision) {
+        let mut shadow_decision = ShadowDecision::from(&admission_response);
+        let mut track = |constraint: ResponseConstraint,
+                         before: &AdmissionResponse,
+                         after: AdmissionResponse| {
+            if before != &after {
+                shadow_decision.altered_by.push(constraint);
+            }
+            after
+        };
+
+        let processed = self.apply_monitor_mode(admission_response.clone());
+        let admission_response = track(
+            ResponseConstraint::MonitorMode,
+            &admission_response,
+            processed,
+        );
+
+        let processed = self.apply_mutation_constraint(admission_response.clone());
+        let admission_response = track(
+            ResponseConstraint::MutationConstraint,
+            &admission_response,
+            processed,
+        );
 
         // Note: apply the custom rejection message as a last step, so that it can override
         // any previous status message.
-        self.apply_custom_rejection_message(admission_response)
+        let processed = self.apply_custom_rejection_message(admission_response.clone());
+        let admission_response = track(
+            ResponseConstraint::CustomRejectionMessage,
+            &admission_response,
+            processed,
+        );
+
+        (admission_response, shadow_decision)
     }
 
     // In monitor mode we always accept the request, but log what would have been the decision of the
     // policy. We also force mutating patches to be none. Status is also overridden, as it's only taken into
     // account when a request is rejected.
-    fn apply_monitor_mode(&'a self, admission_response: AdmissionResponse) -> AdmissionResponse {
+    fn apply_monitor_mode(&self, admission_response: AdmissionResponse) -> AdmissionResponse {
         if self.policy_mode != &PolicyMode::Monitor {
             return admission_response;
         }
@@ -76,7 +120,7 @@ impl<'a> AdmissionResponseHandler<'a> {
     /// If the policy attempted to mutate the request, but it is currently configured to not allow mutations,
     /// the request is rejected.
     fn apply_mutation_constraint(
-        &'a self,
+        &self,
         admission_response: AdmissionResponse,
     ) -> AdmissionResponse {
         if self.policy_mode != &PolicyMode::Protect {
@@ -117,7 +161,7 @@ impl<'a> AdmissionResponseHandler<'a> {
     /// The original rejection message is added to the status details causes
     /// to preserve the original error message.
     fn apply_custom_rejection_message(
-        &'a self,
+        &self,
         admission_response: AdmissionResponse,
     ) -> AdmissionResponse {
         if admission_response.allowed {
@@ -491,4 +535,63 @@ mod tests {
             processed_response, expected_response
         );
     }
+
+    #[rstest]
+    #[case::monitor_mode_rejected(
+        AdmissionResponseHandler::new(&POLICY_ID, &PolicyMode::Monitor, true, None),
+        rejection_response(RejectionDetails::default()),
+        ShadowDecision {
+            allowed: false,
+            message: Some(DEFAULT_REJECTION_MESSAGE.to_string()),
+            patch: None,
+            altered_by: vec![ResponseConstraint::MonitorMode],
+        },
+    )]
+    #[case::monitor_mode_accepted(
+        AdmissionResponseHandler::new(&POLICY_ID, &PolicyMode::Monitor, true, None),
+        accepted_response(),
+        ShadowDecision {
+            allowed: true,
+            ..Default::default()
+        },
+    )]
+    #[case::protect_mode_not_allowed_to_mutate_custom_rejection_message(
+        AdmissionResponseHandler::new(
+            &POLICY_ID,
+            &PolicyMode::Protect,
+            false,
+            Some("Custom rejection message".to_string()),
+        ),
+        mutation_response(),
+        ShadowDecision {
+            allowed: true,
+            message: None,
+            patch: Some("patch".to_string()),
+            altered_by: vec![
+                ResponseConstraint::MutationConstraint,
+                ResponseConstraint::CustomRejectionMessage,
+            ],
+        },
+    )]
+    #[case::protect_mode_rejected_no_custom_rejection_message(
+        AdmissionResponseHandler::new(&POLICY_ID, &PolicyMode::Protect, true, None),
+        rejection_response(RejectionDetails::default()),
+        ShadowDecision {
+            allowed: false,
+            message: Some(DEFAULT_REJECTION_MESSAGE.to_string()),
+            patch: None,
+            altered_by: vec![],
+        },
+    )]
+    fn process_response_with_shadow_decision(
+        #[case] handler: AdmissionResponseHandler,
+        #[case] response: AdmissionResponse,
+        #[case] expected_shadow_decision: ShadowDecision,
+    ) {
+        let (processed_response, shadow_decision) =
+            handler.process_response_with_shadow_decision(response.clone());
+
+        assert_eq!(processed_response, handler.process_response(response));
+        assert_eq!(shadow_decision, expected_shadow_decision);
+    }
 }
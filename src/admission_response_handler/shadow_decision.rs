@@ -0,0 +1,93 @@
+use serde::Serialize;
+
+use crate::admission_response::AdmissionResponse;
+
+/// A constraint applied by the [`AdmissionResponseHandler`](crate::admission_response_handler::AdmissionResponseHandler)
+/// that can alter the response produced by a policy
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum ResponseConstraint {
+    /// The policy is running in "Monitor" mode, hence the request is always accepted
+    MonitorMode,
+    /// The policy attempted to mutate the request, but it is not allowed to do that
+    MutationConstraint,
+    /// The rejection message of the policy has been replaced by a custom one
+    CustomRejectionMessage,
+}
+
+/// The decision taken by a policy, before any of the constraints of the
+/// [`AdmissionResponseHandler`](crate::admission_response_handler::AdmissionResponseHandler)
+/// are applied.
+///
+/// This can be used to find out what would have happened to a request when a policy
+/// is switched from "Monitor" to "Protect" mode, without having to parse logs.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowDecision {
+    /// Whether the policy allowed the request
+    pub allowed: bool,
+
+    /// The message returned by the policy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+
+    /// The patch produced by the policy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub patch: Option<String>,
+
+    /// The constraints that changed the original decision of the policy, in the
+    /// order they have been applied
+    pub altered_by: Vec<ResponseConstraint>,
+}
+
+impl ShadowDecision {
+    /// Returns true when the final response differs from the one produced by the policy
+    pub fn is_altered(&self) -> bool {
+        !self.altered_by.is_empty()
+    }
+
+    /// Returns true when the policy rejected the request, but the request has been
+    /// accepted because of the constraints applied
+    pub fn would_have_denied(&self) -> bool {
+        !self.allowed && self.altered_by.contains(&ResponseConstraint::MonitorMode)
+    }
+}
+
+impl From<&AdmissionResponse> for ShadowDecision {
+    fn from(response: &AdmissionResponse) -> Self {
+        Self {
+            allowed: response.allowed,
+            message: response
+                .status
+                .as_ref()
+                .and_then(|status| status.message.clone()),
+            patch: response.patch.clone(),
+            altered_by: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::denied_in_monitor_mode(false, vec![ResponseConstraint::MonitorMode], true)]
+    #[case::allowed_in_monitor_mode(true, vec![], false)]
+    #[case::denied_in_protect_mode(false, vec![ResponseConstraint::CustomRejectionMessage], false)]
+    fn would_have_denied(
+        #[case] allowed: bool,
+        #[case] altered_by: Vec<ResponseConstraint>,
+        #[case] expected: bool,
+    ) {
+        let shadow_decision = ShadowDecision {
+            allowed,
+            altered_by,
+            ..Default::default()
+        };
+
+        assert_eq!(shadow_decision.would_have_denied(), expected);
+    }
+}
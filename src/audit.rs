@@ -0,0 +1,164 @@
+use serde::Serialize;
+use serde_json::Value;
+use tracing::info;
+
+use crate::evaluation_context::EvaluationContext;
+use crate::policy_metadata::HostCapability;
+
+/// The value replacing the sensitive arguments of the host capabilities
+const REDACTED: &str = "<redacted>";
+
+/// The arguments whose name contains one of these strings are redacted
+const SENSITIVE_ARGUMENTS: [&str; 5] =
+    ["authorization", "credential", "password", "secret", "token"];
+
+/// The outcome of a host capability invocation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum HostCapabilityOutcome {
+    Success,
+    /// The policy has not been granted access to the host capability
+    Denied,
+    Failure {
+        error: String,
+    },
+}
+
+/// An invocation of a host capability made by a policy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HostCapabilityEvent {
+    pub policy_id: String,
+    /// The uid of the admission request being evaluated, if any
+    pub request_uid: Option<String>,
+    pub capability: HostCapability,
+    /// The operation invoked by the policy (e.g.: `kubewarden/oci/v1/verify`)
+    pub operation: String,
+    /// The arguments given by the policy, with the sensitive values redacted
+    pub arguments: Value,
+    pub outcome: HostCapabilityOutcome,
+    /// Whether the result has been served from the cache. Not set for the
+    /// operations that are not cached, or that have not been performed
+    pub cached: Option<bool>,
+}
+
+/// A destination of the audit events. Implementations can write the events to
+/// files, queues or any other storage.
+///
+/// Events are recorded synchronously, while the policy is waiting for the
+/// result of the host capability: implementations should not block.
+pub trait AuditSink: Send + Sync {
+    fn record(&self, event: &HostCapabilityEvent);
+}
+
+/// An [`AuditSink`] that emits the events via `tracing`, using the
+/// `kubewarden_audit` target
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingAuditSink;
+
+impl AuditSink for TracingAuditSink {
+    fn record(&self, event: &HostCapabilityEvent) {
+        info!(
+            target: "kubewarden_audit",
+            policy_id = event.policy_id,
+            request_uid = event.request_uid,
+            capability = %event.capability,
+            operation = event.operation,
+            arguments = %event.arguments,
+            outcome = ?event.outcome,
+            cached = event.cached,
+            "host capability invoked"
+        );
+    }
+}
+
+impl EvaluationContext {
+    /// Report the invocation of a host capability to the audit sink, if any.
+    /// The arguments are computed only when needed
+    pub(crate) fn audit(
+        &self,
+        capability: HostCapability,
+        operation: &str,
+        arguments: impl FnOnce() -> Value,
+        outcome: HostCapabilityOutcome,
+        cached: Option<bool>,
+    ) {
+        if let Some(audit_sink) = &self.audit_sink {
+            audit_sink.record(&HostCapabilityEvent {
+                policy_id: self.policy_id.clone(),
+                request_uid: self.current_request.uid(),
+                capability,
+                operation: operation.to_owned(),
+                arguments: arguments(),
+                outcome,
+                cached,
+            });
+        }
+    }
+}
+
+/// Parse the payload of a host capability and redact its sensitive values.
+/// The values of HTTP headers are always redacted
+pub(crate) fn redacted_arguments(payload: &[u8]) -> Value {
+    let mut arguments = match serde_json::from_slice(payload) {
+        Ok(arguments) => arguments,
+        Err(_) => return Value::String(REDACTED.to_owned()),
+    };
+    redact(&mut arguments);
+    arguments
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let key = key.to_lowercase();
+                if SENSITIVE_ARGUMENTS.iter().any(|s| key.contains(s)) {
+                    *value = Value::String(REDACTED.to_owned());
+                } else if key == "headers"
+                    && let Value::Object(headers) = value
+                {
+                    headers
+                        .values_mut()
+                        .for_each(|header| *header = Value::String(REDACTED.to_owned()));
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+    use serde_json::json;
+
+    #[rstest]
+    #[case::nothing_sensitive(
+        json!({"image": "ghcr.io/kubewarden/policy:v1"}),
+        json!({"image": "ghcr.io/kubewarden/policy:v1"}),
+    )]
+    #[case::headers(
+        json!({"url": "https://corp.lan", "headers": {"Authorization": "Bearer abc", "Accept": "text/plain"}}),
+        json!({"url": "https://corp.lan", "headers": {"Authorization": REDACTED, "Accept": REDACTED}}),
+    )]
+    #[case::nested(
+        json!({"auth": [{"user": "tux", "password": "hunter2", "api_token": "abc"}]}),
+        json!({"auth": [{"user": "tux", "password": REDACTED, "api_token": REDACTED}]}),
+    )]
+    #[case::plain_string(json!("example.com"), json!("example.com"))]
+    fn redact_arguments(#[case] payload: Value, #[case] expected: Value) {
+        let payload = serde_json::to_vec(&payload).unwrap();
+
+        assert_eq!(redacted_arguments(&payload), expected);
+    }
+
+    #[test]
+    fn redact_invalid_payload() {
+        assert_eq!(redacted_arguments(b"not json"), json!(REDACTED));
+    }
+}
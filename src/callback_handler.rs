@@ -1,22 +1,38 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use anyhow::anyhow;
-use kubewarden_policy_sdk::host_capabilities::net::LookupResponse;
 use tokio::sync::{mpsc, oneshot};
 use tracing::{debug, warn};
 
 use crate::callback_requests::{CallbackRequest, CallbackRequestType, CallbackResponse};
+use crate::errors::HostCapabilityError;
 
 mod builder;
+mod cache;
 mod crypto;
-mod kubernetes;
+mod dns;
+mod http;
+pub(crate) mod kubernetes;
+mod notation;
 mod oci;
 mod sigstore_verification;
 
 pub use builder::CallbackHandlerBuilder;
+pub use cache::{CacheConfig, CacheHandle, CachedCapability, CapabilityCacheConfig};
+pub use dns::{DnsLookupResponse, DnsRecord};
+pub use http::{HttpGetConfig, HttpGetResponse};
+pub use kubernetes::{
+    DEFAULT_REFLECTOR_SYNC_TIMEOUT, GetResourceSource, KubernetesError, OfflineKubernetesContext,
+    ReflectorStats, ReflectorsConfig, ReflectorsHandle,
+};
+pub use notation::{
+    NotationSignatureVerification, NotationTrustPolicy, NotationTrustPolicyDocument,
+    NotationTrustStore, NotationVerificationLevel, NotationVerificationResponse,
+};
 
 use sigstore_verification::{
-    get_sigstore_certificate_verification_cached, get_sigstore_github_actions_verification_cached,
+    get_sigstore_attestation_verification_cached, get_sigstore_certificate_verification_cached,
+    get_sigstore_github_actions_verification_cached,
     get_sigstore_keyless_prefix_verification_cached, get_sigstore_keyless_verification_cached,
     get_sigstore_pub_key_verification_cached,
 };
@@ -27,7 +43,12 @@ use sigstore_verification::{
 pub struct CallbackHandler {
     oci_client: Arc<oci::Client>,
     sigstore_client: sigstore_verification::Client,
-    kubernetes_client: Option<kubernetes::Client>,
+    notation_client: Option<notation::Client>,
+    dns_client: Option<dns::Client>,
+    http_client: http::Client,
+    kubernetes_client: Option<kubernetes::ContextProvider>,
+    caches: Arc<cache::Caches>,
+    reflectors_eviction_interval: Option<Duration>,
     rx: mpsc::Receiver<CallbackRequest>,
     tx: mpsc::Sender<CallbackRequest>,
     shutdown_channel: oneshot::Receiver<()>,
@@ -45,7 +66,10 @@ macro_rules! handle_callback {
                 );
                 let payload = serde_json::to_vec(&response.value)
                     .map_err(|e| anyhow!("error serializing payload: {e:?}"))?;
-                Ok(CallbackResponse { payload })
+                Ok(CallbackResponse {
+                    payload,
+                    cached: response.was_cached,
+                })
             })
             .and_then(|r| r);
 
@@ -65,14 +89,44 @@ impl CallbackHandler {
         self.tx.clone()
     }
 
+    /// Returns a handle that can be used to inspect and manage the Kubernetes
+    /// reflectors. Returns `None` when no Kubernetes client has been provided.
+    ///
+    /// Can be invoked as many times as wanted.
+    pub fn reflectors_handle(&self) -> Option<ReflectorsHandle> {
+        match &self.kubernetes_client {
+            Some(kubernetes::ContextProvider::Cluster(client)) => {
+                Some(ReflectorsHandle::new(client.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns a handle that can be used to invalidate the cached results of
+    /// the host capabilities.
+    ///
+    /// Can be invoked as many times as wanted.
+    pub fn cache_handle(&self) -> CacheHandle {
+        CacheHandle::new(self.caches.clone())
+    }
+
     /// Enter an endless loop that:
     ///    1. Waits for requests to be evaluated
     ///    2. Evaluate the request
     ///    3. Send back the result of the evaluation
     ///
+    /// When an idle timeout has been set for the Kubernetes reflectors, the
+    /// idle ones are periodically evicted too.
+    ///
     /// The loop is interrupted only when a message is sent over the
     /// `shutdown_channel`.
     pub async fn loop_eval(&mut self) {
+        let eviction_enabled = self.reflectors_eviction_interval.is_some();
+        let mut eviction_interval = tokio::time::interval(
+            self.reflectors_eviction_interval
+                .unwrap_or(kubernetes::DISABLED_REFLECTORS_EVICTION_INTERVAL),
+        );
+
         loop {
             tokio::select! {
                 // place the shutdown check before the message evaluation,
@@ -84,31 +138,98 @@ impl CallbackHandler {
                     if let Some(req) = req {
                         self.handle_request(req).await;
                    }
+                },
+                _ = eviction_interval.tick(), if eviction_enabled => {
+                    if let Some(kubernetes::ContextProvider::Cluster(kubernetes_client)) = &self.kubernetes_client {
+                        let evicted = kubernetes_client.evict_idle_reflectors().await;
+                        debug!(evicted, "evicted idle reflectors");
+                    }
                 }
             }
         }
     }
 
-    async fn handle_request(&mut self, req: CallbackRequest) {
+    async fn handle_request(&mut self, mut req: CallbackRequest) {
         let oci_client = self.oci_client.clone();
         let mut sigstore_client = self.sigstore_client.clone();
+        let notation_client = self.notation_client.clone();
+        let dns_client = self.dns_client.clone();
+        let http_client = self.http_client.clone();
         let mut kubernetes_client = self.kubernetes_client.clone();
+        let caches = self.caches.clone();
+
+        // When the request has a deadline, the evaluation writes its response to an
+        // internal channel. The response is then forwarded to the requester, unless
+        // the deadline is reached first
+        let deadline = req.deadline.map(|deadline| {
+            let (tx, rx) = oneshot::channel();
+            let response_channel = std::mem::replace(&mut req.response_channel, tx);
+            (deadline, response_channel, rx)
+        });
+
+        let evaluation = async move {
+            // Wait until the capability can evaluate a new request. The wait is part of
+            // the evaluation, hence it's bounded by the deadline of the request and it
+            // doesn't hold back the requests made to the other capabilities.
+            // The permit is released once the evaluation is done, or cancelled
+            let _permit = caches.acquire_permit(&req.request).await;
 
-        tokio::spawn(async move {
             match req.request {
                 CallbackRequestType::OciManifestDigest { image } => {
                     handle_callback!(req, image, "Image digest computed", {
-                        oci::get_oci_digest_cached(&oci_client, &image)
+                        oci::get_oci_digest_cached(&caches.oci_manifest_digest, &oci_client, &image)
                     });
                 }
                 CallbackRequestType::OciManifest { image } => {
                     handle_callback!(req, image, "Image manifest computed", {
-                        oci::get_oci_manifest_cached(&oci_client, &image)
+                        oci::get_oci_manifest_cached(&caches.oci_manifest, &oci_client, &image)
                     });
                 }
                 CallbackRequestType::OciManifestAndConfig { image } => {
                     handle_callback!(req, image, "Image manifest computed", {
-                        oci::get_oci_manifest_and_config_cached(&oci_client, &image)
+                        oci::get_oci_manifest_and_config_cached(
+                            &caches.oci_manifest_and_config,
+                            &oci_client,
+                            &image,
+                        )
+                    });
+                }
+                CallbackRequestType::OciListTags {
+                    image,
+                    page_size,
+                    last,
+                } => {
+                    handle_callback!(req, image, "Image tags listed", {
+                        oci::get_oci_tags_cached(
+                            &caches.oci_tags,
+                            &oci_client,
+                            &image,
+                            page_size,
+                            last.as_deref(),
+                        )
+                    });
+                }
+                CallbackRequestType::OciReferrers {
+                    image,
+                    artifact_type,
+                } => {
+                    handle_callback!(req, image, "Image referrers fetched", {
+                        oci::get_oci_referrers_cached(
+                            &caches.oci_referrers,
+                            &oci_client,
+                            &image,
+                            artifact_type.as_deref(),
+                        )
+                    });
+                }
+                CallbackRequestType::OciManifestAndConfigForPlatform { image, platform } => {
+                    handle_callback!(req, image, "Image manifest for platform computed", {
+                        oci::get_oci_manifest_and_config_for_platform_cached(
+                            &caches.oci_manifest_and_config_for_platform,
+                            &oci_client,
+                            &image,
+                            &platform,
+                        )
                     });
                 }
                 CallbackRequestType::SigstorePubKeyVerify {
@@ -118,6 +239,7 @@ impl CallbackHandler {
                 } => {
                     handle_callback!(req, image, "Sigstore pub key verification done", {
                         get_sigstore_pub_key_verification_cached(
+                            &caches.sigstore_verification,
                             &mut sigstore_client,
                             image.clone(),
                             pub_keys,
@@ -132,6 +254,7 @@ impl CallbackHandler {
                 } => {
                     handle_callback!(req, image, "Sigstore keyless verification done", {
                         get_sigstore_keyless_verification_cached(
+                            &caches.sigstore_verification,
                             &mut sigstore_client,
                             image.clone(),
                             keyless,
@@ -146,6 +269,7 @@ impl CallbackHandler {
                 } => {
                     handle_callback!(req, image, "Sigstore keyless prefix verification done", {
                         get_sigstore_keyless_prefix_verification_cached(
+                            &caches.sigstore_verification,
                             &mut sigstore_client,
                             image.clone(),
                             keyless_prefix,
@@ -161,6 +285,7 @@ impl CallbackHandler {
                 } => {
                     handle_callback!(req, image, "Sigstore GitHub Action verification done", {
                         get_sigstore_github_actions_verification_cached(
+                            &caches.sigstore_verification,
                             &mut sigstore_client,
                             image.clone(),
                             owner,
@@ -178,6 +303,7 @@ impl CallbackHandler {
                 } => {
                     handle_callback!(req, image, "Sigstore GitHub Action verification done", {
                         get_sigstore_certificate_verification_cached(
+                            &caches.sigstore_verification,
                             &mut sigstore_client,
                             &image,
                             &certificate,
@@ -187,21 +313,58 @@ impl CallbackHandler {
                         )
                     })
                 }
+                CallbackRequestType::SigstoreAttestationVerify {
+                    image,
+                    predicate_type,
+                    pub_keys,
+                    keyless,
+                    keyless_prefix,
+                } => {
+                    handle_callback!(req, image, "Sigstore attestation verification done", {
+                        get_sigstore_attestation_verification_cached(
+                            &caches.sigstore_attestation,
+                            &mut sigstore_client,
+                            image.clone(),
+                            predicate_type,
+                            pub_keys,
+                            keyless,
+                            keyless_prefix,
+                        )
+                    })
+                }
+                CallbackRequestType::NotationVerify { image } => {
+                    handle_callback!(req, image, "Notation verification done", {
+                        notation::get_notation_verification_cached(
+                            &caches.notation_verification,
+                            notation_client.as_ref(),
+                            image.clone(),
+                        )
+                    })
+                }
                 CallbackRequestType::DNSLookupHost { host } => {
-                    let response = dns_lookup::lookup_host(&host)
-                        .map(|ip_addresses| {
-                            let res = LookupResponse {
-                                ips: ip_addresses.map(|ip| ip.to_string()).collect(),
-                            };
-                            CallbackResponse {
-                                payload: serde_json::to_vec(&res).unwrap(),
-                            }
-                        })
-                        .map_err(anyhow::Error::new);
-
-                    if let Err(e) = req.response_channel.send(response) {
-                        warn!("callback handler: cannot send response back: {:?}", e);
-                    }
+                    handle_callback!(req, host, "DNS host lookup done", {
+                        dns::lookup_host_cached(&caches.dns_lookup, dns_client.as_ref(), &host)
+                    })
+                }
+                CallbackRequestType::DNSLookup { host, record_type } => {
+                    handle_callback!(req, host, "DNS lookup done", {
+                        dns::lookup_cached(
+                            &caches.dns_lookup,
+                            dns_client.as_ref(),
+                            &host,
+                            record_type,
+                        )
+                    })
+                }
+                CallbackRequestType::DNSReverseLookup { ip } => {
+                    handle_callback!(req, ip, "DNS reverse lookup done", {
+                        dns::reverse_lookup_cached(&caches.dns_lookup, dns_client.as_ref(), ip)
+                    })
+                }
+                CallbackRequestType::HttpGet { url, headers } => {
+                    handle_callback!(req, url.as_str(), "HTTP GET done", {
+                        http::get_http_cached(&caches.http_get, &http_client, &url, &headers)
+                    })
                 }
                 CallbackRequestType::KubernetesListResourceNamespace {
                     api_version,
@@ -254,7 +417,15 @@ impl CallbackHandler {
                     namespace,
                     disable_cache,
                 } => {
-                    if disable_cache {
+                    let skip_cache = disable_cache
+                        || kubernetes::is_get_resource_served_by_reflector(
+                            kubernetes_client.as_mut(),
+                            &api_version,
+                            &kind,
+                            namespace.as_deref(),
+                        )
+                        .await;
+                    if skip_cache {
                         handle_callback!(
                             req,
                             format!("{api_version}/{kind}"),
@@ -276,6 +447,7 @@ impl CallbackHandler {
                             "Get Kubernetes resource",
                             {
                                 kubernetes::get_resource_cached(
+                                    &caches.kubernetes_get_resource,
                                     kubernetes_client.as_mut(),
                                     &api_version,
                                     &kind,
@@ -303,8 +475,6 @@ impl CallbackHandler {
                 CallbackRequestType::HasKubernetesListResourceAllResultChangedSinceInstant {
                     api_version,
                     kind,
-                    label_selector,
-                    field_selector,
                     since,
                 } => {
                     handle_callback!(
@@ -316,8 +486,6 @@ impl CallbackHandler {
                                 kubernetes_client.as_mut(),
                                 &api_version,
                                 &kind,
-                                label_selector,
-                                field_selector,
                                 since,
                             )
                         }
@@ -343,13 +511,129 @@ impl CallbackHandler {
                         )
                     }
                 }
+                CallbackRequestType::KubernetesCanIBatch {
+                    requests,
+                    disable_cache,
+                } => {
+                    handle_callback!(
+                        req,
+                        format!("{} reviews", requests.len()),
+                        "Check if users or service accounts have permissions to perform operations",
+                        {
+                            kubernetes::can_i_batch(
+                                kubernetes_client.as_mut(),
+                                requests,
+                                disable_cache,
+                            )
+                        }
+                    )
+                }
                 CallbackRequestType::CryptoIsCertificateTrusted { request } => {
                     let cert_description = request.cert.to_string();
                     handle_callback!(req, cert_description, "Certificate verification done", {
                         async { crypto::verify_certificate(request) }
                     })
                 }
+                CallbackRequestType::CryptoIsCertificateTrustedV2 { request } => {
+                    let cert_description = request.cert.to_string();
+                    handle_callback!(req, cert_description, "Certificate verification done", {
+                        async { crypto::verify_certificate_v2(request) }
+                    })
+                }
+            }
+        };
+
+        tokio::spawn(async move {
+            let Some((deadline, response_channel, rx)) = deadline else {
+                evaluation.await;
+                return;
+            };
+
+            // dropping the evaluation cancels all the operations that are still in flight
+            let response = match tokio::time::timeout_at(deadline, async move {
+                evaluation.await;
+                rx.await
+            })
+            .await
+            {
+                Ok(Ok(response)) => response,
+                Ok(Err(_)) => {
+                    warn!("callback handler: the evaluation did not produce a response");
+                    return;
+                }
+                Err(_) => {
+                    debug!("callback handler: request cancelled, deadline reached");
+                    Err(HostCapabilityError::Timeout.into())
+                }
+            };
+            if let Err(e) = response_channel.send(response) {
+                warn!("callback handler: cannot send response back: {:?}", e);
             }
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn capability_at_its_limit_does_not_block_the_others() {
+        let (_shutdown_tx, shutdown_rx) = oneshot::channel();
+        let mut callback_handler = CallbackHandlerBuilder::new(shutdown_rx)
+            .cache_config(CacheConfig::default().capability(
+                CachedCapability::DnsLookup,
+                CapabilityCacheConfig {
+                    max_concurrent_requests: Some(1),
+                    ..CapabilityCacheConfig::new(Duration::from_secs(60))
+                },
+            ))
+            .build()
+            .await
+            .expect("cannot build callback handler");
+        let callback_handler_channel = callback_handler.sender_channel();
+        // the only DNS lookup that can be evaluated is in progress
+        let _permit = callback_handler
+            .caches
+            .dns_lookup
+            .acquire_permit()
+            .await
+            .expect("DNS lookups are limited");
+        tokio::spawn(async move {
+            callback_handler.loop_eval().await;
+        });
+
+        let (dns_tx, mut dns_rx) = oneshot::channel();
+        callback_handler_channel
+            .send(CallbackRequest {
+                request: CallbackRequestType::DNSLookupHost {
+                    host: "localhost".to_string(),
+                },
+                response_channel: dns_tx,
+                deadline: None,
+            })
+            .await
+            .unwrap();
+        let (tx, rx) = oneshot::channel();
+        callback_handler_channel
+            .send(CallbackRequest {
+                request: CallbackRequestType::KubernetesGetResourcePluralName {
+                    api_version: "v1".to_string(),
+                    kind: "Pod".to_string(),
+                },
+                response_channel: tx,
+                deadline: None,
+            })
+            .await
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), rx)
+            .await
+            .expect("the request has not been answered")
+            .expect("the response channel has been closed");
+        assert!(
+            dns_rx.try_recv().is_err(),
+            "the DNS lookup has been evaluated beyond the limit"
+        );
+    }
+}
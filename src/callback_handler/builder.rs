@@ -1,12 +1,18 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use policy_fetcher::sigstore::trust::sigstore::SigstoreTrustRoot;
 use policy_fetcher::sources::Sources;
-use std::sync::Arc;
+use std::{collections::BTreeSet, path::PathBuf, sync::Arc};
 use tokio::sync::{mpsc, oneshot};
+use tracing::warn;
 
 use super::CallbackHandler;
-use super::{oci, sigstore_verification};
+use super::cache::{CacheConfig, Caches};
+use super::http::HttpGetConfig;
+use super::kubernetes::{ContextProvider, OfflineKubernetesContext, ReflectorsConfig};
+use super::notation::{NotationTrustPolicyDocument, NotationTrustStore};
+use super::{dns, http, notation, oci, sigstore_verification};
 use crate::callback_requests::CallbackRequest;
+use crate::policy_metadata::ContextAwareResource;
 
 const DEFAULT_CHANNEL_BUFF_SIZE: usize = 100;
 
@@ -16,7 +22,15 @@ pub struct CallbackHandlerBuilder {
     channel_buffer_size: usize,
     shutdown_channel: oneshot::Receiver<()>,
     trust_root: Option<Arc<SigstoreTrustRoot>>,
+    offline_sigstore_trust_root: Option<PathBuf>,
+    notation_trust_store: Option<NotationTrustStore>,
+    notation_trust_policy: Option<NotationTrustPolicyDocument>,
     kube_client: Option<kube::Client>,
+    offline_kubernetes_context: Option<OfflineKubernetesContext>,
+    reflectors_config: ReflectorsConfig,
+    prewarm_resources: BTreeSet<ContextAwareResource>,
+    cache_config: CacheConfig,
+    http_get_config: HttpGetConfig,
 }
 
 impl CallbackHandlerBuilder {
@@ -26,7 +40,15 @@ impl CallbackHandlerBuilder {
             shutdown_channel,
             channel_buffer_size: DEFAULT_CHANNEL_BUFF_SIZE,
             trust_root: None,
+            offline_sigstore_trust_root: None,
+            notation_trust_store: None,
+            notation_trust_policy: None,
             kube_client: None,
+            offline_kubernetes_context: None,
+            reflectors_config: ReflectorsConfig::default(),
+            prewarm_resources: BTreeSet::new(),
+            cache_config: CacheConfig::default(),
+            http_get_config: HttpGetConfig::default(),
         }
     }
 
@@ -41,6 +63,37 @@ impl CallbackHandlerBuilder {
         self
     }
 
+    /// Verify Sigstore signatures without contacting the public Sigstore
+    /// infrastructure, using the trust material stored inside of the given
+    /// `trusted_root.json` file. The file can reference private Fulcio and Rekor
+    /// instances. Optional, cannot be used together with
+    /// [`trust_root`](Self::trust_root)
+    ///
+    /// When set, signatures and attestations are accepted only when they carry
+    /// a Rekor bundle signed by one of the trusted Rekor keys: the verification
+    /// fails closed when the transparency log evidence is missing.
+    pub fn offline_sigstore_trust_root(mut self, path: PathBuf) -> Self {
+        self.offline_sigstore_trust_root = Some(path);
+        self
+    }
+
+    /// Set the trust stores holding the certificates trusted to issue the
+    /// Notation signing certificates. Optional, but required to verify the
+    /// Notation signatures, together with
+    /// [`notation_trust_policy`](Self::notation_trust_policy)
+    pub fn notation_trust_store(mut self, trust_store: NotationTrustStore) -> Self {
+        self.notation_trust_store = Some(trust_store);
+        self
+    }
+
+    /// Set the trust policy defining who is trusted to sign the images of each
+    /// repository with Notation. Optional, but required to verify the Notation
+    /// signatures, together with [`notation_trust_store`](Self::notation_trust_store)
+    pub fn notation_trust_policy(mut self, trust_policy: NotationTrustPolicyDocument) -> Self {
+        self.notation_trust_policy = Some(trust_policy);
+        self
+    }
+
     /// Set the size of the channel used by the sync world to communicate with
     /// the CallbackHandler. Optional
     pub fn channel_buffer_size(mut self, size: usize) -> Self {
@@ -56,24 +109,237 @@ impl CallbackHandlerBuilder {
         self
     }
 
+    /// Serve the requests of context aware policies using the Kubernetes objects
+    /// loaded from manifests, instead of interacting with a Kubernetes API server.
+    /// Optional, cannot be used together with [`kube_client`](Self::kube_client)
+    pub fn offline_kubernetes_context(mut self, context: OfflineKubernetesContext) -> Self {
+        self.offline_kubernetes_context = Some(context);
+        self
+    }
+
+    /// Configure the lifecycle of the reflectors used to serve the
+    /// "list resources" requests of context aware policies. Optional
+    pub fn reflectors_config(mut self, config: ReflectorsConfig) -> Self {
+        self.reflectors_config = config;
+        self
+    }
+
+    /// Kubernetes resources whose reflectors are created, and synchronized, when
+    /// the CallbackHandler is built. This avoids the first queries made by context
+    /// aware policies to wait for the initial synchronization. Optional
+    ///
+    /// Failing to pre-warm a resource is not fatal, the error is logged and the
+    /// reflector is created on demand later.
+    pub fn prewarm_reflectors(mut self, resources: BTreeSet<ContextAwareResource>) -> Self {
+        self.prewarm_resources = resources;
+        self
+    }
+
+    /// Configure the caches of the host capabilities: TTLs, sizes and caching
+    /// of the failures. Optional, see [`CacheConfig`] for the defaults
+    pub fn cache_config(mut self, config: CacheConfig) -> Self {
+        self.cache_config = config;
+        self
+    }
+
+    /// Configure the response size limit and the timeout of the HTTP GET
+    /// requests made by the policies. Optional, see [`HttpGetConfig`] for the defaults
+    pub fn http_get_config(mut self, config: HttpGetConfig) -> Self {
+        self.http_get_config = config;
+        self
+    }
+
     /// Create a CallbackHandler object
     pub async fn build(self) -> Result<CallbackHandler> {
         let (tx, rx) = mpsc::channel::<CallbackRequest>(self.channel_buffer_size);
         let oci_client = Arc::new(oci::Client::new(self.oci_sources.clone()));
-        let sigstore_client =
-            sigstore_verification::Client::new(self.oci_sources.clone(), self.trust_root.clone())
-                .await?
-                .to_owned();
+        let (trust_root, require_rekor_bundle) =
+            sigstore_trust_root(self.trust_root, self.offline_sigstore_trust_root)?;
+        let sigstore_client = sigstore_verification::Client::new(
+            self.oci_sources.clone(),
+            trust_root,
+            require_rekor_bundle,
+        )
+        .await?
+        .to_owned();
+        let notation_client = match (self.notation_trust_store, self.notation_trust_policy) {
+            (Some(trust_store), Some(trust_policy)) => Some(
+                notation::Client::new(oci_client.clone(), trust_store, trust_policy)
+                    .map_err(|e| anyhow!("invalid Notation trust policy: {e}"))?,
+            ),
+            (None, None) => None,
+            _ => {
+                return Err(anyhow!(
+                    "both a Notation trust store and a Notation trust policy are required"
+                ));
+            }
+        };
+        let dns_client = dns::Client::new()
+            .inspect_err(|error| warn!(?error, "DNS lookups are not available"))
+            .ok();
+        let http_client = http::Client::new(self.http_get_config)?;
+        let caches = Arc::new(Caches::new(&self.cache_config));
 
-        let kubernetes_client = self.kube_client.map(super::kubernetes::Client::new);
+        let reflectors_config = self.reflectors_config;
+        let kubernetes_client = match (self.kube_client, self.offline_kubernetes_context) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow!(
+                    "a kube::Client and an offline Kubernetes context cannot be used at the same time"
+                ));
+            }
+            (Some(client), None) => {
+                let mut client = super::kubernetes::Client::new(
+                    client,
+                    reflectors_config.clone(),
+                    caches.kubernetes_can_i.clone(),
+                );
+                for (resource, error) in client.prewarm_reflectors(&self.prewarm_resources).await {
+                    warn!(
+                        api_version = resource.api_version,
+                        kind = resource.kind,
+                        ?error,
+                        "cannot pre-warm reflector"
+                    );
+                }
+                Some(ContextProvider::Cluster(client))
+            }
+            (None, Some(context)) => Some(ContextProvider::Offline(Arc::new(context))),
+            (None, None) => None,
+        };
 
         Ok(CallbackHandler {
             oci_client,
             sigstore_client,
+            notation_client,
+            dns_client,
+            http_client,
             kubernetes_client,
+            caches,
+            reflectors_eviction_interval: reflectors_config.idle_timeout,
             tx,
             rx,
             shutdown_channel: self.shutdown_channel,
         })
     }
 }
+
+/// Select the Sigstore trust root to be used, loading the offline one from disk
+/// when given. The returned flag tells whether the Rekor bundles are required:
+/// this is the case when verifying against an offline trust root
+fn sigstore_trust_root(
+    trust_root: Option<Arc<SigstoreTrustRoot>>,
+    offline_trust_root: Option<PathBuf>,
+) -> Result<(Option<Arc<SigstoreTrustRoot>>, bool)> {
+    match (trust_root, offline_trust_root) {
+        (Some(_), Some(_)) => Err(anyhow!(
+            "a Sigstore trust root and an offline Sigstore trust root cannot be used at the same time"
+        )),
+        (None, Some(path)) => {
+            let data = std::fs::read(&path)
+                .map_err(|e| anyhow!("cannot read Sigstore trust root {}: {e}", path.display()))?;
+            let trust_root = SigstoreTrustRoot::from_trusted_root_json_unchecked(&data)
+                .map_err(|e| anyhow!("cannot load Sigstore trust root {}: {e}", path.display()))?;
+            Ok((Some(Arc::new(trust_root)), true))
+        }
+        (trust_root, None) => Ok((trust_root, false)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRUSTED_ROOT: &str = r#"{
+        "mediaType": "application/vnd.dev.sigstore.trustedroot+json;version=0.1",
+        "tlogs": [],
+        "certificateAuthorities": [],
+        "ctlogs": [],
+        "timestampAuthorities": []
+    }"#;
+
+    fn write_trusted_root(dir: &tempfile::TempDir, contents: &str) -> PathBuf {
+        let path = dir.path().join("trusted_root.json");
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn online_trust_root_does_not_require_rekor_bundles() {
+        let (trust_root, require_rekor_bundle) = sigstore_trust_root(None, None).unwrap();
+
+        assert!(trust_root.is_none());
+        assert!(!require_rekor_bundle);
+    }
+
+    #[test]
+    fn offline_trust_root_requires_rekor_bundles() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_trusted_root(&dir, TRUSTED_ROOT);
+
+        let (trust_root, require_rekor_bundle) = sigstore_trust_root(None, Some(path)).unwrap();
+
+        assert!(trust_root.is_some());
+        assert!(require_rekor_bundle);
+    }
+
+    #[test]
+    fn both_trust_roots_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_trusted_root(&dir, TRUSTED_ROOT);
+        let trust_root =
+            SigstoreTrustRoot::from_trusted_root_json_unchecked(TRUSTED_ROOT.as_bytes()).unwrap();
+
+        let error = sigstore_trust_root(Some(Arc::new(trust_root)), Some(path)).unwrap_err();
+
+        assert!(
+            error
+                .to_string()
+                .contains("cannot be used at the same time")
+        );
+    }
+
+    #[test]
+    fn missing_offline_trust_root() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let error =
+            sigstore_trust_root(None, Some(dir.path().join("trusted_root.json"))).unwrap_err();
+
+        assert!(
+            error
+                .to_string()
+                .contains("cannot read Sigstore trust root")
+        );
+    }
+
+    #[test]
+    fn invalid_offline_trust_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_trusted_root(&dir, "not a trusted root");
+
+        let error = sigstore_trust_root(None, Some(path)).unwrap_err();
+
+        assert!(
+            error
+                .to_string()
+                .contains("cannot load Sigstore trust root")
+        );
+    }
+
+    #[tokio::test]
+    async fn builder_rejects_both_trust_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_trusted_root(&dir, TRUSTED_ROOT);
+        let trust_root =
+            SigstoreTrustRoot::from_trusted_root_json_unchecked(TRUSTED_ROOT.as_bytes()).unwrap();
+        let (_shutdown_tx, shutdown_rx) = oneshot::channel();
+
+        let result = CallbackHandlerBuilder::new(shutdown_rx)
+            .trust_root(Some(Arc::new(trust_root)))
+            .offline_sigstore_trust_root(path)
+            .build()
+            .await;
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,749 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use k8s_openapi::api::authorization::v1::SubjectAccessReviewStatus;
+use kubewarden_policy_sdk::host_capabilities::{
+    oci::ManifestDigestResponse, verification::VerificationResponse,
+};
+use policy_fetcher::oci_client::manifest::{OciImageIndex, OciManifest};
+use tokio::{
+    sync::{OnceCell, OwnedSemaphorePermit, Semaphore},
+    time::Instant,
+};
+
+use super::dns::DnsLookupResponse;
+use super::http::HttpGetResponse;
+use super::notation::NotationVerificationResponse;
+use super::oci::{ManifestAndConfigResponse, OciListTagsResponse};
+use super::sigstore_verification::AttestationVerificationResponse;
+use crate::callback_requests::CallbackRequestType;
+
+/// The host capabilities whose results are cached by the
+/// [`CallbackHandler`](super::CallbackHandler).
+///
+/// Each entry of a cache is about a subject, which is the key to be used
+/// when invalidating the cache via [`CacheHandle::invalidate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum CachedCapability {
+    /// Digest of OCI manifests. The subject is the image reference, as given by the policy
+    OciManifestDigest,
+    /// OCI manifests. The subject is the image reference, as given by the policy
+    OciManifest,
+    /// OCI manifests and configurations. The subject is the image reference, as
+    /// given by the policy
+    OciManifestAndConfig,
+    /// OCI manifests and configurations of the images built for a specific platform.
+    /// The subject is the image reference, as given by the policy
+    OciManifestAndConfigForPlatform,
+    /// Tags of OCI repositories. The subject is the repository, as given by the policy
+    OciTags,
+    /// OCI 1.1 referrers. The subject is the image reference, as given by the policy
+    OciReferrers,
+    /// All the Sigstore verifications. The subject is the image reference, as
+    /// given by the policy
+    SigstoreVerification,
+    /// Notation verifications. The subject is the image reference, as given by the policy
+    NotationVerification,
+    /// Kubernetes objects fetched from the API server. The subject is
+    /// `<apiVersion>/<kind>/<namespace>/<name>`, the namespace is omitted for
+    /// cluster wide resources
+    KubernetesGetResource,
+    /// SubjectAccessReview decisions. The subject is the name of the user
+    KubernetesCanI,
+    /// DNS lookups. The subject is the hostname, or the IP address for reverse
+    /// lookups. The entries are kept for the TTL of the DNS records, up to the
+    /// configured TTL
+    DnsLookup,
+    /// Responses of the HTTP GET requests. The subject is the URL
+    HttpGet,
+}
+
+/// Cache settings of a host capability
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapabilityCacheConfig {
+    /// How long successful results are kept. A zero duration disables the cache
+    pub ttl: Duration,
+    /// The maximum number of entries. When exceeded, the oldest entries are evicted
+    pub max_entries: Option<usize>,
+    /// How long failures are kept. By default failures are not cached
+    pub negative_ttl: Option<Duration>,
+    /// The maximum number of requests of the capability evaluated at the same
+    /// time, including the ones served by the cache or coalesced with an
+    /// operation in flight. The other requests are not evaluated until one of
+    /// them is completed, or their deadline is reached. By default there's no limit
+    pub max_concurrent_requests: Option<usize>,
+}
+
+impl CapabilityCacheConfig {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            max_entries: None,
+            negative_ttl: None,
+            max_concurrent_requests: None,
+        }
+    }
+}
+
+/// Cache settings of all the host capabilities.
+///
+/// By default, the results of OCI, Sigstore, Notation and HTTP operations are cached for 60
+/// seconds, while the results of Kubernetes operations are cached for 5 seconds.
+/// DNS records are cached for their TTL, up to 300 seconds.
+/// The caches are unbounded, failures are not cached and there's no limit on the
+/// number of concurrent operations.
+///
+/// Regardless of the configuration, identical operations that are in flight at
+/// the same time are always coalesced.
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    capabilities: HashMap<CachedCapability, CapabilityCacheConfig>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        let oci = CapabilityCacheConfig::new(Duration::from_secs(60));
+        let kubernetes = CapabilityCacheConfig::new(Duration::from_secs(5));
+        let dns = CapabilityCacheConfig::new(Duration::from_secs(300));
+
+        Self {
+            capabilities: HashMap::from([
+                (CachedCapability::OciManifestDigest, oci),
+                (CachedCapability::OciManifest, oci),
+                (CachedCapability::OciManifestAndConfig, oci),
+                (CachedCapability::OciManifestAndConfigForPlatform, oci),
+                (CachedCapability::OciTags, oci),
+                (CachedCapability::OciReferrers, oci),
+                (CachedCapability::SigstoreVerification, oci),
+                (CachedCapability::NotationVerification, oci),
+                (CachedCapability::KubernetesGetResource, kubernetes),
+                (CachedCapability::KubernetesCanI, kubernetes),
+                (CachedCapability::DnsLookup, dns),
+                (CachedCapability::HttpGet, oci),
+            ]),
+        }
+    }
+}
+
+impl CacheConfig {
+    /// Change the cache settings of the given capability
+    #[must_use]
+    pub fn capability(
+        mut self,
+        capability: CachedCapability,
+        config: CapabilityCacheConfig,
+    ) -> Self {
+        self.capabilities.insert(capability, config);
+        self
+    }
+
+    /// Get the cache settings of the given capability
+    pub fn get(&self, capability: CachedCapability) -> CapabilityCacheConfig {
+        // the default configuration has an entry for each capability
+        self.capabilities[&capability]
+    }
+}
+
+struct Entry<V> {
+    inserted_at: Instant,
+    ttl: Duration,
+    value: std::result::Result<V, String>,
+}
+
+type InFlight<V> = Arc<OnceCell<std::result::Result<V, String>>>;
+
+/// A time bound cache holding the results of a host capability.
+///
+/// Identical operations that are requested while one of them is still in
+/// flight are coalesced: the operation is performed only once, and all the
+/// callers get its result.
+pub(crate) struct CapabilityCache<V> {
+    config: CapabilityCacheConfig,
+    /// The entries, indexed by `(subject, details)`
+    entries: Mutex<HashMap<(String, String), Entry<V>>>,
+    in_flight: Mutex<HashMap<(String, String), InFlight<V>>>,
+    /// Limits the number of requests evaluated at the same time, when configured
+    limiter: Option<Arc<Semaphore>>,
+    /// Computes how long a successful result is valid, when it carries its own TTL
+    value_ttl: Option<fn(&V) -> Duration>,
+}
+
+impl<V: Clone> CapabilityCache<V> {
+    pub fn new(config: CapabilityCacheConfig) -> Self {
+        Self {
+            config,
+            entries: Mutex::new(HashMap::new()),
+            in_flight: Mutex::new(HashMap::new()),
+            limiter: config
+                .max_concurrent_requests
+                .map(|permits| Arc::new(Semaphore::new(permits))),
+            value_ttl: None,
+        }
+    }
+
+    /// Create a cache whose successful results are kept for the TTL computed
+    /// by `value_ttl`, up to the configured TTL
+    pub fn with_value_ttl(config: CapabilityCacheConfig, value_ttl: fn(&V) -> Duration) -> Self {
+        Self {
+            value_ttl: Some(value_ttl),
+            ..Self::new(config)
+        }
+    }
+
+    /// Use the limiter of `other`: the requests evaluated through both the
+    /// caches count against the same limit
+    #[must_use]
+    pub fn share_limiter<U>(mut self, other: &CapabilityCache<U>) -> Self {
+        self.limiter = other.limiter.clone();
+        self
+    }
+
+    fn ttl(&self, value: &std::result::Result<V, String>) -> Duration {
+        match (value, self.value_ttl) {
+            (Ok(value), Some(value_ttl)) => value_ttl(value).min(self.config.ttl),
+            (Ok(_), None) => self.config.ttl,
+            (Err(_), _) => self.config.negative_ttl.unwrap_or_default(),
+        }
+    }
+
+    fn is_expired(&self, entry: &Entry<V>) -> bool {
+        entry.inserted_at.elapsed() >= entry.ttl
+    }
+
+    /// Get the cached result. `subject` and `details` together identify the entry
+    pub fn get(&self, subject: &str, details: &str) -> Option<Result<V>> {
+        let entries = self.entries.lock().expect("cannot lock cache");
+        entries
+            .get(&(subject.to_owned(), details.to_owned()))
+            .filter(|entry| !self.is_expired(entry))
+            .map(|entry| entry.value.clone().map_err(|e| anyhow!(e)))
+    }
+
+    /// Cache the given result, unless the configuration prevents it
+    pub fn insert(&self, subject: &str, details: &str, value: std::result::Result<V, String>) {
+        let ttl = self.ttl(&value);
+        if ttl.is_zero() {
+            return;
+        }
+
+        let mut entries = self.entries.lock().expect("cannot lock cache");
+        entries.retain(|_, entry| !self.is_expired(entry));
+
+        if let Some(max_entries) = self.config.max_entries {
+            while entries.len() >= max_entries {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.inserted_at)
+                    .map(|(key, _)| key.clone());
+                match oldest {
+                    Some(key) => entries.remove(&key),
+                    None => return,
+                };
+            }
+        }
+
+        entries.insert(
+            (subject.to_owned(), details.to_owned()),
+            Entry {
+                inserted_at: Instant::now(),
+                ttl,
+                value,
+            },
+        );
+    }
+
+    /// Return the cached result, or compute and cache it when missing. When an
+    /// identical operation is already in flight, wait for its result instead
+    pub async fn get_or_insert_with<F, Fut>(
+        &self,
+        subject: &str,
+        details: &str,
+        f: F,
+    ) -> Result<cached::Return<V>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V>>,
+    {
+        if let Some(cached) = self.get(subject, details) {
+            return cached.map(|value| cached::Return {
+                was_cached: true,
+                value,
+            });
+        }
+
+        let key = (subject.to_owned(), details.to_owned());
+        let cell = self
+            .in_flight
+            .lock()
+            .expect("cannot lock in-flight operations")
+            .entry(key.clone())
+            .or_default()
+            .clone();
+
+        let mut performed = false;
+        let performed_ref = &mut performed;
+        let result = cell
+            .get_or_init(move || async move {
+                *performed_ref = true;
+                let result = f().await.map_err(|e| e.to_string());
+                // cache the result before the operation stops being in flight,
+                // otherwise a new request could perform it again in between
+                self.insert(subject, details, result.clone());
+                result
+            })
+            .await
+            .clone();
+
+        {
+            let mut in_flight = self
+                .in_flight
+                .lock()
+                .expect("cannot lock in-flight operations");
+            if in_flight
+                .get(&key)
+                .is_some_and(|current| Arc::ptr_eq(current, &cell))
+            {
+                in_flight.remove(&key);
+            }
+        }
+
+        result
+            .map(|value| cached::Return {
+                // the waiters of an in-flight operation got a shared result
+                was_cached: !performed,
+                value,
+            })
+            .map_err(|e| anyhow!(e))
+    }
+
+    /// Wait until a new request can be evaluated, when the number of requests
+    /// evaluated at the same time is limited. The request must be evaluated
+    /// while holding the returned permit
+    pub async fn acquire_permit(&self) -> Option<OwnedSemaphorePermit> {
+        let limiter = self.limiter.clone()?;
+        Some(
+            limiter
+                .acquire_owned()
+                .await
+                .expect("the semaphore is never closed"),
+        )
+    }
+
+    /// Remove all the entries about the given subject. Returns the number of removed entries
+    pub fn invalidate(&self, subject: &str) -> usize {
+        let mut entries = self.entries.lock().expect("cannot lock cache");
+        let before = entries.len();
+        entries.retain(|(entry_subject, _), _| entry_subject != subject);
+        before - entries.len()
+    }
+}
+
+/// All the caches used by the [`CallbackHandler`](super::CallbackHandler)
+pub(crate) struct Caches {
+    pub oci_manifest_digest: CapabilityCache<ManifestDigestResponse>,
+    pub oci_manifest: CapabilityCache<OciManifest>,
+    pub oci_manifest_and_config: CapabilityCache<ManifestAndConfigResponse>,
+    pub oci_manifest_and_config_for_platform: CapabilityCache<ManifestAndConfigResponse>,
+    pub oci_tags: CapabilityCache<OciListTagsResponse>,
+    pub oci_referrers: CapabilityCache<OciImageIndex>,
+    pub sigstore_verification: CapabilityCache<VerificationResponse>,
+    pub sigstore_attestation: CapabilityCache<AttestationVerificationResponse>,
+    pub notation_verification: CapabilityCache<NotationVerificationResponse>,
+    pub kubernetes_get_resource: CapabilityCache<kube::core::DynamicObject>,
+    pub kubernetes_can_i: Arc<CapabilityCache<SubjectAccessReviewStatus>>,
+    pub dns_lookup: CapabilityCache<DnsLookupResponse>,
+    pub http_get: CapabilityCache<HttpGetResponse>,
+}
+
+impl Caches {
+    pub fn new(config: &CacheConfig) -> Self {
+        let sigstore_verification =
+            CapabilityCache::new(config.get(CachedCapability::SigstoreVerification));
+        // attestations are verified by the same sigstore client, the limit on the
+        // concurrent requests applies to both
+        let sigstore_attestation =
+            CapabilityCache::new(config.get(CachedCapability::SigstoreVerification))
+                .share_limiter(&sigstore_verification);
+
+        Self {
+            oci_manifest_digest: CapabilityCache::new(
+                config.get(CachedCapability::OciManifestDigest),
+            ),
+            oci_manifest: CapabilityCache::new(config.get(CachedCapability::OciManifest)),
+            oci_manifest_and_config: CapabilityCache::new(
+                config.get(CachedCapability::OciManifestAndConfig),
+            ),
+            oci_manifest_and_config_for_platform: CapabilityCache::new(
+                config.get(CachedCapability::OciManifestAndConfigForPlatform),
+            ),
+            oci_tags: CapabilityCache::new(config.get(CachedCapability::OciTags)),
+            oci_referrers: CapabilityCache::new(config.get(CachedCapability::OciReferrers)),
+            sigstore_verification,
+            sigstore_attestation,
+            notation_verification: CapabilityCache::new(
+                config.get(CachedCapability::NotationVerification),
+            ),
+            kubernetes_get_resource: CapabilityCache::new(
+                config.get(CachedCapability::KubernetesGetResource),
+            ),
+            kubernetes_can_i: Arc::new(CapabilityCache::new(
+                config.get(CachedCapability::KubernetesCanI),
+            )),
+            dns_lookup: CapabilityCache::with_value_ttl(
+                config.get(CachedCapability::DnsLookup),
+                |response| Duration::from_secs(response.ttl),
+            ),
+            http_get: CapabilityCache::new(config.get(CachedCapability::HttpGet)),
+        }
+    }
+
+    /// Wait until the given request can be evaluated, see
+    /// [`CapabilityCacheConfig::max_concurrent_requests`]. Requests that are
+    /// not about a cached capability are never limited
+    pub async fn acquire_permit(
+        &self,
+        request: &CallbackRequestType,
+    ) -> Option<OwnedSemaphorePermit> {
+        match request {
+            CallbackRequestType::OciManifestDigest { .. } => {
+                self.oci_manifest_digest.acquire_permit().await
+            }
+            CallbackRequestType::OciManifest { .. } => self.oci_manifest.acquire_permit().await,
+            CallbackRequestType::OciManifestAndConfig { .. } => {
+                self.oci_manifest_and_config.acquire_permit().await
+            }
+            CallbackRequestType::OciManifestAndConfigForPlatform { .. } => {
+                self.oci_manifest_and_config_for_platform
+                    .acquire_permit()
+                    .await
+            }
+            CallbackRequestType::OciListTags { .. } => self.oci_tags.acquire_permit().await,
+            CallbackRequestType::OciReferrers { .. } => self.oci_referrers.acquire_permit().await,
+            CallbackRequestType::SigstorePubKeyVerify { .. }
+            | CallbackRequestType::SigstoreKeylessVerify { .. }
+            | CallbackRequestType::SigstoreKeylessPrefixVerify { .. }
+            | CallbackRequestType::SigstoreGithubActionsVerify { .. }
+            | CallbackRequestType::SigstoreCertificateVerify { .. } => {
+                self.sigstore_verification.acquire_permit().await
+            }
+            CallbackRequestType::SigstoreAttestationVerify { .. } => {
+                self.sigstore_attestation.acquire_permit().await
+            }
+            CallbackRequestType::NotationVerify { .. } => {
+                self.notation_verification.acquire_permit().await
+            }
+            CallbackRequestType::KubernetesGetResource { .. } => {
+                self.kubernetes_get_resource.acquire_permit().await
+            }
+            CallbackRequestType::KubernetesCanI { .. }
+            | CallbackRequestType::KubernetesCanIBatch { .. } => {
+                self.kubernetes_can_i.acquire_permit().await
+            }
+            CallbackRequestType::DNSLookupHost { .. }
+            | CallbackRequestType::DNSLookup { .. }
+            | CallbackRequestType::DNSReverseLookup { .. } => {
+                self.dns_lookup.acquire_permit().await
+            }
+            CallbackRequestType::HttpGet { .. } => self.http_get.acquire_permit().await,
+            CallbackRequestType::KubernetesListResourceNamespace { .. }
+            | CallbackRequestType::KubernetesListResourceAll { .. }
+            | CallbackRequestType::KubernetesGetResourcePluralName { .. }
+            | CallbackRequestType::HasKubernetesListResourceAllResultChangedSinceInstant {
+                ..
+            }
+            | CallbackRequestType::CryptoIsCertificateTrusted { .. }
+            | CallbackRequestType::CryptoIsCertificateTrustedV2 { .. } => None,
+        }
+    }
+
+    fn invalidate(&self, capability: CachedCapability, subject: &str) -> usize {
+        match capability {
+            CachedCapability::OciManifestDigest => self.oci_manifest_digest.invalidate(subject),
+            CachedCapability::OciManifest => self.oci_manifest.invalidate(subject),
+            CachedCapability::OciManifestAndConfig => {
+                self.oci_manifest_and_config.invalidate(subject)
+            }
+            CachedCapability::OciManifestAndConfigForPlatform => self
+                .oci_manifest_and_config_for_platform
+                .invalidate(subject),
+            CachedCapability::OciTags => self.oci_tags.invalidate(subject),
+            CachedCapability::OciReferrers => self.oci_referrers.invalidate(subject),
+            CachedCapability::SigstoreVerification => {
+                self.sigstore_verification.invalidate(subject)
+                    + self.sigstore_attestation.invalidate(subject)
+            }
+            CachedCapability::NotationVerification => {
+                self.notation_verification.invalidate(subject)
+            }
+            CachedCapability::KubernetesGetResource => {
+                self.kubernetes_get_resource.invalidate(subject)
+            }
+            CachedCapability::KubernetesCanI => self.kubernetes_can_i.invalidate(subject),
+            CachedCapability::DnsLookup => self.dns_lookup.invalidate(subject),
+            CachedCapability::HttpGet => self.http_get.invalidate(subject),
+        }
+    }
+}
+
+/// A handle that can be used to manage the caches of a
+/// [`CallbackHandler`](super::CallbackHandler), even after its evaluation
+/// loop has been started
+#[derive(Clone)]
+pub struct CacheHandle {
+    caches: Arc<Caches>,
+}
+
+impl CacheHandle {
+    pub(crate) fn new(caches: Arc<Caches>) -> Self {
+        Self { caches }
+    }
+
+    /// Remove all the cached results of the capability about the given subject.
+    /// See [`CachedCapability`] for the format of the subject.
+    /// Returns the number of removed entries
+    pub fn invalidate(&self, capability: CachedCapability, subject: &str) -> usize {
+        self.caches.invalidate(capability, subject)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn cache(
+        ttl: Duration,
+        max_entries: Option<usize>,
+        negative_ttl: Option<Duration>,
+    ) -> CapabilityCache<String> {
+        CapabilityCache::new(CapabilityCacheConfig {
+            ttl,
+            max_entries,
+            negative_ttl,
+            max_concurrent_requests: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn cache_successful_results() {
+        let cache = cache(Duration::from_secs(60), None, None);
+
+        let first = cache
+            .get_or_insert_with("busybox", "", || async { Ok("first".to_owned()) })
+            .await
+            .unwrap();
+        let second = cache
+            .get_or_insert_with("busybox", "", || async { Ok("second".to_owned()) })
+            .await
+            .unwrap();
+
+        assert!(!first.was_cached);
+        assert!(second.was_cached);
+        assert_eq!(second.value, "first");
+    }
+
+    #[rstest]
+    #[case::not_cached(None, false)]
+    #[case::negative_caching(Some(Duration::from_secs(60)), true)]
+    #[tokio::test]
+    async fn cache_failures(#[case] negative_ttl: Option<Duration>, #[case] cached: bool) {
+        let cache = cache(Duration::from_secs(60), None, negative_ttl);
+
+        let first = cache
+            .get_or_insert_with("busybox", "", || async { Err(anyhow!("registry down")) })
+            .await;
+        let second = cache
+            .get_or_insert_with("busybox", "", || async { Ok("digest".to_owned()) })
+            .await;
+
+        assert!(first.is_err());
+        assert_eq!(second.is_err(), cached);
+    }
+
+    #[tokio::test]
+    async fn expire_entries() {
+        let cache = cache(Duration::from_millis(100), None, None);
+        cache.insert("busybox", "", Ok("digest".to_owned()));
+        assert!(cache.get("busybox", "").is_some());
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+
+        assert!(cache.get("busybox", "").is_none());
+    }
+
+    #[test]
+    fn zero_ttl_disables_the_cache() {
+        let cache = cache(Duration::ZERO, None, None);
+        cache.insert("busybox", "", Ok("digest".to_owned()));
+
+        assert!(cache.get("busybox", "").is_none());
+    }
+
+    #[rstest]
+    #[case::value_ttl_shorter(Duration::from_millis(100), Duration::from_secs(60), false)]
+    #[case::configured_ttl_shorter(Duration::from_secs(60), Duration::from_millis(100), false)]
+    #[case::both_long(Duration::from_secs(60), Duration::from_secs(60), true)]
+    #[tokio::test]
+    async fn expire_entries_with_value_ttl(
+        #[case] value_ttl: Duration,
+        #[case] configured_ttl: Duration,
+        #[case] still_cached: bool,
+    ) {
+        let cache = CapabilityCache::with_value_ttl(
+            CapabilityCacheConfig::new(configured_ttl),
+            |value: &Duration| *value,
+        );
+        cache.insert("example.com", "a", Ok(value_ttl));
+        assert!(cache.get("example.com", "a").is_some());
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+
+        assert_eq!(cache.get("example.com", "a").is_some(), still_cached);
+    }
+
+    #[test]
+    fn evict_oldest_entries() {
+        let cache = cache(Duration::from_secs(60), Some(2), None);
+        cache.insert("busybox", "", Ok("1".to_owned()));
+        cache.insert("alpine", "", Ok("2".to_owned()));
+        cache.insert("nginx", "", Ok("3".to_owned()));
+
+        assert!(cache.get("busybox", "").is_none());
+        assert!(cache.get("alpine", "").is_some());
+        assert!(cache.get("nginx", "").is_some());
+    }
+
+    #[test]
+    fn invalidate_subject() {
+        let cache = cache(Duration::from_secs(60), None, None);
+        cache.insert("busybox", "pub_key", Ok("1".to_owned()));
+        cache.insert("busybox", "keyless", Ok("2".to_owned()));
+        cache.insert("alpine", "pub_key", Ok("3".to_owned()));
+
+        assert_eq!(cache.invalidate("busybox"), 2);
+        assert!(cache.get("busybox", "pub_key").is_none());
+        assert!(cache.get("alpine", "pub_key").is_some());
+    }
+
+    #[rstest]
+    #[case::cache_enabled(Duration::from_secs(60))]
+    #[case::cache_disabled(Duration::ZERO)]
+    #[tokio::test]
+    async fn coalesce_in_flight_operations(#[case] ttl: Duration) {
+        let cache = cache(ttl, None, None);
+        let counter = AtomicUsize::new(0);
+        let lookups = &counter;
+
+        let lookup = move || async move {
+            lookups.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok("digest".to_owned())
+        };
+        let results = futures::future::join_all(
+            (0..10).map(|_| cache.get_or_insert_with("busybox", "", lookup)),
+        )
+        .await;
+
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+        assert!(
+            results
+                .into_iter()
+                .all(|r| r.is_ok_and(|r| r.value == "digest"))
+        );
+    }
+
+    #[tokio::test]
+    async fn share_failures_with_waiters() {
+        let cache = cache(Duration::from_secs(60), None, None);
+
+        let lookup = || async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Err(anyhow!("registry down"))
+        };
+        let (first, second) = tokio::join!(
+            cache.get_or_insert_with("busybox", "", lookup),
+            cache.get_or_insert_with("busybox", "", lookup),
+        );
+
+        assert!(first.is_err());
+        assert!(second.is_err());
+        // failures are not cached, the next request performs the operation again
+        let third = cache
+            .get_or_insert_with("busybox", "", || async { Ok("digest".to_owned()) })
+            .await;
+        assert!(third.is_ok_and(|r| !r.was_cached));
+    }
+
+    #[tokio::test]
+    async fn no_limit_on_concurrent_operations() {
+        let cache: CapabilityCache<String> =
+            CapabilityCache::new(CapabilityCacheConfig::new(Duration::from_secs(60)));
+
+        assert!(cache.acquire_permit().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn limit_concurrent_operations() {
+        let cache = CapabilityCache::new(CapabilityCacheConfig {
+            max_concurrent_requests: Some(2),
+            ..CapabilityCacheConfig::new(Duration::from_secs(60))
+        });
+        let running_counter = AtomicUsize::new(0);
+        let max_running_counter = AtomicUsize::new(0);
+        let running = &running_counter;
+        let max_running = &max_running_counter;
+
+        let lookup = move || async move {
+            let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(now_running, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+            Ok("digest".to_owned())
+        };
+        let images: Vec<String> = (0..10).map(|i| format!("image-{i}")).collect();
+        let results = futures::future::join_all(images.iter().map(|image| {
+            let cache = &cache;
+            async move {
+                let _permit = cache.acquire_permit().await;
+                cache.get_or_insert_with(image, "", lookup).await
+            }
+        }))
+        .await;
+
+        assert!(results.into_iter().all(|r| r.is_ok()));
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn sigstore_verifications_and_attestations_share_the_limit() {
+        let caches = Caches::new(&CacheConfig::default().capability(
+            CachedCapability::SigstoreVerification,
+            CapabilityCacheConfig {
+                max_concurrent_requests: Some(1),
+                ..CapabilityCacheConfig::new(Duration::from_secs(60))
+            },
+        ));
+
+        let permit = caches.sigstore_verification.acquire_permit().await;
+        assert!(permit.is_some());
+        assert!(
+            tokio::time::timeout(
+                Duration::from_millis(20),
+                caches.sigstore_attestation.acquire_permit()
+            )
+            .await
+            .is_err(),
+            "the attestation has been evaluated beyond the limit"
+        );
+
+        drop(permit);
+        assert!(caches.sigstore_attestation.acquire_permit().await.is_some());
+    }
+}
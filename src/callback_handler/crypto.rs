@@ -3,14 +3,95 @@ use kubewarden_policy_sdk::host_capabilities::{
     crypto::{Certificate, CertificateEncoding},
     crypto_v1::{CertificateVerificationRequest, CertificateVerificationResponse},
 };
-use pki_types::{CertificateDer, TrustAnchor, UnixTime, pem::PemObject};
-use webpki::{EndEntityCert, Error};
+use pki_types::{
+    CertificateDer, CertificateRevocationListDer, ServerName, TrustAnchor, UnixTime, pem::PemObject,
+};
+use serde::{Deserialize, Serialize};
+use webpki::{
+    BorrowedCertRevocationList, CertRevocationList, EndEntityCert, Error, ExpirationPolicy,
+    RevocationCheckDepth, RevocationOptionsBuilder, UnknownStatusPolicy,
+};
+use x509_cert::{der::Decode, ext::pkix};
+
+use crate::callback_requests::{
+    CertificateRevocationList, CertificateVerificationV2Request, ExtendedKeyUsage, KeyUsage,
+};
 
 const CERTIFICATE_USED_AFTER_EXPIRATION: &str =
     "Certificate is being used after its expiration date";
 const CERTIFICATE_USED_BEFORE_VALIDITY: &str = "Certificate is being used before its validity date";
 const CERTIFICATE_NOT_TRUSTED_BY_CHAIN: &str =
     "Certificate is not trusted by the provided cert chain";
+const CERTIFICATE_REVOKED: &str = "Certificate has been revoked";
+const CERTIFICATE_REVOCATION_STATUS_UNKNOWN: &str =
+    "Certificate is not covered by the provided CRLs";
+const CRL_EXPIRED: &str = "The CRL covering the certificate is expired";
+
+/// The value of the `id-kp-codeSigning` OID: 1.3.6.1.5.5.7.3.3
+const EKU_CODE_SIGNING: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x03];
+
+/// The outcome of the v2 certificate verification
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CertificateVerificationV2Response {
+    pub trusted: bool,
+    /// All the reasons why the certificate is not trusted. Empty when the
+    /// certificate is trusted
+    pub reasons: Vec<CertificateVerificationReason>,
+}
+
+/// A reason why a certificate is not trusted
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CertificateVerificationReason {
+    pub code: CertificateVerificationReasonCode,
+    /// Human readable description of the reason
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CertificateVerificationReasonCode {
+    NotTrustedByChain,
+    Expired,
+    NotValidYet,
+    Revoked,
+    UnknownRevocationStatus,
+    MissingKeyUsage,
+    MissingExtendedKeyUsage,
+    NameMismatch,
+    Invalid,
+}
+
+impl CertificateVerificationReason {
+    fn new(code: CertificateVerificationReasonCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<Error> for CertificateVerificationReason {
+    fn from(error: Error) -> Self {
+        use CertificateVerificationReasonCode::*;
+
+        match error {
+            Error::InvalidSignatureForPublicKey | Error::UnknownIssuer => {
+                Self::new(NotTrustedByChain, CERTIFICATE_NOT_TRUSTED_BY_CHAIN)
+            }
+            Error::CertExpired { .. } => Self::new(Expired, CERTIFICATE_USED_AFTER_EXPIRATION),
+            Error::CertNotValidYet { .. } => {
+                Self::new(NotValidYet, CERTIFICATE_USED_BEFORE_VALIDITY)
+            }
+            Error::CertRevoked => Self::new(Revoked, CERTIFICATE_REVOKED),
+            Error::UnknownRevocationStatus => Self::new(
+                UnknownRevocationStatus,
+                CERTIFICATE_REVOCATION_STATUS_UNKNOWN,
+            ),
+            Error::CrlExpired { .. } => Self::new(UnknownRevocationStatus, CRL_EXPIRED),
+            e => Self::new(Invalid, format!("Certificate not trusted: {}", e)),
+        }
+    }
+}
 
 // Helper function to convert a KW Certificate to a webpki CertificateDer
 fn get_certificate_der<'a>(cert: &'a Certificate) -> Result<CertificateDer<'a>> {
@@ -21,6 +102,39 @@ fn get_certificate_der<'a>(cert: &'a Certificate) -> Result<CertificateDer<'a>>
     }
 }
 
+// Helper function to convert a CRL to a webpki CertificateRevocationListDer
+fn get_crl_der(crl: &CertificateRevocationList) -> Result<CertificateRevocationListDer<'_>> {
+    match crl.encoding {
+        CertificateEncoding::Pem => CertificateRevocationListDer::from_pem_slice(&crl.data)
+            .map_err(|e| anyhow!("CRL PEM data is not valid: {}", e)),
+        CertificateEncoding::Der => Ok(CertificateRevocationListDer::from(crl.data.as_slice())),
+    }
+}
+
+/// The time at which the certificates are verified: either the given RFC 3339
+/// timestamp, or the current time
+fn verification_time(not_after: Option<&str>) -> Result<UnixTime> {
+    match not_after {
+        Some(not_after_str) => {
+            // picky - the library we used earlier - deals with UTCTime as defined in:
+            //   https://www.rfc-editor.org/rfc/rfc5280#section-4.1.2.5.1
+            //
+            // Convert RFC 3339 not_after string from the request to chrono's
+            // DateTime<Utc>, to ensure Zulu:
+            let not_after_utc = chrono::DateTime::parse_from_rfc3339(not_after_str)
+                .map_err(|_| anyhow!("Timestamp not_after is not in RFC3339 format"))?
+                .to_utc();
+            Ok(UnixTime::since_unix_epoch(std::time::Duration::from_secs(
+                not_after_utc.timestamp() as u64,
+            )))
+        }
+        None => {
+            let now = std::time::Duration::from_secs(chrono::Utc::now().timestamp() as u64);
+            Ok(UnixTime::since_unix_epoch(now))
+        }
+    }
+}
+
 /// Verify a certificate against an optional chain of trust.
 /// The certificate is checked for validation time, and if a cert chain is provided,
 /// the certificate is checked against the chain of trust.
@@ -37,29 +151,153 @@ pub fn verify_certificate(
         .map_err(|e| anyhow!("Certificate is not a valid end-entity certificate: {}", e))?;
 
     // verify validity
-    let verification_time = match &req.not_after {
-        Some(not_after_str) => {
-            // picky - the library we used earlier - deals with UTCTime as defined in:
-            //   https://www.rfc-editor.org/rfc/rfc5280#section-4.1.2.5.1
-            //
-            // Convert RFC 3339 not_after string from the request to chrono's
-            // DateTime<Utc>, to ensure Zulu:
-            let not_after_utc = chrono::DateTime::parse_from_rfc3339(not_after_str)
-                .map_err(|_| anyhow!("Timestamp not_after is not in RFC3339 format"))?
-                .to_utc();
-            UnixTime::since_unix_epoch(std::time::Duration::from_secs(
-                not_after_utc.timestamp() as u64
-            ))
-        }
-        None => {
-            let now = std::time::Duration::from_secs(chrono::Utc::now().timestamp() as u64);
-            UnixTime::since_unix_epoch(now)
-        }
-    };
+    let verification_time = verification_time(req.not_after.as_deref())?;
 
     verify_cert_chain(req.cert_chain, end_entity_certificate, verification_time)
 }
 
+/// Verify a certificate like [`verify_certificate`] does, and additionally:
+/// - check its revocation status against the provided CRLs
+/// - ensure it has the required key usages and extended key usages
+/// - ensure it is valid for the given DNS names, according to its subject
+///   alternative names and to the name constraints of its CAs
+///
+/// All the reasons why the certificate is not trusted are reported.
+pub fn verify_certificate_v2(
+    req: CertificateVerificationV2Request,
+) -> Result<cached::Return<CertificateVerificationV2Response>> {
+    let cert_der = get_certificate_der(&req.cert)?;
+    let end_entity_certificate = EndEntityCert::try_from(&cert_der)
+        .map_err(|e| anyhow!("Certificate is not a valid end-entity certificate: {}", e))?;
+    let verification_time = verification_time(req.not_after.as_deref())?;
+
+    let cert_pool = match &req.cert_chain {
+        None => CertificatePool::from_webpki_roots(),
+        Some(chain) => CertificatePool::from_certificates(chain),
+    }?;
+
+    let crls_der = req
+        .crls
+        .iter()
+        .map(get_crl_der)
+        .collect::<Result<Vec<_>>>()?;
+    let crls = crls_der
+        .iter()
+        .map(|crl| BorrowedCertRevocationList::from_der(crl).map(CertRevocationList::from))
+        .collect::<Result<Vec<_>, Error>>()
+        .map_err(|e| anyhow!("CRL is not valid: {}", e))?;
+    let crls = crls.iter().collect::<Vec<_>>();
+    // Only the revocation status of the end-entity certificate is checked: the
+    // CRLs of the intermediate CAs are usually not at hand
+    let revocation = RevocationOptionsBuilder::new(&crls).ok().map(|builder| {
+        builder
+            .with_depth(RevocationCheckDepth::EndEntity)
+            .with_status_policy(UnknownStatusPolicy::Deny)
+            .with_expiration_policy(ExpirationPolicy::Enforce)
+            .build()
+    });
+
+    let mut reasons = vec![];
+    if let Err(e) = end_entity_certificate.verify_for_usage(
+        webpki::ALL_VERIFICATION_ALGS,
+        &cert_pool.trusted_roots,
+        &cert_pool.intermediates,
+        verification_time,
+        KeyUsageAlwaysValid::accept_any(),
+        revocation,
+        None,
+    ) {
+        reasons.push(e.into());
+    }
+
+    reasons.extend(missing_key_usages(
+        &cert_der,
+        &req.key_usages,
+        &req.extended_key_usages,
+    )?);
+
+    for dns_name in &req.dns_names {
+        let valid = ServerName::try_from(dns_name.as_str()).is_ok_and(|name| {
+            end_entity_certificate
+                .verify_is_valid_for_subject_name(&name)
+                .is_ok()
+        });
+        if !valid {
+            reasons.push(CertificateVerificationReason::new(
+                CertificateVerificationReasonCode::NameMismatch,
+                format!("Certificate is not valid for {dns_name}"),
+            ));
+        }
+    }
+
+    Ok(cached::Return {
+        value: CertificateVerificationV2Response {
+            trusted: reasons.is_empty(),
+            reasons,
+        },
+        was_cached: false,
+    })
+}
+
+/// Look for the key usages and the extended key usages the certificate doesn't
+/// have. A certificate without the Extended Key Usage extension doesn't have
+/// any extended key usage
+fn missing_key_usages(
+    cert_der: &CertificateDer,
+    key_usages: &[KeyUsage],
+    extended_key_usages: &[ExtendedKeyUsage],
+) -> Result<Vec<CertificateVerificationReason>> {
+    if key_usages.is_empty() && extended_key_usages.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let certificate = x509_cert::Certificate::from_der(cert_der)
+        .map_err(|e| anyhow!("Certificate is not valid: {}", e))?;
+    let certificate_key_usage = certificate
+        .tbs_certificate
+        .get::<pkix::KeyUsage>()
+        .map_err(|e| anyhow!("Certificate Key Usage extension is not valid: {}", e))?
+        .map(|(_, key_usage)| key_usage);
+    let certificate_extended_key_usages = certificate
+        .tbs_certificate
+        .get::<pkix::ExtendedKeyUsage>()
+        .map_err(|e| {
+            anyhow!(
+                "Certificate Extended Key Usage extension is not valid: {}",
+                e
+            )
+        })?
+        .map(|(_, extended_key_usage)| extended_key_usage.0)
+        .unwrap_or_default();
+
+    let missing_key_usages = key_usages
+        .iter()
+        .filter(|usage| {
+            !certificate_key_usage
+                .as_ref()
+                .is_some_and(|key_usage| usage.is_set(key_usage))
+        })
+        .map(|usage| {
+            CertificateVerificationReason::new(
+                CertificateVerificationReasonCode::MissingKeyUsage,
+                format!("Certificate does not have the {usage:?} key usage"),
+            )
+        });
+    let missing_extended_key_usages = extended_key_usages
+        .iter()
+        .filter(|usage| !certificate_extended_key_usages.contains(&usage.oid()))
+        .map(|usage| {
+            CertificateVerificationReason::new(
+                CertificateVerificationReasonCode::MissingExtendedKeyUsage,
+                format!("Certificate does not have the {usage:?} extended key usage"),
+            )
+        });
+
+    Ok(missing_key_usages
+        .chain(missing_extended_key_usages)
+        .collect())
+}
+
 fn verify_cert_chain(
     cert_chain: Option<Vec<Certificate>>,
     end_entity_certificate: EndEntityCert,
@@ -134,6 +372,51 @@ fn verify_cert_chain(
     }
 }
 
+/// Verify that the DER encoded code signing certificate has been issued, at the
+/// given time, by one of the trusted certificates. Each trusted certificate is
+/// used as a trust anchor, regardless of being a root CA or an intermediate one.
+pub(crate) fn verify_certificate_issued_by(
+    cert_der: &CertificateDer,
+    trusted_certs: &[CertificateDer],
+    verification_time: UnixTime,
+) -> Result<()> {
+    verify_certificate_chain(cert_der, &[], trusted_certs, verification_time)
+}
+
+/// Verify that the DER encoded certificate has been issued, at the given time, by
+/// one of the trusted certificates, possibly through the given intermediate
+/// certificates. Like [`verify_certificate_issued_by`], each trusted certificate
+/// is used as a trust anchor.
+///
+/// The certificate is used to sign artifacts: it must have the `codeSigning`
+/// extended key usage.
+pub(crate) fn verify_certificate_chain(
+    cert_der: &CertificateDer,
+    intermediates: &[CertificateDer],
+    trusted_certs: &[CertificateDer],
+    verification_time: UnixTime,
+) -> Result<()> {
+    let end_entity_certificate = EndEntityCert::try_from(cert_der)
+        .map_err(|e| anyhow!("Certificate is not a valid end-entity certificate: {}", e))?;
+    let trusted_roots = trusted_certs
+        .iter()
+        .map(|cert| webpki::anchor_from_trusted_cert(cert).map(|anchor| anchor.to_owned()))
+        .collect::<Result<Vec<TrustAnchor>, Error>>()?;
+
+    end_entity_certificate
+        .verify_for_usage(
+            webpki::ALL_VERIFICATION_ALGS,
+            &trusted_roots,
+            intermediates,
+            verification_time,
+            webpki::KeyUsage::required(EKU_CODE_SIGNING),
+            None,
+            None,
+        )
+        .map(|_| ())
+        .map_err(|e| anyhow!("{CERTIFICATE_NOT_TRUSTED_BY_CHAIN}: {e}"))
+}
+
 /// A collection of trusted certificates, both root, and intermediate ones.
 #[derive(Default, Debug)]
 struct CertificatePool<'a> {
@@ -198,7 +481,9 @@ mod tests {
     use kubewarden_policy_sdk::host_capabilities::crypto_v1::CertificateVerificationRequest;
     use lazy_static::lazy_static;
     use rcgen::{
-        CertificateParams, CertifiedKey, ExtendedKeyUsagePurpose, Issuer, KeyPair, KeyUsagePurpose,
+        CertificateParams, CertificateRevocationListParams, CertifiedKey, DnType,
+        ExtendedKeyUsagePurpose, Issuer, KeyIdMethod, KeyPair, KeyUsagePurpose, RevocationReason,
+        RevokedCertParams, SerialNumber,
     };
     use rstest::rstest;
     use time::{Duration, OffsetDateTime};
@@ -727,4 +1012,260 @@ mod tests {
             }
         }
     }
+
+    fn verification_v2_request(
+        cert: Certificate,
+        cert_chain: Vec<Certificate>,
+    ) -> CertificateVerificationV2Request {
+        CertificateVerificationV2Request {
+            cert,
+            cert_chain: Some(cert_chain),
+            not_after: None,
+            key_usages: vec![],
+            extended_key_usages: vec![],
+            dns_names: vec![],
+            crls: vec![],
+        }
+    }
+
+    fn valid_certificate_chain() -> (Certificate, Vec<Certificate>) {
+        generate_certificate_chain(
+            CertificateGenerationSpec {
+                subject_alt_names: &["root.kubewarden.io"],
+                not_before: *TWO_YEARS_AGO,
+                not_after: *TWO_YEARS_IN_FUTURE,
+            },
+            None,
+            CertificateGenerationSpec {
+                subject_alt_names: &["endentity.kubewarden.io"],
+                not_before: *TEN_DAYS_AGO,
+                not_after: *TEN_DAYS_IN_FUTURE,
+            },
+        )
+    }
+
+    #[rstest]
+    #[case::no_requirements(vec![], vec![], vec![], vec![])]
+    #[case::satisfied_requirements(
+        vec![KeyUsage::DigitalSignature, KeyUsage::KeyEncipherment],
+        vec![ExtendedKeyUsage::ServerAuth],
+        vec!["endentity.kubewarden.io"],
+        vec![]
+    )]
+    #[case::missing_key_usage(
+        vec![KeyUsage::KeyCertSign],
+        vec![],
+        vec![],
+        vec![CertificateVerificationReasonCode::MissingKeyUsage]
+    )]
+    #[case::missing_extended_key_usage(
+        vec![],
+        vec![ExtendedKeyUsage::ServerAuth, ExtendedKeyUsage::CodeSigning],
+        vec![],
+        vec![CertificateVerificationReasonCode::MissingExtendedKeyUsage]
+    )]
+    #[case::name_mismatch(
+        vec![],
+        vec![],
+        vec!["endentity.kubewarden.io", "other.kubewarden.io"],
+        vec![CertificateVerificationReasonCode::NameMismatch]
+    )]
+    #[case::all_reasons(
+        vec![KeyUsage::CrlSign],
+        vec![ExtendedKeyUsage::ClientAuth],
+        vec!["other.kubewarden.io"],
+        vec![
+            CertificateVerificationReasonCode::MissingKeyUsage,
+            CertificateVerificationReasonCode::MissingExtendedKeyUsage,
+            CertificateVerificationReasonCode::NameMismatch,
+        ]
+    )]
+    fn certificate_v2_requirements(
+        #[case] key_usages: Vec<KeyUsage>,
+        #[case] extended_key_usages: Vec<ExtendedKeyUsage>,
+        #[case] dns_names: Vec<&str>,
+        #[case] expected_reasons: Vec<CertificateVerificationReasonCode>,
+    ) {
+        let (end_entity_cert, cert_chain) = valid_certificate_chain();
+        let req = CertificateVerificationV2Request {
+            key_usages,
+            extended_key_usages,
+            dns_names: dns_names.into_iter().map(String::from).collect(),
+            ..verification_v2_request(end_entity_cert, cert_chain)
+        };
+
+        let response = verify_certificate_v2(req).unwrap().value;
+
+        assert_eq!(response.trusted, expected_reasons.is_empty());
+        assert_eq!(
+            response
+                .reasons
+                .iter()
+                .map(|reason| reason.code)
+                .collect::<Vec<_>>(),
+            expected_reasons
+        );
+    }
+
+    #[test]
+    fn certificate_v2_is_not_trusted() {
+        let (end_entity_cert, _) = valid_certificate_chain();
+        let (_, other_cert_chain) = valid_certificate_chain();
+
+        let response =
+            verify_certificate_v2(verification_v2_request(end_entity_cert, other_cert_chain))
+                .unwrap()
+                .value;
+
+        assert_eq!(
+            response,
+            CertificateVerificationV2Response {
+                trusted: false,
+                reasons: vec![CertificateVerificationReason::new(
+                    CertificateVerificationReasonCode::NotTrustedByChain,
+                    CERTIFICATE_NOT_TRUSTED_BY_CHAIN,
+                )],
+            }
+        );
+    }
+
+    #[rstest]
+    #[case::not_revoked(false, true, vec![])]
+    #[case::revoked(true, true, vec![CertificateVerificationReasonCode::Revoked])]
+    #[case::crl_of_another_ca(
+        false,
+        false,
+        vec![CertificateVerificationReasonCode::UnknownRevocationStatus]
+    )]
+    fn certificate_v2_revocation(
+        #[case] revoked: bool,
+        #[case] crl_issued_by_ca: bool,
+        #[case] expected_reasons: Vec<CertificateVerificationReasonCode>,
+    ) {
+        let root_ca_spec = CertificateGenerationSpec {
+            subject_alt_names: &["root.kubewarden.io"],
+            not_before: *TWO_YEARS_AGO,
+            not_after: *TWO_YEARS_IN_FUTURE,
+        };
+        let root_ca = generate_certificate(root_ca_spec.clone(), true, None).unwrap();
+        let root_ca_cert = Certificate {
+            encoding: CertificateEncoding::Pem,
+            data: root_ca.cert.pem().as_bytes().to_vec(),
+        };
+        let issuer = Issuer::from_ca_cert_der(root_ca.cert.der(), root_ca.signing_key).unwrap();
+
+        let serial_number = SerialNumber::from(42u64);
+        let mut end_entity_params = build_cert_params(
+            CertificateGenerationSpec {
+                subject_alt_names: &["endentity.kubewarden.io"],
+                not_before: *TEN_DAYS_AGO,
+                not_after: *TEN_DAYS_IN_FUTURE,
+            },
+            false,
+        );
+        end_entity_params.serial_number = Some(serial_number.clone());
+        let end_entity = end_entity_params
+            .signed_by(&KeyPair::generate().unwrap(), &issuer)
+            .unwrap();
+        let end_entity_cert = Certificate {
+            encoding: CertificateEncoding::Pem,
+            data: end_entity.pem().as_bytes().to_vec(),
+        };
+
+        let revoked_certs = if revoked {
+            vec![RevokedCertParams {
+                serial_number,
+                revocation_time: *TEN_DAYS_AGO,
+                reason_code: Some(RevocationReason::KeyCompromise),
+                invalidity_date: None,
+            }]
+        } else {
+            vec![]
+        };
+        let crl_params = CertificateRevocationListParams {
+            this_update: *TEN_DAYS_AGO,
+            next_update: *TEN_DAYS_IN_FUTURE,
+            crl_number: SerialNumber::from(1u64),
+            issuing_distribution_point: None,
+            revoked_certs,
+            key_identifier_method: KeyIdMethod::Sha256,
+        };
+        let crl = if crl_issued_by_ca {
+            crl_params.signed_by(&issuer)
+        } else {
+            // CRLs are matched by issuer name, the other CA must have a different one
+            let mut other_root_ca_params = build_cert_params(root_ca_spec, true);
+            other_root_ca_params
+                .distinguished_name
+                .push(DnType::CommonName, "other root CA");
+            let other_root_ca_key = KeyPair::generate().unwrap();
+            let other_root_ca = other_root_ca_params
+                .self_signed(&other_root_ca_key)
+                .unwrap();
+            let other_issuer =
+                Issuer::from_ca_cert_der(other_root_ca.der(), other_root_ca_key).unwrap();
+            crl_params.signed_by(&other_issuer)
+        }
+        .unwrap();
+
+        let req = CertificateVerificationV2Request {
+            crls: vec![CertificateRevocationList {
+                encoding: CertificateEncoding::Der,
+                data: crl.der().to_vec(),
+            }],
+            ..verification_v2_request(end_entity_cert, vec![root_ca_cert])
+        };
+        let response = verify_certificate_v2(req).unwrap().value;
+
+        assert_eq!(
+            response
+                .reasons
+                .iter()
+                .map(|reason| reason.code)
+                .collect::<Vec<_>>(),
+            expected_reasons
+        );
+    }
+
+    #[rstest]
+    #[case::code_signing(vec![ExtendedKeyUsagePurpose::CodeSigning], true)]
+    #[case::server_auth(vec![ExtendedKeyUsagePurpose::ServerAuth], false)]
+    #[case::no_extended_key_usage(vec![], false)]
+    fn verify_code_signing_certificate_chain(
+        #[case] extended_key_usages: Vec<ExtendedKeyUsagePurpose>,
+        #[case] trusted: bool,
+    ) {
+        let root_ca = generate_certificate(
+            CertificateGenerationSpec {
+                subject_alt_names: &["root.kubewarden.io"],
+                not_before: *TWO_YEARS_AGO,
+                not_after: *TWO_YEARS_IN_FUTURE,
+            },
+            true,
+            None,
+        )
+        .unwrap();
+        let mut end_entity_params = build_cert_params(
+            CertificateGenerationSpec {
+                subject_alt_names: &["signer.kubewarden.io"],
+                not_before: *TEN_DAYS_AGO,
+                not_after: *TEN_DAYS_IN_FUTURE,
+            },
+            false,
+        );
+        end_entity_params.extended_key_usages = extended_key_usages;
+        let issuer = Issuer::from_ca_cert_der(root_ca.cert.der(), root_ca.signing_key).unwrap();
+        let end_entity_cert = end_entity_params
+            .signed_by(&KeyPair::generate().unwrap(), &issuer)
+            .unwrap();
+
+        let result = verify_certificate_chain(
+            end_entity_cert.der(),
+            &[],
+            &[root_ca.cert.der().clone()],
+            UnixTime::now(),
+        );
+
+        assert_eq!(result.is_ok(), trusted, "{result:?}");
+    }
 }
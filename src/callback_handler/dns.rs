@@ -0,0 +1,284 @@
+use std::net::IpAddr;
+
+use anyhow::{Result, anyhow};
+use hickory_resolver::{
+    Name, TokioResolver,
+    lookup::Lookup,
+    proto::rr::{RData, RecordType},
+};
+use kubewarden_policy_sdk::host_capabilities::net::LookupResponse;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::callback_handler::cache::CapabilityCache;
+use crate::callback_requests::DnsRecordType;
+
+impl From<DnsRecordType> for RecordType {
+    fn from(record_type: DnsRecordType) -> Self {
+        match record_type {
+            DnsRecordType::A => RecordType::A,
+            DnsRecordType::Aaaa => RecordType::AAAA,
+            DnsRecordType::Cname => RecordType::CNAME,
+            DnsRecordType::Txt => RecordType::TXT,
+            DnsRecordType::Srv => RecordType::SRV,
+            DnsRecordType::Mx => RecordType::MX,
+        }
+    }
+}
+
+/// A DNS record returned by a lookup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "UPPERCASE")]
+pub enum DnsRecord {
+    A {
+        address: IpAddr,
+    },
+    Aaaa {
+        address: IpAddr,
+    },
+    Cname {
+        target: String,
+    },
+    Txt {
+        /// The character strings of the record, joined together
+        text: String,
+    },
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+    },
+    Mx {
+        preference: u16,
+        exchange: String,
+    },
+    Ptr {
+        name: String,
+    },
+}
+
+/// The result of a DNS lookup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsLookupResponse {
+    pub records: Vec<DnsRecord>,
+    /// How long the records are still valid, in seconds
+    pub ttl: u64,
+}
+
+/// Helper struct to perform asynchronous DNS lookups, using the resolver
+/// configuration of the host
+#[derive(Clone)]
+pub(crate) struct Client {
+    resolver: TokioResolver,
+}
+
+impl Client {
+    pub fn new() -> Result<Self> {
+        let resolver = TokioResolver::builder_tokio()
+            .map_err(|e| anyhow!("cannot read the DNS resolver configuration: {e}"))?
+            .build();
+        Ok(Client { resolver })
+    }
+
+    /// Lookup the IPv4 and IPv6 addresses of the given host
+    pub async fn lookup_host(&self, host: &str) -> Result<DnsLookupResponse> {
+        let lookup = self.resolver.lookup_ip(host).await?;
+        let records = lookup
+            .iter()
+            .map(|address| match address {
+                IpAddr::V4(_) => DnsRecord::A { address },
+                IpAddr::V6(_) => DnsRecord::Aaaa { address },
+            })
+            .collect();
+
+        Ok(DnsLookupResponse {
+            records,
+            ttl: remaining_ttl(lookup.valid_until()),
+        })
+    }
+
+    /// Lookup the records of the given type about the given host
+    pub async fn lookup(
+        &self,
+        host: &str,
+        record_type: DnsRecordType,
+    ) -> Result<DnsLookupResponse> {
+        let lookup = self.resolver.lookup(host, record_type.into()).await?;
+        Ok(lookup_response(&lookup))
+    }
+
+    /// Lookup the names associated with the given IP address, via its PTR records
+    pub async fn reverse_lookup(&self, ip: IpAddr) -> Result<DnsLookupResponse> {
+        let lookup = self
+            .resolver
+            .lookup(Name::from(ip), RecordType::PTR)
+            .await?;
+        Ok(lookup_response(&lookup))
+    }
+}
+
+fn lookup_response(lookup: &Lookup) -> DnsLookupResponse {
+    DnsLookupResponse {
+        records: lookup.iter().filter_map(record).collect(),
+        ttl: remaining_ttl(lookup.valid_until()),
+    }
+}
+
+/// Convert the data of a DNS record. Records of types that have not been
+/// requested, which can be part of the answer, are ignored
+fn record(data: &RData) -> Option<DnsRecord> {
+    let record = match data {
+        RData::A(a) => DnsRecord::A {
+            address: IpAddr::V4(a.0),
+        },
+        RData::AAAA(aaaa) => DnsRecord::Aaaa {
+            address: IpAddr::V6(aaaa.0),
+        },
+        RData::CNAME(cname) => DnsRecord::Cname {
+            target: name(&cname.0),
+        },
+        RData::TXT(txt) => DnsRecord::Txt {
+            text: txt
+                .txt_data()
+                .iter()
+                .map(|data| String::from_utf8_lossy(data))
+                .collect(),
+        },
+        RData::SRV(srv) => DnsRecord::Srv {
+            priority: srv.priority(),
+            weight: srv.weight(),
+            port: srv.port(),
+            target: name(srv.target()),
+        },
+        RData::MX(mx) => DnsRecord::Mx {
+            preference: mx.preference(),
+            exchange: name(mx.exchange()),
+        },
+        RData::PTR(ptr) => DnsRecord::Ptr { name: name(&ptr.0) },
+        _ => return None,
+    };
+    Some(record)
+}
+
+/// Render a domain name without the trailing dot of fully qualified names
+fn name(name: &Name) -> String {
+    name.to_utf8().trim_end_matches('.').to_owned()
+}
+
+fn remaining_ttl(valid_until: std::time::Instant) -> u64 {
+    Instant::from_std(valid_until)
+        .saturating_duration_since(Instant::now())
+        .as_secs()
+}
+
+fn client(client: Option<&Client>) -> Result<&Client> {
+    client.ok_or_else(|| anyhow!("the DNS resolver is not configured"))
+}
+
+pub(crate) async fn lookup_host_cached(
+    cache: &CapabilityCache<DnsLookupResponse>,
+    dns_client: Option<&Client>,
+    host: &str,
+) -> Result<cached::Return<LookupResponse>> {
+    let dns_client = client(dns_client)?;
+    let response = cache
+        .get_or_insert_with(host, "host", || dns_client.lookup_host(host))
+        .await?;
+
+    Ok(cached::Return {
+        was_cached: response.was_cached,
+        value: LookupResponse {
+            ips: response
+                .value
+                .records
+                .iter()
+                .filter_map(|record| match record {
+                    DnsRecord::A { address } | DnsRecord::Aaaa { address } => {
+                        Some(address.to_string())
+                    }
+                    _ => None,
+                })
+                .collect(),
+        },
+    })
+}
+
+pub(crate) async fn lookup_cached(
+    cache: &CapabilityCache<DnsLookupResponse>,
+    dns_client: Option<&Client>,
+    host: &str,
+    record_type: DnsRecordType,
+) -> Result<cached::Return<DnsLookupResponse>> {
+    let dns_client = client(dns_client)?;
+    let details = format!("{record_type:?}");
+    cache
+        .get_or_insert_with(host, &details, || dns_client.lookup(host, record_type))
+        .await
+}
+
+pub(crate) async fn reverse_lookup_cached(
+    cache: &CapabilityCache<DnsLookupResponse>,
+    dns_client: Option<&Client>,
+    ip: IpAddr,
+) -> Result<cached::Return<DnsLookupResponse>> {
+    let dns_client = client(dns_client)?;
+    cache
+        .get_or_insert_with(&ip.to_string(), "ptr", || dns_client.reverse_lookup(ip))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hickory_resolver::proto::rr::rdata::{CNAME, MX, SRV, TXT};
+    use rstest::rstest;
+    use std::str::FromStr;
+
+    #[rstest]
+    #[case::cname(
+        RData::CNAME(CNAME(Name::from_str("www.example.com.").unwrap())),
+        Some(DnsRecord::Cname { target: "www.example.com".to_owned() }),
+    )]
+    #[case::txt(
+        RData::TXT(TXT::new(vec!["v=spf1 ".to_owned(), "-all".to_owned()])),
+        Some(DnsRecord::Txt { text: "v=spf1 -all".to_owned() }),
+    )]
+    #[case::srv(
+        RData::SRV(SRV::new(10, 5, 443, Name::from_str("svc.example.com.").unwrap())),
+        Some(DnsRecord::Srv {
+            priority: 10,
+            weight: 5,
+            port: 443,
+            target: "svc.example.com".to_owned(),
+        }),
+    )]
+    #[case::mx(
+        RData::MX(MX::new(10, Name::from_str("mail.example.com.").unwrap())),
+        Some(DnsRecord::Mx { preference: 10, exchange: "mail.example.com".to_owned() }),
+    )]
+    #[case::not_supported(RData::Update0(RecordType::NS), None)]
+    fn convert_records(#[case] data: RData, #[case] expected: Option<DnsRecord>) {
+        assert_eq!(record(&data), expected);
+    }
+
+    #[test]
+    fn serialize_records() {
+        let response = DnsLookupResponse {
+            records: vec![DnsRecord::Mx {
+                preference: 10,
+                exchange: "mail.example.com".to_owned(),
+            }],
+            ttl: 60,
+        };
+
+        assert_eq!(
+            serde_json::to_value(&response).unwrap(),
+            serde_json::json!({
+                "records": [{"type": "MX", "preference": 10, "exchange": "mail.example.com"}],
+                "ttl": 60,
+            })
+        );
+    }
+}
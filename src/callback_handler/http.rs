@@ -0,0 +1,273 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::{Result, anyhow};
+use reqwest::{header::CONTENT_TYPE, redirect};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use url::Url;
+
+use crate::callback_handler::cache::CapabilityCache;
+
+/// Settings of the `net/v1/http_get` host capability
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpGetConfig {
+    /// The maximum size of a response body, in bytes
+    pub max_response_size: usize,
+    /// How long to wait for the whole response, including its body
+    pub timeout: Duration,
+}
+
+impl Default for HttpGetConfig {
+    fn default() -> Self {
+        Self {
+            max_response_size: 1024 * 1024,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// The response to an HTTP GET request
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HttpGetResponse {
+    pub status: u16,
+    /// The value of the `Content-Type` header, when provided
+    pub content_type: Option<String>,
+    pub body: String,
+}
+
+/// Helper struct to perform the HTTP GET requests of the policies. Redirects
+/// are not followed, the allow-list of the policy would be bypassed otherwise
+#[derive(Clone)]
+pub(crate) struct Client {
+    http_client: reqwest::Client,
+    max_response_size: usize,
+}
+
+impl Client {
+    pub fn new(config: HttpGetConfig) -> Result<Self> {
+        let http_client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .redirect(redirect::Policy::none())
+            .build()
+            .map_err(|e| anyhow!("cannot create HTTP client: {e}"))?;
+
+        Ok(Client {
+            http_client,
+            max_response_size: config.max_response_size,
+        })
+    }
+
+    /// Perform the request. Server errors are reported as failures, so that
+    /// they are not cached like successful responses
+    pub async fn get(
+        &self,
+        url: &Url,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<HttpGetResponse> {
+        let mut request = self.http_client.get(url.clone());
+        for (name, value) in headers {
+            if is_reserved_header(name) {
+                debug!(header = name, "ignoring header set by the policy");
+                continue;
+            }
+            request = request.header(name, value);
+        }
+        let mut response = request.send().await?;
+
+        let status = response.status();
+        if status.is_server_error() {
+            return Err(anyhow!("{url} replied with {status}"));
+        }
+        let too_large = || {
+            anyhow!(
+                "the response of {url} exceeds the maximum size of {} bytes",
+                self.max_response_size
+            )
+        };
+        if response
+            .content_length()
+            .is_some_and(|length| length > self.max_response_size as u64)
+        {
+            return Err(too_large());
+        }
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > self.max_response_size {
+                return Err(too_large());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        let body =
+            String::from_utf8(body).map_err(|_| anyhow!("the response of {url} is not UTF-8"))?;
+
+        Ok(HttpGetResponse {
+            status: status.as_u16(),
+            content_type,
+            body,
+        })
+    }
+}
+
+/// Headers that are managed by the host. The policies cannot set them: the
+/// `Host` header would target a different server than the one allowed by the
+/// URL, the hop-by-hop ones would alter the connection to the server or to
+/// the proxy
+fn is_reserved_header(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    matches!(
+        name.as_str(),
+        "host" | "connection" | "keep-alive" | "te" | "trailer" | "transfer-encoding" | "upgrade"
+    ) || name.starts_with("proxy-")
+}
+
+pub(crate) async fn get_http_cached(
+    cache: &CapabilityCache<HttpGetResponse>,
+    client: &Client,
+    url: &Url,
+    headers: &BTreeMap<String, String>,
+) -> Result<cached::Return<HttpGetResponse>> {
+    let details = format!("{headers:?}");
+    cache
+        .get_or_insert_with(url.as_str(), &details, || client.get(url, headers))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Start a local HTTP server replying to a single request with the given
+    /// status line and body. Returns the URL of the server
+    fn serve_once(status: &'static str, body: &'static str) -> Url {
+        serve_once_and_capture(status, body).0
+    }
+
+    /// Like [`serve_once`], the returned handle yields the request received by the server
+    fn serve_once_and_capture(
+        status: &'static str,
+        body: &'static str,
+    ) -> (Url, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 4096];
+            let read = stream.read(&mut request).unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+            String::from_utf8_lossy(&request[..read]).into_owned()
+        });
+
+        (
+            format!("http://{address}/exemptions").parse().unwrap(),
+            handle,
+        )
+    }
+
+    #[tokio::test]
+    async fn get_response() {
+        let url = serve_once("200 OK", r#"{"exempted": true}"#);
+        let client = Client::new(HttpGetConfig::default()).unwrap();
+
+        let response = client.get(&url, &BTreeMap::new()).await.unwrap();
+
+        assert_eq!(
+            response,
+            HttpGetResponse {
+                status: 200,
+                content_type: Some("application/json".to_owned()),
+                body: r#"{"exempted": true}"#.to_owned(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn get_ignores_reserved_headers() {
+        let (url, server) = serve_once_and_capture("200 OK", "{}");
+        let client = Client::new(HttpGetConfig::default()).unwrap();
+        let headers = BTreeMap::from([
+            ("Host".to_owned(), "internal.example.com".to_owned()),
+            (
+                "Proxy-Authorization".to_owned(),
+                "Basic c2VjcmV0".to_owned(),
+            ),
+            ("Transfer-Encoding".to_owned(), "chunked".to_owned()),
+            ("Authorization".to_owned(), "Bearer token".to_owned()),
+        ]);
+
+        client.get(&url, &headers).await.unwrap();
+
+        let request = server.join().unwrap().to_ascii_lowercase();
+        let host = format!("host: {}", url.authority());
+        assert!(request.contains(&host), "{request}");
+        assert!(!request.contains("internal.example.com"), "{request}");
+        assert!(!request.contains("proxy-authorization"), "{request}");
+        assert!(!request.contains("transfer-encoding"), "{request}");
+        assert!(request.contains("authorization: bearer token"), "{request}");
+    }
+
+    #[rstest]
+    #[case::reserved("Host", true)]
+    #[case::reserved_any_case("TRANSFER-ENCODING", true)]
+    #[case::proxy("Proxy-Authorization", true)]
+    #[case::allowed("Authorization", false)]
+    fn reserved_headers(#[case] name: &str, #[case] reserved: bool) {
+        assert_eq!(is_reserved_header(name), reserved);
+    }
+
+    #[rstest]
+    #[case::not_found("404 Not Found", "", true)]
+    #[case::redirect_not_followed("302 Found", "", true)]
+    #[case::server_error("503 Service Unavailable", "", false)]
+    #[case::too_large("200 OK", "0123456789abcdef", false)]
+    #[tokio::test]
+    async fn get_statuses_and_limits(
+        #[case] status: &'static str,
+        #[case] body: &'static str,
+        #[case] ok: bool,
+    ) {
+        let url = serve_once(status, body);
+        let client = Client::new(HttpGetConfig {
+            max_response_size: 8,
+            ..HttpGetConfig::default()
+        })
+        .unwrap();
+
+        let response = client.get(&url, &BTreeMap::new()).await;
+
+        assert_eq!(response.is_ok(), ok, "{response:?}");
+    }
+
+    #[tokio::test]
+    async fn get_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url: Url = format!("http://{}/", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let client = Client::new(HttpGetConfig {
+            timeout: Duration::from_millis(100),
+            ..HttpGetConfig::default()
+        })
+        .unwrap();
+
+        // the connection is queued by the listener, but it never gets a reply
+        let response = client.get(&url, &BTreeMap::new()).await;
+
+        assert!(response.is_err());
+        drop(listener);
+    }
+}
@@ -1,10 +1,12 @@
-use std::time::Duration;
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
 
 mod client;
+mod offline;
 mod reflector;
+mod sar_cache;
+pub(crate) mod selector;
 
 use anyhow::{Result, anyhow};
-use cached::proc_macro::cached;
 use k8s_openapi::api::authorization::v1::SubjectAccessReviewStatus;
 use kube::core::ObjectList;
 use kubewarden_policy_sdk::host_capabilities::kubernetes::SubjectAccessReview as KWSubjectAccessReview;
@@ -12,6 +14,12 @@ use serde::Serialize;
 
 pub(crate) use client::Client;
 
+use crate::callback_handler::cache::CapabilityCache;
+use crate::callback_requests::CanIBatchResult;
+use crate::policy_metadata::ContextAwareResource;
+
+pub use offline::OfflineKubernetesContext;
+
 #[derive(Eq, Hash, PartialEq)]
 struct ApiVersionKind {
     api_version: String,
@@ -24,8 +32,274 @@ pub(crate) struct KubeResource {
     pub namespaced: bool,
 }
 
+/// Configuration of the reflectors used to serve the "list resources" requests
+/// made by context aware policies. Optionally, reflectors can serve the
+/// "get resource" requests too, see [`GetResourceSource`].
+///
+/// A reflector is created for each resource type, the queries are then served by
+/// filtering its objects locally. By default reflectors are never evicted.
+#[derive(Debug, Clone)]
+pub struct ReflectorsConfig {
+    /// Evict the reflectors that have not been used for longer than this duration
+    pub idle_timeout: Option<Duration>,
+    /// Maximum number of reflectors that can run at the same time. When exceeded,
+    /// the least recently used reflectors are evicted
+    pub max_reflectors: Option<usize>,
+    /// Maximum number of objects cached by all the reflectors. When exceeded,
+    /// the least recently used reflectors are evicted. The objects of a new
+    /// reflector are counted once its initial list has been loaded
+    pub max_cached_objects: Option<usize>,
+    /// How long to wait for a reflector to load the initial list of objects.
+    /// When exceeded, a [`KubernetesError::CacheNotReady`] error is returned
+    /// to the policy. Defaults to [`DEFAULT_REFLECTOR_SYNC_TIMEOUT`], `None`
+    /// waits forever
+    pub sync_timeout: Option<Duration>,
+    /// How the "get resource" requests are served
+    pub get_resource_source: GetResourceSource,
+}
+
+/// How long to wait, by default, for a reflector to load the initial list of objects
+pub const DEFAULT_REFLECTOR_SYNC_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Period of the reflectors eviction timer when no idle timeout is set. The
+/// timer is never awaited in this case, tokio just requires a non-zero period
+pub(crate) const DISABLED_REFLECTORS_EVICTION_INTERVAL: Duration = Duration::from_secs(3600);
+
+impl Default for ReflectorsConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout: None,
+            max_reflectors: None,
+            max_cached_objects: None,
+            sync_timeout: Some(DEFAULT_REFLECTOR_SYNC_TIMEOUT),
+            get_resource_source: GetResourceSource::default(),
+        }
+    }
+}
+
+/// How the "get resource" requests made by context aware policies are served
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum GetResourceSource {
+    /// Query the Kubernetes API server. Unless disabled by the policy, the
+    /// answers are cached for a few seconds
+    #[default]
+    ApiServer,
+    /// Look up the object inside of the reflector of its resource type, when
+    /// one is already running. Query the Kubernetes API server otherwise
+    ActiveReflectors,
+    /// Always look up the objects of the given resource types inside of their
+    /// reflectors, which are created when needed. These are usually the context
+    /// aware resources granted to the policies being served. The objects of the
+    /// other resource types are looked up like with
+    /// [`GetResourceSource::ActiveReflectors`]
+    Reflectors(BTreeSet<ContextAwareResource>),
+}
+
+impl GetResourceSource {
+    /// Whether the "get resource" requests about the given resource type are
+    /// served by a reflector. `reflector_running` tells if a reflector is
+    /// already watching the objects being looked up
+    fn serves_from_reflector(
+        &self,
+        resource: &kube::api::ApiResource,
+        reflector_running: bool,
+    ) -> bool {
+        match self {
+            GetResourceSource::ApiServer => false,
+            GetResourceSource::ActiveReflectors => reflector_running,
+            GetResourceSource::Reflectors(resources) => {
+                reflector_running
+                    || resources.contains(&ContextAwareResource {
+                        api_version: resource.api_version.clone(),
+                        kind: resource.kind.clone(),
+                    })
+            }
+        }
+    }
+}
+
+/// Errors returned by the Kubernetes host capabilities
+#[derive(thiserror::Error, Debug)]
+pub enum KubernetesError {
+    #[error(
+        "cache not ready: the list of {api_version}/{kind} objects was not loaded within {timeout:?}"
+    )]
+    CacheNotReady {
+        api_version: String,
+        kind: String,
+        timeout: Duration,
+    },
+    #[error(
+        "too many objects: the {object_count} {api_version}/{kind} objects exceed the limit of {max_cached_objects} cached objects"
+    )]
+    TooManyObjects {
+        api_version: String,
+        kind: String,
+        object_count: usize,
+        max_cached_objects: usize,
+    },
+}
+
+/// Statistics about a running reflector
+#[derive(Debug, Clone)]
+pub struct ReflectorStats {
+    /// Unique identifier of the reflector
+    pub id: String,
+    pub api_version: String,
+    pub kind: String,
+    /// The namespace watched by the reflector, `None` when watching all the namespaces
+    pub namespace: Option<String>,
+    /// Number of objects currently cached by the reflector
+    pub object_count: usize,
+    /// Last time the reflector saw a change of the watched objects
+    pub last_change_seen_at: tokio::time::Instant,
+    /// Last time the data of the reflector has been used
+    pub last_used_at: tokio::time::Instant,
+}
+
+/// A handle that can be used to inspect and manage the reflectors owned by
+/// a [`CallbackHandler`](crate::callback_handler::CallbackHandler), even after
+/// its evaluation loop has been started
+#[derive(Clone)]
+pub struct ReflectorsHandle {
+    client: Client,
+}
+
+impl ReflectorsHandle {
+    pub(crate) fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    /// Statistics about all the reflectors currently running
+    pub async fn stats(&self) -> Vec<ReflectorStats> {
+        self.client.reflectors_stats().await
+    }
+
+    /// Evict the reflectors that have been idle for longer than the configured
+    /// idle timeout. Returns the number of evicted reflectors
+    pub async fn evict_idle(&self) -> usize {
+        self.client.evict_idle_reflectors().await
+    }
+}
+
+/// The source of the Kubernetes data served to context aware policies
+#[derive(Clone)]
+pub(crate) enum ContextProvider {
+    /// The data is fetched from a Kubernetes API server
+    Cluster(Client),
+    /// The data is read from manifests loaded in memory
+    Offline(Arc<OfflineKubernetesContext>),
+}
+
+impl ContextProvider {
+    async fn list_resources_by_namespace(
+        &mut self,
+        api_version: &str,
+        kind: &str,
+        namespace: &str,
+        label_selector: Option<String>,
+        field_selector: Option<String>,
+    ) -> Result<ObjectList<kube::core::DynamicObject>> {
+        match self {
+            ContextProvider::Cluster(client) => {
+                client
+                    .list_resources_by_namespace(
+                        api_version,
+                        kind,
+                        namespace,
+                        label_selector,
+                        field_selector,
+                    )
+                    .await
+            }
+            ContextProvider::Offline(context) => context.list_resources_by_namespace(
+                api_version,
+                kind,
+                namespace,
+                label_selector,
+                field_selector,
+            ),
+        }
+    }
+
+    async fn list_resources_all(
+        &mut self,
+        api_version: &str,
+        kind: &str,
+        label_selector: Option<String>,
+        field_selector: Option<String>,
+    ) -> Result<ObjectList<kube::core::DynamicObject>> {
+        match self {
+            ContextProvider::Cluster(client) => {
+                client
+                    .list_resources_all(api_version, kind, label_selector, field_selector)
+                    .await
+            }
+            ContextProvider::Offline(context) => {
+                context.list_resources_all(api_version, kind, label_selector, field_selector)
+            }
+        }
+    }
+
+    async fn get_resource(
+        &mut self,
+        api_version: &str,
+        kind: &str,
+        name: &str,
+        namespace: Option<&str>,
+    ) -> Result<kube::core::DynamicObject> {
+        match self {
+            ContextProvider::Cluster(client) => {
+                client
+                    .get_resource(api_version, kind, name, namespace)
+                    .await
+            }
+            ContextProvider::Offline(context) => {
+                context.get_resource(api_version, kind, name, namespace)
+            }
+        }
+    }
+
+    async fn get_resource_plural_name(&mut self, api_version: &str, kind: &str) -> Result<String> {
+        match self {
+            ContextProvider::Cluster(client) => {
+                client.get_resource_plural_name(api_version, kind).await
+            }
+            ContextProvider::Offline(context) => {
+                context.get_resource_plural_name(api_version, kind)
+            }
+        }
+    }
+
+    async fn has_list_resources_all_result_changed_since_instant(
+        &mut self,
+        api_version: &str,
+        kind: &str,
+        since: tokio::time::Instant,
+    ) -> Result<bool> {
+        match self {
+            ContextProvider::Cluster(client) => {
+                client
+                    .has_list_resources_all_result_changed_since_instant(api_version, kind, since)
+                    .await
+            }
+            // the manifests never change once loaded
+            ContextProvider::Offline(_) => Ok(false),
+        }
+    }
+
+    fn cluster_client(&mut self) -> Result<&mut Client> {
+        match self {
+            ContextProvider::Cluster(client) => Ok(client),
+            ContextProvider::Offline(_) => Err(anyhow!(
+                "SubjectAccessReview requests cannot be evaluated without a Kubernetes API server"
+            )),
+        }
+    }
+}
+
 pub(crate) async fn list_resources_by_namespace(
-    client: Option<&mut Client>,
+    client: Option<&mut ContextProvider>,
     api_version: &str,
     kind: &str,
     namespace: &str,
@@ -44,7 +318,7 @@ pub(crate) async fn list_resources_by_namespace(
 }
 
 pub(crate) async fn list_resources_all(
-    client: Option<&mut Client>,
+    client: Option<&mut ContextProvider>,
     api_version: &str,
     kind: &str,
     label_selector: Option<String>,
@@ -62,7 +336,7 @@ pub(crate) async fn list_resources_all(
 }
 
 pub(crate) async fn get_resource(
-    client: Option<&mut Client>,
+    client: Option<&mut ContextProvider>,
     api_version: &str,
     kind: &str,
     name: &str,
@@ -82,26 +356,58 @@ pub(crate) async fn get_resource(
         })
 }
 
-#[cached(
-    time = 5,
-    result = true,
-    sync_writes = "default",
-    key = "String",
-    convert = r#"{ format!("get_resource_cached({},{}),{},{:?}", api_version, kind, name, namespace) }"#,
-    with_cached_flag = true
-)]
+/// Key used to cache the object inside of the
+/// [`CachedCapability::KubernetesGetResource`](crate::callback_handler::CachedCapability::KubernetesGetResource)
+/// cache
+pub(crate) fn get_resource_cache_key(
+    api_version: &str,
+    kind: &str,
+    name: &str,
+    namespace: Option<&str>,
+) -> String {
+    match namespace {
+        Some(namespace) => format!("{api_version}/{kind}/{namespace}/{name}"),
+        None => format!("{api_version}/{kind}/{name}"),
+    }
+}
+
 pub(crate) async fn get_resource_cached(
-    client: Option<&mut Client>,
+    cache: &CapabilityCache<kube::core::DynamicObject>,
+    client: Option<&mut ContextProvider>,
     api_version: &str,
     kind: &str,
     name: &str,
     namespace: Option<&str>,
 ) -> Result<cached::Return<kube::core::DynamicObject>> {
-    get_resource(client, api_version, kind, name, namespace).await
+    let key = get_resource_cache_key(api_version, kind, name, namespace);
+    cache
+        .get_or_insert_with(&key, "", || async {
+            get_resource(client, api_version, kind, name, namespace)
+                .await
+                .map(|r| r.value)
+        })
+        .await
+}
+
+/// Returns true when the "get resource" request is served by a reflector. In this
+/// case the answer is always fresh, hence it doesn't have to be cached
+pub(crate) async fn is_get_resource_served_by_reflector(
+    client: Option<&mut ContextProvider>,
+    api_version: &str,
+    kind: &str,
+    namespace: Option<&str>,
+) -> bool {
+    match client {
+        Some(ContextProvider::Cluster(client)) => client
+            .serves_get_resource_from_reflector(api_version, kind, namespace)
+            .await
+            .unwrap_or_default(),
+        _ => false,
+    }
 }
 
 pub(crate) async fn get_resource_plural_name(
-    client: Option<&mut Client>,
+    client: Option<&mut ContextProvider>,
     api_version: &str,
     kind: &str,
 ) -> Result<cached::Return<String>> {
@@ -124,11 +430,9 @@ pub(crate) async fn get_resource_plural_name(
 /// Check if the results of the "list all resources" query have changed since the provided instant
 /// This is done by querying the reflector that keeps track of this query
 pub(crate) async fn has_list_resources_all_result_changed_since_instant(
-    client: Option<&mut Client>,
+    client: Option<&mut ContextProvider>,
     api_version: &str,
     kind: &str,
-    label_selector: Option<String>,
-    field_selector: Option<String>,
     since: tokio::time::Instant,
 ) -> Result<cached::Return<bool>> {
     if client.is_none() {
@@ -137,19 +441,13 @@ pub(crate) async fn has_list_resources_all_result_changed_since_instant(
 
     client
         .unwrap()
-        .has_list_resources_all_result_changed_since_instant(
-            api_version,
-            kind,
-            label_selector,
-            field_selector,
-            since,
-        )
+        .has_list_resources_all_result_changed_since_instant(api_version, kind, since)
         .await
         .map(cached::Return::new)
 }
 
 pub(crate) async fn can_i(
-    client: Option<&mut Client>,
+    client: Option<&mut ContextProvider>,
     request: KWSubjectAccessReview,
 ) -> Result<cached::Return<SubjectAccessReviewStatus>> {
     if client.is_none() {
@@ -158,6 +456,7 @@ pub(crate) async fn can_i(
 
     client
         .unwrap()
+        .cluster_client()?
         .can_i(request)
         .await
         .map(|value| cached::Return {
@@ -166,21 +465,83 @@ pub(crate) async fn can_i(
         })
 }
 
-#[cached(
-    time = 5,
-    result = true,
-    // We can use the request type as key because cached requires the key to implement Hash + Eq
-    // traits. As we already implement these traits, there is no need to have a custom logic for key
-    // generation. If we do that, we will only convert it into a type (e.g. string)  that
-    // implements the traits as well. 
-    key = "KWSubjectAccessReview",
-    convert = r#"{request.clone()}"#,
-    sync_writes = "default",
-    with_cached_flag = true
-)]
 pub(crate) async fn can_i_cached(
-    client: Option<&mut Client>,
+    client: Option<&mut ContextProvider>,
     request: KWSubjectAccessReview,
 ) -> Result<cached::Return<SubjectAccessReviewStatus>> {
-    can_i(client, request).await
+    if client.is_none() {
+        return Err(anyhow!("kube::Client was not initialized properly"));
+    }
+
+    client
+        .unwrap()
+        .cluster_client()?
+        .can_i_cached(request)
+        .await
+}
+
+pub(crate) async fn can_i_batch(
+    client: Option<&mut ContextProvider>,
+    requests: Vec<KWSubjectAccessReview>,
+    disable_cache: bool,
+) -> Result<cached::Return<Vec<CanIBatchResult>>> {
+    if client.is_none() {
+        return Err(anyhow!("kube::Client was not initialized properly"));
+    }
+
+    let results = client
+        .unwrap()
+        .cluster_client()?
+        .can_i_batch(requests, disable_cache)
+        .await;
+    Ok(cached::Return::new(results))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    fn api_resource(kind: &str) -> kube::api::ApiResource {
+        kube::api::ApiResource {
+            group: String::new(),
+            version: "v1".to_owned(),
+            api_version: "v1".to_owned(),
+            kind: kind.to_owned(),
+            plural: format!("{}s", kind.to_lowercase()),
+        }
+    }
+
+    fn reflectors(kinds: &[&str]) -> GetResourceSource {
+        GetResourceSource::Reflectors(
+            kinds
+                .iter()
+                .map(|kind| ContextAwareResource {
+                    api_version: "v1".to_owned(),
+                    kind: kind.to_string(),
+                })
+                .collect(),
+        )
+    }
+
+    #[rstest]
+    #[case::api_server(GetResourceSource::ApiServer, "Pod", true, false)]
+    #[case::active_reflector(GetResourceSource::ActiveReflectors, "Pod", true, true)]
+    #[case::no_active_reflector(GetResourceSource::ActiveReflectors, "Pod", false, false)]
+    #[case::listed_resource(reflectors(&["Pod"]), "Pod", false, true)]
+    #[case::not_listed_resource(reflectors(&["Pod"]), "Secret", false, false)]
+    #[case::not_listed_resource_with_active_reflector(reflectors(&["Pod"]), "Secret", true, true)]
+    #[case::no_resources_listed(reflectors(&[]), "Pod", false, false)]
+    fn get_resource_served_from_reflector(
+        #[case] source: GetResourceSource,
+        #[case] kind: &str,
+        #[case] reflector_running: bool,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(
+            source.serves_from_reflector(&api_resource(kind), reflector_running),
+            expected
+        );
+    }
 }
@@ -1,29 +1,85 @@
 use anyhow::{Result, anyhow};
+use futures::StreamExt;
 use k8s_openapi::api::authorization::v1::{SubjectAccessReview, SubjectAccessReviewStatus};
 use kube::{
     Api,
     api::PostParams,
     core::{DynamicObject, ObjectList},
+    runtime::reflector::ObjectRef,
 };
 use kubewarden_policy_sdk::host_capabilities::kubernetes::SubjectAccessReview as KWSubjectAccessReview;
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
 use tokio::{sync::RwLock, time::Instant};
+use tracing::info;
+
+use crate::callback_handler::cache::CapabilityCache;
+use crate::callback_handler::kubernetes::{
+    ApiVersionKind, GetResourceSource, KubeResource, KubernetesError, ReflectorStats,
+    ReflectorsConfig,
+    reflector::Reflector,
+    sar_cache::SarCache,
+    selector::{FieldSelector, LabelSelector},
+};
+use crate::callback_requests::CanIBatchResult;
+use crate::policy_metadata::ContextAwareResource;
+
+/// Filter applied to the objects of a reflector, which watches all the objects
+/// of a resource type, to serve a query
+struct ReflectorFilter {
+    namespace: Option<String>,
+    label_selector: LabelSelector,
+    field_selector: FieldSelector,
+}
 
-use crate::callback_handler::kubernetes::{ApiVersionKind, KubeResource, reflector::Reflector};
+impl ReflectorFilter {
+    fn new(
+        namespace: Option<String>,
+        label_selector: Option<&str>,
+        field_selector: Option<&str>,
+    ) -> Result<Self> {
+        Ok(Self {
+            namespace,
+            label_selector: LabelSelector::parse(label_selector.unwrap_or_default())?,
+            field_selector: FieldSelector::parse(field_selector.unwrap_or_default())?,
+        })
+    }
+
+    fn matches(&self, obj: &DynamicObject) -> bool {
+        if self.namespace.is_some() && obj.metadata.namespace != self.namespace {
+            return false;
+        }
+        let labels_match = match &obj.metadata.labels {
+            Some(labels) => self.label_selector.matches(labels),
+            None => self.label_selector.matches(&Default::default()),
+        };
+        labels_match && self.field_selector.matches(obj)
+    }
+}
 
 #[derive(Clone)]
 pub(crate) struct Client {
     kube_client: kube::Client,
     kube_resources: Arc<RwLock<HashMap<ApiVersionKind, KubeResource>>>,
     reflectors: Arc<RwLock<HashMap<String, Reflector>>>,
+    reflectors_config: ReflectorsConfig,
+    sar_cache: Arc<SarCache>,
 }
 
 impl Client {
-    pub fn new(client: kube::Client) -> Self {
+    pub fn new(
+        client: kube::Client,
+        reflectors_config: ReflectorsConfig,
+        can_i_cache: Arc<CapabilityCache<SubjectAccessReviewStatus>>,
+    ) -> Self {
         Self {
             kube_client: client,
             kube_resources: Arc::new(RwLock::new(HashMap::new())),
             reflectors: Arc::new(RwLock::new(HashMap::new())),
+            reflectors_config,
+            sar_cache: Arc::new(SarCache::new(can_i_cache)),
         }
     }
 
@@ -92,42 +148,121 @@ impl Client {
         Ok(kube_resource)
     }
 
+    /// Get the reader of the reflector watching the objects of the given
+    /// resource inside of `namespace`, or inside of all the namespaces when
+    /// `namespace` is `None`. The reflector is created when needed.
+    ///
+    /// The queries are served by filtering the objects of the reflector locally.
+    /// This avoids the creation of a new watch for each selector used by the policies.
     async fn get_reflector_reader(
         &mut self,
-        reflector_id: &str,
         resource: KubeResource,
-        namespace: Option<String>,
-        label_selector: Option<String>,
-        field_selector: Option<String>,
+        namespace: Option<&str>,
     ) -> Result<kube::runtime::reflector::Store<kube::core::DynamicObject>> {
-        let reader = {
+        let reflector_id = Reflector::compute_id(&resource, namespace);
+        let sync_timeout = self.reflectors_config.sync_timeout;
+
+        let existing = {
             let reflectors = self.reflectors.read().await;
-            reflectors
-                .get(reflector_id)
-                .map(|reflector| reflector.reader.clone())
+            reflectors.get(&reflector_id).map(|reflector| {
+                reflector.touch();
+                reflector.reader.clone()
+            })
+        };
+        let reader = match existing {
+            Some(reader) => reader,
+            None => {
+                let reflector = Reflector::create_and_run(
+                    self.kube_client.clone(),
+                    resource.clone(),
+                    namespace.map(str::to_owned),
+                );
+                let reader = reflector.reader.clone();
+
+                let mut reflectors = self.reflectors.write().await;
+                reflectors.insert(reflector_id.clone(), reflector);
+                enforce_reflectors_limits(&mut reflectors, &self.reflectors_config, &reflector_id);
+                reader
+            }
         };
-        if let Some(reader) = reader {
-            return Ok(reader);
-        }
 
-        let reflector = Reflector::create_and_run(
-            self.kube_client.clone(),
-            resource,
-            namespace,
-            label_selector,
-            field_selector,
-        )
-        .await?;
-        let reader = reflector.reader.clone();
+        // The lock is not held while waiting, other queries can be served in the meantime
+        Reflector::wait_until_synced(&reader, &resource, sync_timeout).await?;
 
-        {
+        // The objects of a reflector are known only once the initial list has been loaded
+        if let Some(max_cached_objects) = self.reflectors_config.max_cached_objects {
             let mut reflectors = self.reflectors.write().await;
-            reflectors.insert(reflector_id.to_string(), reflector);
+            if !enforce_max_cached_objects(&mut reflectors, max_cached_objects, &reflector_id) {
+                reflectors.remove(&reflector_id);
+                return Err(KubernetesError::TooManyObjects {
+                    api_version: resource.resource.api_version.clone(),
+                    kind: resource.resource.kind.clone(),
+                    object_count: reader.len(),
+                    max_cached_objects,
+                }
+                .into());
+            }
         }
 
         Ok(reader)
     }
 
+    /// Create the reflectors watching all the objects of the given resources,
+    /// and wait for them to be synchronized. This requires the permission to
+    /// list and watch these resources in all the namespaces.
+    ///
+    /// Subsequent queries about these resources are served by these reflectors,
+    /// regardless of the namespace and selectors being used.
+    pub async fn prewarm_reflectors(
+        &mut self,
+        resources: &BTreeSet<ContextAwareResource>,
+    ) -> Vec<(ContextAwareResource, anyhow::Error)> {
+        let mut errors = Vec::new();
+
+        for car in resources {
+            let result = match self.build_kube_resource(&car.api_version, &car.kind).await {
+                Ok(resource) => self.get_reflector_reader(resource, None).await.map(|_| ()),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                errors.push((car.clone(), e));
+            }
+        }
+
+        errors
+    }
+
+    /// Evict all the reflectors that have not been used for longer than the
+    /// idle timeout set by the user. Returns the number of evicted reflectors
+    pub async fn evict_idle_reflectors(&self) -> usize {
+        match self.reflectors_config.idle_timeout {
+            Some(idle_timeout) => {
+                let mut reflectors = self.reflectors.write().await;
+                evict_idle_reflectors(&mut reflectors, idle_timeout, None)
+            }
+            None => 0,
+        }
+    }
+
+    /// Statistics about all the reflectors currently running
+    pub async fn reflectors_stats(&self) -> Vec<ReflectorStats> {
+        let reflectors = self.reflectors.read().await;
+
+        let mut stats = Vec::with_capacity(reflectors.len());
+        for (id, reflector) in reflectors.iter() {
+            stats.push(ReflectorStats {
+                id: id.clone(),
+                api_version: reflector.resource.resource.api_version.clone(),
+                kind: reflector.resource.resource.kind.clone(),
+                namespace: reflector.namespace.clone(),
+                object_count: reflector.object_count(),
+                last_change_seen_at: reflector.last_change_seen_at().await,
+                last_used_at: reflector.last_used_at(),
+            });
+        }
+        stats
+    }
+
     pub async fn list_resources_by_namespace(
         &mut self,
         api_version: &str,
@@ -145,7 +280,7 @@ impl Client {
 
         self.list_resources_from_reflector(
             resource,
-            Some(namespace.to_owned()),
+            Some(namespace),
             label_selector,
             field_selector,
         )
@@ -165,52 +300,39 @@ impl Client {
             .await
     }
 
+    /// Any change to the objects of the resource, in any namespace, is reported
     pub async fn has_list_resources_all_result_changed_since_instant(
         &mut self,
         api_version: &str,
         kind: &str,
-        label_selector: Option<String>,
-        field_selector: Option<String>,
         since: Instant,
     ) -> Result<bool> {
         let resource = self.build_kube_resource(api_version, kind).await?;
 
         Ok(self
-            .have_reflector_resources_changed_since(
-                &resource,
-                None,
-                label_selector,
-                field_selector,
-                since,
-            )
+            .have_reflector_resources_changed_since(&resource, since)
             .await)
     }
 
     async fn list_resources_from_reflector(
         &mut self,
         resource: KubeResource,
-        namespace: Option<String>,
+        namespace: Option<&str>,
         label_selector: Option<String>,
         field_selector: Option<String>,
     ) -> Result<ObjectList<kube::core::DynamicObject>> {
         let api_version = resource.resource.api_version.clone();
         let kind = resource.resource.kind.clone();
 
-        let reflector_id = Reflector::compute_id(
-            &resource,
-            namespace.as_deref(),
+        // parse the selectors before creating the reflector, to fail fast
+        let filter = ReflectorFilter::new(
+            namespace.map(str::to_owned),
             label_selector.as_deref(),
             field_selector.as_deref(),
-        );
-
+        )?;
+        let reflector_namespace = self.reflector_namespace(&resource, namespace).await;
         let reader = self
-            .get_reflector_reader(
-                &reflector_id,
-                resource,
-                namespace,
-                label_selector,
-                field_selector,
-            )
+            .get_reflector_reader(resource, reflector_namespace)
             .await?;
 
         Ok(ObjectList {
@@ -222,26 +344,39 @@ impl Client {
             items: reader
                 .state()
                 .iter()
+                .filter(|v| filter.matches(v))
                 .map(|v| DynamicObject::clone(v))
                 .collect(),
         })
     }
 
-    /// Check if the resources cached by the reflector have changed since the provided instant
+    /// The namespace watched by the reflector serving the queries about the
+    /// objects inside of `namespace`. A reflector watching all the namespaces is
+    /// reused when already running. Otherwise, the reflector watches only
+    /// `namespace`, which requires only the permission to list and watch the
+    /// resources inside of it
+    async fn reflector_namespace<'a>(
+        &self,
+        resource: &KubeResource,
+        namespace: Option<&'a str>,
+    ) -> Option<&'a str> {
+        let namespace = namespace.filter(|_| resource.namespaced)?;
+        let cluster_wide_id = Reflector::compute_id(resource, None);
+        if self.reflectors.read().await.contains_key(&cluster_wide_id) {
+            None
+        } else {
+            Some(namespace)
+        }
+    }
+
+    /// Check if the resources cached by the reflector watching all the namespaces
+    /// have changed since the provided instant
     async fn have_reflector_resources_changed_since(
         &mut self,
         resource: &KubeResource,
-        namespace: Option<String>,
-        label_selector: Option<String>,
-        field_selector: Option<String>,
         since: Instant,
     ) -> bool {
-        let reflector_id = Reflector::compute_id(
-            resource,
-            namespace.as_deref(),
-            label_selector.as_deref(),
-            field_selector.as_deref(),
-        );
+        let reflector_id = Reflector::compute_id(resource, None);
 
         let last_change_seen_at = {
             let reflectors = self.reflectors.read().await;
@@ -263,6 +398,15 @@ impl Client {
     ) -> Result<kube::core::DynamicObject> {
         let resource = self.build_kube_resource(api_version, kind).await?;
 
+        if self
+            .is_get_resource_served_by_reflector(&resource, namespace)
+            .await
+        {
+            return self
+                .get_resource_from_reflector(resource, name, namespace)
+                .await;
+        }
+
         let api = match resource.namespaced {
             true => kube::api::Api::<kube::core::DynamicObject>::namespaced_with(
                 self.kube_client.clone(),
@@ -287,6 +431,68 @@ impl Client {
             .ok_or_else(|| anyhow!("Cannot find {api_version}/{kind} named '{name}' inside of namespace '{namespace:?}'"))
     }
 
+    /// Returns true when the "get resource" requests about the given resource
+    /// are served by a reflector, instead of querying the Kubernetes API server.
+    /// The answers of the reflector are always fresh, there's no need to cache them
+    pub async fn serves_get_resource_from_reflector(
+        &mut self,
+        api_version: &str,
+        kind: &str,
+        namespace: Option<&str>,
+    ) -> Result<bool> {
+        let resource = self.build_kube_resource(api_version, kind).await?;
+        Ok(self
+            .is_get_resource_served_by_reflector(&resource, namespace)
+            .await)
+    }
+
+    async fn is_get_resource_served_by_reflector(
+        &self,
+        resource: &KubeResource,
+        namespace: Option<&str>,
+    ) -> bool {
+        if self.reflectors_config.get_resource_source == GetResourceSource::ApiServer {
+            return false;
+        }
+
+        let reflector_namespace = self.reflector_namespace(resource, namespace).await;
+        let reflector_id = Reflector::compute_id(resource, reflector_namespace);
+        let reflector_running = self.reflectors.read().await.contains_key(&reflector_id);
+        self.reflectors_config
+            .get_resource_source
+            .serves_from_reflector(&resource.resource, reflector_running)
+    }
+
+    async fn get_resource_from_reflector(
+        &mut self,
+        resource: KubeResource,
+        name: &str,
+        namespace: Option<&str>,
+    ) -> Result<kube::core::DynamicObject> {
+        let api_version = resource.resource.api_version.clone();
+        let kind = resource.resource.kind.clone();
+
+        let mut object_ref = ObjectRef::new_with(name, resource.resource.clone());
+        if resource.namespaced {
+            let namespace = namespace.ok_or_else(|| {
+                anyhow!(
+                    "Resource {api_version}/{kind} is namespaced, but no namespace was provided"
+                )
+            })?;
+            object_ref = object_ref.within(namespace);
+        }
+
+        let reflector_namespace = self.reflector_namespace(&resource, namespace).await;
+        let reader = self
+            .get_reflector_reader(resource, reflector_namespace)
+            .await?;
+
+        reader
+            .get(&object_ref)
+            .map(|obj| DynamicObject::clone(&obj))
+            .ok_or_else(|| anyhow!("Cannot find {api_version}/{kind} named '{name}' inside of namespace '{namespace:?}'"))
+    }
+
     pub async fn get_resource_plural_name(
         &mut self,
         api_version: &str,
@@ -315,4 +521,355 @@ impl Client {
                 .ok_or(anyhow!("SubjectAccessReview did not return a response"))
         })
     }
+
+    /// Like [`can_i`](Self::can_i), but the decisions are cached. Identical reviews
+    /// requested while one of them is in flight are coalesced
+    pub async fn can_i_cached(
+        &mut self,
+        request: KWSubjectAccessReview,
+    ) -> Result<cached::Return<SubjectAccessReviewStatus>> {
+        let review = request.clone();
+        let mut client = self.clone();
+
+        self.sar_cache
+            .get_or_review(&review, move || async move { client.can_i(request).await })
+            .await
+    }
+
+    /// Evaluate many reviews, at most [`CAN_I_BATCH_CONCURRENCY`] at the same
+    /// time. The outcomes are returned in the same order of the reviews, the
+    /// failure of a review doesn't affect the other ones
+    pub async fn can_i_batch(
+        &mut self,
+        requests: Vec<KWSubjectAccessReview>,
+        disable_cache: bool,
+    ) -> Vec<CanIBatchResult> {
+        let client = self.clone();
+        let outcomes = evaluate_concurrently(requests, CAN_I_BATCH_CONCURRENCY, |request| {
+            let mut client = client.clone();
+            async move {
+                if disable_cache {
+                    client.can_i(request).await
+                } else {
+                    client.can_i_cached(request).await.map(|r| r.value)
+                }
+            }
+        })
+        .await;
+
+        outcomes
+            .into_iter()
+            .map(|outcome| match outcome {
+                Ok(status) => CanIBatchResult::Status(status),
+                Err(e) => CanIBatchResult::Error(e.to_string()),
+            })
+            .collect()
+    }
+}
+
+/// Maximum number of reviews of a `can_i_batch` request evaluated at the same time
+const CAN_I_BATCH_CONCURRENCY: usize = 10;
+
+/// Run `f` over all the items, at most `concurrency` at the same time.
+/// The outcomes are returned in the same order of the items
+async fn evaluate_concurrently<I, T, F, Fut>(
+    items: Vec<I>,
+    concurrency: usize,
+    f: F,
+) -> Vec<Result<T>>
+where
+    F: Fn(I) -> Fut,
+    Fut: std::future::Future<Output = Result<T>>,
+{
+    let mut outcomes: Vec<(usize, Result<T>)> =
+        futures::stream::iter(items.into_iter().enumerate())
+            .map(|(index, item)| {
+                let outcome = f(item);
+                async move { (index, outcome.await) }
+            })
+            .buffer_unordered(concurrency)
+            .collect()
+            .await;
+
+    outcomes.sort_by_key(|(index, _)| *index);
+    outcomes.into_iter().map(|(_, outcome)| outcome).collect()
+}
+
+/// Evict reflectors until the limits set by the user are satisfied.
+/// The reflector identified by `keep` is never evicted
+fn enforce_reflectors_limits(
+    reflectors: &mut HashMap<String, Reflector>,
+    reflectors_config: &ReflectorsConfig,
+    keep: &str,
+) {
+    if let Some(idle_timeout) = reflectors_config.idle_timeout {
+        evict_idle_reflectors(reflectors, idle_timeout, Some(keep));
+    }
+
+    if let Some(max_reflectors) = reflectors_config.max_reflectors {
+        while reflectors.len() > max_reflectors {
+            if !evict_least_recently_used_reflector(reflectors, keep) {
+                break;
+            }
+        }
+    }
+
+    if let Some(max_cached_objects) = reflectors_config.max_cached_objects {
+        enforce_max_cached_objects(reflectors, max_cached_objects, keep);
+    }
+}
+
+/// Evict the least recently used reflectors until the objects they cache are
+/// within the limit. The reflector identified by `keep` is never evicted.
+/// Returns false when the limit is still exceeded
+fn enforce_max_cached_objects(
+    reflectors: &mut HashMap<String, Reflector>,
+    max_cached_objects: usize,
+    keep: &str,
+) -> bool {
+    while reflectors
+        .values()
+        .map(Reflector::object_count)
+        .sum::<usize>()
+        > max_cached_objects
+    {
+        if !evict_least_recently_used_reflector(reflectors, keep) {
+            return false;
+        }
+    }
+    true
+}
+
+fn evict_idle_reflectors(
+    reflectors: &mut HashMap<String, Reflector>,
+    idle_timeout: std::time::Duration,
+    keep: Option<&str>,
+) -> usize {
+    let before = reflectors.len();
+    reflectors.retain(|id, reflector| {
+        let retain = Some(id.as_str()) == keep || reflector.last_used_at().elapsed() < idle_timeout;
+        if !retain {
+            info!(reflector = id, "evicting idle reflector");
+        }
+        retain
+    });
+    before - reflectors.len()
+}
+
+/// Evict the reflector that has not been used for the longest time. Returns
+/// false when there's nothing that can be evicted
+fn evict_least_recently_used_reflector(
+    reflectors: &mut HashMap<String, Reflector>,
+    keep: &str,
+) -> bool {
+    let lru = reflectors
+        .iter()
+        .filter(|(id, _)| id.as_str() != keep)
+        .min_by_key(|(_, reflector)| reflector.last_used_at())
+        .map(|(id, _)| id.clone());
+
+    match lru {
+        Some(id) => {
+            info!(reflector = id, "evicting least recently used reflector");
+            reflectors.remove(&id);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+    use std::time::Duration;
+
+    fn kube_resource(kind: &str) -> KubeResource {
+        KubeResource {
+            resource: kube::api::ApiResource {
+                group: String::new(),
+                version: "v1".to_owned(),
+                api_version: "v1".to_owned(),
+                kind: kind.to_owned(),
+                plural: format!("{}s", kind.to_lowercase()),
+            },
+            namespaced: true,
+        }
+    }
+
+    /// Build the reflectors, identified by their kind, with the given number
+    /// of objects and the given idle time, in seconds
+    fn reflectors(specs: &[(&str, usize, u64)]) -> HashMap<String, Reflector> {
+        specs
+            .iter()
+            .map(|(kind, object_count, idle_secs)| {
+                let resource = kube_resource(kind);
+                let objects = (0..*object_count)
+                    .map(|i| DynamicObject::new(&format!("{kind}-{i}"), &resource.resource))
+                    .collect();
+                let last_used_at = Instant::now() - Duration::from_secs(*idle_secs);
+                (
+                    kind.to_string(),
+                    Reflector::with_objects(resource, objects, last_used_at),
+                )
+            })
+            .collect()
+    }
+
+    fn ids(reflectors: &HashMap<String, Reflector>) -> BTreeSet<&str> {
+        reflectors.keys().map(String::as_str).collect()
+    }
+
+    #[rstest]
+    #[case::nothing_idle(3600, None, 0, &["ConfigMap", "Secret", "Service"])]
+    #[case::idle(60, None, 2, &["ConfigMap"])]
+    #[case::idle_but_kept(60, Some("Service"), 1, &["ConfigMap", "Service"])]
+    #[tokio::test]
+    async fn evict_idle(
+        #[case] idle_timeout_secs: u64,
+        #[case] keep: Option<&str>,
+        #[case] expected_evicted: usize,
+        #[case] expected: &[&str],
+    ) {
+        let mut reflectors =
+            reflectors(&[("ConfigMap", 0, 1), ("Secret", 0, 120), ("Service", 0, 600)]);
+
+        let evicted = evict_idle_reflectors(
+            &mut reflectors,
+            Duration::from_secs(idle_timeout_secs),
+            keep,
+        );
+
+        assert_eq!(evicted, expected_evicted);
+        assert_eq!(ids(&reflectors), expected.iter().copied().collect());
+    }
+
+    #[tokio::test]
+    async fn evict_idle_keeps_reflectors_in_use() {
+        let mut reflectors = reflectors(&[("ConfigMap", 0, 120), ("Secret", 0, 120)]);
+        reflectors["ConfigMap"].touch();
+
+        let evicted = evict_idle_reflectors(&mut reflectors, Duration::from_secs(60), None);
+
+        assert_eq!(evicted, 1);
+        assert_eq!(ids(&reflectors), BTreeSet::from(["ConfigMap"]));
+    }
+
+    #[rstest]
+    #[case::oldest("ConfigMap", true, &["ConfigMap", "Secret"])]
+    #[case::oldest_is_kept("Service", true, &["ConfigMap", "Service"])]
+    #[tokio::test]
+    async fn evict_least_recently_used(
+        #[case] keep: &str,
+        #[case] expected_evicted: bool,
+        #[case] expected: &[&str],
+    ) {
+        let mut reflectors =
+            reflectors(&[("ConfigMap", 0, 1), ("Secret", 0, 10), ("Service", 0, 100)]);
+
+        let evicted = evict_least_recently_used_reflector(&mut reflectors, keep);
+
+        assert_eq!(evicted, expected_evicted);
+        assert_eq!(ids(&reflectors), expected.iter().copied().collect());
+    }
+
+    #[tokio::test]
+    async fn evict_least_recently_used_nothing_to_evict() {
+        let mut reflectors = reflectors(&[("ConfigMap", 0, 1)]);
+
+        assert!(!evict_least_recently_used_reflector(
+            &mut reflectors,
+            "ConfigMap"
+        ));
+        assert_eq!(reflectors.len(), 1);
+    }
+
+    #[rstest]
+    #[case::no_limits(ReflectorsConfig::default(), &["ConfigMap", "Secret", "Service"])]
+    #[case::idle_timeout(
+        ReflectorsConfig { idle_timeout: Some(Duration::from_secs(60)), ..Default::default() },
+        &["ConfigMap"],
+    )]
+    #[case::max_reflectors(
+        ReflectorsConfig { max_reflectors: Some(2), ..Default::default() },
+        &["ConfigMap", "Secret"],
+    )]
+    #[case::max_cached_objects(
+        ReflectorsConfig { max_cached_objects: Some(5), ..Default::default() },
+        &["ConfigMap"],
+    )]
+    #[tokio::test]
+    async fn enforce_limits(#[case] config: ReflectorsConfig, #[case] expected: &[&str]) {
+        let mut reflectors =
+            reflectors(&[("ConfigMap", 4, 1), ("Secret", 3, 120), ("Service", 2, 600)]);
+
+        enforce_reflectors_limits(&mut reflectors, &config, "ConfigMap");
+
+        assert_eq!(ids(&reflectors), expected.iter().copied().collect());
+    }
+
+    #[rstest]
+    #[case::within_limit(10, true, &["ConfigMap", "Secret"])]
+    #[case::evict_others(5, true, &["ConfigMap"])]
+    #[case::kept_reflector_too_large(3, false, &["ConfigMap"])]
+    #[tokio::test]
+    async fn enforce_cached_objects(
+        #[case] max_cached_objects: usize,
+        #[case] within_limit: bool,
+        #[case] expected: &[&str],
+    ) {
+        let mut reflectors = reflectors(&[("ConfigMap", 4, 1), ("Secret", 3, 120)]);
+
+        assert_eq!(
+            enforce_max_cached_objects(&mut reflectors, max_cached_objects, "ConfigMap"),
+            within_limit
+        );
+        assert_eq!(ids(&reflectors), expected.iter().copied().collect());
+    }
+
+    #[test]
+    fn default_sync_timeout_is_finite() {
+        assert_eq!(
+            ReflectorsConfig::default().sync_timeout,
+            Some(crate::callback_handler::kubernetes::DEFAULT_REFLECTOR_SYNC_TIMEOUT)
+        );
+    }
+
+    #[tokio::test]
+    async fn evaluate_concurrently_keeps_order_and_errors() {
+        let running = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let max_running = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let outcomes = evaluate_concurrently((0..20u64).collect(), 3, |i| {
+            let running = running.clone();
+            let max_running = max_running.clone();
+            async move {
+                let now_running = running.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+                max_running.fetch_max(now_running, std::sync::atomic::Ordering::SeqCst);
+                // the later items complete first
+                tokio::time::sleep(Duration::from_millis(20 - i)).await;
+                running.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+
+                if i % 5 == 0 {
+                    Err(anyhow!("review {i} failed"))
+                } else {
+                    Ok(i)
+                }
+            }
+        })
+        .await;
+
+        assert!(max_running.load(std::sync::atomic::Ordering::SeqCst) <= 3);
+        assert_eq!(outcomes.len(), 20);
+        for (i, outcome) in outcomes.into_iter().enumerate() {
+            match outcome {
+                Ok(value) => assert_eq!(value, i as u64),
+                Err(e) => {
+                    assert_eq!(i % 5, 0);
+                    assert_eq!(e.to_string(), format!("review {i} failed"));
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,518 @@
+use anyhow::{Result, anyhow};
+use kube::core::{DynamicObject, ObjectList};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::callback_handler::kubernetes::selector::{FieldSelector, LabelSelector};
+use crate::discovery::{DiscoveredResource, DiscoverySnapshot, split_api_version};
+
+/// Namespace assigned to the namespaced objects that do not specify one,
+/// like `kubectl apply` does
+const DEFAULT_NAMESPACE: &str = "default";
+
+/// Kubernetes objects loaded from manifests, used to serve the requests made by
+/// context aware policies without interacting with a Kubernetes API server.
+///
+/// This is useful to evaluate context aware policies inside of CI pipelines, or
+/// against the contents of a GitOps repository.
+///
+/// The plural names and the scope of the resources are looked up inside of a
+/// [`DiscoverySnapshot`]. By default the built-in resources of Kubernetes are
+/// known, plus all the custom resources defined by the
+/// `CustomResourceDefinition` objects found inside of the manifests.
+#[derive(Debug, Clone)]
+pub struct OfflineKubernetesContext {
+    /// The objects, indexed by `(apiVersion, kind)`
+    objects: HashMap<(String, String), Vec<DynamicObject>>,
+    discovery: DiscoverySnapshot,
+}
+
+impl Default for OfflineKubernetesContext {
+    fn default() -> Self {
+        Self {
+            objects: HashMap::new(),
+            discovery: DiscoverySnapshot::builtin(),
+        }
+    }
+}
+
+impl OfflineKubernetesContext {
+    /// Load the objects from a file or from a directory.
+    ///
+    /// Directories are scanned recursively, all the files with a `.yaml`, `.yml`
+    /// or `.json` extension are loaded. Each file can contain multiple YAML
+    /// documents, and `List` objects (like the ones produced by `kubectl get -o yaml`).
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let mut context = OfflineKubernetesContext::default();
+
+        for file in manifest_files(path)? {
+            let contents = std::fs::read_to_string(&file)
+                .map_err(|e| anyhow!("cannot read {}: {e}", file.display()))?;
+            context
+                .add_manifests(&contents)
+                .map_err(|e| anyhow!("cannot load {}: {e}", file.display()))?;
+        }
+
+        Ok(context)
+    }
+
+    /// Load the objects from a multi-document YAML string, see [`from_path`](Self::from_path)
+    pub fn from_yaml(contents: &str) -> Result<Self> {
+        let mut context = OfflineKubernetesContext::default();
+        context.add_manifests(contents)?;
+
+        Ok(context)
+    }
+
+    /// Add the resources of the given snapshot to the ones already known.
+    /// This is needed to serve custom resources whose definition is not part
+    /// of the manifests
+    #[must_use]
+    pub fn discovery(mut self, snapshot: DiscoverySnapshot) -> Self {
+        for resource in snapshot.resources() {
+            self.discovery.insert(resource.clone());
+        }
+        self
+    }
+
+    fn add_manifests(&mut self, contents: &str) -> Result<()> {
+        for document in serde_yaml::Deserializer::from_str(contents) {
+            let value = serde_json::Value::deserialize(document)?;
+            self.add_value(value)?;
+        }
+
+        Ok(())
+    }
+
+    fn add_value(&mut self, value: serde_json::Value) -> Result<()> {
+        if value.is_null() {
+            // empty YAML document
+            return Ok(());
+        }
+
+        let is_list = value
+            .get("kind")
+            .and_then(|kind| kind.as_str())
+            .is_some_and(|kind| kind.ends_with("List"));
+        if is_list && let Some(items) = value.get("items").and_then(|items| items.as_array()) {
+            for item in items {
+                self.add_value(item.clone())?;
+            }
+            return Ok(());
+        }
+
+        let obj: DynamicObject = serde_json::from_value(value)?;
+        self.insert(obj)
+    }
+
+    /// Add an object to the context. Objects with the same name are not deduplicated
+    pub fn insert(&mut self, obj: DynamicObject) -> Result<()> {
+        let types = obj.types.clone().ok_or_else(|| {
+            anyhow!(
+                "object {} does not have apiVersion and kind",
+                obj.metadata.name.as_deref().unwrap_or_default()
+            )
+        })?;
+
+        if types.api_version == "apiextensions.k8s.io/v1"
+            && types.kind == "CustomResourceDefinition"
+        {
+            self.register_custom_resource(&obj)?;
+        }
+
+        self.objects
+            .entry((types.api_version, types.kind))
+            .or_default()
+            .push(obj);
+
+        Ok(())
+    }
+
+    /// Make the resources defined by a CRD known to the discovery snapshot
+    fn register_custom_resource(&mut self, crd: &DynamicObject) -> Result<()> {
+        let spec = crd
+            .data
+            .get("spec")
+            .ok_or_else(|| anyhow!("CustomResourceDefinition without spec"))?;
+        let field = |pointer: &str| {
+            spec.pointer(pointer)
+                .and_then(|value| value.as_str())
+                .ok_or_else(|| anyhow!("CustomResourceDefinition without {pointer}"))
+        };
+        let group = field("/group")?;
+        let kind = field("/names/kind")?;
+        let plural = field("/names/plural")?;
+        let namespaced = field("/scope")? == "Namespaced";
+
+        let versions = spec
+            .get("versions")
+            .and_then(|versions| versions.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|version| version.get("name").and_then(|name| name.as_str()));
+        for version in versions {
+            self.discovery.insert(DiscoveredResource {
+                group: group.to_owned(),
+                version: version.to_owned(),
+                api_version: format!("{group}/{version}"),
+                kind: kind.to_owned(),
+                plural: plural.to_owned(),
+                namespaced,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn resource(&self, api_version: &str, kind: &str) -> Result<&DiscoveredResource> {
+        split_api_version(api_version)?;
+        self.discovery
+            .get(api_version, kind)
+            .ok_or_else(|| anyhow!("Cannot find resource {api_version}/{kind}"))
+    }
+
+    /// The objects of the given resource, together with their effective namespace
+    fn objects<'a>(
+        &'a self,
+        resource: &DiscoveredResource,
+    ) -> impl Iterator<Item = (&'a DynamicObject, Option<&'a str>)> {
+        let namespaced = resource.namespaced;
+        self.objects
+            .get(&(resource.api_version.clone(), resource.kind.clone()))
+            .into_iter()
+            .flatten()
+            .map(move |obj| {
+                let namespace = namespaced.then(|| {
+                    obj.metadata
+                        .namespace
+                        .as_deref()
+                        .unwrap_or(DEFAULT_NAMESPACE)
+                });
+                (obj, namespace)
+            })
+    }
+
+    fn list_resources(
+        &self,
+        resource: &DiscoveredResource,
+        namespace: Option<&str>,
+        label_selector: Option<String>,
+        field_selector: Option<String>,
+    ) -> Result<ObjectList<DynamicObject>> {
+        let label_selector = LabelSelector::parse(label_selector.as_deref().unwrap_or_default())?;
+        let field_selector = FieldSelector::parse(field_selector.as_deref().unwrap_or_default())?;
+
+        let items = self
+            .objects(resource)
+            .filter(|(_, obj_namespace)| namespace.is_none() || *obj_namespace == namespace)
+            .map(|(obj, obj_namespace)| {
+                let mut obj = obj.clone();
+                obj.metadata.namespace = obj_namespace.map(str::to_owned);
+                obj
+            })
+            .filter(|obj| {
+                label_selector.matches(&obj.metadata.labels.clone().unwrap_or_default())
+                    && field_selector.matches(obj)
+            })
+            .collect();
+
+        Ok(ObjectList {
+            types: kube::core::TypeMeta {
+                api_version: resource.api_version.clone(),
+                kind: format!("{}List", resource.kind),
+            },
+            metadata: Default::default(),
+            items,
+        })
+    }
+
+    pub(crate) fn list_resources_by_namespace(
+        &self,
+        api_version: &str,
+        kind: &str,
+        namespace: &str,
+        label_selector: Option<String>,
+        field_selector: Option<String>,
+    ) -> Result<ObjectList<DynamicObject>> {
+        let resource = self.resource(api_version, kind)?;
+        if !resource.namespaced {
+            return Err(anyhow!(
+                "resource {api_version}/{kind} is cluster wide. Cannot search for it inside of a namespace"
+            ));
+        }
+
+        self.list_resources(resource, Some(namespace), label_selector, field_selector)
+    }
+
+    pub(crate) fn list_resources_all(
+        &self,
+        api_version: &str,
+        kind: &str,
+        label_selector: Option<String>,
+        field_selector: Option<String>,
+    ) -> Result<ObjectList<DynamicObject>> {
+        let resource = self.resource(api_version, kind)?;
+
+        self.list_resources(resource, None, label_selector, field_selector)
+    }
+
+    pub(crate) fn get_resource(
+        &self,
+        api_version: &str,
+        kind: &str,
+        name: &str,
+        namespace: Option<&str>,
+    ) -> Result<DynamicObject> {
+        let resource = self.resource(api_version, kind)?;
+        if resource.namespaced && namespace.is_none() {
+            return Err(anyhow!(
+                "Resource {api_version}/{kind} is namespaced, but no namespace was provided"
+            ));
+        }
+        let namespace = namespace.filter(|_| resource.namespaced);
+
+        self.objects(resource)
+            .find(|(obj, obj_namespace)| {
+                obj.metadata.name.as_deref() == Some(name) && *obj_namespace == namespace
+            })
+            .map(|(obj, obj_namespace)| {
+                let mut obj = obj.clone();
+                obj.metadata.namespace = obj_namespace.map(str::to_owned);
+                obj
+            })
+            .ok_or_else(|| anyhow!("Cannot find {api_version}/{kind} named '{name}' inside of namespace '{namespace:?}'"))
+    }
+
+    pub(crate) fn get_resource_plural_name(&self, api_version: &str, kind: &str) -> Result<String> {
+        self.resource(api_version, kind)
+            .map(|resource| resource.plural.clone())
+    }
+}
+
+/// Find all the manifest files, sorted by path to have a predictable outcome
+fn manifest_files(path: &Path) -> Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files = Vec::new();
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = std::fs::read_dir(&dir)
+            .map_err(|e| anyhow!("cannot read directory {}: {e}", dir.display()))?;
+        for entry in entries {
+            let entry_path = entry?.path();
+            if entry_path.is_dir() {
+                dirs.push(entry_path);
+            } else if entry_path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| matches!(ext, "yaml" | "yml" | "json"))
+            {
+                files.push(entry_path);
+            }
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    const MANIFESTS: &str = r#"
+apiVersion: v1
+kind: Namespace
+metadata:
+  name: team-a
+---
+apiVersion: v1
+kind: Pod
+metadata:
+  name: nginx
+  labels:
+    app: nginx
+spec:
+  nodeName: node-1
+---
+apiVersion: v1
+kind: List
+items:
+  - apiVersion: v1
+    kind: Pod
+    metadata:
+      name: redis
+      namespace: team-a
+      labels:
+        app: redis
+    spec:
+      nodeName: node-1
+  - apiVersion: v1
+    kind: Pod
+    metadata:
+      name: nginx
+      namespace: team-a
+      labels:
+        app: nginx
+    spec:
+      nodeName: node-2
+---
+apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: widgets.example.com
+spec:
+  group: example.com
+  scope: Namespaced
+  names:
+    kind: Widget
+    plural: widgets
+  versions:
+    - name: v1alpha1
+      served: true
+      storage: true
+---
+apiVersion: example.com/v1alpha1
+kind: Widget
+metadata:
+  name: foo
+  namespace: team-a
+"#;
+
+    fn names(list: &ObjectList<DynamicObject>) -> Vec<(String, Option<String>)> {
+        list.items
+            .iter()
+            .map(|obj| {
+                (
+                    obj.metadata.name.clone().unwrap(),
+                    obj.metadata.namespace.clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[rstest]
+    #[case::all(None, None, None, vec![("nginx", Some("default")), ("redis", Some("team-a")), ("nginx", Some("team-a"))])]
+    #[case::by_namespace(Some("team-a"), None, None, vec![("redis", Some("team-a")), ("nginx", Some("team-a"))])]
+    #[case::by_label(None, Some("app=nginx"), None, vec![("nginx", Some("default")), ("nginx", Some("team-a"))])]
+    #[case::by_field(None, None, Some("spec.nodeName=node-1"), vec![("nginx", Some("default")), ("redis", Some("team-a"))])]
+    #[case::by_namespace_label_and_field(Some("team-a"), Some("app=nginx"), Some("spec.nodeName=node-1"), vec![])]
+    fn list_pods(
+        #[case] namespace: Option<&str>,
+        #[case] label_selector: Option<&str>,
+        #[case] field_selector: Option<&str>,
+        #[case] expected: Vec<(&str, Option<&str>)>,
+    ) {
+        let context =
+            OfflineKubernetesContext::from_yaml(MANIFESTS).expect("cannot load manifests");
+        let label_selector = label_selector.map(str::to_owned);
+        let field_selector = field_selector.map(str::to_owned);
+
+        let list = match namespace {
+            Some(namespace) => context.list_resources_by_namespace(
+                "v1",
+                "Pod",
+                namespace,
+                label_selector,
+                field_selector,
+            ),
+            None => context.list_resources_all("v1", "Pod", label_selector, field_selector),
+        }
+        .expect("cannot list pods");
+
+        assert_eq!(list.types.kind, "PodList");
+        let expected: Vec<(String, Option<String>)> = expected
+            .into_iter()
+            .map(|(name, namespace)| (name.to_owned(), namespace.map(str::to_owned)))
+            .collect();
+        assert_eq!(names(&list), expected);
+    }
+
+    #[test]
+    fn list_cluster_wide_resource_by_namespace() {
+        let context = OfflineKubernetesContext::from_yaml(MANIFESTS).unwrap();
+
+        assert!(
+            context
+                .list_resources_by_namespace("v1", "Namespace", "team-a", None, None)
+                .is_err()
+        );
+        assert_eq!(
+            context
+                .list_resources_all("v1", "Namespace", None, None)
+                .unwrap()
+                .items
+                .len(),
+            1
+        );
+    }
+
+    #[rstest]
+    #[case::namespaced("v1", "Pod", "nginx", Some("team-a"), true)]
+    #[case::default_namespace("v1", "Pod", "nginx", Some("default"), true)]
+    #[case::missing_namespace("v1", "Pod", "nginx", None, false)]
+    #[case::wrong_namespace("v1", "Pod", "redis", Some("default"), false)]
+    #[case::cluster_wide("v1", "Namespace", "team-a", None, true)]
+    #[case::custom_resource("example.com/v1alpha1", "Widget", "foo", Some("team-a"), true)]
+    #[case::unknown_resource("example.com/v1", "Gadget", "foo", Some("team-a"), false)]
+    fn get_resource(
+        #[case] api_version: &str,
+        #[case] kind: &str,
+        #[case] name: &str,
+        #[case] namespace: Option<&str>,
+        #[case] found: bool,
+    ) {
+        let context = OfflineKubernetesContext::from_yaml(MANIFESTS).unwrap();
+
+        let obj = context.get_resource(api_version, kind, name, namespace);
+
+        assert_eq!(obj.is_ok(), found, "unexpected result: {obj:?}");
+        if let Ok(obj) = obj {
+            assert_eq!(obj.metadata.name.as_deref(), Some(name));
+        }
+    }
+
+    #[rstest]
+    #[case::builtin("apps/v1", "Deployment", Some("deployments"))]
+    #[case::custom_resource("example.com/v1alpha1", "Widget", Some("widgets"))]
+    #[case::unknown("example.com/v1alpha1", "Gadget", None)]
+    fn plural_names(#[case] api_version: &str, #[case] kind: &str, #[case] expected: Option<&str>) {
+        let context = OfflineKubernetesContext::from_yaml(MANIFESTS).unwrap();
+
+        assert_eq!(
+            context.get_resource_plural_name(api_version, kind).ok(),
+            expected.map(str::to_owned)
+        );
+    }
+
+    #[test]
+    fn load_from_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("nested")).unwrap();
+        std::fs::write(dir.path().join("manifests.yaml"), MANIFESTS).unwrap();
+        std::fs::write(
+            dir.path().join("nested").join("pod.json"),
+            r#"{"apiVersion": "v1", "kind": "Pod", "metadata": {"name": "busybox", "namespace": "team-b"}}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("README.md"), "not a manifest").unwrap();
+
+        let context = OfflineKubernetesContext::from_path(dir.path()).unwrap();
+
+        assert_eq!(
+            context
+                .list_resources_all("v1", "Pod", None, None)
+                .unwrap()
+                .items
+                .len(),
+            4
+        );
+    }
+}
@@ -5,11 +5,11 @@ use kube::{
     ResourceExt,
     runtime::{WatchStreamExt, reflector::store::Writer, watcher},
 };
-use std::hash::Hash;
-use tokio::{sync::watch, time::Instant};
+use std::{hash::Hash, sync::Mutex, time::Duration};
+use tokio::{sync::watch, task::AbortHandle, time::Instant};
 use tracing::{debug, info, warn};
 
-use crate::callback_handler::kubernetes::KubeResource;
+use crate::callback_handler::kubernetes::{KubeResource, KubernetesError};
 
 /// Like `kube::runtime::reflector::reflector`, but also sends the time of the last change to a
 /// watch channel
@@ -47,50 +47,54 @@ where
 /// data.
 ///
 /// Finally, when started, the Reflector takes some time to make the loaded data available to
-/// consumers.
+/// consumers. Use [`Reflector::wait_until_synced`] before reading data from it.
+///
+/// ## Lifecycle
+///
+/// The background task that keeps the Reflector updated is stopped when the
+/// Reflector is dropped.
 pub(crate) struct Reflector {
     /// Read-only access to the data cached by the Reflector
     pub reader: kube::runtime::reflector::Store<kube::core::DynamicObject>,
+    /// The resource watched by the Reflector
+    pub resource: KubeResource,
+    /// The namespace watched by the Reflector, `None` when watching all the namespaces
+    pub namespace: Option<String>,
     last_change_seen_at: watch::Receiver<Instant>,
+    last_used_at: Mutex<Instant>,
+    watcher_task: AbortHandle,
+}
+
+impl Drop for Reflector {
+    fn drop(&mut self) {
+        self.watcher_task.abort();
+    }
 }
 
 impl Reflector {
     /// Compute a unique identifier for the Reflector. This is used to prevent the creation of two
     /// Reflectors watching the same set of resources.
-    pub fn compute_id(
-        resource: &KubeResource,
-        namespace: Option<&str>,
-        label_selector: Option<&str>,
-        field_selector: Option<&str>,
-    ) -> String {
+    pub fn compute_id(resource: &KubeResource, namespace: Option<&str>) -> String {
         format!(
-            "{}|{}|{namespace:?}|{label_selector:?}|{field_selector:?}",
+            "{}|{}|{namespace:?}",
             resource.resource.api_version, resource.resource.kind
         )
     }
 
     /// Create the reflector and start a tokio task in the background that keeps
-    /// the contents of the Reflector updated
-    pub async fn create_and_run(
+    /// the contents of the Reflector updated.
+    ///
+    /// This does not wait for the initial list of objects to be loaded.
+    pub fn create_and_run(
         kube_client: kube::Client,
         resource: KubeResource,
         namespace: Option<String>,
-        label_selector: Option<String>,
-        field_selector: Option<String>,
-    ) -> Result<Self> {
+    ) -> Self {
         let group = resource.resource.group.clone();
         let version = resource.resource.version.clone();
         let kind = resource.resource.kind.clone();
 
-        info!(
-            group,
-            version,
-            kind,
-            ?namespace,
-            ?label_selector,
-            ?field_selector,
-            "creating new reflector"
-        );
+        info!(group, version, kind, ?namespace, "creating new reflector");
 
         let api = match namespace {
             Some(ref ns) => kube::api::Api::<kube::core::DynamicObject>::namespaced_with(
@@ -104,15 +108,10 @@ impl Reflector {
             ),
         };
 
-        let writer = Writer::new(resource.resource);
+        let writer = Writer::new(resource.resource.clone());
         let reader = writer.as_reader();
 
-        let filter = watcher::Config {
-            label_selector: label_selector.clone(),
-            field_selector: field_selector.clone(),
-            ..Default::default()
-        };
-        let stream = watcher(api, filter).map_ok(|ev| {
+        let stream = watcher(api, watcher::Config::default()).map_ok(|ev| {
             ev.modify(|obj| {
                 // clear managed fields to reduce memory usage
                 obj.managed_fields_mut().clear();
@@ -127,7 +126,9 @@ impl Reflector {
 
         let rf = reflector_tracking_changes_instant(writer, stream, updated_at_watch_tx);
 
-        tokio::spawn(async move {
+        let reflector_namespace = namespace.clone();
+
+        let watcher_task = tokio::spawn(async move {
             let infinite_watch = rf.default_backoff().touched_objects().for_each(|obj| {
                 match obj {
                     Ok(o) => debug!(
@@ -135,8 +136,6 @@ impl Reflector {
                         version,
                         kind,
                         ?namespace,
-                        ?label_selector,
-                        ?field_selector,
                         object=?o,
                         "watcher saw object"
                     ),
@@ -145,8 +144,6 @@ impl Reflector {
                         version,
                         kind,
                         ?namespace,
-                        ?label_selector,
-                        ?field_selector,
                         error=?e,
                         "watcher error"
                     ),
@@ -154,18 +151,85 @@ impl Reflector {
                 ready(())
             });
             infinite_watch.await
-        });
-
-        reader.wait_until_ready().await?;
+        })
+        .abort_handle();
 
-        Ok(Reflector {
+        Reflector {
             reader,
+            resource,
+            namespace: reflector_namespace,
             last_change_seen_at: updated_at_watch_rx,
-        })
+            last_used_at: Mutex::new(Instant::now()),
+            watcher_task,
+        }
+    }
+
+    /// Wait for the reader to be populated with the initial list of objects.
+    ///
+    /// When a timeout is provided and expires, a [`KubernetesError::CacheNotReady`]
+    /// error is returned. The reflector keeps loading the data in the background,
+    /// hence a later invocation can succeed.
+    pub async fn wait_until_synced(
+        reader: &kube::runtime::reflector::Store<kube::core::DynamicObject>,
+        resource: &KubeResource,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, reader.wait_until_ready())
+                .await
+                .map_err(|_| KubernetesError::CacheNotReady {
+                    api_version: resource.resource.api_version.clone(),
+                    kind: resource.resource.kind.clone(),
+                    timeout,
+                })??,
+            None => reader.wait_until_ready().await?,
+        };
+
+        Ok(())
     }
 
     /// Get the last time a change was seen by the reflector
     pub async fn last_change_seen_at(&self) -> Instant {
         *self.last_change_seen_at.borrow()
     }
+
+    /// Get the last time the data of the reflector has been accessed
+    pub fn last_used_at(&self) -> Instant {
+        *self.last_used_at.lock().unwrap()
+    }
+
+    /// Record an access to the data of the reflector
+    pub fn touch(&self) {
+        *self.last_used_at.lock().unwrap() = Instant::now();
+    }
+
+    /// Number of objects currently cached by the reflector
+    pub fn object_count(&self) -> usize {
+        self.reader.len()
+    }
+}
+
+#[cfg(test)]
+impl Reflector {
+    /// Build a reflector caching the given objects, without watching any cluster
+    pub(crate) fn with_objects(
+        resource: KubeResource,
+        objects: Vec<kube::core::DynamicObject>,
+        last_used_at: Instant,
+    ) -> Self {
+        let mut writer = Writer::new(resource.resource.clone());
+        for object in objects {
+            writer.apply_watcher_event(&watcher::Event::Apply(object));
+        }
+        let (_, last_change_seen_at) = watch::channel(Instant::now());
+
+        Reflector {
+            reader: writer.as_reader(),
+            resource,
+            namespace: None,
+            last_change_seen_at,
+            last_used_at: Mutex::new(last_used_at),
+            watcher_task: tokio::spawn(async {}).abort_handle(),
+        }
+    }
 }
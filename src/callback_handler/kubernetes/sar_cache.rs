@@ -0,0 +1,202 @@
+use anyhow::Result;
+use k8s_openapi::api::authorization::v1::SubjectAccessReviewStatus;
+use kubewarden_policy_sdk::host_capabilities::kubernetes::{
+    ResourceAttributes, SubjectAccessReview as KWSubjectAccessReview,
+};
+use std::{collections::BTreeSet, future::Future, sync::Arc};
+
+use crate::callback_handler::cache::CapabilityCache;
+
+/// Key of the SubjectAccessReview cache. Two reviews about the same user,
+/// the same groups (regardless of their order) and the same resource attributes
+/// share the same key
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SarCacheKey {
+    user: String,
+    groups: BTreeSet<String>,
+    resource_attributes: ResourceAttributes,
+}
+
+impl SarCacheKey {
+    /// The entries of the capability cache are indexed by user, which makes
+    /// possible to invalidate all the decisions about a user
+    fn details(&self) -> String {
+        format!("{:?}/{:?}", self.groups, self.resource_attributes)
+    }
+}
+
+impl From<&KWSubjectAccessReview> for SarCacheKey {
+    fn from(review: &KWSubjectAccessReview) -> Self {
+        SarCacheKey {
+            user: review.user.clone(),
+            groups: review.groups.iter().flatten().cloned().collect(),
+            resource_attributes: review.resource_attributes.clone(),
+        }
+    }
+}
+
+/// Cache of the SubjectAccessReview decisions.
+///
+/// Decisions are stored inside of the given capability cache, which also
+/// coalesces the identical reviews that are requested while one of them is
+/// still in flight: only one SubjectAccessReview is sent to the Kubernetes
+/// API server, and all the callers get its result.
+pub(crate) struct SarCache {
+    entries: Arc<CapabilityCache<SubjectAccessReviewStatus>>,
+}
+
+impl SarCache {
+    pub fn new(entries: Arc<CapabilityCache<SubjectAccessReviewStatus>>) -> Self {
+        Self { entries }
+    }
+
+    /// Return the cached decision about the given review. When missing, `review_fn`
+    /// is used to obtain it, unless an identical review is already in flight.
+    pub async fn get_or_review<F, Fut>(
+        &self,
+        review: &KWSubjectAccessReview,
+        review_fn: F,
+    ) -> Result<cached::Return<SubjectAccessReviewStatus>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<SubjectAccessReviewStatus>>,
+    {
+        let key = SarCacheKey::from(review);
+
+        self.entries
+            .get_or_insert_with(&key.user, &key.details(), review_fn)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::anyhow;
+    use rstest::rstest;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use crate::callback_handler::cache::CapabilityCacheConfig;
+
+    fn sar_cache(ttl: Duration) -> SarCache {
+        SarCache::new(Arc::new(CapabilityCache::new(CapabilityCacheConfig::new(
+            ttl,
+        ))))
+    }
+
+    fn review(user: &str, groups: &[&str]) -> KWSubjectAccessReview {
+        KWSubjectAccessReview {
+            user: user.to_owned(),
+            groups: Some(groups.iter().map(|g| g.to_string()).collect()),
+            resource_attributes: ResourceAttributes {
+                verb: "create".to_owned(),
+                resource: "pods".to_owned(),
+                namespace: Some("kube-system".to_owned()),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn allowed() -> SubjectAccessReviewStatus {
+        SubjectAccessReviewStatus {
+            allowed: true,
+            ..Default::default()
+        }
+    }
+
+    #[rstest]
+    #[case::same_review(review("alice", &["dev", "ops"]), review("alice", &["dev", "ops"]), 1)]
+    #[case::groups_order(review("alice", &["dev", "ops"]), review("alice", &["ops", "dev"]), 1)]
+    #[case::different_user(review("alice", &["dev"]), review("bob", &["dev"]), 2)]
+    #[case::different_groups(review("alice", &["dev"]), review("alice", &["ops"]), 2)]
+    #[tokio::test]
+    async fn cache_key(
+        #[case] first: KWSubjectAccessReview,
+        #[case] second: KWSubjectAccessReview,
+        #[case] expected_reviews: usize,
+    ) {
+        let cache = sar_cache(Duration::from_secs(60));
+        let counter = AtomicUsize::new(0);
+        let reviews = &counter;
+
+        for review in [first, second] {
+            cache
+                .get_or_review(&review, move || async move {
+                    reviews.fetch_add(1, Ordering::SeqCst);
+                    Ok(allowed())
+                })
+                .await
+                .expect("review failed");
+        }
+
+        assert_eq!(reviews.load(Ordering::SeqCst), expected_reviews);
+    }
+
+    #[tokio::test]
+    async fn coalesce_in_flight_reviews() {
+        let cache = sar_cache(Duration::from_secs(60));
+        let counter = AtomicUsize::new(0);
+        let reviews = &counter;
+        let review = review("alice", &["dev"]);
+
+        let review_fn = move || async move {
+            reviews.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(allowed())
+        };
+        let (first, second) = tokio::join!(
+            cache.get_or_review(&review, review_fn),
+            cache.get_or_review(&review, review_fn),
+        );
+
+        assert!(first.unwrap().value.allowed);
+        assert!(second.unwrap().value.allowed);
+        assert_eq!(reviews.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn expire_decisions() {
+        let cache = sar_cache(Duration::from_millis(100));
+        let review = review("alice", &["dev"]);
+
+        let first = cache
+            .get_or_review(&review, || async { Ok(allowed()) })
+            .await
+            .unwrap();
+        assert!(!first.was_cached);
+
+        let second = cache
+            .get_or_review(&review, || async { Ok(allowed()) })
+            .await
+            .unwrap();
+        assert!(second.was_cached);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+
+        let third = cache
+            .get_or_review(&review, || async { Ok(allowed()) })
+            .await
+            .unwrap();
+        assert!(!third.was_cached);
+    }
+
+    #[tokio::test]
+    async fn errors_are_not_cached() {
+        let cache = sar_cache(Duration::from_secs(60));
+        let review = review("alice", &["dev"]);
+
+        let result = cache
+            .get_or_review(&review, || async { Err(anyhow!("boom")) })
+            .await;
+        assert!(result.is_err());
+
+        let result = cache
+            .get_or_review(&review, || async { Ok(allowed()) })
+            .await;
+        assert!(result.is_ok());
+    }
+}
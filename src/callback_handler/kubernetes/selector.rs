@@ -0,0 +1,360 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{Result, anyhow};
+use kube::core::DynamicObject;
+
+/// A single requirement of a label selector
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) enum LabelRequirement {
+    /// `key=value` or `key==value`
+    Equals { key: String, value: String },
+    /// `key!=value`
+    NotEquals { key: String, value: String },
+    /// `key`
+    Exists { key: String },
+    /// `!key`
+    DoesNotExist { key: String },
+    /// `key in (value1,value2)`
+    In {
+        key: String,
+        values: BTreeSet<String>,
+    },
+    /// `key notin (value1,value2)`
+    NotIn {
+        key: String,
+        values: BTreeSet<String>,
+    },
+}
+
+impl LabelRequirement {
+    fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        match self {
+            LabelRequirement::Equals { key, value } => labels.get(key) == Some(value),
+            LabelRequirement::NotEquals { key, value } => labels.get(key) != Some(value),
+            LabelRequirement::Exists { key } => labels.contains_key(key),
+            LabelRequirement::DoesNotExist { key } => !labels.contains_key(key),
+            LabelRequirement::In { key, values } => {
+                labels.get(key).is_some_and(|value| values.contains(value))
+            }
+            LabelRequirement::NotIn { key, values } => {
+                labels.get(key).is_none_or(|value| !values.contains(value))
+            }
+        }
+    }
+}
+
+/// A parsed Kubernetes label selector, using the same syntax accepted by the
+/// `labelSelector` query parameter of the Kubernetes API server.
+///
+/// Both equality based (`=`, `==`, `!=`) and set based (`in`, `notin`, `key`, `!key`)
+/// requirements are supported.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct LabelSelector {
+    requirements: BTreeSet<LabelRequirement>,
+}
+
+impl LabelSelector {
+    pub fn parse(selector: &str) -> Result<Self> {
+        let mut requirements = BTreeSet::new();
+
+        for term in split_terms(selector)? {
+            requirements.insert(parse_requirement(term)?);
+        }
+
+        Ok(LabelSelector { requirements })
+    }
+
+    /// Build a selector made by the given requirements, e.g. the ones of a
+    /// Kubernetes `LabelSelector` object
+    pub fn from_requirements(requirements: BTreeSet<LabelRequirement>) -> Self {
+        LabelSelector { requirements }
+    }
+
+    /// Returns true when the labels satisfy all the requirements of the selector
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.requirements.iter().all(|r| r.matches(labels))
+    }
+}
+
+/// Split the selector into its requirements. Commas found inside of
+/// parentheses separate the values of set based requirements
+fn split_terms(selector: &str) -> Result<Vec<&str>> {
+    let mut terms = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, c) in selector.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Err(anyhow!("unbalanced parentheses in '{selector}'")),
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                terms.push(selector[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(anyhow!("unbalanced parentheses in '{selector}'"));
+    }
+    terms.push(selector[start..].trim());
+
+    Ok(terms.into_iter().filter(|term| !term.is_empty()).collect())
+}
+
+fn parse_requirement(term: &str) -> Result<LabelRequirement> {
+    if let Some((head, values)) = term.split_once('(') {
+        return parse_set_requirement(head, values, term);
+    }
+
+    if let Some(key) = term.strip_prefix('!') {
+        return Ok(LabelRequirement::DoesNotExist {
+            key: parse_key(key, term)?,
+        });
+    }
+
+    if let Some((key, value)) = term.split_once("!=") {
+        return Ok(LabelRequirement::NotEquals {
+            key: parse_key(key, term)?,
+            value: parse_value(value, term)?,
+        });
+    }
+
+    if let Some((key, value)) = term.split_once("==").or_else(|| term.split_once('=')) {
+        return Ok(LabelRequirement::Equals {
+            key: parse_key(key, term)?,
+            value: parse_value(value, term)?,
+        });
+    }
+
+    Ok(LabelRequirement::Exists {
+        key: parse_key(term, term)?,
+    })
+}
+
+/// Parse requirements like `key in (value1,value2)`. `head` is the text
+/// before the opening parenthesis, `values` the one after it
+fn parse_set_requirement(head: &str, values: &str, term: &str) -> Result<LabelRequirement> {
+    let invalid = || anyhow!("invalid label selector requirement '{term}'");
+
+    let (key, operator) = head
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or_else(invalid)?;
+    let values = values.trim_end().strip_suffix(')').ok_or_else(invalid)?;
+    if values.trim().is_empty() {
+        return Err(invalid());
+    }
+    let values = values
+        .split(',')
+        .map(|value| parse_value(value, term))
+        .collect::<Result<BTreeSet<String>>>()?;
+    let key = parse_key(key, term)?;
+
+    match operator.trim() {
+        "in" => Ok(LabelRequirement::In { key, values }),
+        "notin" => Ok(LabelRequirement::NotIn { key, values }),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_key(key: &str, term: &str) -> Result<String> {
+    let key = key.trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return Err(anyhow!("invalid label selector requirement '{term}'"));
+    }
+    Ok(key.to_owned())
+}
+
+fn parse_value(value: &str, term: &str) -> Result<String> {
+    let value = value.trim();
+    if value.contains(['=', '!', ' ', '(', ')', ',']) {
+        return Err(anyhow!("invalid label selector requirement '{term}'"));
+    }
+    Ok(value.to_owned())
+}
+
+/// A single requirement of a field selector
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) enum FieldRequirement {
+    /// `field=value` or `field==value`
+    Equals { field: String, value: String },
+    /// `field!=value`
+    NotEquals { field: String, value: String },
+}
+
+/// A parsed Kubernetes field selector, using the same syntax accepted by the
+/// `fieldSelector` query parameter of the Kubernetes API server.
+///
+/// Fields are referenced using their dotted path inside of the object, e.g.
+/// `metadata.name` or `status.phase`. A field that is not set is compared as
+/// an empty string, like the Kubernetes API server does.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct FieldSelector {
+    requirements: BTreeSet<FieldRequirement>,
+}
+
+impl FieldSelector {
+    pub fn parse(selector: &str) -> Result<Self> {
+        let mut requirements = BTreeSet::new();
+
+        for term in selector.split(',').map(str::trim) {
+            if term.is_empty() {
+                continue;
+            }
+            let requirement = if let Some((field, value)) = term.split_once("!=") {
+                FieldRequirement::NotEquals {
+                    field: parse_key(field, term)?,
+                    value: parse_value(value, term)?,
+                }
+            } else if let Some((field, value)) =
+                term.split_once("==").or_else(|| term.split_once('='))
+            {
+                FieldRequirement::Equals {
+                    field: parse_key(field, term)?,
+                    value: parse_value(value, term)?,
+                }
+            } else {
+                return Err(anyhow!("invalid field selector requirement '{term}'"));
+            };
+            requirements.insert(requirement);
+        }
+
+        Ok(FieldSelector { requirements })
+    }
+
+    /// Returns true when the object satisfies all the requirements of the selector
+    pub fn matches(&self, obj: &DynamicObject) -> bool {
+        if self.requirements.is_empty() {
+            return true;
+        }
+
+        // Serialize the object only when a field outside of the metadata is referenced
+        let mut json: Option<serde_json::Value> = None;
+
+        self.requirements.iter().all(|requirement| {
+            let (field, value, equals) = match requirement {
+                FieldRequirement::Equals { field, value } => (field, value, true),
+                FieldRequirement::NotEquals { field, value } => (field, value, false),
+            };
+            let actual = match field.as_str() {
+                "metadata.name" => obj.metadata.name.clone(),
+                "metadata.namespace" => obj.metadata.namespace.clone(),
+                _ => {
+                    let json =
+                        json.get_or_insert_with(|| serde_json::to_value(obj).unwrap_or_default());
+                    field_value(json, field)
+                }
+            }
+            .unwrap_or_default();
+
+            (&actual == value) == equals
+        })
+    }
+}
+
+/// Get the value of the field with the given dotted path, converted to a string.
+/// Only scalar values are taken into account
+fn field_value(json: &serde_json::Value, field: &str) -> Option<String> {
+    let value = field
+        .split('.')
+        .try_fold(json, |value, segment| value.get(segment))?;
+
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    fn labels() -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("app".to_string(), "nginx".to_string()),
+            ("tier".to_string(), "frontend".to_string()),
+        ])
+    }
+
+    #[rstest]
+    #[case::empty("", true)]
+    #[case::equals("app=nginx", true)]
+    #[case::double_equals("app==nginx", true)]
+    #[case::equals_mismatch("app=redis", false)]
+    #[case::not_equals("app!=redis", true)]
+    #[case::not_equals_missing_label("env!=prod", true)]
+    #[case::exists("tier", true)]
+    #[case::does_not_exist("!tier", false)]
+    #[case::multiple_requirements("app=nginx, tier=frontend,!env", true)]
+    #[case::multiple_requirements_mismatch("app=nginx,tier=backend", false)]
+    #[case::in_set("app in (nginx, redis)", true)]
+    #[case::in_set_mismatch("app in (redis)", false)]
+    #[case::in_set_missing_label("env in (prod)", false)]
+    #[case::not_in_set("app notin (redis,postgres)", true)]
+    #[case::not_in_set_mismatch("tier notin (frontend)", false)]
+    #[case::not_in_set_missing_label("env notin (prod)", true)]
+    #[case::mixed("app in (nginx,redis),tier!=backend,!env", true)]
+    fn match_labels(#[case] selector: &str, #[case] expected: bool) {
+        let selector = LabelSelector::parse(selector).expect("cannot parse selector");
+
+        assert_eq!(selector.matches(&labels()), expected);
+    }
+
+    #[rstest]
+    #[case::empty_key("=nginx")]
+    #[case::invalid_value("app=ng=inx")]
+    #[case::empty_does_not_exist("!")]
+    #[case::unknown_set_operator("app within (nginx)")]
+    #[case::set_without_operator("app (nginx)")]
+    #[case::unbalanced_parentheses("app in (nginx")]
+    #[case::empty_set("app in ()")]
+    fn parse_invalid_selector(#[case] selector: &str) {
+        assert!(LabelSelector::parse(selector).is_err());
+    }
+
+    #[rstest]
+    #[case::empty("", true)]
+    #[case::name("metadata.name=nginx", true)]
+    #[case::name_double_equals("metadata.name==nginx", true)]
+    #[case::name_mismatch("metadata.name=redis", false)]
+    #[case::namespace("metadata.namespace!=kube-system", true)]
+    #[case::status("status.phase=Running", true)]
+    #[case::nested("spec.nodeName=node-1,metadata.namespace=default", true)]
+    #[case::numeric("spec.priority=10", true)]
+    #[case::missing_field_equals_empty("spec.hostname=", true)]
+    #[case::missing_field_not_equals("spec.hostname!=foo", true)]
+    fn match_fields(#[case] selector: &str, #[case] expected: bool) {
+        let obj: DynamicObject = serde_json::from_value(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": "nginx",
+                "namespace": "default",
+            },
+            "spec": {
+                "nodeName": "node-1",
+                "priority": 10,
+            },
+            "status": {
+                "phase": "Running",
+            },
+        }))
+        .unwrap();
+        let selector = FieldSelector::parse(selector).expect("cannot parse selector");
+
+        assert_eq!(selector.matches(&obj), expected);
+    }
+
+    #[rstest]
+    #[case::no_operator("metadata.name")]
+    #[case::empty_field("=nginx")]
+    fn parse_invalid_field_selector(#[case] selector: &str) {
+        assert!(FieldSelector::parse(selector).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use std::sync::Arc;
+
+use anyhow::{Result, anyhow};
+use pki_types::{CertificateDer, UnixTime};
+use policy_fetcher::oci_client::{Reference, manifest::OciManifest};
+use serde::{Deserialize, Serialize};
+
+mod envelope;
+mod trust_policy;
+
+pub use trust_policy::{
+    NotationSignatureVerification, NotationTrustPolicy, NotationTrustPolicyDocument,
+    NotationTrustStore, NotationVerificationLevel,
+};
+
+use crate::callback_handler::cache::CapabilityCache;
+use crate::callback_handler::{crypto, oci};
+
+/// Artifact type of the Notation signatures, stored as OCI referrers of the
+/// signed images
+const SIGNATURE_ARTIFACT_TYPE: &str = "application/vnd.cncf.notary.signature";
+
+/// The result of the verification of the Notation signatures of an image
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NotationVerificationResponse {
+    /// The digest of the image
+    pub digest: String,
+    pub is_trusted: bool,
+    /// The distinguished name of the certificate that signed the image
+    pub signer: String,
+}
+
+/// Helper struct to verify the Notation signatures of the images, according to
+/// a trust policy
+#[derive(Clone)]
+pub(crate) struct Client {
+    oci_client: Arc<oci::Client>,
+    trust_store: Arc<NotationTrustStore>,
+    trust_policy: Arc<NotationTrustPolicyDocument>,
+}
+
+impl Client {
+    pub fn new(
+        oci_client: Arc<oci::Client>,
+        trust_store: NotationTrustStore,
+        trust_policy: NotationTrustPolicyDocument,
+    ) -> Result<Self> {
+        trust_policy.validate(&trust_store)?;
+
+        Ok(Client {
+            oci_client,
+            trust_store: Arc::new(trust_store),
+            trust_policy: Arc::new(trust_policy),
+        })
+    }
+
+    /// Verify the image has at least one Notation signature trusted by the
+    /// trust policy that applies to its repository
+    pub async fn verify(&self, image: &str) -> Result<NotationVerificationResponse> {
+        let image_ref: Reference = image.parse()?;
+        let repository = format!("{}/{}", image_ref.registry(), image_ref.repository());
+        let policy = self
+            .trust_policy
+            .policy_for(&repository)
+            .ok_or_else(|| anyhow!("no Notation trust policy applies to {repository}"))?;
+        if policy.signature_verification.level == NotationVerificationLevel::Skip {
+            return Err(anyhow!(
+                "Notation trust policy {} skips the verification of {repository}",
+                policy.name
+            ));
+        }
+
+        let digest = self.oci_client.digest(image).await?;
+        let signatures = self
+            .oci_client
+            .referrers(
+                &image_ref.clone_with_digest(digest.clone()).whole(),
+                Some(SIGNATURE_ARTIFACT_TYPE),
+            )
+            .await?;
+
+        let mut errors = vec![];
+        for signature in signatures.manifests {
+            let signature_image = image_ref
+                .clone_with_digest(signature.digest.clone())
+                .whole();
+            match self
+                .verify_signature(&signature_image, &digest, policy)
+                .await
+            {
+                Ok(signer) => {
+                    return Ok(NotationVerificationResponse {
+                        digest,
+                        is_trusted: true,
+                        signer,
+                    });
+                }
+                Err(e) => errors.push(format!("{}: {e}", signature.digest)),
+            }
+        }
+
+        if errors.is_empty() {
+            return Err(anyhow!("no Notation signature found for {image}"));
+        }
+        Err(anyhow!(
+            "no trusted Notation signature found for {image}: {}",
+            errors.join(", ")
+        ))
+    }
+
+    /// Verify the signature stored inside of `signature_image` is about the
+    /// given digest and has been made by a trusted identity. Returns the
+    /// distinguished name of the signer
+    async fn verify_signature(
+        &self,
+        signature_image: &str,
+        digest: &str,
+        policy: &NotationTrustPolicy,
+    ) -> Result<String> {
+        let OciManifest::Image(manifest) = self.oci_client.manifest(signature_image).await? else {
+            return Err(anyhow!("the signature is not an image manifest"));
+        };
+        let [layer] = manifest.layers.as_slice() else {
+            return Err(anyhow!("the signature must have exactly one envelope"));
+        };
+        let data = self.oci_client.blob(signature_image, layer).await?;
+
+        let envelope = envelope::verify_envelope(&layer.media_type, &data)?;
+        if envelope.target_digest != digest {
+            return Err(anyhow!(
+                "the signature is about {}, not {digest}",
+                envelope.target_digest
+            ));
+        }
+
+        let (signing_cert, intermediates) = envelope
+            .cert_chain
+            .split_first()
+            .ok_or_else(|| anyhow!("the envelope has no signing certificate"))?;
+        let trusted_certs = policy
+            .trust_stores
+            .iter()
+            .flat_map(|store| self.trust_store.certificates(store))
+            .cloned()
+            .collect::<Vec<CertificateDer>>();
+        crypto::verify_certificate_chain(
+            signing_cert,
+            intermediates,
+            &trusted_certs,
+            UnixTime::now(),
+        )?;
+
+        if !policy.trusts_identity(&envelope.signer) {
+            return Err(anyhow!("the signer {} is not trusted", envelope.signer));
+        }
+        Ok(envelope.signer)
+    }
+}
+
+pub(crate) async fn get_notation_verification_cached(
+    cache: &CapabilityCache<NotationVerificationResponse>,
+    client: Option<&Client>,
+    image: String,
+) -> Result<cached::Return<NotationVerificationResponse>> {
+    let client = client.ok_or_else(|| anyhow!("Notation verification is not configured"))?;
+    cache
+        .get_or_insert_with(&image, "notation", || client.verify(&image))
+        .await
+}
@@ -0,0 +1,547 @@
+use anyhow::{Result, anyhow};
+use base64::{
+    Engine as _,
+    engine::general_purpose::{STANDARD as BASE64, URL_SAFE_NO_PAD as BASE64_URL},
+};
+use coset::{
+    CborSerializable, CoseSign1, Label, RegisteredLabel, RegisteredLabelWithPrivate,
+    TaggedCborSerializable, cbor::value::Value, iana,
+};
+use pki_types::CertificateDer;
+use policy_fetcher::sigstore::crypto::{CosignVerificationKey, Signature, SigningScheme};
+use serde::Deserialize;
+use x509_cert::der::{Decode, Encode};
+
+/// Media type of the JWS signature envelopes
+pub(crate) const JWS_MEDIA_TYPE: &str = "application/jose+json";
+/// Media type of the COSE signature envelopes
+pub(crate) const COSE_MEDIA_TYPE: &str = "application/cose";
+
+const PAYLOAD_CONTENT_TYPE: &str = "application/vnd.cncf.notary.payload.v1+json";
+const SIGNING_SCHEME_HEADER: &str = "io.cncf.notary.signingScheme";
+const EXPIRY_HEADER: &str = "io.cncf.notary.expiry";
+/// Only the signatures made with certificates issued by a CA are supported,
+/// the ones relying on a signing authority need a timestamp countersignature
+const SIGNING_SCHEME_X509: &str = "notary.x509";
+/// The COSE header holding the certificate chain, as defined by RFC 9360
+const COSE_X5CHAIN_LABEL: i64 = 33;
+/// CBOR tag of the dates expressed as seconds since the epoch
+const CBOR_EPOCH_DATETIME_TAG: u64 = 1;
+
+/// The content of a Notation signature envelope whose signature has been
+/// verified using the signing certificate
+#[derive(Debug)]
+pub(crate) struct VerifiedEnvelope {
+    /// The digest of the signed artifact
+    pub target_digest: String,
+    /// The distinguished name of the signing certificate
+    pub signer: String,
+    /// The signing certificate, followed by the intermediate ones. Note well:
+    /// the chain has not been verified yet
+    pub cert_chain: Vec<CertificateDer<'static>>,
+}
+
+/// The signature algorithms allowed by the Notary Project specification,
+/// besides ES512 which is not supported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Algorithm {
+    PS256,
+    PS384,
+    PS512,
+    ES256,
+    ES384,
+}
+
+impl Algorithm {
+    fn signing_scheme(self) -> SigningScheme {
+        match self {
+            Algorithm::PS256 => SigningScheme::RSA_PSS_SHA256(0),
+            Algorithm::PS384 => SigningScheme::RSA_PSS_SHA384(0),
+            Algorithm::PS512 => SigningScheme::RSA_PSS_SHA512(0),
+            Algorithm::ES256 => SigningScheme::ECDSA_P256_SHA256_ASN1,
+            Algorithm::ES384 => SigningScheme::ECDSA_P384_SHA384_ASN1,
+        }
+    }
+
+    fn is_ecdsa(self) -> bool {
+        matches!(self, Algorithm::ES256 | Algorithm::ES384)
+    }
+}
+
+impl TryFrom<&str> for Algorithm {
+    type Error = anyhow::Error;
+
+    fn try_from(alg: &str) -> Result<Self> {
+        match alg {
+            "PS256" => Ok(Algorithm::PS256),
+            "PS384" => Ok(Algorithm::PS384),
+            "PS512" => Ok(Algorithm::PS512),
+            "ES256" => Ok(Algorithm::ES256),
+            "ES384" => Ok(Algorithm::ES384),
+            _ => Err(anyhow!("unsupported signature algorithm {alg}")),
+        }
+    }
+}
+
+impl TryFrom<iana::Algorithm> for Algorithm {
+    type Error = anyhow::Error;
+
+    fn try_from(alg: iana::Algorithm) -> Result<Self> {
+        match alg {
+            iana::Algorithm::PS256 => Ok(Algorithm::PS256),
+            iana::Algorithm::PS384 => Ok(Algorithm::PS384),
+            iana::Algorithm::PS512 => Ok(Algorithm::PS512),
+            iana::Algorithm::ES256 => Ok(Algorithm::ES256),
+            iana::Algorithm::ES384 => Ok(Algorithm::ES384),
+            _ => Err(anyhow!("unsupported signature algorithm {alg:?}")),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Payload {
+    target_artifact: TargetArtifact,
+}
+
+#[derive(Deserialize)]
+struct TargetArtifact {
+    digest: String,
+}
+
+/// Verify the signature of the envelope, returning its content
+pub(crate) fn verify_envelope(media_type: &str, data: &[u8]) -> Result<VerifiedEnvelope> {
+    let (payload, signer, cert_chain) = match media_type {
+        JWS_MEDIA_TYPE => verify_jws(data)?,
+        COSE_MEDIA_TYPE => verify_cose(data)?,
+        _ => return Err(anyhow!("unsupported signature envelope {media_type}")),
+    };
+
+    let payload: Payload =
+        serde_json::from_slice(&payload).map_err(|e| anyhow!("invalid signature payload: {e}"))?;
+    Ok(VerifiedEnvelope {
+        target_digest: payload.target_artifact.digest,
+        signer,
+        cert_chain,
+    })
+}
+
+/// The payload, the signer and the certificate chain of a verified envelope
+type SignedContent = (Vec<u8>, String, Vec<CertificateDer<'static>>);
+
+#[derive(Deserialize)]
+struct JwsEnvelope {
+    payload: String,
+    protected: String,
+    header: JwsUnprotectedHeader,
+    signature: String,
+}
+
+#[derive(Deserialize)]
+struct JwsUnprotectedHeader {
+    x5c: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct JwsProtectedHeader {
+    alg: String,
+    cty: Option<String>,
+    #[serde(rename = "io.cncf.notary.signingScheme")]
+    signing_scheme: Option<String>,
+    #[serde(rename = "io.cncf.notary.expiry")]
+    expiry: Option<String>,
+    #[serde(default)]
+    crit: Vec<String>,
+}
+
+/// Verify a JWS envelope using the JSON serialization
+fn verify_jws(data: &[u8]) -> Result<SignedContent> {
+    let envelope: JwsEnvelope =
+        serde_json::from_slice(data).map_err(|e| anyhow!("invalid JWS envelope: {e}"))?;
+    let header: JwsProtectedHeader =
+        serde_json::from_slice(&BASE64_URL.decode(&envelope.protected)?)
+            .map_err(|e| anyhow!("invalid JWS protected header: {e}"))?;
+
+    let expiry = header
+        .expiry
+        .as_deref()
+        .map(|expiry| {
+            chrono::DateTime::parse_from_rfc3339(expiry)
+                .map(|expiry| expiry.timestamp())
+                .map_err(|e| anyhow!("invalid expiry {expiry}: {e}"))
+        })
+        .transpose()?;
+    check_protected_header(
+        header.cty.as_deref(),
+        header.signing_scheme.as_deref(),
+        expiry,
+        &header.crit,
+    )?;
+
+    let cert_chain = envelope
+        .header
+        .x5c
+        .iter()
+        .map(|cert| BASE64.decode(cert).map(CertificateDer::from))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let signing_certificate = signing_certificate(&cert_chain)?;
+    let signing_input = format!("{}.{}", envelope.protected, envelope.payload);
+    verify_signature(
+        Algorithm::try_from(header.alg.as_str())?,
+        &signing_certificate,
+        signing_input.as_bytes(),
+        &BASE64_URL.decode(&envelope.signature)?,
+    )?;
+
+    Ok((
+        BASE64_URL.decode(&envelope.payload)?,
+        signing_certificate.tbs_certificate.subject.to_string(),
+        cert_chain,
+    ))
+}
+
+/// Verify a `COSE_Sign1` envelope
+fn verify_cose(data: &[u8]) -> Result<SignedContent> {
+    let sign1 = CoseSign1::from_tagged_slice(data)
+        .or_else(|_| CoseSign1::from_slice(data))
+        .map_err(|e| anyhow!("invalid COSE envelope: {e:?}"))?;
+    let header = &sign1.protected.header;
+
+    let alg = match &header.alg {
+        Some(RegisteredLabelWithPrivate::Assigned(alg)) => Algorithm::try_from(*alg)?,
+        alg => return Err(anyhow!("unsupported signature algorithm {alg:?}")),
+    };
+    let content_type = match &header.content_type {
+        Some(RegisteredLabel::Text(content_type)) => Some(content_type.as_str()),
+        _ => None,
+    };
+    let signing_scheme = header
+        .rest
+        .iter()
+        .find_map(|(label, value)| match (label, value) {
+            (Label::Text(label), Value::Text(scheme)) if label == SIGNING_SCHEME_HEADER => {
+                Some(scheme.as_str())
+            }
+            _ => None,
+        });
+    let expiry = header
+        .rest
+        .iter()
+        .find(|(label, _)| matches!(label, Label::Text(label) if label == EXPIRY_HEADER))
+        .map(|(_, value)| match value {
+            Value::Tag(CBOR_EPOCH_DATETIME_TAG, value) => value
+                .as_integer()
+                .and_then(|expiry| i64::try_from(expiry).ok())
+                .ok_or_else(|| anyhow!("invalid expiry")),
+            _ => Err(anyhow!("invalid expiry")),
+        })
+        .transpose()?;
+    let critical = header
+        .crit
+        .iter()
+        .map(|label| match label {
+            RegisteredLabel::Text(label) => label.clone(),
+            RegisteredLabel::Assigned(label) => format!("{label:?}"),
+        })
+        .collect::<Vec<_>>();
+    check_protected_header(content_type, signing_scheme, expiry, &critical)?;
+
+    let cert_chain = match sign1
+        .unprotected
+        .rest
+        .iter()
+        .find(|(label, _)| *label == Label::Int(COSE_X5CHAIN_LABEL))
+        .map(|(_, value)| value)
+    {
+        Some(Value::Bytes(cert)) => vec![CertificateDer::from(cert.clone())],
+        Some(Value::Array(certs)) => certs
+            .iter()
+            .map(|cert| {
+                cert.as_bytes()
+                    .map(|cert| CertificateDer::from(cert.clone()))
+                    .ok_or_else(|| anyhow!("invalid certificate chain"))
+            })
+            .collect::<Result<Vec<_>>>()?,
+        _ => return Err(anyhow!("the envelope has no certificate chain")),
+    };
+    let payload = sign1
+        .payload
+        .clone()
+        .ok_or_else(|| anyhow!("the envelope has no payload"))?;
+    let signing_certificate = signing_certificate(&cert_chain)?;
+    sign1.verify_signature(b"", |signature, message| {
+        verify_signature(alg, &signing_certificate, message, signature)
+    })?;
+
+    Ok((
+        payload,
+        signing_certificate.tbs_certificate.subject.to_string(),
+        cert_chain,
+    ))
+}
+
+/// Ensure the envelope is about a Notation payload, signed using a supported
+/// signing scheme, and not expired. All the critical headers must be understood
+fn check_protected_header(
+    content_type: Option<&str>,
+    signing_scheme: Option<&str>,
+    expiry: Option<i64>,
+    critical: &[String],
+) -> Result<()> {
+    if content_type != Some(PAYLOAD_CONTENT_TYPE) {
+        return Err(anyhow!("unsupported payload content type {content_type:?}"));
+    }
+    if signing_scheme != Some(SIGNING_SCHEME_X509) {
+        return Err(anyhow!("unsupported signing scheme {signing_scheme:?}"));
+    }
+    if let Some(header) = critical
+        .iter()
+        .find(|header| *header != SIGNING_SCHEME_HEADER && *header != EXPIRY_HEADER)
+    {
+        return Err(anyhow!("unsupported critical header {header}"));
+    }
+    if expiry.is_some_and(|expiry| expiry < chrono::Utc::now().timestamp()) {
+        return Err(anyhow!("the signature is expired"));
+    }
+    Ok(())
+}
+
+/// The signing certificate, the first one of the chain
+fn signing_certificate(cert_chain: &[CertificateDer]) -> Result<x509_cert::Certificate> {
+    let signing_cert = cert_chain
+        .first()
+        .ok_or_else(|| anyhow!("the envelope has no signing certificate"))?;
+    x509_cert::Certificate::from_der(signing_cert)
+        .map_err(|e| anyhow!("invalid signing certificate: {e}"))
+}
+
+/// Verify the signature using the public key of the signing certificate
+fn verify_signature(
+    alg: Algorithm,
+    certificate: &x509_cert::Certificate,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<()> {
+    let public_key = certificate
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()?;
+    let key = CosignVerificationKey::from_der(&public_key, &alg.signing_scheme())
+        .map_err(|e| anyhow!("unsupported signing certificate: {e}"))?;
+
+    let signature = if alg.is_ecdsa() {
+        ecdsa_signature_to_der(signature)?
+    } else {
+        signature.to_vec()
+    };
+    key.verify_signature(Signature::Raw(&signature), message)
+        .map_err(|e| anyhow!("invalid signature: {e}"))
+}
+
+/// Convert a raw ECDSA signature, `r || s` as used by JWS and COSE, to the
+/// ASN.1 DER encoding
+fn ecdsa_signature_to_der(signature: &[u8]) -> Result<Vec<u8>> {
+    if signature.is_empty() || !signature.len().is_multiple_of(2) {
+        return Err(anyhow!("invalid ECDSA signature"));
+    }
+    let (r, s) = signature.split_at(signature.len() / 2);
+
+    let mut content = der_integer(r);
+    content.extend(der_integer(s));
+    let length = u8::try_from(content.len())
+        .ok()
+        .filter(|length| *length < 0x80)
+        .ok_or_else(|| anyhow!("invalid ECDSA signature"))?;
+
+    let mut der = vec![0x30, length];
+    der.extend(content);
+    Ok(der)
+}
+
+/// DER encoding of a non empty, big endian, unsigned integer
+fn der_integer(value: &[u8]) -> Vec<u8> {
+    let first = value
+        .iter()
+        .position(|byte| *byte != 0)
+        .unwrap_or(value.len() - 1);
+    let value = &value[first..];
+
+    let mut der = vec![0x02];
+    if value[0] & 0x80 != 0 {
+        der.extend([value.len() as u8 + 1, 0]);
+    } else {
+        der.push(value.len() as u8);
+    }
+    der.extend_from_slice(value);
+    der
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use coset::{CoseSign1Builder, HeaderBuilder};
+    use rcgen::{CertificateParams, DistinguishedName, DnType, KeyPair, SigningKey};
+    use rstest::rstest;
+
+    const DIGEST: &str = "sha256:0000000000000000000000000000000000000000000000000000000000000001";
+
+    fn signing_certificate() -> (KeyPair, Vec<u8>) {
+        let key_pair = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::default();
+        let mut subject = DistinguishedName::new();
+        subject.push(DnType::CountryName, "US");
+        subject.push(DnType::OrganizationName, "acme-rockets.io");
+        params.distinguished_name = subject;
+        let cert = params.self_signed(&key_pair).unwrap();
+        (key_pair, cert.der().to_vec())
+    }
+
+    /// Convert an ASN.1 DER encoded ECDSA P-256 signature to `r || s`
+    fn ecdsa_signature_to_raw(der: &[u8]) -> Vec<u8> {
+        let mut raw = vec![];
+        let mut rest = &der[2..];
+        for _ in 0..2 {
+            let length = rest[1] as usize;
+            let value = &rest[2..2 + length];
+            let value = &value[value.len().saturating_sub(32)..];
+            raw.extend(std::iter::repeat_n(0, 32 - value.len()));
+            raw.extend(value);
+            rest = &rest[2 + length..];
+        }
+        raw
+    }
+
+    fn payload(digest: &str) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "targetArtifact": {
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "digest": digest,
+                "size": 1024,
+            }
+        }))
+        .unwrap()
+    }
+
+    fn jws_envelope(
+        key_pair: &KeyPair,
+        cert: &[u8],
+        protected: serde_json::Value,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let protected = BASE64_URL.encode(serde_json::to_vec(&protected).unwrap());
+        let payload = BASE64_URL.encode(payload);
+        let signature = key_pair
+            .sign(format!("{protected}.{payload}").as_bytes())
+            .unwrap();
+        serde_json::to_vec(&serde_json::json!({
+            "payload": payload,
+            "protected": protected,
+            "header": {"x5c": [BASE64.encode(cert)]},
+            "signature": BASE64_URL.encode(ecdsa_signature_to_raw(&signature)),
+        }))
+        .unwrap()
+    }
+
+    fn jws_protected_header() -> serde_json::Value {
+        serde_json::json!({
+            "alg": "ES256",
+            "cty": PAYLOAD_CONTENT_TYPE,
+            "crit": [SIGNING_SCHEME_HEADER],
+            SIGNING_SCHEME_HEADER: SIGNING_SCHEME_X509,
+        })
+    }
+
+    #[test]
+    fn verify_jws_envelope() {
+        let (key_pair, cert) = signing_certificate();
+        let envelope = jws_envelope(&key_pair, &cert, jws_protected_header(), &payload(DIGEST));
+
+        let verified = verify_envelope(JWS_MEDIA_TYPE, &envelope).unwrap();
+
+        assert_eq!(verified.target_digest, DIGEST);
+        assert_eq!(verified.signer, "O=acme-rockets.io,C=US");
+        assert_eq!(verified.cert_chain, vec![CertificateDer::from(cert)]);
+    }
+
+    #[rstest]
+    #[case::unsupported_signing_scheme(SIGNING_SCHEME_HEADER, serde_json::json!("notary.x509.signingAuthority"))]
+    #[case::unsupported_content_type("cty", serde_json::json!("application/json"))]
+    #[case::unknown_critical_header(
+        "crit",
+        serde_json::json!([SIGNING_SCHEME_HEADER, "io.cncf.notary.authenticSigningTime"])
+    )]
+    #[case::expired(EXPIRY_HEADER, serde_json::json!("2020-01-01T00:00:00Z"))]
+    fn reject_jws_protected_header(#[case] header: &str, #[case] value: serde_json::Value) {
+        let (key_pair, cert) = signing_certificate();
+        let mut protected = jws_protected_header();
+        protected[header] = value;
+        let envelope = jws_envelope(&key_pair, &cert, protected, &payload(DIGEST));
+
+        assert!(verify_envelope(JWS_MEDIA_TYPE, &envelope).is_err());
+    }
+
+    #[test]
+    fn reject_tampered_jws_envelope() {
+        let (key_pair, cert) = signing_certificate();
+        let envelope = jws_envelope(&key_pair, &cert, jws_protected_header(), &payload(DIGEST));
+        let mut envelope: serde_json::Value = serde_json::from_slice(&envelope).unwrap();
+        envelope["payload"] = serde_json::json!(BASE64_URL.encode(payload(
+            "sha256:0000000000000000000000000000000000000000000000000000000000000002"
+        )));
+
+        assert!(verify_envelope(JWS_MEDIA_TYPE, &serde_json::to_vec(&envelope).unwrap()).is_err());
+    }
+
+    #[test]
+    fn reject_jws_envelope_signed_by_another_key() {
+        let (key_pair, _) = signing_certificate();
+        let (_, cert) = signing_certificate();
+        let envelope = jws_envelope(&key_pair, &cert, jws_protected_header(), &payload(DIGEST));
+
+        assert!(verify_envelope(JWS_MEDIA_TYPE, &envelope).is_err());
+    }
+
+    #[test]
+    fn verify_cose_envelope() {
+        let (key_pair, cert) = signing_certificate();
+        let protected = HeaderBuilder::new()
+            .algorithm(iana::Algorithm::ES256)
+            .content_type(PAYLOAD_CONTENT_TYPE.to_owned())
+            .add_critical_label(RegisteredLabel::Text(SIGNING_SCHEME_HEADER.to_owned()))
+            .text_value(
+                SIGNING_SCHEME_HEADER.to_owned(),
+                Value::Text(SIGNING_SCHEME_X509.to_owned()),
+            )
+            .build();
+        let unprotected = HeaderBuilder::new()
+            .value(
+                COSE_X5CHAIN_LABEL,
+                Value::Array(vec![Value::Bytes(cert.clone())]),
+            )
+            .build();
+        let envelope = CoseSign1Builder::new()
+            .protected(protected)
+            .unprotected(unprotected)
+            .payload(payload(DIGEST))
+            .create_signature(b"", |message| {
+                ecdsa_signature_to_raw(&key_pair.sign(message).unwrap())
+            })
+            .build()
+            .to_tagged_vec()
+            .unwrap();
+
+        let verified = verify_envelope(COSE_MEDIA_TYPE, &envelope).unwrap();
+
+        assert_eq!(verified.target_digest, DIGEST);
+        assert_eq!(verified.signer, "O=acme-rockets.io,C=US");
+    }
+
+    #[rstest]
+    #[case::no_leading_zeros(&[0x01, 0x02], vec![0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02])]
+    #[case::leading_zeros(&[0x00, 0x01, 0x00, 0x00], vec![0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x00])]
+    #[case::high_bit_set(&[0x80, 0x01], vec![0x30, 0x07, 0x02, 0x02, 0x00, 0x80, 0x02, 0x01, 0x01])]
+    fn convert_ecdsa_signature_to_der(#[case] raw: &[u8], #[case] expected: Vec<u8>) {
+        assert_eq!(ecdsa_signature_to_der(raw).unwrap(), expected);
+    }
+}
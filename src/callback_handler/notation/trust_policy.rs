@@ -0,0 +1,477 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use pki_types::{CertificateDer, pem::PemObject};
+use serde::{Deserialize, Serialize};
+
+const SUPPORTED_TRUST_POLICY_VERSION: &str = "1.0";
+const WILDCARD: &str = "*";
+const X509_SUBJECT_PREFIX: &str = "x509.subject:";
+/// The only supported type of trust store: the one holding the certificate
+/// authorities issuing the signing certificates
+const CA_TRUST_STORE_TYPE: &str = "ca";
+
+/// The trust stores referenced by the Notation trust policies.
+///
+/// Each trust store is identified by its type and its name, e.g. `ca:acme-rockets`,
+/// and holds the certificates used as trust anchors.
+#[derive(Debug, Clone, Default)]
+pub struct NotationTrustStore {
+    stores: HashMap<String, Vec<CertificateDer<'static>>>,
+}
+
+impl NotationTrustStore {
+    /// Load the trust stores from a directory laid out like the one used by the
+    /// Notation CLI: `<path>/x509/<type>/<name>/<certificate files>`.
+    /// The certificates can be either PEM or DER encoded
+    pub fn from_dir(path: &Path) -> Result<Self> {
+        let mut trust_store = Self::default();
+
+        for (store_type, store_type_path) in subdirectories(&path.join("x509"))? {
+            for (name, store_path) in subdirectories(&store_type_path)? {
+                let store = format!("{store_type}:{name}");
+                for entry in std::fs::read_dir(&store_path)? {
+                    let file = entry?.path();
+                    if file.is_file() {
+                        let data = std::fs::read(&file)
+                            .map_err(|e| anyhow!("cannot read {}: {e}", file.display()))?;
+                        trust_store.add_certificates(&store, &data)?;
+                    }
+                }
+            }
+        }
+
+        Ok(trust_store)
+    }
+
+    /// Add PEM or DER encoded certificates to the given trust store, e.g.
+    /// `ca:acme-rockets`. The trust store is created when it doesn't exist
+    pub fn add_certificates(&mut self, store: &str, data: &[u8]) -> Result<()> {
+        if !store.contains(':') {
+            return Err(anyhow!(
+                "invalid trust store name {store}, expected <type>:<name>"
+            ));
+        }
+
+        let mut certificates = CertificateDer::pem_slice_iter(data)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("invalid certificates for trust store {store}: {e}"))?;
+        if certificates.is_empty() {
+            certificates.push(CertificateDer::from(data.to_vec()));
+        }
+
+        self.stores
+            .entry(store.to_owned())
+            .or_default()
+            .extend(certificates);
+        Ok(())
+    }
+
+    /// The certificates of the given trust store
+    pub(crate) fn certificates(&self, store: &str) -> &[CertificateDer<'static>] {
+        self.stores
+            .get(store)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
+
+fn subdirectories(path: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut dirs = vec![];
+    for entry in
+        std::fs::read_dir(path).map_err(|e| anyhow!("cannot read {}: {e}", path.display()))?
+    {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            dirs.push((
+                entry.file_name().to_string_lossy().into_owned(),
+                entry.path(),
+            ));
+        }
+    }
+    Ok(dirs)
+}
+
+/// A Notation trust policy document, as defined by the Notary Project
+/// specification
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotationTrustPolicyDocument {
+    /// The version of the document, only `1.0` is supported
+    pub version: String,
+    pub trust_policies: Vec<NotationTrustPolicy>,
+}
+
+/// A Notation trust policy, defining who is trusted to sign the images of a set
+/// of repositories
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotationTrustPolicy {
+    pub name: String,
+    /// The repositories the policy applies to, as `<registry>/<repository>`.
+    /// The `*` wildcard matches all the repositories not matched by the other policies
+    pub registry_scopes: Vec<String>,
+    pub signature_verification: NotationSignatureVerification,
+    /// The trust stores holding the trust anchors, e.g. `ca:acme-rockets`
+    #[serde(default)]
+    pub trust_stores: Vec<String>,
+    /// The identities trusted to sign the images, e.g.
+    /// `x509.subject: C=US, ST=WA, O=acme-rockets.io`. The `*` wildcard trusts
+    /// all the certificates issued by the trust stores
+    #[serde(default)]
+    pub trusted_identities: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotationSignatureVerification {
+    pub level: NotationVerificationLevel,
+}
+
+/// How the signatures of the images are verified
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotationVerificationLevel {
+    /// All the verifications are enforced
+    Strict,
+    /// Only the integrity and the authenticity of the signatures are enforced,
+    /// the other failures are logged. Not supported, rejected when validating
+    /// the trust policies
+    Permissive,
+    /// Only the integrity of the signatures is enforced, the other failures
+    /// are logged. Not supported, rejected when validating the trust policies
+    Audit,
+    /// The signatures are not verified, hence the images are never considered trusted
+    Skip,
+}
+
+impl NotationTrustPolicyDocument {
+    /// Ensure the document is valid and references only known trust stores
+    pub(crate) fn validate(&self, trust_store: &NotationTrustStore) -> Result<()> {
+        if self.version != SUPPORTED_TRUST_POLICY_VERSION {
+            return Err(anyhow!(
+                "unsupported trust policy version {}, expected {SUPPORTED_TRUST_POLICY_VERSION}",
+                self.version
+            ));
+        }
+
+        let mut scopes = HashSet::new();
+        for policy in &self.trust_policies {
+            if policy.registry_scopes.is_empty() {
+                return Err(anyhow!(
+                    "trust policy {} has no registry scope",
+                    policy.name
+                ));
+            }
+            if policy.registry_scopes.len() > 1
+                && policy.registry_scopes.iter().any(|s| s == WILDCARD)
+            {
+                return Err(anyhow!(
+                    "trust policy {}: the {WILDCARD} registry scope cannot be used with other scopes",
+                    policy.name
+                ));
+            }
+            for scope in &policy.registry_scopes {
+                if !scopes.insert(scope) {
+                    return Err(anyhow!(
+                        "registry scope {scope} is used by more than one trust policy"
+                    ));
+                }
+            }
+
+            match policy.signature_verification.level {
+                NotationVerificationLevel::Strict => {}
+                NotationVerificationLevel::Skip => continue,
+                NotationVerificationLevel::Permissive | NotationVerificationLevel::Audit => {
+                    return Err(anyhow!(
+                        "trust policy {}: only the strict and skip verification levels are supported",
+                        policy.name
+                    ));
+                }
+            }
+            if policy.trust_stores.is_empty() {
+                return Err(anyhow!("trust policy {} has no trust store", policy.name));
+            }
+            if let Some(store) = policy
+                .trust_stores
+                .iter()
+                .find(|store| store.split_once(':').map(|(t, _)| t) != Some(CA_TRUST_STORE_TYPE))
+            {
+                return Err(anyhow!(
+                    "trust policy {} references the trust store {store}, only the {CA_TRUST_STORE_TYPE} trust stores are supported",
+                    policy.name
+                ));
+            }
+            if let Some(store) = policy
+                .trust_stores
+                .iter()
+                .find(|store| trust_store.certificates(store).is_empty())
+            {
+                return Err(anyhow!(
+                    "trust policy {} references the unknown trust store {store}",
+                    policy.name
+                ));
+            }
+            if policy.trusted_identities.is_empty() {
+                return Err(anyhow!(
+                    "trust policy {} has no trusted identity",
+                    policy.name
+                ));
+            }
+            for identity in &policy.trusted_identities {
+                if identity == WILDCARD {
+                    continue;
+                }
+                identity
+                    .strip_prefix(X509_SUBJECT_PREFIX)
+                    .ok_or_else(|| anyhow!("unsupported trusted identity {identity}"))
+                    .and_then(parse_distinguished_name)
+                    .map_err(|e| anyhow!("trust policy {}: {e}", policy.name))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Find the trust policy applying to the given repository, written as
+    /// `<registry>/<repository>`
+    pub(crate) fn policy_for(&self, repository: &str) -> Option<&NotationTrustPolicy> {
+        let with_scope = |scope: &str| {
+            self.trust_policies
+                .iter()
+                .find(|policy| policy.registry_scopes.iter().any(|s| s == scope))
+        };
+        with_scope(repository).or_else(|| with_scope(WILDCARD))
+    }
+}
+
+impl NotationTrustPolicy {
+    /// Whether the signer, identified by the distinguished name of its
+    /// certificate, is trusted. The attributes of a trusted identity must all
+    /// be part of the distinguished name of the signer
+    pub(crate) fn trusts_identity(&self, subject: &str) -> bool {
+        let Ok(subject) = parse_distinguished_name(subject) else {
+            return false;
+        };
+
+        self.trusted_identities.iter().any(|identity| {
+            identity == WILDCARD
+                || identity
+                    .strip_prefix(X509_SUBJECT_PREFIX)
+                    .and_then(|dn| parse_distinguished_name(dn).ok())
+                    .is_some_and(|trusted| trusted.iter().all(|attr| subject.contains(attr)))
+        })
+    }
+}
+
+/// Split a distinguished name, like `C=US, ST=WA, O=acme-rockets.io`, into its
+/// attributes. The separators escaped with a backslash are part of the values
+fn parse_distinguished_name(dn: &str) -> Result<Vec<(String, String)>> {
+    let mut parts = vec![];
+    let mut current = String::new();
+    let mut chars = dn.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => current.extend(chars.next()),
+            ',' | '+' => parts.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+    }
+    parts.push(current);
+
+    let attributes = parts
+        .iter()
+        .map(|part| {
+            part.split_once('=')
+                .map(|(key, value)| (key.trim().to_uppercase(), value.trim().to_owned()))
+                .filter(|(key, value)| !key.is_empty() && !value.is_empty())
+                .ok_or_else(|| anyhow!("invalid distinguished name {dn}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(attributes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    fn trust_policy(
+        name: &str,
+        registry_scopes: &[&str],
+        trusted_identities: &[&str],
+    ) -> NotationTrustPolicy {
+        NotationTrustPolicy {
+            name: name.to_owned(),
+            registry_scopes: registry_scopes.iter().map(|s| s.to_string()).collect(),
+            signature_verification: NotationSignatureVerification {
+                level: NotationVerificationLevel::Strict,
+            },
+            trust_stores: vec!["ca:acme-rockets".to_owned()],
+            trusted_identities: trusted_identities.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn trust_store() -> NotationTrustStore {
+        let mut trust_store = NotationTrustStore::default();
+        trust_store
+            .add_certificates("ca:acme-rockets", b"not really a certificate")
+            .unwrap();
+        trust_store
+    }
+
+    #[test]
+    fn deserialize_trust_policy_document() {
+        let document: NotationTrustPolicyDocument = serde_json::from_value(serde_json::json!({
+            "version": "1.0",
+            "trustPolicies": [{
+                "name": "acme-rockets",
+                "registryScopes": ["registry.acme-rockets.io/software/net-monitor"],
+                "signatureVerification": {"level": "strict"},
+                "trustStores": ["ca:acme-rockets"],
+                "trustedIdentities": ["x509.subject: C=US, ST=WA, O=acme-rockets.io"],
+            }],
+        }))
+        .unwrap();
+
+        assert_eq!(
+            document.trust_policies,
+            vec![trust_policy(
+                "acme-rockets",
+                &["registry.acme-rockets.io/software/net-monitor"],
+                &["x509.subject: C=US, ST=WA, O=acme-rockets.io"],
+            )]
+        );
+        assert!(document.validate(&trust_store()).is_ok());
+    }
+
+    #[rstest]
+    #[case::exact_scope("registry.acme-rockets.io/software/net-monitor", Some("net-monitor"))]
+    #[case::wildcard("registry.acme-rockets.io/software/other", Some("wildcard"))]
+    fn find_trust_policy(#[case] repository: &str, #[case] expected: Option<&str>) {
+        let document = NotationTrustPolicyDocument {
+            version: "1.0".to_owned(),
+            trust_policies: vec![
+                trust_policy("wildcard", &["*"], &["*"]),
+                trust_policy(
+                    "net-monitor",
+                    &["registry.acme-rockets.io/software/net-monitor"],
+                    &["*"],
+                ),
+            ],
+        };
+
+        assert_eq!(
+            document.policy_for(repository).map(|p| p.name.as_str()),
+            expected
+        );
+    }
+
+    #[test]
+    fn no_trust_policy_without_wildcard() {
+        let document = NotationTrustPolicyDocument {
+            version: "1.0".to_owned(),
+            trust_policies: vec![trust_policy(
+                "net-monitor",
+                &["registry.acme-rockets.io/software/net-monitor"],
+                &["*"],
+            )],
+        };
+
+        assert!(document.policy_for("docker.io/library/busybox").is_none());
+    }
+
+    #[rstest]
+    #[case::wildcard(&["*"], true)]
+    #[case::same_attributes(&["x509.subject: CN=signer,O=acme-rockets.io,C=US"], true)]
+    #[case::subset_of_attributes(&["x509.subject: C=US, O=acme-rockets.io"], true)]
+    #[case::different_value(&["x509.subject: C=US, O=wabbit-networks.io"], false)]
+    #[case::missing_attribute(&["x509.subject: C=US, ST=WA, O=acme-rockets.io"], false)]
+    #[case::any_identity(
+        &["x509.subject: O=wabbit-networks.io", "x509.subject: O=acme-rockets.io"],
+        true
+    )]
+    fn trust_identity(#[case] trusted_identities: &[&str], #[case] trusted: bool) {
+        let policy = trust_policy("acme-rockets", &["*"], trusted_identities);
+
+        assert_eq!(
+            policy.trusts_identity("CN=signer,O=acme-rockets.io,C=US"),
+            trusted
+        );
+    }
+
+    #[rstest]
+    #[case::unsupported_version("2.0", vec![trust_policy("acme", &["*"], &["*"])])]
+    #[case::wildcard_with_other_scopes(
+        "1.0",
+        vec![trust_policy("acme", &["*", "registry.acme-rockets.io/software/net-monitor"], &["*"])]
+    )]
+    #[case::duplicated_scope(
+        "1.0",
+        vec![trust_policy("acme", &["*"], &["*"]), trust_policy("wabbit", &["*"], &["*"])]
+    )]
+    #[case::no_trusted_identity("1.0", vec![trust_policy("acme", &["*"], &[])])]
+    #[case::invalid_trusted_identity("1.0", vec![trust_policy("acme", &["*"], &["x509.subject: acme"])])]
+    #[case::unsupported_trusted_identity("1.0", vec![trust_policy("acme", &["*"], &["acme"])])]
+    fn reject_invalid_trust_policy_documents(
+        #[case] version: &str,
+        #[case] trust_policies: Vec<NotationTrustPolicy>,
+    ) {
+        let document = NotationTrustPolicyDocument {
+            version: version.to_owned(),
+            trust_policies,
+        };
+
+        assert!(document.validate(&trust_store()).is_err());
+    }
+
+    #[test]
+    fn reject_unknown_trust_store() {
+        let mut policy = trust_policy("acme", &["*"], &["*"]);
+        policy.trust_stores = vec!["ca:wabbit-networks".to_owned()];
+        let document = NotationTrustPolicyDocument {
+            version: "1.0".to_owned(),
+            trust_policies: vec![policy],
+        };
+
+        assert!(document.validate(&trust_store()).is_err());
+    }
+
+    #[rstest]
+    #[case::permissive(NotationVerificationLevel::Permissive)]
+    #[case::audit(NotationVerificationLevel::Audit)]
+    fn reject_unsupported_verification_levels(#[case] level: NotationVerificationLevel) {
+        let mut policy = trust_policy("acme", &["*"], &["*"]);
+        policy.signature_verification.level = level;
+        let document = NotationTrustPolicyDocument {
+            version: "1.0".to_owned(),
+            trust_policies: vec![policy],
+        };
+
+        let error = document.validate(&trust_store()).unwrap_err();
+        assert!(error.to_string().contains("verification levels"), "{error}");
+    }
+
+    #[test]
+    fn reject_unsupported_trust_store_type() {
+        let mut trust_store = trust_store();
+        trust_store
+            .add_certificates("signingAuthority:acme-rockets", b"not really a certificate")
+            .unwrap();
+        let mut policy = trust_policy("acme", &["*"], &["*"]);
+        policy.trust_stores = vec!["signingAuthority:acme-rockets".to_owned()];
+        let document = NotationTrustPolicyDocument {
+            version: "1.0".to_owned(),
+            trust_policies: vec![policy],
+        };
+
+        let error = document.validate(&trust_store).unwrap_err();
+        assert!(
+            error.to_string().contains("only the ca trust stores"),
+            "{error}"
+        );
+    }
+}
@@ -1,22 +1,29 @@
 use std::time::Duration;
 
-use anyhow::Result;
-use cached::proc_macro::cached;
+use anyhow::{Result, anyhow};
 use kubewarden_policy_sdk::host_capabilities::oci::ManifestDigestResponse;
 use policy_fetcher::{
     oci_client::{
-        Reference,
-        manifest::{OciImageManifest, OciManifest},
+        self, Reference,
+        manifest::{OciDescriptor, OciImageIndex, OciImageManifest, OciManifest},
+        secrets::RegistryAuth,
     },
     registry::Registry,
+    sigstore,
     sources::Sources,
 };
 use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::callback_handler::cache::CapabilityCache;
+use crate::callback_requests::OciPlatform;
 
 /// Helper struct to interact with an OCI registry
 pub(crate) struct Client {
     sources: Option<Sources>,
     registry: Registry,
+    /// Used by the operations that are not offered by `Registry`
+    oci_client: oci_client::Client,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -26,10 +33,27 @@ pub struct ManifestAndConfigResponse {
     pub config: serde_json::Value,
 }
 
+/// A page of the tags of an OCI repository
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OciListTagsResponse {
+    /// The name of the repository
+    pub name: String,
+    pub tags: Vec<String>,
+    /// The value to be used as `last` to fetch the next page. `None` when there
+    /// are no more tags
+    pub next: Option<String>,
+}
+
 impl Client {
     pub fn new(sources: Option<Sources>) -> Self {
         let registry = Registry {};
-        Client { sources, registry }
+        let oci_client = build_oci_client(sources.as_ref());
+
+        Client {
+            sources,
+            registry,
+            oci_client,
+        }
     }
 
     /// Fetch the manifest digest of the OCI resource referenced via `image`
@@ -75,6 +99,182 @@ impl Client {
             config,
         })
     }
+
+    /// List the tags of the repository referenced via `image`. At most `page_size`
+    /// tags are returned, starting after the `last` one
+    pub async fn list_tags(
+        &self,
+        image: &str,
+        page_size: Option<usize>,
+        last: Option<&str>,
+    ) -> Result<OciListTagsResponse> {
+        let image_ref: Reference = image.parse()?;
+        let auth = registry_auth(&image_ref).await;
+        let response = self
+            .oci_client
+            .list_tags(&image_ref, &auth, page_size, last)
+            .await?;
+
+        // a full page means that more tags could be available
+        let next = match page_size {
+            Some(page_size) if response.tags.len() >= page_size => response.tags.last().cloned(),
+            _ => None,
+        };
+
+        Ok(OciListTagsResponse {
+            name: response.name,
+            tags: response.tags,
+            next,
+        })
+    }
+
+    /// Fetch the OCI 1.1 referrers of the OCI resource referenced via `image`,
+    /// optionally filtered by artifact type
+    pub async fn referrers(
+        &self,
+        image: &str,
+        artifact_type: Option<&str>,
+    ) -> Result<OciImageIndex> {
+        let mut image_ref: Reference = image.parse()?;
+        // the referrers API works only with digests
+        if image_ref.digest().is_none() {
+            let digest = self.digest(image).await?;
+            image_ref = image_ref.clone_with_digest(digest);
+        }
+
+        let auth = registry_auth(&image_ref).await;
+        self.oci_client
+            .store_auth_if_needed(image_ref.resolve_registry(), &auth)
+            .await;
+        let index = self
+            .oci_client
+            .pull_referrers(&image_ref, artifact_type)
+            .await?;
+        Ok(index)
+    }
+
+    /// Fetch the blob described by `descriptor` from the repository referenced
+    /// via `image`
+    pub async fn blob(&self, image: &str, descriptor: &OciDescriptor) -> Result<Vec<u8>> {
+        let image_ref: Reference = image.parse()?;
+
+        let auth = registry_auth(&image_ref).await;
+        self.oci_client
+            .store_auth_if_needed(image_ref.resolve_registry(), &auth)
+            .await;
+        let mut data = Vec::new();
+        self.oci_client
+            .pull_blob(&image_ref, descriptor, &mut data)
+            .await?;
+        Ok(data)
+    }
+
+    /// Fetch the manifest, digest and config of the image referenced via `image`,
+    /// built for the given platform. When `image` points to an image index, the
+    /// manifest matching the platform is looked up inside of it
+    pub async fn manifest_and_config_for_platform(
+        &self,
+        image: &str,
+        platform: &OciPlatform,
+    ) -> Result<ManifestAndConfigResponse> {
+        match self.manifest(image).await? {
+            OciManifest::ImageIndex(index) => {
+                let descriptor = index
+                    .manifests
+                    .iter()
+                    .find(|descriptor| {
+                        descriptor.platform.as_ref().is_some_and(|p| {
+                            platform_matches(platform, &p.os, &p.architecture, p.variant.as_deref())
+                        })
+                    })
+                    .ok_or_else(|| {
+                        anyhow!("image {image} is not available for platform {platform}")
+                    })?;
+
+                let image_ref: Reference = image.parse()?;
+                let platform_image = image_ref.clone_with_digest(descriptor.digest.clone());
+                self.manifest_and_config(&platform_image.whole()).await
+            }
+            OciManifest::Image(_) => {
+                let response = self.manifest_and_config(image).await?;
+                let os = response.config["os"].as_str().unwrap_or_default();
+                let architecture = response.config["architecture"].as_str().unwrap_or_default();
+                let variant = response.config["variant"].as_str();
+                if !platform_matches(platform, os, architecture, variant) {
+                    return Err(anyhow!(
+                        "image {image} is not available for platform {platform}"
+                    ));
+                }
+                Ok(response)
+            }
+        }
+    }
+}
+
+/// Build a client that interacts with OCI registries, honoring the given sources
+pub(crate) fn build_oci_client(sources: Option<&Sources>) -> oci_client::Client {
+    let client_config: sigstore::registry::ClientConfig =
+        sources.cloned().unwrap_or_default().into();
+    oci_client::Client::new(client_config.into())
+}
+
+/// Look up the credentials of the registry inside of the docker configuration,
+/// falling back to anonymous access.
+///
+/// The docker configuration, and the credential helpers, are read by a
+/// blocking task, to not stall the async runtime
+pub(crate) async fn registry_auth(image_ref: &Reference) -> RegistryAuth {
+    let registry = image_ref.resolve_registry().to_owned();
+    tokio::task::spawn_blocking(move || docker_registry_auth(&registry))
+        .await
+        .unwrap_or_else(|e| {
+            warn!(error = %e, "cannot look up the registry credentials, using anonymous access");
+            RegistryAuth::Anonymous
+        })
+}
+
+fn docker_registry_auth(registry: &str) -> RegistryAuth {
+    match docker_credential::get_credential(registry) {
+        Ok(docker_credential::DockerCredential::UsernamePassword(username, password)) => {
+            RegistryAuth::Basic(username, password)
+        }
+        Ok(docker_credential::DockerCredential::IdentityToken(_)) => {
+            // identity tokens are OAuth2 refresh tokens, which cannot be
+            // exchanged by the OCI client
+            warn!(
+                registry,
+                "the docker configuration provides an identity token, which is not supported: using anonymous access"
+            );
+            RegistryAuth::Anonymous
+        }
+        Err(
+            docker_credential::CredentialRetrievalError::ConfigNotFound
+            | docker_credential::CredentialRetrievalError::NoCredentialConfigured,
+        ) => RegistryAuth::Anonymous,
+        Err(e) => {
+            warn!(
+                registry,
+                error = %e,
+                "cannot read the registry credentials from the docker configuration: using anonymous access"
+            );
+            RegistryAuth::Anonymous
+        }
+    }
+}
+
+/// Returns true when the given os, architecture and variant satisfy the wanted platform
+fn platform_matches(
+    wanted: &OciPlatform,
+    os: &str,
+    architecture: &str,
+    variant: Option<&str>,
+) -> bool {
+    wanted.os == os
+        && wanted.architecture == architecture
+        && wanted
+            .variant
+            .as_deref()
+            .is_none_or(|wanted_variant| Some(wanted_variant) == variant)
 }
 
 // Interacting with a remote OCI registry is time expensive, this can cause a massive slow down
@@ -84,25 +284,22 @@ impl Client {
 // Details about this cache:
 //   * only the image "url" is used as key. oci::Client is not hashable, plus
 //     the client is always the same
-//   * the cache is time bound: cached values are purged after 60 seconds
-//   * only successful results are cached
-#[cached(
-    time = 60,
-    result = true,
-    sync_writes = "default",
-    key = "String",
-    convert = r#"{ format!("{}", img) }"#,
-    with_cached_flag = true
-)]
+//   * the cache is time bound, by default cached values are purged after 60 seconds.
+//     See `CacheConfig` for all the settings
+//   * by default only successful results are cached
 pub(crate) async fn get_oci_digest_cached(
+    cache: &CapabilityCache<ManifestDigestResponse>,
     oci_client: &Client,
     img: &str,
 ) -> Result<cached::Return<ManifestDigestResponse>> {
-    oci_client
-        .digest(img)
+    cache
+        .get_or_insert_with(img, "", || async {
+            oci_client
+                .digest(img)
+                .await
+                .map(|digest| ManifestDigestResponse { digest })
+        })
         .await
-        .map(|digest| ManifestDigestResponse { digest })
-        .map(cached::Return::new)
 }
 
 // Interacting with a remote OCI registry is time expensive, this can cause a massive slow down
@@ -112,37 +309,106 @@ pub(crate) async fn get_oci_digest_cached(
 // Details about this cache:
 //   * only the image "url" is used as key. oci::Client is not hashable, plus
 //     the client is always the same
-//   * the cache is time bound: cached values are purged after 60 seconds
-//   * only successful results are cached
-#[cached(
-    time = 60,
-    result = true,
-    sync_writes = "default",
-    key = "String",
-    convert = r#"{ format!("{}", img) }"#,
-    with_cached_flag = true
-)]
+//   * the cache is time bound, by default cached values are purged after 60 seconds.
+//     See `CacheConfig` for all the settings
+//   * by default only successful results are cached
 pub(crate) async fn get_oci_manifest_cached(
+    cache: &CapabilityCache<OciManifest>,
     oci_client: &Client,
     img: &str,
 ) -> Result<cached::Return<OciManifest>> {
-    oci_client.manifest(img).await.map(cached::Return::new)
+    cache
+        .get_or_insert_with(img, "", || oci_client.manifest(img))
+        .await
 }
 
-#[cached(
-    time = 60,
-    result = true,
-    sync_writes = "default",
-    key = "String",
-    convert = r#"{ format!("{}", img) }"#,
-    with_cached_flag = true
-)]
 pub(crate) async fn get_oci_manifest_and_config_cached(
+    cache: &CapabilityCache<ManifestAndConfigResponse>,
     oci_client: &Client,
     img: &str,
 ) -> Result<cached::Return<ManifestAndConfigResponse>> {
-    oci_client
-        .manifest_and_config(img)
+    cache
+        .get_or_insert_with(img, "", || oci_client.manifest_and_config(img))
+        .await
+}
+
+pub(crate) async fn get_oci_tags_cached(
+    cache: &CapabilityCache<OciListTagsResponse>,
+    oci_client: &Client,
+    img: &str,
+    page_size: Option<usize>,
+    last: Option<&str>,
+) -> Result<cached::Return<OciListTagsResponse>> {
+    let details = format!("{page_size:?}{last:?}");
+    cache
+        .get_or_insert_with(img, &details, || oci_client.list_tags(img, page_size, last))
         .await
-        .map(cached::Return::new)
+}
+
+pub(crate) async fn get_oci_referrers_cached(
+    cache: &CapabilityCache<OciImageIndex>,
+    oci_client: &Client,
+    img: &str,
+    artifact_type: Option<&str>,
+) -> Result<cached::Return<OciImageIndex>> {
+    cache
+        .get_or_insert_with(img, artifact_type.unwrap_or_default(), || {
+            oci_client.referrers(img, artifact_type)
+        })
+        .await
+}
+
+pub(crate) async fn get_oci_manifest_and_config_for_platform_cached(
+    cache: &CapabilityCache<ManifestAndConfigResponse>,
+    oci_client: &Client,
+    img: &str,
+    platform: &OciPlatform,
+) -> Result<cached::Return<ManifestAndConfigResponse>> {
+    cache
+        .get_or_insert_with(img, &platform.to_string(), || {
+            oci_client.manifest_and_config_for_platform(img, platform)
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    fn platform(os: &str, architecture: &str, variant: Option<&str>) -> OciPlatform {
+        OciPlatform {
+            os: os.to_owned(),
+            architecture: architecture.to_owned(),
+            variant: variant.map(|v| v.to_owned()),
+        }
+    }
+
+    #[rstest]
+    #[case::same_platform(platform("linux", "arm64", None), "linux", "arm64", None, true)]
+    #[case::any_variant(platform("linux", "arm64", None), "linux", "arm64", Some("v8"), true)]
+    #[case::same_variant(platform("linux", "arm", Some("v7")), "linux", "arm", Some("v7"), true)]
+    #[case::different_variant(
+        platform("linux", "arm", Some("v7")),
+        "linux",
+        "arm",
+        Some("v6"),
+        false
+    )]
+    #[case::missing_variant(platform("linux", "arm", Some("v7")), "linux", "arm", None, false)]
+    #[case::different_architecture(platform("linux", "arm64", None), "linux", "amd64", None, false)]
+    #[case::different_os(platform("linux", "amd64", None), "windows", "amd64", None, false)]
+    fn match_platform(
+        #[case] wanted: OciPlatform,
+        #[case] os: &str,
+        #[case] architecture: &str,
+        #[case] variant: Option<&str>,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(
+            platform_matches(&wanted, os, architecture, variant),
+            expected
+        );
+    }
 }
@@ -1,13 +1,20 @@
 use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
+mod attestation;
+mod constraints;
+
 use anyhow::{Result, anyhow};
-use cached::proc_macro::cached;
 use itertools::Itertools;
 use kubewarden_policy_sdk::host_capabilities::verification::{
     KeylessInfo, KeylessPrefixInfo, VerificationResponse,
 };
 use policy_fetcher::{
-    sigstore::{self, trust::sigstore::SigstoreTrustRoot},
+    oci_client::{self, Reference},
+    sigstore::{
+        self,
+        crypto::CosignVerificationKey,
+        trust::{TrustRoot, sigstore::SigstoreTrustRoot},
+    },
     sources::Sources,
     verify::{
         Verifier,
@@ -15,35 +22,65 @@ use policy_fetcher::{
         fetch_sigstore_remote_data,
     },
 };
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use sigstore::{
     cosign::verification_constraint::{
-        AnnotationVerifier, CertificateVerifier, VerificationConstraintVec,
+        AnnotationVerifier, CertificateVerifier, PublicKeyVerifier, VerificationConstraintVec,
     },
+    crypto::SigningScheme,
     registry::{Certificate, CertificateEncoding},
 };
 use tokio::sync::Mutex;
 use tracing::warn;
 
+use crate::callback_handler::cache::CapabilityCache;
+use crate::callback_handler::oci::{build_oci_client, registry_auth};
+use attestation::{AttestationSigner, DSSE_ENVELOPE_MEDIA_TYPE, TrustMaterial};
+use constraints::{SignatureConstraint, Signer};
+
+const GITHUB_ACTIONS_ISSUER: &str = "https://token.actions.githubusercontent.com";
+
 #[derive(Clone)]
 pub(crate) struct Client {
     cosign_client: Arc<Mutex<sigstore::cosign::Client>>,
     verifier: Verifier,
+    /// Used to fetch the attestations, which are not handled by the cosign client
+    oci_client: oci_client::Client,
+    trust_root: Option<Arc<SigstoreTrustRoot>>,
+    /// Fail closed when the signatures have not been recorded inside of the
+    /// transparency log
+    require_rekor_bundle: bool,
+}
+
+/// The result of the verification of the attestations of an image
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AttestationVerificationResponse {
+    /// The digest of the image
+    pub digest: String,
+    pub is_trusted: bool,
+    /// The predicates of all the verified attestations
+    pub predicates: Vec<serde_json::Value>,
 }
 
 impl Client {
     pub async fn new(
         sources: Option<Sources>,
         trust_root: Option<Arc<SigstoreTrustRoot>>,
+        require_rekor_bundle: bool,
     ) -> Result<Self> {
         let cosign_client = Arc::new(Mutex::new(
-            Self::build_cosign_client(sources.clone(), trust_root).await?,
+            Self::build_cosign_client(sources.clone(), trust_root.clone()).await?,
         ));
+        let oci_client = build_oci_client(sources.as_ref());
         let verifier = Verifier::new_from_cosign_client(cosign_client.clone(), sources);
 
         Ok(Client {
             cosign_client,
             verifier,
+            oci_client,
+            trust_root,
+            require_rekor_bundle,
         })
     }
 
@@ -86,6 +123,18 @@ impl Client {
         if pub_keys.is_empty() {
             return Err(anyhow!("Must provide at least one pub key"));
         }
+        if self.require_rekor_bundle {
+            let signers = pub_keys
+                .iter()
+                .map(|k| {
+                    PublicKeyVerifier::new(k.as_bytes(), &SigningScheme::default())
+                        .map(Signer::PubKey)
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            return self
+                .verify_signers_with_rekor_bundle(&image, signers, annotations)
+                .await;
+        }
         let mut signatures_all_of: Vec<Signature> = Vec::new();
         for k in pub_keys.iter() {
             let signature = Signature::PubKey {
@@ -119,6 +168,18 @@ impl Client {
         if keyless.is_empty() {
             return Err(anyhow!("Must provide keyless info"));
         }
+        if self.require_rekor_bundle {
+            let signers = keyless
+                .into_iter()
+                .map(|k| Signer::Keyless {
+                    issuer: k.issuer,
+                    subject: Subject::Equal(k.subject),
+                })
+                .collect();
+            return self
+                .verify_signers_with_rekor_bundle(&image, signers, annotations)
+                .await;
+        }
         // Build interim VerificationConfig:
         //
         let mut signatures_all_of: Vec<Signature> = Vec::new();
@@ -154,6 +215,20 @@ impl Client {
         if keyless_prefix.is_empty() {
             return Err(anyhow!("Must provide keyless info"));
         }
+        if self.require_rekor_bundle {
+            let signers = keyless_prefix
+                .into_iter()
+                .map(|k| {
+                    url::Url::parse(&k.url_prefix).map(|prefix| Signer::Keyless {
+                        issuer: k.issuer,
+                        subject: Subject::UrlPrefix(prefix),
+                    })
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            return self
+                .verify_signers_with_rekor_bundle(&image, signers, annotations)
+                .await;
+        }
         // Build interim VerificationConfig:
         //
         let mut signatures_all_of: Vec<Signature> = Vec::new();
@@ -191,6 +266,19 @@ impl Client {
         if owner.is_empty() {
             return Err(anyhow!("Must provide owner info"));
         }
+        if self.require_rekor_bundle {
+            let prefix = match &repo {
+                Some(repo) => format!("https://github.com/{owner}/{repo}/"),
+                None => format!("https://github.com/{owner}/"),
+            };
+            let signers = vec![Signer::Keyless {
+                issuer: GITHUB_ACTIONS_ISSUER.to_owned(),
+                subject: Subject::UrlPrefix(url::Url::parse(&prefix)?),
+            }];
+            return self
+                .verify_signers_with_rekor_bundle(&image, signers, annotations)
+                .await;
+        }
         // Build interim VerificationConfig:
         //
         let mut signatures_all_of: Vec<Signature> = Vec::new();
@@ -235,8 +323,11 @@ impl Client {
                 .collect()
         });
 
-        let cert_verifier =
-            CertificateVerifier::from_pem(certificate, require_rekor_bundle, chain.as_deref())?;
+        let cert_verifier = CertificateVerifier::from_pem(
+            certificate,
+            require_rekor_bundle || self.require_rekor_bundle,
+            chain.as_deref(),
+        )?;
 
         let mut verification_constraints: VerificationConstraintVec = vec![Box::new(cert_verifier)];
         if let Some(a) = annotations {
@@ -256,6 +347,130 @@ impl Client {
             Err(e) => Err(e),
         }
     }
+
+    /// Verify the in-toto attestations attached by cosign to the image. The
+    /// attestations must have the given predicate type, and must be signed by
+    /// at least one of the given public keys or keyless identities.
+    ///
+    /// The predicates of all the verified attestations are returned.
+    pub async fn verify_attestation(
+        &mut self,
+        image: &str,
+        predicate_type: &str,
+        pub_keys: Vec<String>,
+        keyless: Vec<KeylessInfo>,
+        keyless_prefix: Vec<KeylessPrefixInfo>,
+    ) -> Result<AttestationVerificationResponse> {
+        let mut signers = Vec::new();
+        for key in pub_keys {
+            signers.push(AttestationSigner::PubKey(
+                CosignVerificationKey::try_from_pem(key.as_bytes())?,
+            ));
+        }
+        for k in keyless {
+            signers.push(AttestationSigner::Keyless {
+                issuer: k.issuer,
+                subject: Subject::Equal(k.subject),
+            });
+        }
+        for k in keyless_prefix {
+            signers.push(AttestationSigner::Keyless {
+                issuer: k.issuer,
+                subject: Subject::UrlPrefix(url::Url::parse(&k.url_prefix)?),
+            });
+        }
+        if signers.is_empty() {
+            return Err(anyhow!("Must provide at least one pub key or keyless info"));
+        }
+
+        let image_ref: Reference = image.parse()?;
+        let auth = registry_auth(&image_ref).await;
+        let digest = self
+            .oci_client
+            .fetch_manifest_digest(&image_ref, &auth)
+            .await?;
+
+        // cosign stores the attestations using a tag derived from the digest of the image
+        let attestations_ref = Reference::with_tag(
+            image_ref.registry().to_owned(),
+            image_ref.repository().to_owned(),
+            format!("{}.att", digest.replace(':', "-")),
+        );
+        let attestations = self
+            .oci_client
+            .pull(&attestations_ref, &auth, vec![DSSE_ENVELOPE_MEDIA_TYPE])
+            .await
+            .map_err(|e| anyhow!("cannot fetch the attestations of {image}: {e}"))?;
+
+        let predicates = attestation::verify_attestations(
+            &attestations.layers,
+            &digest,
+            predicate_type,
+            &signers,
+            &self.trust_material()?,
+            self.require_rekor_bundle,
+        )?;
+
+        Ok(AttestationVerificationResponse {
+            digest,
+            is_trusted: true,
+            predicates,
+        })
+    }
+
+    /// Verify the image is signed by all the signers. Each signature must carry
+    /// the given annotations and must have been recorded inside of the
+    /// transparency log
+    async fn verify_signers_with_rekor_bundle(
+        &mut self,
+        image: &str,
+        signers: Vec<Signer>,
+        annotations: Option<BTreeMap<String, String>>,
+    ) -> Result<VerificationResponse> {
+        let (source_image_digest, trusted_layers) =
+            fetch_sigstore_remote_data(&self.cosign_client, image).await?;
+
+        let verification_constraints: VerificationConstraintVec = signers
+            .into_iter()
+            .map(|signer| {
+                Box::new(SignatureConstraint {
+                    signer,
+                    annotations: annotations.clone(),
+                    require_rekor_bundle: true,
+                }) as _
+            })
+            .collect();
+
+        sigstore::cosign::verify_constraints(&trusted_layers, verification_constraints.iter())
+            .map_err(|e| anyhow!("verification failed: {}", e))?;
+
+        Ok(VerificationResponse {
+            digest: source_image_digest,
+            is_trusted: true,
+        })
+    }
+
+    fn trust_material(&self) -> Result<TrustMaterial> {
+        let Some(trust_root) = &self.trust_root else {
+            return Ok(TrustMaterial::default());
+        };
+
+        let fulcio_certs = trust_root
+            .fulcio_certs()?
+            .into_iter()
+            .map(|cert| cert.into_owned())
+            .collect();
+        let rekor_keys = trust_root
+            .rekor_keys()?
+            .into_iter()
+            .map(CosignVerificationKey::try_from_der)
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(TrustMaterial {
+            fulcio_certs,
+            rekor_keys,
+        })
+    }
 }
 
 // Sigstore verifications are time expensive, this can cause a massive slow down
@@ -263,26 +478,25 @@ impl Client {
 // Because of that we will keep a cache of the digests results.
 //
 // Details about this cache:
-//   * the cache is time bound: cached values are purged after 60 seconds
-//   * only successful results are cached
-#[cached(
-    time = 60,
-    result = true,
-    sync_writes = "default",
-    key = "String",
-    convert = r#"{ format!("{}{:?}{:?}", image, pub_keys, annotations)}"#,
-    with_cached_flag = true
-)]
+//   * the image is used as subject of the cache entries, all the results about
+//     an image can be invalidated at once
+//   * the cache is time bound, by default cached values are purged after 60 seconds.
+//     See `CacheConfig` for all the settings
+//   * by default only successful results are cached
 pub(crate) async fn get_sigstore_pub_key_verification_cached(
+    cache: &CapabilityCache<VerificationResponse>,
     client: &mut Client,
     image: String,
     pub_keys: Vec<String>,
     annotations: Option<BTreeMap<String, String>>,
 ) -> Result<cached::Return<VerificationResponse>> {
-    client
-        .verify_public_key(image, pub_keys, annotations)
+    let subject = image.clone();
+    let details = format!("pub_key{pub_keys:?}{annotations:?}");
+    cache
+        .get_or_insert_with(&subject, &details, || {
+            client.verify_public_key(image, pub_keys, annotations)
+        })
         .await
-        .map(cached::Return::new)
 }
 
 // Sigstore verifications are time expensive, this can cause a massive slow down
@@ -290,26 +504,25 @@ pub(crate) async fn get_sigstore_pub_key_verification_cached(
 // Because of that we will keep a cache of the digests results.
 //
 // Details about this cache:
-//   * the cache is time bound: cached values are purged after 60 seconds
-//   * only successful results are cached
-#[cached(
-    time = 60,
-    result = true,
-    sync_writes = "default",
-    key = "String",
-    convert = r#"{ format!("{}{:?}{:?}", image, keyless, annotations)}"#,
-    with_cached_flag = true
-)]
+//   * the image is used as subject of the cache entries, all the results about
+//     an image can be invalidated at once
+//   * the cache is time bound, by default cached values are purged after 60 seconds.
+//     See `CacheConfig` for all the settings
+//   * by default only successful results are cached
 pub(crate) async fn get_sigstore_keyless_verification_cached(
+    cache: &CapabilityCache<VerificationResponse>,
     client: &mut Client,
     image: String,
     keyless: Vec<KeylessInfo>,
     annotations: Option<BTreeMap<String, String>>,
 ) -> Result<cached::Return<VerificationResponse>> {
-    client
-        .verify_keyless(image, keyless, annotations)
+    let subject = image.clone();
+    let details = format!("keyless{keyless:?}{annotations:?}");
+    cache
+        .get_or_insert_with(&subject, &details, || {
+            client.verify_keyless(image, keyless, annotations)
+        })
         .await
-        .map(cached::Return::new)
 }
 
 // Sigstore verifications are time expensive, this can cause a massive slow down
@@ -317,26 +530,25 @@ pub(crate) async fn get_sigstore_keyless_verification_cached(
 // Because of that we will keep a cache of the digests results.
 //
 // Details about this cache:
-//   * the cache is time bound: cached values are purged after 60 seconds
-//   * only successful results are cached
-#[cached(
-    time = 60,
-    result = true,
-    sync_writes = "default",
-    key = "String",
-    convert = r#"{ format!("{}{:?}{:?}", image, keyless_prefix, annotations)}"#,
-    with_cached_flag = true
-)]
+//   * the image is used as subject of the cache entries, all the results about
+//     an image can be invalidated at once
+//   * the cache is time bound, by default cached values are purged after 60 seconds.
+//     See `CacheConfig` for all the settings
+//   * by default only successful results are cached
 pub(crate) async fn get_sigstore_keyless_prefix_verification_cached(
+    cache: &CapabilityCache<VerificationResponse>,
     client: &mut Client,
     image: String,
     keyless_prefix: Vec<KeylessPrefixInfo>,
     annotations: Option<BTreeMap<String, String>>,
 ) -> Result<cached::Return<VerificationResponse>> {
-    client
-        .verify_keyless_prefix(image, keyless_prefix, annotations)
+    let subject = image.clone();
+    let details = format!("keyless_prefix{keyless_prefix:?}{annotations:?}");
+    cache
+        .get_or_insert_with(&subject, &details, || {
+            client.verify_keyless_prefix(image, keyless_prefix, annotations)
+        })
         .await
-        .map(cached::Return::new)
 }
 
 // Sigstore verifications are time expensive, this can cause a massive slow down
@@ -344,27 +556,26 @@ pub(crate) async fn get_sigstore_keyless_prefix_verification_cached(
 // Because of that we will keep a cache of the digests results.
 //
 // Details about this cache:
-//   * the cache is time bound: cached values are purged after 60 seconds
-//   * only successful results are cached
-#[cached(
-    time = 60,
-    result = true,
-    sync_writes = "default",
-    key = "String",
-    convert = r#"{ format!("{}{:?}{:?}{:?}", image, owner, repo, annotations)}"#,
-    with_cached_flag = true
-)]
+//   * the image is used as subject of the cache entries, all the results about
+//     an image can be invalidated at once
+//   * the cache is time bound, by default cached values are purged after 60 seconds.
+//     See `CacheConfig` for all the settings
+//   * by default only successful results are cached
 pub(crate) async fn get_sigstore_github_actions_verification_cached(
+    cache: &CapabilityCache<VerificationResponse>,
     client: &mut Client,
     image: String,
     owner: String,
     repo: Option<String>,
     annotations: Option<BTreeMap<String, String>>,
 ) -> Result<cached::Return<VerificationResponse>> {
-    client
-        .verify_github_actions(image, owner, repo, annotations)
+    let subject = image.clone();
+    let details = format!("github_actions{owner:?}{repo:?}{annotations:?}");
+    cache
+        .get_or_insert_with(&subject, &details, || {
+            client.verify_github_actions(image, owner, repo, annotations)
+        })
         .await
-        .map(cached::Return::new)
 }
 
 fn get_sigstore_certificate_verification_cache_key(
@@ -402,15 +613,8 @@ fn get_sigstore_certificate_verification_cache_key(
     format!("{:x}", hasher.finalize())
 }
 
-#[cached(
-    time = 60,
-    result = true,
-    sync_writes = "default",
-    key = "String",
-    convert = r#"{ format!("{}", get_sigstore_certificate_verification_cache_key(image, certificate, certificate_chain, require_rekor_bundle, annotations.as_ref()))}"#,
-    with_cached_flag = true
-)]
 pub(crate) async fn get_sigstore_certificate_verification_cached(
+    cache: &CapabilityCache<VerificationResponse>,
     client: &mut Client,
     image: &str,
     certificate: &[u8],
@@ -418,14 +622,42 @@ pub(crate) async fn get_sigstore_certificate_verification_cached(
     require_rekor_bundle: bool,
     annotations: Option<BTreeMap<String, String>>,
 ) -> Result<cached::Return<VerificationResponse>> {
-    client
-        .verify_certificate(
+    let details = format!(
+        "certificate{}",
+        get_sigstore_certificate_verification_cache_key(
             image,
             certificate,
             certificate_chain,
             require_rekor_bundle,
-            annotations,
+            annotations.as_ref(),
         )
+    );
+    cache
+        .get_or_insert_with(image, &details, || {
+            client.verify_certificate(
+                image,
+                certificate,
+                certificate_chain,
+                require_rekor_bundle,
+                annotations,
+            )
+        })
+        .await
+}
+
+pub(crate) async fn get_sigstore_attestation_verification_cached(
+    cache: &CapabilityCache<AttestationVerificationResponse>,
+    client: &mut Client,
+    image: String,
+    predicate_type: String,
+    pub_keys: Vec<String>,
+    keyless: Vec<KeylessInfo>,
+    keyless_prefix: Vec<KeylessPrefixInfo>,
+) -> Result<cached::Return<AttestationVerificationResponse>> {
+    let details = format!("attestation{predicate_type}{pub_keys:?}{keyless:?}{keyless_prefix:?}");
+    cache
+        .get_or_insert_with(&image, &details, || {
+            client.verify_attestation(&image, &predicate_type, pub_keys, keyless, keyless_prefix)
+        })
         .await
-        .map(cached::Return::new)
 }
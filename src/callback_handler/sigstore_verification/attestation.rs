@@ -0,0 +1,816 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use pki_types::{CertificateDer, UnixTime, pem::PemObject};
+use policy_fetcher::{
+    oci_client::client::ImageLayer,
+    sigstore::crypto::{CosignVerificationKey, Signature},
+    verify::config::Subject,
+};
+use serde::{Deserialize, Serialize};
+use x509_cert::{
+    Certificate,
+    der::{Decode, Encode, asn1::Utf8StringRef},
+    ext::pkix::{SubjectAltName, name::GeneralName},
+};
+
+use crate::callback_handler::crypto::verify_certificate_issued_by;
+
+/// Media type of the layers holding DSSE envelopes
+pub(crate) const DSSE_ENVELOPE_MEDIA_TYPE: &str = "application/vnd.dsse.envelope.v1+json";
+const IN_TOTO_PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+
+const CERTIFICATE_ANNOTATION: &str = "dev.sigstore.cosign/certificate";
+const BUNDLE_ANNOTATION: &str = "dev.sigstore.cosign/bundle";
+
+const FULCIO_ISSUER_V1_OID: &str = "1.3.6.1.4.1.57264.1.1";
+const FULCIO_ISSUER_V2_OID: &str = "1.3.6.1.4.1.57264.1.8";
+const SUBJECT_ALT_NAME_OID: &str = "2.5.29.17";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DsseEnvelope {
+    payload_type: String,
+    payload: String,
+    signatures: Vec<DsseSignature>,
+}
+
+#[derive(Deserialize)]
+struct DsseSignature {
+    sig: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InTotoStatement {
+    subject: Vec<InTotoSubject>,
+    predicate_type: String,
+    #[serde(default)]
+    predicate: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct InTotoSubject {
+    digest: BTreeMap<String, String>,
+}
+
+/// The Rekor bundle attached by cosign to the signatures
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RekorBundle {
+    signed_entry_timestamp: String,
+    payload: RekorBundlePayload,
+}
+
+/// The fields are declared in lexicographic order: the serialized payload is
+/// the canonical JSON signed by Rekor
+#[derive(Serialize, Deserialize)]
+struct RekorBundlePayload {
+    body: String,
+    #[serde(rename = "integratedTime")]
+    integrated_time: i64,
+    #[serde(rename = "logID")]
+    log_id: String,
+    #[serde(rename = "logIndex")]
+    log_index: i64,
+}
+
+/// The entry recorded by Rekor, as found inside of the body of the bundle.
+/// Only the fields binding the entry to a signature are described
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum RekorEntry {
+    /// Created by cosign when uploading attestations
+    Intoto {
+        spec: RekorIntotoSpec,
+    },
+    Dsse {
+        spec: RekorDsseSpec,
+    },
+}
+
+#[derive(Deserialize)]
+struct RekorIntotoSpec {
+    content: RekorIntotoContent,
+}
+
+#[derive(Deserialize)]
+struct RekorIntotoContent {
+    envelope: RekorIntotoEnvelope,
+}
+
+#[derive(Deserialize)]
+struct RekorIntotoEnvelope {
+    signatures: Vec<RekorIntotoSignature>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RekorIntotoSignature {
+    sig: String,
+    public_key: String,
+}
+
+#[derive(Deserialize)]
+struct RekorDsseSpec {
+    signatures: Vec<RekorDsseSignature>,
+}
+
+#[derive(Deserialize)]
+struct RekorDsseSignature {
+    signature: String,
+    verifier: String,
+}
+
+impl RekorEntry {
+    /// The signatures recorded by the entry, each one with the PEM encoded
+    /// certificate, or public key, that verifies it
+    fn signatures(&self) -> Result<Vec<(&str, Vec<u8>)>> {
+        match self {
+            RekorEntry::Intoto { spec } => spec
+                .content
+                .envelope
+                .signatures
+                .iter()
+                .map(|s| Ok((s.sig.as_str(), BASE64.decode(&s.public_key)?)))
+                .collect(),
+            RekorEntry::Dsse { spec } => spec
+                .signatures
+                .iter()
+                .map(|s| Ok((s.signature.as_str(), BASE64.decode(&s.verifier)?)))
+                .collect(),
+        }
+    }
+}
+
+/// Returns true when the signature recorded by Rekor is the given signature
+/// of a DSSE envelope. Depending on the kind of the entry, Rekor encodes the
+/// signature once more with base64
+fn is_same_signature(recorded: &str, envelope_signature: &str) -> bool {
+    if recorded == envelope_signature {
+        return true;
+    }
+    let Ok(signature) = BASE64.decode(envelope_signature) else {
+        return false;
+    };
+    match BASE64.decode(recorded) {
+        Ok(decoded) => {
+            decoded == signature
+                || decoded == envelope_signature.as_bytes()
+                || BASE64
+                    .decode(&decoded)
+                    .is_ok_and(|decoded| decoded == signature)
+        }
+        Err(_) => false,
+    }
+}
+
+/// Who is trusted to sign the attestations
+pub(crate) enum AttestationSigner {
+    /// The owner of a private key
+    PubKey(CosignVerificationKey),
+    /// The owner of a short lived certificate issued by Fulcio
+    Keyless { issuer: String, subject: Subject },
+}
+
+/// The trust material used to verify keyless attestations
+#[derive(Default)]
+pub(crate) struct TrustMaterial {
+    /// The certificates of the Fulcio instances
+    pub fulcio_certs: Vec<CertificateDer<'static>>,
+    /// The public keys of the Rekor instances
+    pub rekor_keys: Vec<CosignVerificationKey>,
+}
+
+/// Pre-Authentication Encoding, this is what is actually signed inside of a
+/// DSSE envelope
+fn pae(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut encoded = format!(
+        "DSSEv1 {} {} {} ",
+        payload_type.len(),
+        payload_type,
+        payload.len()
+    )
+    .into_bytes();
+    encoded.extend_from_slice(payload);
+    encoded
+}
+
+/// Verify the attestations found inside of the given layers, and return the
+/// predicates of the ones that:
+///   * are about the image with the given digest
+///   * have the given predicate type
+///   * are signed by one of the given signers
+///
+/// Keyless attestations must always be recorded inside of the transparency log.
+/// When `require_rekor_bundle` is set, this applies to the attestations signed
+/// with a public key too.
+///
+/// The layers that cannot be parsed, or verified, are skipped. Their errors are
+/// reported only when no attestation can be trusted
+pub(crate) fn verify_attestations(
+    layers: &[ImageLayer],
+    image_digest: &str,
+    predicate_type: &str,
+    signers: &[AttestationSigner],
+    trust_material: &TrustMaterial,
+    require_rekor_bundle: bool,
+) -> Result<Vec<serde_json::Value>> {
+    let (digest_algorithm, digest_value) = image_digest
+        .split_once(':')
+        .ok_or_else(|| anyhow!("invalid image digest: {image_digest}"))?;
+
+    let mut predicates = Vec::new();
+    let mut errors = vec![];
+    for (index, layer) in layers
+        .iter()
+        .enumerate()
+        .filter(|(_, layer)| layer.media_type == DSSE_ENVELOPE_MEDIA_TYPE)
+    {
+        match verify_attestation(
+            layer,
+            (digest_algorithm, digest_value),
+            predicate_type,
+            signers,
+            trust_material,
+            require_rekor_bundle,
+        ) {
+            Ok(Some(predicate)) => predicates.push(predicate),
+            Ok(None) => {}
+            Err(e) => errors.push(format!("layer {index}: {e}")),
+        }
+    }
+
+    if predicates.is_empty() {
+        let mut message = format!(
+            "no attestation with predicate type {predicate_type} signed by the trusted signers"
+        );
+        if !errors.is_empty() {
+            message = format!("{message}: {}", errors.join(", "));
+        }
+        return Err(anyhow!(message));
+    }
+    Ok(predicates)
+}
+
+/// Verify the attestation found inside of the layer. Returns its predicate when
+/// it's about the image, has the given predicate type and is signed by one of
+/// the given signers
+fn verify_attestation(
+    layer: &ImageLayer,
+    (digest_algorithm, digest_value): (&str, &str),
+    predicate_type: &str,
+    signers: &[AttestationSigner],
+    trust_material: &TrustMaterial,
+    require_rekor_bundle: bool,
+) -> Result<Option<serde_json::Value>> {
+    let envelope: DsseEnvelope = serde_json::from_slice(&layer.data)?;
+    if envelope.payload_type != IN_TOTO_PAYLOAD_TYPE {
+        return Ok(None);
+    }
+    let payload = BASE64.decode(&envelope.payload)?;
+
+    let statement: InTotoStatement = serde_json::from_slice(&payload)?;
+    if statement.predicate_type != predicate_type {
+        return Ok(None);
+    }
+    let about_image = statement.subject.iter().any(|subject| {
+        subject
+            .digest
+            .get(digest_algorithm)
+            .is_some_and(|digest| digest == digest_value)
+    });
+    if !about_image {
+        return Ok(None);
+    }
+
+    let message = pae(&envelope.payload_type, &payload);
+    let signed = is_signed_by_any(
+        &envelope,
+        &message,
+        layer.annotations.as_ref(),
+        signers,
+        trust_material,
+        require_rekor_bundle,
+    )?;
+    Ok(signed.then_some(statement.predicate))
+}
+
+fn is_signed_by_any(
+    envelope: &DsseEnvelope,
+    message: &[u8],
+    annotations: Option<&BTreeMap<String, String>>,
+    signers: &[AttestationSigner],
+    trust_material: &TrustMaterial,
+    require_rekor_bundle: bool,
+) -> Result<bool> {
+    let signed_by = |key: &CosignVerificationKey| {
+        envelope.signatures.iter().find(|signature| {
+            key.verify_signature(Signature::Base64Encoded(signature.sig.as_bytes()), message)
+                .is_ok()
+        })
+    };
+
+    let signed_by_pub_key = signers.iter().any(|signer| match signer {
+        AttestationSigner::PubKey(key) => signed_by(key).is_some_and(|signature| {
+            !require_rekor_bundle
+                || verify_rekor_bundle(annotations, trust_material, signature, None).is_ok()
+        }),
+        AttestationSigner::Keyless { .. } => false,
+    });
+    if signed_by_pub_key {
+        return Ok(true);
+    }
+
+    let keyless_signers: Vec<(&String, &Subject)> = signers
+        .iter()
+        .filter_map(|signer| match signer {
+            AttestationSigner::Keyless { issuer, subject } => Some((issuer, subject)),
+            AttestationSigner::PubKey(_) => None,
+        })
+        .collect();
+    if keyless_signers.is_empty() {
+        return Ok(false);
+    }
+    let Some(certificate_pem) = annotations.and_then(|a| a.get(CERTIFICATE_ANNOTATION)) else {
+        return Ok(false);
+    };
+
+    let certificate_der = CertificateDer::from_pem_slice(certificate_pem.as_bytes())
+        .map_err(|e| anyhow!("invalid signing certificate: {e}"))?;
+    let (key, issuer, identities) = certificate_identity(&certificate_der)?;
+    let Some(signature) = signed_by(&key) else {
+        return Ok(false);
+    };
+
+    // Fulcio certificates are short lived: they must be checked at the time the
+    // signature was recorded inside of the transparency log
+    let integrated_time = verify_rekor_bundle(
+        annotations,
+        trust_material,
+        signature,
+        Some(&certificate_der),
+    )?;
+    verify_certificate_issued_by(
+        &certificate_der,
+        &trust_material.fulcio_certs,
+        UnixTime::since_unix_epoch(std::time::Duration::from_secs(integrated_time)),
+    )?;
+
+    let identity_trusted = keyless_signers
+        .iter()
+        .any(|(wanted_issuer, wanted_subject)| {
+            issuer.as_ref() == Some(*wanted_issuer)
+                && identities
+                    .iter()
+                    .any(|identity| subject_matches(wanted_subject, identity))
+        });
+
+    Ok(identity_trusted)
+}
+
+/// Verify the signed entry timestamp of the Rekor bundle, returns the time at
+/// which the entry has been integrated inside of the transparency log.
+///
+/// The entry must record the given signature of the envelope and, when
+/// provided, the certificate used to create it. Otherwise the bundle could
+/// belong to any other entry of the transparency log
+fn verify_rekor_bundle(
+    annotations: Option<&BTreeMap<String, String>>,
+    trust_material: &TrustMaterial,
+    signature: &DsseSignature,
+    certificate_der: Option<&CertificateDer>,
+) -> Result<u64> {
+    let bundle = annotations
+        .and_then(|a| a.get(BUNDLE_ANNOTATION))
+        .ok_or_else(|| anyhow!("keyless attestation without Rekor bundle"))?;
+    let bundle: RekorBundle = serde_json::from_str(bundle)?;
+
+    let signed_payload = serde_json::to_vec(&bundle.payload)?;
+    let verified = trust_material.rekor_keys.iter().any(|key| {
+        key.verify_signature(
+            Signature::Base64Encoded(bundle.signed_entry_timestamp.as_bytes()),
+            &signed_payload,
+        )
+        .is_ok()
+    });
+    if !verified {
+        return Err(anyhow!(
+            "the Rekor bundle is not signed by any of the trusted Rekor instances"
+        ));
+    }
+
+    let entry: RekorEntry = serde_json::from_slice(&BASE64.decode(&bundle.payload.body)?)
+        .map_err(|e| anyhow!("unsupported Rekor entry: {e}"))?;
+    let bound_to_signature = entry.signatures()?.iter().any(|(recorded, verifier_pem)| {
+        is_same_signature(recorded, &signature.sig)
+            && certificate_der.is_none_or(|certificate_der| {
+                CertificateDer::from_pem_slice(verifier_pem)
+                    .is_ok_and(|recorded| recorded.as_ref() == certificate_der.as_ref())
+            })
+    });
+    if !bound_to_signature {
+        return Err(anyhow!(
+            "the Rekor bundle is about a different signature, or signing certificate"
+        ));
+    }
+
+    u64::try_from(bundle.payload.integrated_time)
+        .map_err(|_| anyhow!("invalid Rekor integrated time"))
+}
+
+/// Returns the public key of the certificate, the OIDC issuer and the identities
+/// of its owner
+fn certificate_identity(
+    certificate_der: &CertificateDer,
+) -> Result<(CosignVerificationKey, Option<String>, Vec<String>)> {
+    let certificate = Certificate::from_der(certificate_der)?;
+    let key = CosignVerificationKey::try_from_der(
+        &certificate
+            .tbs_certificate
+            .subject_public_key_info
+            .to_der()?,
+    )?;
+
+    let mut issuer = None;
+    let mut identities = Vec::new();
+    for extension in certificate.tbs_certificate.extensions.iter().flatten() {
+        match extension.extn_id.to_string().as_str() {
+            FULCIO_ISSUER_V1_OID => {
+                issuer = Some(String::from_utf8(extension.extn_value.as_bytes().to_vec())?);
+            }
+            FULCIO_ISSUER_V2_OID => {
+                issuer =
+                    Some(Utf8StringRef::from_der(extension.extn_value.as_bytes())?.to_string());
+            }
+            SUBJECT_ALT_NAME_OID => {
+                let san = SubjectAltName::from_der(extension.extn_value.as_bytes())?;
+                for name in san.0 {
+                    match name {
+                        GeneralName::Rfc822Name(email) => identities.push(email.to_string()),
+                        GeneralName::UniformResourceIdentifier(uri) => {
+                            identities.push(uri.to_string())
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok((key, issuer, identities))
+}
+
+pub(super) fn subject_matches(wanted: &Subject, identity: &str) -> bool {
+    match wanted {
+        Subject::Equal(subject) => subject == identity,
+        Subject::UrlPrefix(prefix) => {
+            // `https://github.com/kubewarden` must not match the identities
+            // of `https://github.com/kubewarden-fake`
+            let prefix = prefix.as_str();
+            if prefix.ends_with('/') {
+                identity.starts_with(prefix)
+            } else {
+                identity.starts_with(&format!("{prefix}/"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use policy_fetcher::sigstore::crypto::SigningScheme;
+    use rstest::rstest;
+
+    const DIGEST: &str = "sha256:0000000000000000000000000000000000000000000000000000000000000001";
+    const SLSA_PROVENANCE: &str = "https://slsa.dev/provenance/v1";
+
+    fn attestation_layer(
+        signer: &policy_fetcher::sigstore::crypto::signing_key::SigStoreSigner,
+        digest: &str,
+        predicate_type: &str,
+    ) -> ImageLayer {
+        let (algorithm, value) = digest.split_once(':').unwrap();
+        let statement = serde_json::json!({
+            "_type": "https://in-toto.io/Statement/v1",
+            "subject": [{"name": "busybox", "digest": {algorithm: value}}],
+            "predicateType": predicate_type,
+            "predicate": {"builder": {"id": "https://github.com/actions/runner"}},
+        });
+        let payload = serde_json::to_vec(&statement).unwrap();
+        let signature = signer.sign(&pae(IN_TOTO_PAYLOAD_TYPE, &payload)).unwrap();
+        let envelope = serde_json::json!({
+            "payloadType": IN_TOTO_PAYLOAD_TYPE,
+            "payload": BASE64.encode(&payload),
+            "signatures": [{"keyid": "", "sig": BASE64.encode(signature)}],
+        });
+
+        ImageLayer::new(
+            serde_json::to_vec(&envelope).unwrap().into(),
+            DSSE_ENVELOPE_MEDIA_TYPE.to_owned(),
+            None,
+        )
+    }
+
+    fn signer_and_key() -> (
+        policy_fetcher::sigstore::crypto::signing_key::SigStoreSigner,
+        CosignVerificationKey,
+    ) {
+        let signer = SigningScheme::default().create_signer().unwrap();
+        let pem = signer
+            .to_sigstore_keypair()
+            .unwrap()
+            .public_key_to_pem()
+            .unwrap();
+        let key = CosignVerificationKey::try_from_pem(pem.as_bytes()).unwrap();
+        (signer, key)
+    }
+
+    #[test]
+    fn pre_authentication_encoding() {
+        assert_eq!(
+            pae("http://example.com/HelloWorld", b"hello world"),
+            b"DSSEv1 29 http://example.com/HelloWorld 11 hello world".to_vec()
+        );
+    }
+
+    #[rstest]
+    #[case::valid_attestation(DIGEST, SLSA_PROVENANCE, true, true)]
+    #[case::different_predicate_type(
+        DIGEST,
+        "https://cosign.sigstore.dev/attestation/vuln/v1",
+        true,
+        false
+    )]
+    #[case::different_image(
+        "sha256:0000000000000000000000000000000000000000000000000000000000000002",
+        SLSA_PROVENANCE,
+        true,
+        false
+    )]
+    #[case::untrusted_signer(DIGEST, SLSA_PROVENANCE, false, false)]
+    fn verify_pub_key_attestations(
+        #[case] attestation_digest: &str,
+        #[case] attestation_predicate_type: &str,
+        #[case] trusted_signer: bool,
+        #[case] valid: bool,
+    ) {
+        let (signer, key) = signer_and_key();
+        let (_, other_key) = signer_and_key();
+        let layer = attestation_layer(&signer, attestation_digest, attestation_predicate_type);
+        let signers = vec![AttestationSigner::PubKey(if trusted_signer {
+            key
+        } else {
+            other_key
+        })];
+
+        let result = verify_attestations(
+            &[layer],
+            DIGEST,
+            SLSA_PROVENANCE,
+            &signers,
+            &TrustMaterial::default(),
+            false,
+        );
+
+        assert_eq!(result.is_ok(), valid, "{result:?}");
+        if valid {
+            assert_eq!(
+                result.unwrap(),
+                vec![serde_json::json!({"builder": {"id": "https://github.com/actions/runner"}})]
+            );
+        }
+    }
+
+    #[test]
+    fn require_rekor_bundle() {
+        let (signer, key) = signer_and_key();
+        let layer = attestation_layer(&signer, DIGEST, SLSA_PROVENANCE);
+
+        let result = verify_attestations(
+            &[layer],
+            DIGEST,
+            SLSA_PROVENANCE,
+            &[AttestationSigner::PubKey(key)],
+            &TrustMaterial::default(),
+            true,
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn skip_unparsable_layers() {
+        let (signer, key) = signer_and_key();
+        let invalid_layer = || {
+            ImageLayer::new(
+                b"not a DSSE envelope".to_vec().into(),
+                DSSE_ENVELOPE_MEDIA_TYPE.to_owned(),
+                None,
+            )
+        };
+        let layer = attestation_layer(&signer, DIGEST, SLSA_PROVENANCE);
+        let signers = [AttestationSigner::PubKey(key)];
+
+        let predicates = verify_attestations(
+            &[invalid_layer(), layer],
+            DIGEST,
+            SLSA_PROVENANCE,
+            &signers,
+            &TrustMaterial::default(),
+            false,
+        )
+        .expect("the valid attestation should be trusted");
+        assert_eq!(predicates.len(), 1);
+
+        let err = verify_attestations(
+            &[invalid_layer()],
+            DIGEST,
+            SLSA_PROVENANCE,
+            &signers,
+            &TrustMaterial::default(),
+            false,
+        )
+        .expect_err("no attestation can be trusted");
+        assert!(err.to_string().contains("layer 0"), "{err}");
+    }
+
+    const CERTIFICATE_PEM: &str = "-----BEGIN CERTIFICATE-----\nAQID\n-----END CERTIFICATE-----\n";
+    const OTHER_CERTIFICATE_PEM: &str =
+        "-----BEGIN CERTIFICATE-----\nBAUG\n-----END CERTIFICATE-----\n";
+    const INTEGRATED_TIME: i64 = 1_700_000_000;
+
+    /// Build the annotations holding a Rekor bundle, signed by `rekor_signer`,
+    /// about the given entry
+    fn rekor_bundle_annotations(
+        rekor_signer: &policy_fetcher::sigstore::crypto::signing_key::SigStoreSigner,
+        entry: serde_json::Value,
+    ) -> BTreeMap<String, String> {
+        let payload = RekorBundlePayload {
+            body: BASE64.encode(serde_json::to_vec(&entry).unwrap()),
+            integrated_time: INTEGRATED_TIME,
+            log_id: "c0d23d6ad406973f9559f3ba2d1ca01f84147d8ffc5b8445c224f98b9591801d".to_owned(),
+            log_index: 42,
+        };
+        let signed_entry_timestamp = rekor_signer
+            .sign(&serde_json::to_vec(&payload).unwrap())
+            .unwrap();
+        let bundle = serde_json::json!({
+            "SignedEntryTimestamp": BASE64.encode(signed_entry_timestamp),
+            "Payload": payload,
+        });
+
+        BTreeMap::from([(BUNDLE_ANNOTATION.to_owned(), bundle.to_string())])
+    }
+
+    fn intoto_entry(signature: &str, certificate_pem: &str) -> serde_json::Value {
+        serde_json::json!({
+            "apiVersion": "0.0.2",
+            "kind": "intoto",
+            "spec": {
+                "content": {
+                    "envelope": {
+                        "payloadType": IN_TOTO_PAYLOAD_TYPE,
+                        "signatures": [{
+                            "sig": BASE64.encode(signature),
+                            "publicKey": BASE64.encode(certificate_pem),
+                        }],
+                    },
+                },
+            },
+        })
+    }
+
+    fn dsse_entry(signature: &str, certificate_pem: &str) -> serde_json::Value {
+        serde_json::json!({
+            "apiVersion": "0.0.1",
+            "kind": "dsse",
+            "spec": {
+                "signatures": [{
+                    "signature": signature,
+                    "verifier": BASE64.encode(certificate_pem),
+                }],
+            },
+        })
+    }
+
+    #[rstest]
+    #[case::intoto_entry(intoto_entry("c2lnbmF0dXJl", CERTIFICATE_PEM), true)]
+    #[case::dsse_entry(dsse_entry("c2lnbmF0dXJl", CERTIFICATE_PEM), true)]
+    #[case::entry_of_another_signature(intoto_entry("b3RoZXI=", CERTIFICATE_PEM), false)]
+    #[case::entry_of_another_certificate(
+        intoto_entry("c2lnbmF0dXJl", OTHER_CERTIFICATE_PEM),
+        false
+    )]
+    #[case::unsupported_entry(serde_json::json!({"kind": "hashedrekord", "spec": {}}), false)]
+    fn rekor_bundle_bound_to_signature(#[case] entry: serde_json::Value, #[case] valid: bool) {
+        let (rekor_signer, rekor_key) = signer_and_key();
+        let trust_material = TrustMaterial {
+            rekor_keys: vec![rekor_key],
+            ..Default::default()
+        };
+        let annotations = rekor_bundle_annotations(&rekor_signer, entry);
+        let signature = DsseSignature {
+            sig: "c2lnbmF0dXJl".to_owned(),
+        };
+        let certificate_der = CertificateDer::from_pem_slice(CERTIFICATE_PEM.as_bytes()).unwrap();
+
+        let result = verify_rekor_bundle(
+            Some(&annotations),
+            &trust_material,
+            &signature,
+            Some(&certificate_der),
+        );
+
+        assert_eq!(result.is_ok(), valid, "{result:?}");
+        if valid {
+            assert_eq!(result.unwrap(), INTEGRATED_TIME as u64);
+        }
+    }
+
+    #[rstest]
+    #[case::bundle_of_the_attestation(true, true)]
+    #[case::bundle_of_another_signature(false, false)]
+    fn require_rekor_bundle_of_pub_key_attestation(
+        #[case] same_signature: bool,
+        #[case] valid: bool,
+    ) {
+        let (signer, key) = signer_and_key();
+        let (rekor_signer, rekor_key) = signer_and_key();
+        let trust_material = TrustMaterial {
+            rekor_keys: vec![rekor_key],
+            ..Default::default()
+        };
+        let layer = attestation_layer(&signer, DIGEST, SLSA_PROVENANCE);
+        let envelope: serde_json::Value = serde_json::from_slice(&layer.data).unwrap();
+        let signature = if same_signature {
+            envelope["signatures"][0]["sig"].as_str().unwrap()
+        } else {
+            "b3RoZXI="
+        };
+        let annotations =
+            rekor_bundle_annotations(&rekor_signer, dsse_entry(signature, CERTIFICATE_PEM));
+        let layer = ImageLayer::new(layer.data, layer.media_type, Some(annotations));
+
+        let result = verify_attestations(
+            &[layer],
+            DIGEST,
+            SLSA_PROVENANCE,
+            &[AttestationSigner::PubKey(key)],
+            &trust_material,
+            true,
+        );
+
+        assert_eq!(result.is_ok(), valid, "{result:?}");
+    }
+
+    #[test]
+    fn rekor_bundle_signed_by_untrusted_instance() {
+        let (rekor_signer, _) = signer_and_key();
+        let (_, other_rekor_key) = signer_and_key();
+        let trust_material = TrustMaterial {
+            rekor_keys: vec![other_rekor_key],
+            ..Default::default()
+        };
+        let annotations =
+            rekor_bundle_annotations(&rekor_signer, dsse_entry("c2lnbmF0dXJl", CERTIFICATE_PEM));
+        let signature = DsseSignature {
+            sig: "c2lnbmF0dXJl".to_owned(),
+        };
+
+        assert!(
+            verify_rekor_bundle(Some(&annotations), &trust_material, &signature, None).is_err()
+        );
+    }
+
+    #[rstest]
+    #[case::equal(Subject::Equal("user@example.com".to_owned()), "user@example.com", true)]
+    #[case::not_equal(Subject::Equal("user@example.com".to_owned()), "admin@example.com", false)]
+    #[case::prefix(
+        Subject::UrlPrefix(url::Url::parse("https://github.com/kubewarden/").unwrap()),
+        "https://github.com/kubewarden/policy-server/.github/workflows/release.yml@refs/tags/v1.0.0",
+        true
+    )]
+    #[case::different_prefix(
+        Subject::UrlPrefix(url::Url::parse("https://github.com/kubewarden/").unwrap()),
+        "https://github.com/someone-else/policy-server/.github/workflows/release.yml@refs/tags/v1.0.0",
+        false
+    )]
+    #[case::prefix_without_trailing_slash(
+        Subject::UrlPrefix(url::Url::parse("https://github.com/kubewarden").unwrap()),
+        "https://github.com/kubewarden/policy-server/.github/workflows/release.yml@refs/tags/v1.0.0",
+        true
+    )]
+    #[case::prefix_of_another_organization(
+        Subject::UrlPrefix(url::Url::parse("https://github.com/kubewarden").unwrap()),
+        "https://github.com/kubewarden-fake/policy-server/.github/workflows/release.yml@refs/tags/v1.0.0",
+        false
+    )]
+    fn match_subject(#[case] wanted: Subject, #[case] identity: &str, #[case] expected: bool) {
+        assert_eq!(subject_matches(&wanted, identity), expected);
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+
+use policy_fetcher::{
+    sigstore::{
+        cosign::{
+            signature_layers::{CertificateSubject, SignatureLayer},
+            verification_constraint::{
+                AnnotationVerifier, PublicKeyVerifier, VerificationConstraint,
+            },
+        },
+        errors::Result as SigstoreResult,
+    },
+    verify::config::Subject,
+};
+
+use super::attestation::subject_matches;
+
+/// Who is trusted to sign an image
+#[derive(Debug)]
+pub(crate) enum Signer {
+    /// The owner of a private key
+    PubKey(PublicKeyVerifier),
+    /// The owner of a short lived certificate issued by Fulcio
+    Keyless { issuer: String, subject: Subject },
+}
+
+/// A constraint satisfied by the signature layers that are signed by the given
+/// signer, that carry the given annotations and, optionally, that have been
+/// recorded inside of the transparency log.
+///
+/// Unlike using different constraints, all the checks are done against the
+/// same signature layer.
+#[derive(Debug)]
+pub(crate) struct SignatureConstraint {
+    pub signer: Signer,
+    pub annotations: Option<BTreeMap<String, String>>,
+    /// Reject the signature layers without a Rekor bundle. Note well: the
+    /// bundles have already been verified against the trusted Rekor keys when
+    /// the signature layers have been fetched
+    pub require_rekor_bundle: bool,
+}
+
+impl VerificationConstraint for SignatureConstraint {
+    fn verify(&self, signature_layer: &SignatureLayer) -> SigstoreResult<bool> {
+        if self.require_rekor_bundle && signature_layer.bundle.is_none() {
+            return Ok(false);
+        }
+
+        if let Some(annotations) = &self.annotations {
+            let annotation_verifier = AnnotationVerifier {
+                annotations: annotations.clone(),
+            };
+            if !annotation_verifier.verify(signature_layer)? {
+                return Ok(false);
+            }
+        }
+
+        match &self.signer {
+            Signer::PubKey(verifier) => verifier.verify(signature_layer),
+            Signer::Keyless { issuer, subject } => Ok(signature_layer
+                .certificate_signature
+                .as_ref()
+                .is_some_and(|certificate_signature| {
+                    let identity = match &certificate_signature.subject {
+                        CertificateSubject::Email(email) => email,
+                        CertificateSubject::Uri(uri) => uri,
+                    };
+                    certificate_signature.issuer.as_ref() == Some(issuer)
+                        && subject_matches(subject, identity)
+                })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+    use policy_fetcher::sigstore::{
+        cosign::signature_layers::CertificateSignature,
+        crypto::{CosignVerificationKey, SigningScheme, signing_key::SigStoreSigner},
+    };
+    use rstest::rstest;
+
+    const ISSUER: &str = "https://token.actions.githubusercontent.com";
+    const IDENTITY: &str = "https://github.com/kubewarden/policy-server/.github/workflows/release.yml@refs/tags/v1.0.0";
+
+    fn signer_and_pem() -> (SigStoreSigner, String) {
+        let signer = SigningScheme::default().create_signer().unwrap();
+        let pem = signer
+            .to_sigstore_keypair()
+            .unwrap()
+            .public_key_to_pem()
+            .unwrap();
+        (signer, pem)
+    }
+
+    /// Build a signature layer signed by `signer`, annotated with `env=prod`
+    fn signature_layer(
+        signer: &SigStoreSigner,
+        certificate_signature: Option<CertificateSignature>,
+        with_bundle: bool,
+    ) -> SignatureLayer {
+        let simple_signing = serde_json::json!({
+            "critical": {
+                "identity": {"docker-reference": "ghcr.io/kubewarden/policies/pod-privileged"},
+                "image": {"docker-manifest-digest": "sha256:0000000000000000000000000000000000000000000000000000000000000001"},
+                "type": "cosign container image signature",
+            },
+            "optional": {"env": "prod"},
+        });
+        let raw_data = serde_json::to_vec(&simple_signing).unwrap();
+        let signature = BASE64.encode(signer.sign(&raw_data).unwrap());
+        let bundle = with_bundle.then(|| {
+            serde_json::from_value(serde_json::json!({
+                "SignedEntryTimestamp": "c2lnbmF0dXJl",
+                "Payload": {
+                    "body": "e30=",
+                    "integratedTime": 1_700_000_000,
+                    "logIndex": 42,
+                    "logID": "c0d23d6ad406973f9559f3ba2d1ca01f84147d8ffc5b8445c224f98b9591801d",
+                },
+            }))
+            .unwrap()
+        });
+
+        SignatureLayer {
+            simple_signing: serde_json::from_value(simple_signing).unwrap(),
+            oci_digest: "sha256:0000000000000000000000000000000000000000000000000000000000000002"
+                .to_owned(),
+            certificate_signature,
+            bundle,
+            signature: Some(signature),
+            raw_data,
+        }
+    }
+
+    fn certificate_signature(issuer: &str, subject: CertificateSubject) -> CertificateSignature {
+        let (_, pem) = signer_and_pem();
+        CertificateSignature {
+            verification_key: CosignVerificationKey::try_from_pem(pem.as_bytes()).unwrap(),
+            issuer: Some(issuer.to_owned()),
+            github_workflow_trigger: None,
+            github_workflow_sha: None,
+            github_workflow_name: None,
+            github_workflow_repository: None,
+            github_workflow_ref: None,
+            subject,
+        }
+    }
+
+    #[rstest]
+    #[case::trusted_key(true, None, false, true, true)]
+    #[case::untrusted_key(false, None, false, true, false)]
+    #[case::matching_annotations(true, Some(("env", "prod")), false, false, true)]
+    #[case::different_annotations(true, Some(("env", "dev")), false, false, false)]
+    #[case::rekor_bundle(true, None, true, true, true)]
+    #[case::missing_rekor_bundle(true, None, true, false, false)]
+    fn pub_key_signer(
+        #[case] trusted_key: bool,
+        #[case] annotation: Option<(&str, &str)>,
+        #[case] require_rekor_bundle: bool,
+        #[case] with_bundle: bool,
+        #[case] expected: bool,
+    ) {
+        let (signer, pem) = signer_and_pem();
+        let (_, other_pem) = signer_and_pem();
+        let trusted_pem = if trusted_key { pem } else { other_pem };
+        let constraint = SignatureConstraint {
+            signer: Signer::PubKey(
+                PublicKeyVerifier::new(trusted_pem.as_bytes(), &SigningScheme::default()).unwrap(),
+            ),
+            annotations: annotation
+                .map(|(key, value)| BTreeMap::from([(key.to_owned(), value.to_owned())])),
+            require_rekor_bundle,
+        };
+
+        let layer = signature_layer(&signer, None, with_bundle);
+
+        assert_eq!(constraint.verify(&layer).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case::email(
+        ISSUER,
+        CertificateSubject::Email("user@example.com".to_owned()),
+        Subject::Equal("user@example.com".to_owned()),
+        true
+    )]
+    #[case::different_issuer(
+        "https://accounts.google.com",
+        CertificateSubject::Email("user@example.com".to_owned()),
+        Subject::Equal("user@example.com".to_owned()),
+        false
+    )]
+    #[case::different_email(
+        ISSUER,
+        CertificateSubject::Email("admin@example.com".to_owned()),
+        Subject::Equal("user@example.com".to_owned()),
+        false
+    )]
+    #[case::url_prefix(
+        ISSUER,
+        CertificateSubject::Uri(IDENTITY.to_owned()),
+        Subject::UrlPrefix(url::Url::parse("https://github.com/kubewarden").unwrap()),
+        true
+    )]
+    #[case::url_prefix_of_another_organization(
+        ISSUER,
+        CertificateSubject::Uri(IDENTITY.replace("kubewarden", "kubewarden-fake")),
+        Subject::UrlPrefix(url::Url::parse("https://github.com/kubewarden").unwrap()),
+        false
+    )]
+    fn keyless_signer(
+        #[case] certificate_issuer: &str,
+        #[case] certificate_subject: CertificateSubject,
+        #[case] subject: Subject,
+        #[case] expected: bool,
+    ) {
+        let (signer, _) = signer_and_pem();
+        let constraint = SignatureConstraint {
+            signer: Signer::Keyless {
+                issuer: ISSUER.to_owned(),
+                subject,
+            },
+            annotations: None,
+            require_rekor_bundle: false,
+        };
+
+        let layer = signature_layer(
+            &signer,
+            Some(certificate_signature(
+                certificate_issuer,
+                certificate_subject,
+            )),
+            false,
+        );
+
+        assert_eq!(constraint.verify(&layer).unwrap(), expected);
+    }
+
+    #[test]
+    fn keyless_signer_without_certificate() {
+        let (signer, _) = signer_and_pem();
+        let constraint = SignatureConstraint {
+            signer: Signer::Keyless {
+                issuer: ISSUER.to_owned(),
+                subject: Subject::Equal("user@example.com".to_owned()),
+            },
+            annotations: None,
+            require_rekor_bundle: false,
+        };
+
+        assert!(
+            !constraint
+                .verify(&signature_layer(&signer, None, false))
+                .unwrap()
+        );
+    }
+}
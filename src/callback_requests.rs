@@ -1,4 +1,6 @@
 use anyhow::Result;
+use k8s_openapi::api::authorization::v1::SubjectAccessReviewStatus;
+use kubewarden_policy_sdk::host_capabilities::crypto::{Certificate, CertificateEncoding};
 use kubewarden_policy_sdk::host_capabilities::crypto_v1::CertificateVerificationRequest;
 use kubewarden_policy_sdk::host_capabilities::kubernetes::CanIRequest;
 use kubewarden_policy_sdk::host_capabilities::kubernetes::SubjectAccessReview as KWSubjectAccessReview;
@@ -8,13 +10,18 @@ use kubewarden_policy_sdk::host_capabilities::{
 };
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::net::IpAddr;
 use tokio::{sync::oneshot, time::Instant};
+use url::Url;
+use x509_cert::{der::oid::ObjectIdentifier, ext::pkix};
 
 /// Holds the response to a waPC evaluation request
 #[derive(Debug, Clone)]
 pub struct CallbackResponse {
     /// The data to be given back to the waPC guest
     pub payload: Vec<u8>,
+    /// Whether the data has been served from the cache of the host capability
+    pub cached: bool,
 }
 
 /// A request sent by some synchronous code (usually waPC's host_callback)
@@ -25,6 +32,11 @@ pub struct CallbackRequest {
     pub request: CallbackRequestType,
     /// A tokio oneshot channel over which the evaluation response has to be sent
     pub response_channel: oneshot::Sender<Result<CallbackResponse>>,
+    /// Optional deadline of the request. Once reached, the evaluation of the
+    /// request is cancelled and a
+    /// [`HostCapabilityError::Timeout`](crate::errors::HostCapabilityError::Timeout)
+    /// error is sent back
+    pub deadline: Option<Instant>,
 }
 
 /// Describes the different kinds of request a waPC guest can make to
@@ -52,6 +64,37 @@ pub enum CallbackRequestType {
         image: String,
     },
 
+    /// Require the list of tags of an OCI repository. The results are paginated
+    OciListTags {
+        /// String pointing to the repository (e.g.: `registry.testing.lan/busybox`)
+        image: String,
+        /// Maximum number of tags to be returned. When not set, the registry
+        /// decides how many tags are returned
+        page_size: Option<usize>,
+        /// Return only the tags that come after this one. Used to fetch the next page
+        last: Option<String>,
+    },
+
+    /// Require the OCI 1.1 referrers of an OCI object, like SBOMs, signatures
+    /// and attestations attached to an image
+    OciReferrers {
+        /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+        image: String,
+        /// Return only the referrers with this artifact type
+        /// (e.g.: `application/vnd.cyclonedx+json`)
+        artifact_type: Option<String>,
+    },
+
+    /// Require the OCI object manifest, digest and config of the image built for
+    /// the given platform. When the object is an image index, the manifest
+    /// matching the platform is looked up inside of it
+    OciManifestAndConfigForPlatform {
+        /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+        image: String,
+        /// The wanted platform
+        platform: OciPlatform,
+    },
+
     /// Require the verification of the manifest digest of an OCI object (be
     /// it an image or anything else that can be stored into an OCI registry)
     /// to be signed by Sigstore, using public keys mode
@@ -122,9 +165,50 @@ pub enum CallbackRequestType {
         annotations: Option<BTreeMap<String, String>>,
     },
 
+    /// Require the verification of the in-toto attestations attached by cosign
+    /// to an OCI object. The attestations must be signed by at least one of the
+    /// given public keys or keyless identities. The predicates of the verified
+    /// attestations are returned, to be inspected by the policy
+    SigstoreAttestationVerify {
+        /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+        image: String,
+        /// The type of the predicate (e.g.: `https://slsa.dev/provenance/v1`)
+        predicate_type: String,
+        /// PEM encoded public keys trusted to sign the attestations
+        pub_keys: Vec<String>,
+        /// Keyless identities trusted to sign the attestations
+        keyless: Vec<KeylessInfo>,
+        /// Keyless identities, matched by URL prefix, trusted to sign the attestations
+        keyless_prefix: Vec<KeylessPrefixInfo>,
+    },
+
+    /// Require the verification of the Notation signatures of an image, according
+    /// to the trust policy configured on the host
+    NotationVerify {
+        /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+        image: String,
+    },
+
     /// Lookup the addresses for a given hostname via DNS
     DNSLookupHost { host: String },
 
+    /// Lookup the DNS records of the given type about a hostname
+    DNSLookup {
+        host: String,
+        record_type: DnsRecordType,
+    },
+
+    /// Lookup the names associated with an IP address via DNS PTR records
+    DNSReverseLookup { ip: IpAddr },
+
+    /// Fetch an HTTP endpoint the policy has been allowed to reach
+    HttpGet {
+        url: Url,
+        /// Headers to be sent with the request. `Host`, `Proxy-*` and the
+        /// hop-by-hop headers are ignored
+        headers: BTreeMap<String, String>,
+    },
+
     /// Get all the Kubernetes resources defined inside of the given
     /// namespace
     /// Note: cannot be used with cluster-wide resources
@@ -188,18 +272,12 @@ pub enum CallbackRequestType {
         kind: String,
     },
 
-    /// Checks if the data of the reflector tracking this query changed since the given instant
+    /// Checks if any object of the given resource changed since the given instant
     HasKubernetesListResourceAllResultChangedSinceInstant {
         /// apiVersion of the resource (v1 for core group, groupName/groupVersions for other).
         api_version: String,
         /// Singular PascalCase name of the resource
         kind: String,
-        /// A selector to restrict the list of returned objects by their labels.
-        /// Defaults to everything if `None`
-        label_selector: Option<String>,
-        /// A selector to restrict the list of returned objects by their fields.
-        /// Defaults to everything if `None`
-        field_selector: Option<String>,
         /// The instant in time to compare the last change of the resources
         #[serde(with = "tokio_instant_serializer")]
         since: Instant,
@@ -223,6 +301,18 @@ pub enum CallbackRequestType {
         disable_cache: bool,
     },
 
+    /// Check, with a single request, if many users have the permissions to
+    /// perform some operations. Identical reviews are evaluated only once
+    KubernetesCanIBatch {
+        /// The reviews to be evaluated. The outcomes, see [`CanIBatchResult`],
+        /// are returned in the same order
+        requests: Vec<KWSubjectAccessReview>,
+
+        /// Disable caching of results obtained from Kubernetes API Server.
+        /// See `KubernetesCanI`
+        disable_cache: bool,
+    },
+
     /// Check if the given certificate is trusted by the certificate chain and if
     /// it is not expired
     CryptoIsCertificateTrusted {
@@ -230,6 +320,13 @@ pub enum CallbackRequestType {
         /// a chain to validate it with.
         request: CertificateVerificationRequest,
     },
+
+    /// Check if the given certificate is trusted by the certificate chain, is
+    /// not expired, is not revoked, has the required key usages and is valid
+    /// for the given DNS names
+    CryptoIsCertificateTrustedV2 {
+        request: CertificateVerificationV2Request,
+    },
 }
 mod tokio_instant_serializer {
     use serde::de::Error;
@@ -395,8 +492,364 @@ impl From<CanIRequest> for CallbackRequestType {
     }
 }
 
+/// Payload of the `v1/verify_attestation` OCI host capability
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SigstoreAttestationVerifyRequest {
+    /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+    pub image: String,
+    /// The type of the predicate (e.g.: `https://slsa.dev/provenance/v1`)
+    pub predicate_type: String,
+    /// PEM encoded public keys trusted to sign the attestations
+    #[serde(default)]
+    pub pub_keys: Vec<String>,
+    /// Keyless identities trusted to sign the attestations
+    #[serde(default)]
+    pub keyless: Vec<KeylessInfo>,
+    /// Keyless identities, matched by URL prefix, trusted to sign the attestations
+    #[serde(default)]
+    pub keyless_prefix: Vec<KeylessPrefixInfo>,
+}
+
+impl From<SigstoreAttestationVerifyRequest> for CallbackRequestType {
+    fn from(req: SigstoreAttestationVerifyRequest) -> Self {
+        CallbackRequestType::SigstoreAttestationVerify {
+            image: req.image,
+            predicate_type: req.predicate_type,
+            pub_keys: req.pub_keys,
+            keyless: req.keyless,
+            keyless_prefix: req.keyless_prefix,
+        }
+    }
+}
+
+/// Payload of the `v1/verify_notation` OCI host capability
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotationVerifyRequest {
+    /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+    pub image: String,
+}
+
+impl From<NotationVerifyRequest> for CallbackRequestType {
+    fn from(req: NotationVerifyRequest) -> Self {
+        CallbackRequestType::NotationVerify { image: req.image }
+    }
+}
+
+/// A platform an image can be built for
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct OciPlatform {
+    /// The operating system (e.g.: `linux`)
+    pub os: String,
+    /// The CPU architecture (e.g.: `arm64`)
+    pub architecture: String,
+    /// The variant of the CPU (e.g.: `v8`). When not set, any variant is accepted
+    #[serde(default)]
+    pub variant: Option<String>,
+}
+
+impl std::fmt::Display for OciPlatform {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+        if let Some(variant) = &self.variant {
+            write!(f, "/{variant}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Payload of the `v1/list_tags` OCI host capability
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OciListTagsRequest {
+    /// String pointing to the repository (e.g.: `registry.testing.lan/busybox`)
+    pub image: String,
+    /// Maximum number of tags to be returned
+    #[serde(default)]
+    pub page_size: Option<usize>,
+    /// Return only the tags that come after this one. Used to fetch the next page
+    #[serde(default)]
+    pub last: Option<String>,
+}
+
+impl From<OciListTagsRequest> for CallbackRequestType {
+    fn from(req: OciListTagsRequest) -> Self {
+        CallbackRequestType::OciListTags {
+            image: req.image,
+            page_size: req.page_size,
+            last: req.last,
+        }
+    }
+}
+
+/// Payload of the `v1/referrers` OCI host capability
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OciReferrersRequest {
+    /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+    pub image: String,
+    /// Return only the referrers with this artifact type
+    #[serde(default)]
+    pub artifact_type: Option<String>,
+}
+
+impl From<OciReferrersRequest> for CallbackRequestType {
+    fn from(req: OciReferrersRequest) -> Self {
+        CallbackRequestType::OciReferrers {
+            image: req.image,
+            artifact_type: req.artifact_type,
+        }
+    }
+}
+
+/// Payload of the `v1/oci_manifest_config_for_platform` OCI host capability
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OciManifestAndConfigForPlatformRequest {
+    /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+    pub image: String,
+    /// The wanted platform
+    pub platform: OciPlatform,
+}
+
+impl From<OciManifestAndConfigForPlatformRequest> for CallbackRequestType {
+    fn from(req: OciManifestAndConfigForPlatformRequest) -> Self {
+        CallbackRequestType::OciManifestAndConfigForPlatform {
+            image: req.image,
+            platform: req.platform,
+        }
+    }
+}
+
+/// Payload of the `can_i_batch` host capability
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CanIBatchRequest {
+    /// The reviews to be evaluated. The outcomes, see [`CanIBatchResult`],
+    /// are returned in the same order
+    pub subject_access_reviews: Vec<KWSubjectAccessReview>,
+
+    /// Disable caching of results obtained from Kubernetes API Server
+    #[serde(default)]
+    pub disable_cache: bool,
+}
+
+/// Outcome of one of the reviews of a `can_i_batch` request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CanIBatchResult {
+    /// The decision about the review
+    Status(SubjectAccessReviewStatus),
+    /// The review could not be evaluated
+    Error(String),
+}
+
+impl From<CanIBatchRequest> for CallbackRequestType {
+    fn from(req: CanIBatchRequest) -> Self {
+        CallbackRequestType::KubernetesCanIBatch {
+            requests: req.subject_access_reviews,
+            disable_cache: req.disable_cache,
+        }
+    }
+}
+
 impl From<CertificateVerificationRequest> for CallbackRequestType {
     fn from(request: CertificateVerificationRequest) -> Self {
         CallbackRequestType::CryptoIsCertificateTrusted { request }
     }
 }
+
+/// Payload of the `v2/is_certificate_trusted` crypto host capability
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertificateVerificationV2Request {
+    /// The certificate to be verified
+    pub cert: Certificate,
+    /// The chain of trust, the last certificate being the root CA. When not
+    /// provided, Mozilla's CA root certificates are used
+    #[serde(default)]
+    pub cert_chain: Option<Vec<Certificate>>,
+    /// RFC 3339 time at which the certificate is verified, the current time
+    /// is used when not provided
+    #[serde(default)]
+    pub not_after: Option<String>,
+    /// Key usages the certificate must have
+    #[serde(default)]
+    pub key_usages: Vec<KeyUsage>,
+    /// Extended key usages the certificate must have
+    #[serde(default)]
+    pub extended_key_usages: Vec<ExtendedKeyUsage>,
+    /// DNS names the certificate must be valid for, according to its subject
+    /// alternative names
+    #[serde(default)]
+    pub dns_names: Vec<String>,
+    /// Certificate revocation lists issued by the CA of the certificate. When
+    /// provided, the certificate must be covered by one of them
+    #[serde(default)]
+    pub crls: Vec<CertificateRevocationList>,
+}
+
+impl From<CertificateVerificationV2Request> for CallbackRequestType {
+    fn from(request: CertificateVerificationV2Request) -> Self {
+        CallbackRequestType::CryptoIsCertificateTrustedV2 { request }
+    }
+}
+
+/// A certificate revocation list
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertificateRevocationList {
+    /// Which encoding is used by the CRL
+    pub encoding: CertificateEncoding,
+    /// Actual CRL
+    pub data: Vec<u8>,
+}
+
+/// The key usages defined by RFC 5280
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyUsage {
+    DigitalSignature,
+    NonRepudiation,
+    KeyEncipherment,
+    DataEncipherment,
+    KeyAgreement,
+    KeyCertSign,
+    CrlSign,
+    EncipherOnly,
+    DecipherOnly,
+}
+
+impl KeyUsage {
+    pub(crate) const ALL: [KeyUsage; 9] = [
+        KeyUsage::DigitalSignature,
+        KeyUsage::NonRepudiation,
+        KeyUsage::KeyEncipherment,
+        KeyUsage::DataEncipherment,
+        KeyUsage::KeyAgreement,
+        KeyUsage::KeyCertSign,
+        KeyUsage::CrlSign,
+        KeyUsage::EncipherOnly,
+        KeyUsage::DecipherOnly,
+    ];
+
+    /// Whether the key usage is set inside of the Key Usage extension
+    pub(crate) fn is_set(self, key_usage: &pkix::KeyUsage) -> bool {
+        match self {
+            KeyUsage::DigitalSignature => key_usage.digital_signature(),
+            KeyUsage::NonRepudiation => key_usage.non_repudiation(),
+            KeyUsage::KeyEncipherment => key_usage.key_encipherment(),
+            KeyUsage::DataEncipherment => key_usage.data_encipherment(),
+            KeyUsage::KeyAgreement => key_usage.key_agreement(),
+            KeyUsage::KeyCertSign => key_usage.key_cert_sign(),
+            KeyUsage::CrlSign => key_usage.crl_sign(),
+            KeyUsage::EncipherOnly => key_usage.encipher_only(),
+            KeyUsage::DecipherOnly => key_usage.decipher_only(),
+        }
+    }
+}
+
+/// The extended key usages defined by RFC 5280
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtendedKeyUsage {
+    ServerAuth,
+    ClientAuth,
+    CodeSigning,
+    EmailProtection,
+    TimeStamping,
+    OcspSigning,
+}
+
+impl ExtendedKeyUsage {
+    const ALL: [ExtendedKeyUsage; 6] = [
+        ExtendedKeyUsage::ServerAuth,
+        ExtendedKeyUsage::ClientAuth,
+        ExtendedKeyUsage::CodeSigning,
+        ExtendedKeyUsage::EmailProtection,
+        ExtendedKeyUsage::TimeStamping,
+        ExtendedKeyUsage::OcspSigning,
+    ];
+
+    /// The object identifier of the extended key usage
+    pub(crate) fn oid(self) -> ObjectIdentifier {
+        match self {
+            ExtendedKeyUsage::ServerAuth => ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.3.1"),
+            ExtendedKeyUsage::ClientAuth => ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.3.2"),
+            ExtendedKeyUsage::CodeSigning => ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.3.3"),
+            ExtendedKeyUsage::EmailProtection => ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.3.4"),
+            ExtendedKeyUsage::TimeStamping => ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.3.8"),
+            ExtendedKeyUsage::OcspSigning => ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.3.9"),
+        }
+    }
+
+    /// The extended key usage identified by the given object identifier, if known
+    pub(crate) fn from_oid(oid: &ObjectIdentifier) -> Option<Self> {
+        Self::ALL.into_iter().find(|usage| usage.oid() == *oid)
+    }
+}
+
+/// The DNS record types that can be looked up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum DnsRecordType {
+    A,
+    Aaaa,
+    Cname,
+    Txt,
+    Srv,
+    Mx,
+}
+
+/// Payload of the `v1/dns_lookup` net host capability
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsLookupRequest {
+    /// The hostname to be looked up
+    pub host: String,
+    /// The type of the records to be returned
+    pub record_type: DnsRecordType,
+}
+
+impl From<DnsLookupRequest> for CallbackRequestType {
+    fn from(req: DnsLookupRequest) -> Self {
+        CallbackRequestType::DNSLookup {
+            host: req.host,
+            record_type: req.record_type,
+        }
+    }
+}
+
+/// Payload of the `v1/dns_reverse_lookup` net host capability
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsReverseLookupRequest {
+    /// The IP address to be looked up
+    pub ip: IpAddr,
+}
+
+impl From<DnsReverseLookupRequest> for CallbackRequestType {
+    fn from(req: DnsReverseLookupRequest) -> Self {
+        CallbackRequestType::DNSReverseLookup { ip: req.ip }
+    }
+}
+
+/// Payload of the `v1/http_get` net host capability
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpGetRequest {
+    /// The URL to be fetched, it must be allowed by the evaluation context
+    pub url: Url,
+    /// Headers to be sent with the request
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl std::fmt::Debug for HttpGetRequest {
+    // the values of the headers can hold credentials, they are not logged
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("HttpGetRequest")
+            .field("url", &self.url.as_str())
+            .field("headers", &self.headers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl From<HttpGetRequest> for CallbackRequestType {
+    fn from(req: HttpGetRequest) -> Self {
+        CallbackRequestType::HttpGet {
+            url: req.url,
+            headers: req.headers,
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::Deserialize;
+
+use crate::errors::DiscoveryError;
+
+/// A Kubernetes resource, as known by the discovery API of the cluster
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredResource {
+    /// The API group of the resource, empty for the core group
+    pub group: String,
+    /// The version of the resource
+    pub version: String,
+    /// The `apiVersion` of the resource, e.g. `apps/v1`
+    pub api_version: String,
+    /// The kind of the resource, e.g. `Deployment`
+    pub kind: String,
+    /// The plural name of the resource, e.g. `deployments`
+    pub plural: String,
+    /// Whether the resource is namespaced or cluster wide
+    pub namespaced: bool,
+}
+
+/// This models the `APIResourceList` object returned by the discovery endpoints
+/// of the Kubernetes API server (e.g. `kubectl get --raw /apis/apps/v1`).
+/// Only the fields we care about are deserialized
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ApiResourceList {
+    group_version: String,
+    #[serde(default)]
+    resources: Vec<ApiResource>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ApiResource {
+    name: String,
+    kind: String,
+    #[serde(default)]
+    namespaced: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ApiResourceLists {
+    Many(Vec<ApiResourceList>),
+    One(ApiResourceList),
+}
+
+/// The built-in resources of Kubernetes: `(apiVersion, kind, plural, namespaced)`
+const BUILTIN_RESOURCES: &[(&str, &str, &str, bool)] = &[
+    ("v1", "ConfigMap", "configmaps", true),
+    ("v1", "Endpoints", "endpoints", true),
+    ("v1", "Event", "events", true),
+    ("v1", "LimitRange", "limitranges", true),
+    ("v1", "Namespace", "namespaces", false),
+    ("v1", "Node", "nodes", false),
+    ("v1", "PersistentVolume", "persistentvolumes", false),
+    (
+        "v1",
+        "PersistentVolumeClaim",
+        "persistentvolumeclaims",
+        true,
+    ),
+    ("v1", "Pod", "pods", true),
+    ("v1", "PodTemplate", "podtemplates", true),
+    (
+        "v1",
+        "ReplicationController",
+        "replicationcontrollers",
+        true,
+    ),
+    ("v1", "ResourceQuota", "resourcequotas", true),
+    ("v1", "Secret", "secrets", true),
+    ("v1", "Service", "services", true),
+    ("v1", "ServiceAccount", "serviceaccounts", true),
+    ("apps/v1", "ControllerRevision", "controllerrevisions", true),
+    ("apps/v1", "DaemonSet", "daemonsets", true),
+    ("apps/v1", "Deployment", "deployments", true),
+    ("apps/v1", "ReplicaSet", "replicasets", true),
+    ("apps/v1", "StatefulSet", "statefulsets", true),
+    (
+        "autoscaling/v2",
+        "HorizontalPodAutoscaler",
+        "horizontalpodautoscalers",
+        true,
+    ),
+    ("batch/v1", "CronJob", "cronjobs", true),
+    ("batch/v1", "Job", "jobs", true),
+    ("coordination.k8s.io/v1", "Lease", "leases", true),
+    (
+        "discovery.k8s.io/v1",
+        "EndpointSlice",
+        "endpointslices",
+        true,
+    ),
+    ("networking.k8s.io/v1", "Ingress", "ingresses", true),
+    (
+        "networking.k8s.io/v1",
+        "IngressClass",
+        "ingressclasses",
+        false,
+    ),
+    (
+        "networking.k8s.io/v1",
+        "NetworkPolicy",
+        "networkpolicies",
+        true,
+    ),
+    ("node.k8s.io/v1", "RuntimeClass", "runtimeclasses", false),
+    (
+        "policy/v1",
+        "PodDisruptionBudget",
+        "poddisruptionbudgets",
+        true,
+    ),
+    (
+        "rbac.authorization.k8s.io/v1",
+        "ClusterRole",
+        "clusterroles",
+        false,
+    ),
+    (
+        "rbac.authorization.k8s.io/v1",
+        "ClusterRoleBinding",
+        "clusterrolebindings",
+        false,
+    ),
+    ("rbac.authorization.k8s.io/v1", "Role", "roles", true),
+    (
+        "rbac.authorization.k8s.io/v1",
+        "RoleBinding",
+        "rolebindings",
+        true,
+    ),
+    (
+        "scheduling.k8s.io/v1",
+        "PriorityClass",
+        "priorityclasses",
+        false,
+    ),
+    ("storage.k8s.io/v1", "CSIDriver", "csidrivers", false),
+    ("storage.k8s.io/v1", "CSINode", "csinodes", false),
+    ("storage.k8s.io/v1", "StorageClass", "storageclasses", false),
+    (
+        "storage.k8s.io/v1",
+        "VolumeAttachment",
+        "volumeattachments",
+        false,
+    ),
+    (
+        "admissionregistration.k8s.io/v1",
+        "MutatingWebhookConfiguration",
+        "mutatingwebhookconfigurations",
+        false,
+    ),
+    (
+        "admissionregistration.k8s.io/v1",
+        "ValidatingWebhookConfiguration",
+        "validatingwebhookconfigurations",
+        false,
+    ),
+    (
+        "apiextensions.k8s.io/v1",
+        "CustomResourceDefinition",
+        "customresourcedefinitions",
+        false,
+    ),
+];
+
+/// An offline snapshot of the Kubernetes discovery API.
+///
+/// This allows to resolve the plural name of a resource, and its scope,
+/// without having to interact with a Kubernetes API server.
+#[derive(Debug, Clone, Default)]
+pub struct DiscoverySnapshot {
+    resources: HashMap<(String, String), DiscoveredResource>,
+}
+
+impl DiscoverySnapshot {
+    /// A snapshot containing the built-in resources of Kubernetes, like
+    /// `Pod`, `Deployment` or `Namespace`.
+    ///
+    /// Custom resources are not part of it.
+    pub fn builtin() -> Self {
+        let mut snapshot = DiscoverySnapshot::default();
+
+        for (api_version, kind, plural, namespaced) in BUILTIN_RESOURCES {
+            let (group, version) =
+                split_api_version(api_version).expect("built-in apiVersion must be valid");
+            snapshot.insert(DiscoveredResource {
+                group: group.to_owned(),
+                version: version.to_owned(),
+                api_version: api_version.to_string(),
+                kind: kind.to_string(),
+                plural: plural.to_string(),
+                namespaced: *namespaced,
+            });
+        }
+
+        snapshot
+    }
+
+    /// Load the snapshot from a file.
+    ///
+    /// The file can be either YAML or JSON. It can contain one or more
+    /// `APIResourceList` objects, either as a list or as multiple YAML documents.
+    pub fn from_path(path: &Path) -> Result<Self, DiscoveryError> {
+        let contents = std::fs::read_to_string(path).map_err(DiscoveryError::Path)?;
+        Self::from_contents(&contents)
+    }
+
+    /// Load the snapshot from a YAML or JSON string, see [`from_path`](Self::from_path)
+    pub fn from_contents(contents: &str) -> Result<Self, DiscoveryError> {
+        let mut snapshot = DiscoverySnapshot::default();
+
+        for document in serde_yaml::Deserializer::from_str(contents) {
+            let lists = ApiResourceLists::deserialize(document).map_err(DiscoveryError::Parse)?;
+            let lists = match lists {
+                ApiResourceLists::Many(lists) => lists,
+                ApiResourceLists::One(list) => vec![list],
+            };
+            for list in lists {
+                snapshot.add_api_resource_list(list)?;
+            }
+        }
+
+        Ok(snapshot)
+    }
+
+    fn add_api_resource_list(&mut self, list: ApiResourceList) -> Result<(), DiscoveryError> {
+        let (group, version) = split_api_version(&list.group_version)?;
+
+        for resource in list.resources {
+            if resource.name.contains('/') {
+                // skip subresources, like `pods/status`
+                continue;
+            }
+            self.insert(DiscoveredResource {
+                group: group.to_owned(),
+                version: version.to_owned(),
+                api_version: list.group_version.clone(),
+                kind: resource.kind,
+                plural: resource.name,
+                namespaced: resource.namespaced,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Add a resource to the snapshot, replacing any previous definition of it
+    pub fn insert(&mut self, resource: DiscoveredResource) {
+        self.resources.insert(
+            (resource.api_version.clone(), resource.kind.clone()),
+            resource,
+        );
+    }
+
+    /// Look up a resource by its `apiVersion` and `kind`
+    pub fn get(&self, api_version: &str, kind: &str) -> Option<&DiscoveredResource> {
+        self.resources
+            .get(&(api_version.to_owned(), kind.to_owned()))
+    }
+
+    /// Iterate over all the resources known by the snapshot
+    pub fn resources(&self) -> impl Iterator<Item = &DiscoveredResource> {
+        self.resources.values()
+    }
+}
+
+/// Split an `apiVersion` into its group and version. The core group is
+/// represented by an empty string
+pub(crate) fn split_api_version(api_version: &str) -> Result<(&str, &str), DiscoveryError> {
+    match api_version.split_once('/') {
+        None if !api_version.is_empty() => Ok(("", api_version)),
+        Some((group, version))
+            if !group.is_empty() && !version.is_empty() && !version.contains('/') =>
+        {
+            Ok((group, version))
+        }
+        _ => Err(DiscoveryError::InvalidApiVersion(api_version.to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    const DISCOVERY_SNAPSHOT: &str = r#"
+kind: APIResourceList
+groupVersion: v1
+resources:
+  - name: pods
+    kind: Pod
+    namespaced: true
+  - name: pods/status
+    kind: Pod
+    namespaced: true
+  - name: namespaces
+    kind: Namespace
+    namespaced: false
+---
+[
+  {
+    "kind": "APIResourceList",
+    "apiVersion": "v1",
+    "groupVersion": "apps/v1",
+    "resources": [
+      { "name": "deployments", "kind": "Deployment", "namespaced": true }
+    ]
+  }
+]
+"#;
+
+    #[test]
+    fn load_snapshot() {
+        let snapshot =
+            DiscoverySnapshot::from_contents(DISCOVERY_SNAPSHOT).expect("cannot load snapshot");
+
+        assert_eq!(snapshot.resources().count(), 3);
+        assert_eq!(
+            snapshot.get("v1", "Pod"),
+            Some(&DiscoveredResource {
+                group: "".to_string(),
+                version: "v1".to_string(),
+                api_version: "v1".to_string(),
+                kind: "Pod".to_string(),
+                plural: "pods".to_string(),
+                namespaced: true,
+            })
+        );
+        assert_eq!(
+            snapshot
+                .get("apps/v1", "Deployment")
+                .map(|r| r.group.as_str()),
+            Some("apps")
+        );
+        assert!(!snapshot.get("v1", "Namespace").unwrap().namespaced);
+        assert!(snapshot.get("v1", "Secret").is_none());
+    }
+
+    #[test]
+    fn builtin_snapshot() {
+        let snapshot = DiscoverySnapshot::builtin();
+
+        let ingress = snapshot
+            .get("networking.k8s.io/v1", "Ingress")
+            .expect("cannot find Ingress");
+        assert_eq!(ingress.group, "networking.k8s.io");
+        assert_eq!(ingress.plural, "ingresses");
+        assert!(ingress.namespaced);
+        assert!(!snapshot.get("v1", "Namespace").unwrap().namespaced);
+    }
+
+    #[rstest]
+    #[case::core("v1", Some(("", "v1")))]
+    #[case::group("apps/v1", Some(("apps", "v1")))]
+    #[case::empty("", None)]
+    #[case::too_many_parts("a/b/c", None)]
+    #[case::empty_group("/v1", None)]
+    fn split_api_versions(#[case] api_version: &str, #[case] expected: Option<(&str, &str)>) {
+        assert_eq!(split_api_version(api_version).ok(), expected);
+    }
+}
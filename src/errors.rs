@@ -70,6 +70,9 @@ pub enum PolicyEvaluatorBuilderError {
 
     #[error("error when building rego precompiled stack")]
     NewRegoStackPre(#[source] wasmtime::Error),
+
+    #[error("invalid settings schema: {0}")]
+    InvalidSettingsSchema(String),
 }
 
 #[derive(Error, Debug)]
@@ -102,3 +105,38 @@ pub enum ResponseError {
     #[error("cannot deserialize JSONPatch: {0}")]
     Deserialize(#[source] serde_json::Error),
 }
+
+#[derive(Error, Debug)]
+pub enum WebhookConfigurationError {
+    #[error(
+        "webhook configurations can be generated only for kubernetes policies, not for {0} ones"
+    )]
+    NotKubernetesPolicy(String),
+
+    #[error("cannot generate a mutating webhook configuration for a policy that is not mutating")]
+    NotMutating,
+}
+
+#[derive(Error, Debug)]
+pub enum DiscoveryError {
+    #[error("cannot read discovery snapshot from path: {0}")]
+    Path(#[source] std::io::Error),
+
+    #[error("cannot parse discovery snapshot: {0}")]
+    Parse(#[source] serde_yaml::Error),
+
+    #[error("invalid apiVersion: '{0}'")]
+    InvalidApiVersion(String),
+}
+
+#[derive(Error, Debug)]
+pub enum HostCapabilityError {
+    #[error("host capability timed out")]
+    Timeout,
+}
+
+#[derive(Error, Debug)]
+pub enum RbacError {
+    #[error("resource {api_version}/{kind} is not part of the discovery snapshot")]
+    UnknownResource { api_version: String, kind: String },
+}
@@ -3,9 +3,11 @@ pub extern crate burrego;
 pub mod admission_request;
 pub mod admission_response;
 pub mod admission_response_handler;
+pub mod audit;
 pub mod callback_handler;
 pub mod callback_requests;
 pub mod constants;
+pub mod discovery;
 pub mod errors;
 pub mod evaluation_context;
 pub mod policy_artifacthub;
@@ -13,7 +15,10 @@ pub mod policy_evaluator;
 pub mod policy_group_evaluator;
 pub mod policy_metadata;
 mod policy_tracing;
+pub mod rbac;
+pub mod request_matcher;
 pub mod runtimes;
+pub mod webhook_configuration;
 
 // API's that expose other crate types (such as Kubewarden Policy SDK
 // or `policy_fetcher`) can either implement their own exposed types,
@@ -530,9 +530,11 @@ mod tests {
             mutating: false,
             background_audit: true,
             context_aware_resources: BTreeSet::new(),
+            host_capabilities: None,
             execution_mode: Default::default(),
             policy_type: PolicyType::Kubernetes,
             minimum_kubewarden_version: None,
+            settings_schema: None,
         }
     }
 
@@ -599,9 +601,11 @@ mod tests {
             mutating: false,
             background_audit: true,
             context_aware_resources,
+            host_capabilities: None,
             execution_mode: Default::default(),
             minimum_kubewarden_version: None,
             policy_type: Default::default(),
+            settings_schema: None,
         }
     }
 
@@ -1,11 +1,16 @@
 pub mod errors;
 mod evaluator;
+mod matching_evaluator;
 pub mod policy_evaluator_builder;
 mod policy_evaluator_pre;
+mod settings_schema;
 mod stack_pre;
 
-pub use evaluator::PolicyEvaluator;
+pub use evaluator::{PolicyEvaluator, ValidationReport};
+pub use matching_evaluator::MatchingPolicyEvaluator;
 pub use policy_evaluator_pre::PolicyEvaluatorPre;
+// the logs returned by `PolicyEvaluator::validate_with_logs`
+pub use crate::policy_tracing::{PolicyLogLevel, PolicyLogRecord, PolicyLogRuntime};
 
 use anyhow::{Result, anyhow};
 use k8s_openapi::apimachinery::pkg::runtime::RawExtension;
@@ -1,25 +1,42 @@
 use kubewarden_policy_sdk::{metadata::ProtocolVersion, settings::SettingsValidationResponse};
+use serde::Serialize;
 use std::fmt;
+use std::time::Instant;
 
 use crate::admission_response::AdmissionResponse;
 use crate::errors::PolicyEvaluatorError;
 use crate::evaluation_context::EvaluationContext;
-use crate::policy_evaluator::{PolicySettings, ValidateRequest};
+use crate::policy_evaluator::{PolicySettings, ValidateRequest, settings_schema::SettingsSchema};
+use crate::policy_tracing::PolicyLogRecord;
 use crate::runtimes::Runtime;
 use crate::runtimes::rego::Runtime as BurregoRuntime;
 use crate::runtimes::wapc::Runtime as WapcRuntime;
 use crate::runtimes::wasi_cli::Runtime as WasiRuntime;
 
+/// The outcome of an evaluation, together with the logs emitted by the policy
+/// while evaluating the request
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationReport {
+    pub response: AdmissionResponse,
+    pub logs: Vec<PolicyLogRecord>,
+}
+
 pub struct PolicyEvaluator {
     runtime: Runtime,
     eval_ctx: EvaluationContext,
+    settings_schema: Option<SettingsSchema>,
 }
 
 impl PolicyEvaluator {
-    pub(crate) fn new(runtime: Runtime, eval_ctx: &EvaluationContext) -> Self {
+    pub(crate) fn new(
+        runtime: Runtime,
+        eval_ctx: &EvaluationContext,
+        settings_schema: Option<SettingsSchema>,
+    ) -> Self {
         Self {
             runtime,
             eval_ctx: eval_ctx.to_owned(),
+            settings_schema,
         }
     }
 
@@ -29,15 +46,33 @@ impl PolicyEvaluator {
         request: ValidateRequest,
         settings: &PolicySettings,
     ) -> AdmissionResponse {
-        match self.runtime {
+        let deadline = self
+            .eval_ctx
+            .evaluation_timeout
+            .map(|timeout| Instant::now() + timeout);
+        self.validate_until(request, settings, deadline)
+    }
+
+    /// Validate the request, the host capabilities invoked by the policy are
+    /// cancelled once the given deadline is reached
+    pub(crate) fn validate_until(
+        &mut self,
+        request: ValidateRequest,
+        settings: &PolicySettings,
+        deadline: Option<Instant>,
+    ) -> AdmissionResponse {
+        // the host capabilities invoked by the policy are reported together
+        // with the uid of the request
+        self.eval_ctx
+            .current_request
+            .set_uid(Some(request.uid().to_owned()));
+        self.eval_ctx.deadline.set(deadline);
+        let response = match self.runtime {
             Runtime::Wapc(ref mut wapc_stack) => {
                 WapcRuntime(wapc_stack).validate(settings, &request)
             }
             Runtime::Rego(ref mut burrego_evaluator) => {
-                let kube_ctx = burrego_evaluator.build_kubernetes_context(
-                    self.eval_ctx.callback_channel.as_ref(),
-                    &self.eval_ctx.ctx_aware_resources_allow_list,
-                );
+                let kube_ctx = burrego_evaluator.build_kubernetes_context(&self.eval_ctx);
                 match kube_ctx {
                     Ok(ctx) => BurregoRuntime(burrego_evaluator).validate(settings, &request, &ctx),
                     Err(e) => {
@@ -46,11 +81,54 @@ impl PolicyEvaluator {
                 }
             }
             Runtime::Cli(ref mut cli_stack) => WasiRuntime(cli_stack).validate(settings, &request),
-        }
+        };
+        self.eval_ctx.current_request.set_uid(None);
+        self.eval_ctx.deadline.set(None);
+
+        response
+    }
+
+    /// Validate the request, capturing the logs emitted by the policy. The
+    /// logs are still forwarded to `tracing`
+    pub fn validate_with_logs(
+        &mut self,
+        request: ValidateRequest,
+        settings: &PolicySettings,
+    ) -> ValidationReport {
+        let deadline = self
+            .eval_ctx
+            .evaluation_timeout
+            .map(|timeout| Instant::now() + timeout);
+        self.validate_with_logs_until(request, settings, deadline)
+    }
+
+    /// Like [`validate_with_logs`](Self::validate_with_logs), the host
+    /// capabilities invoked by the policy are cancelled once the given
+    /// deadline is reached
+    pub(crate) fn validate_with_logs_until(
+        &mut self,
+        request: ValidateRequest,
+        settings: &PolicySettings,
+        deadline: Option<Instant>,
+    ) -> ValidationReport {
+        self.eval_ctx.policy_logs.start();
+        let response = self.validate_until(request, settings, deadline);
+        let logs = self.eval_ctx.policy_logs.finish();
+
+        ValidationReport { response, logs }
     }
 
     #[tracing::instrument]
     pub fn validate_settings(&mut self, settings: &PolicySettings) -> SettingsValidationResponse {
+        // The settings schema is enforced by the host, before invoking the guest. This is
+        // done regardless of the execution mode of the policy
+        if let Some(settings_schema) = &self.settings_schema {
+            let response = settings_schema.validate(settings);
+            if !response.valid {
+                return response;
+            }
+        }
+
         let settings_str = match serde_json::to_string(settings) {
             Ok(settings) => settings,
             Err(err) => {
@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+
+use kubewarden_policy_sdk::settings::SettingsValidationResponse;
+use tracing::debug;
+
+use crate::admission_response::AdmissionResponse;
+use crate::policy_evaluator::{PolicyEvaluator, PolicySettings, ValidateRequest};
+use crate::request_matcher::RequestMatcher;
+
+/// A [`PolicyEvaluator`] that evaluates only the requests matched by a
+/// [`RequestMatcher`].
+///
+/// Requests that are not matched are accepted without invoking the policy,
+/// like the Kubernetes API server does when a request does not match the
+/// configuration of a webhook.
+pub struct MatchingPolicyEvaluator {
+    evaluator: PolicyEvaluator,
+    matcher: RequestMatcher,
+}
+
+impl MatchingPolicyEvaluator {
+    pub fn new(evaluator: PolicyEvaluator, matcher: RequestMatcher) -> Self {
+        Self { evaluator, matcher }
+    }
+
+    /// Validate the request, if it's matched by the [`RequestMatcher`].
+    ///
+    /// `namespace_labels` are the labels of the namespace the request belongs to,
+    /// see [`RequestMatcher::matches`].
+    #[tracing::instrument(skip(self, request, namespace_labels))]
+    pub fn validate(
+        &mut self,
+        request: ValidateRequest,
+        settings: &PolicySettings,
+        namespace_labels: Option<&BTreeMap<String, String>>,
+    ) -> AdmissionResponse {
+        match self.matcher.matches(&request, namespace_labels) {
+            Ok(true) => self.evaluator.validate(request, settings),
+            Ok(false) => {
+                debug!(
+                    uid = request.uid(),
+                    "request not matched, skipping policy evaluation"
+                );
+                AdmissionResponse {
+                    uid: request.uid().to_string(),
+                    allowed: true,
+                    ..Default::default()
+                }
+            }
+            Err(e) => AdmissionResponse::reject(
+                request.uid().to_string(),
+                format!("cannot match request: {e}"),
+                500,
+            ),
+        }
+    }
+
+    pub fn validate_settings(&mut self, settings: &PolicySettings) -> SettingsValidationResponse {
+        self.evaluator.validate_settings(settings)
+    }
+
+    /// Access the wrapped [`PolicyEvaluator`]
+    pub fn evaluator(&mut self) -> &mut PolicyEvaluator {
+        &mut self.evaluator
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, LabelSelectorRequirement};
+    use rstest::rstest;
+    use serde_json::json;
+
+    use crate::evaluation_context::EvaluationContext;
+    use crate::policy_evaluator::{
+        PolicyExecutionMode, policy_evaluator_builder::PolicyEvaluatorBuilder,
+    };
+
+    /// A matching evaluator of a policy that rejects all the requests it
+    /// evaluates, only the requests targeting `env=prod` namespaces are matched
+    fn matching_evaluator() -> MatchingPolicyEvaluator {
+        let evaluator = PolicyEvaluatorBuilder::new()
+            .execution_mode(PolicyExecutionMode::OpaGatekeeper)
+            .policy_contents(include_bytes!(
+                "../../tests/data/gatekeeper_always_unhappy_policy.wasm"
+            ))
+            .build_pre()
+            .unwrap()
+            .rehydrate(&EvaluationContext::default())
+            .unwrap();
+        let env_prod = LabelSelector {
+            match_expressions: Some(vec![LabelSelectorRequirement {
+                key: "env".to_string(),
+                operator: "In".to_string(),
+                values: Some(vec!["prod".to_string()]),
+            }]),
+            ..Default::default()
+        };
+
+        MatchingPolicyEvaluator::new(
+            evaluator,
+            RequestMatcher::new(&[]).namespace_selector(env_prod),
+        )
+    }
+
+    /// A request about a Namespace, stored inside of the given field of the request
+    fn namespace_request(operation: &str, object_field: &str, env: &str) -> ValidateRequest {
+        let mut request = json!({
+            "uid": "uid",
+            "kind": { "group": "", "version": "v1", "kind": "Namespace" },
+            "resource": { "group": "", "version": "v1", "resource": "namespaces" },
+            "name": "team-a",
+            "operation": operation,
+            "userInfo": { "username": "alice" },
+        });
+        request[object_field] = json!({
+            "apiVersion": "v1",
+            "kind": "Namespace",
+            "metadata": { "name": "team-a", "labels": { "env": env } }
+        });
+
+        ValidateRequest::AdmissionRequest(Box::new(
+            serde_json::from_value(request).expect("cannot deserialize request"),
+        ))
+    }
+
+    #[rstest]
+    #[case::matched("CREATE", "object", "prod", false)]
+    #[case::not_matched("CREATE", "object", "dev", true)]
+    #[case::delete_matched_on_old_object("DELETE", "oldObject", "prod", false)]
+    #[case::delete_not_matched_on_old_object("DELETE", "oldObject", "dev", true)]
+    fn evaluate_matched_requests_only(
+        #[case] operation: &str,
+        #[case] object_field: &str,
+        #[case] env: &str,
+        #[case] allowed: bool,
+    ) {
+        let mut evaluator = matching_evaluator();
+
+        let response = evaluator.validate(
+            namespace_request(operation, object_field, env),
+            &Default::default(),
+            None,
+        );
+
+        assert_eq!(response.uid, "uid");
+        assert_eq!(response.allowed, allowed, "{response:?}");
+        if allowed {
+            assert!(response.status.is_none(), "the policy has been invoked");
+        }
+    }
+
+    #[test]
+    fn reject_request_that_cannot_be_matched() {
+        let mut evaluator = MatchingPolicyEvaluator::new(
+            matching_evaluator().evaluator,
+            RequestMatcher::new(&[]).namespace_selector(LabelSelector {
+                match_expressions: Some(vec![LabelSelectorRequirement {
+                    key: "env".to_string(),
+                    operator: "Gt".to_string(),
+                    values: Some(vec!["1".to_string()]),
+                }]),
+                ..Default::default()
+            }),
+        );
+
+        let response = evaluator.validate(
+            namespace_request("CREATE", "object", "prod"),
+            &Default::default(),
+            None,
+        );
+
+        assert!(!response.allowed);
+        assert_eq!(response.status.and_then(|status| status.code), Some(500));
+    }
+}
@@ -1,11 +1,16 @@
+use std::borrow::Cow;
 use std::path::Path;
 use std::result::Result;
 
+use tracing::warn;
 use wasmtime_provider::wasmtime;
 
 use crate::errors::PolicyEvaluatorBuilderError;
 use crate::policy_evaluator::errors::InvalidUserInputError;
-use crate::policy_evaluator::{PolicyEvaluatorPre, PolicyExecutionMode, stack_pre::StackPre};
+use crate::policy_evaluator::{
+    PolicyEvaluatorPre, PolicyExecutionMode, settings_schema::SettingsSchema, stack_pre::StackPre,
+};
+use crate::policy_metadata::Metadata;
 use crate::runtimes::{rego, wapc, wasi_cli};
 
 /// Configure behavior of wasmtime [epoch-based interruptions](https://docs.rs/wasmtime/latest/wasmtime/struct.Config.html#method.epoch_interruption)
@@ -24,6 +29,9 @@ pub(crate) struct EpochDeadlines {
     pub wapc_func: u64,
 }
 
+/// The first bytes of a WebAssembly module in the binary format
+const WASM_MAGIC: &[u8] = b"\0asm";
+
 /// Helper Struct that creates a `PolicyEvaluator` object
 #[derive(Default)]
 pub struct PolicyEvaluatorBuilder {
@@ -34,6 +42,7 @@ pub struct PolicyEvaluatorBuilder {
     execution_mode: Option<PolicyExecutionMode>,
     wasmtime_cache: bool,
     epoch_deadlines: Option<EpochDeadlines>,
+    settings_schema: Option<serde_json::Value>,
 }
 
 impl PolicyEvaluatorBuilder {
@@ -92,6 +101,18 @@ impl PolicyEvaluatorBuilder {
         self
     }
 
+    /// JSON Schema the policy settings must conform to. When not set, the
+    /// schema is taken from the [`Metadata`](crate::policy_metadata::Metadata)
+    /// embedded into the policy given via `policy_file` or `policy_contents`.
+    ///
+    /// The settings are validated against the schema by the host, before
+    /// invoking the `validate_settings` function of the policy
+    #[must_use]
+    pub fn settings_schema(mut self, schema: serde_json::Value) -> PolicyEvaluatorBuilder {
+        self.settings_schema = Some(schema);
+        self
+    }
+
     /// Enable Wasmtime cache feature
     #[must_use]
     pub fn enable_wasmtime_cache(mut self) -> PolicyEvaluatorBuilder {
@@ -162,8 +183,20 @@ impl PolicyEvaluatorBuilder {
         self.validate_user_input()
             .map_err(PolicyEvaluatorBuilderError::InvalidUserInput)?;
 
+        let contents = self.read_policy_contents()?;
+        // Reading the metadata is not required to build the policy, failures are
+        // handled like a policy without metadata
+        let metadata = contents.as_deref().and_then(read_metadata);
+        let settings_schema = self
+            .settings_schema
+            .as_ref()
+            .or_else(|| metadata.as_ref().and_then(|m| m.settings_schema.as_ref()))
+            .map(SettingsSchema::new)
+            .transpose()
+            .map_err(PolicyEvaluatorBuilderError::InvalidSettingsSchema)?;
+
         let engine = self.build_engine()?;
-        let module = self.build_module(&engine)?;
+        let module = self.build_module(&engine, contents.as_deref())?;
 
         let execution_mode = self.execution_mode.unwrap_or_default();
 
@@ -191,7 +224,23 @@ impl PolicyEvaluatorBuilder {
             }
         };
 
-        Ok(PolicyEvaluatorPre::new(stack_pre))
+        Ok(PolicyEvaluatorPre::new(
+            stack_pre,
+            settings_schema,
+            metadata.and_then(|m| m.host_capabilities),
+        ))
+    }
+
+    /// Load the policy given via `policy_contents` or `policy_file`. Policies
+    /// given as [`wasmtime::Module`] have no contents
+    fn read_policy_contents(&self) -> Result<Option<Cow<'_, [u8]>>, PolicyEvaluatorBuilderError> {
+        match (&self.policy_contents, &self.policy_file) {
+            (Some(contents), _) => Ok(Some(Cow::Borrowed(contents))),
+            (None, Some(file)) => std::fs::read(file)
+                .map(|contents| Some(Cow::Owned(contents)))
+                .map_err(|e| PolicyEvaluatorBuilderError::WasmModuleBuild(e.into())),
+            (None, None) => Ok(None),
+        }
     }
 
     fn build_engine(&self) -> Result<wasmtime::Engine, PolicyEvaluatorBuilderError> {
@@ -218,26 +267,79 @@ impl PolicyEvaluatorBuilder {
     fn build_module(
         &self,
         engine: &wasmtime::Engine,
+        contents: Option<&[u8]>,
     ) -> Result<wasmtime::Module, PolicyEvaluatorBuilderError> {
-        if let Some(m) = &self.policy_module {
+        match (&self.policy_module, contents) {
             // it's fine to clone a Module, this is a cheap operation that just
             // copies its internal reference. See wasmtime docs
-            Ok(m.clone())
-        } else {
-            match &self.policy_file {
-                Some(file) => wasmtime::Module::from_file(engine, file)
-                    .map_err(PolicyEvaluatorBuilderError::WasmModuleBuild),
-                None => wasmtime::Module::new(engine, self.policy_contents.as_ref().unwrap())
-                    .map_err(PolicyEvaluatorBuilderError::WasmModuleBuild),
-            }
+            (Some(m), _) => Ok(m.clone()),
+            (None, Some(contents)) => wasmtime::Module::new(engine, contents)
+                .map_err(PolicyEvaluatorBuilderError::WasmModuleBuild),
+            (None, None) => unreachable!("user input has been validated"),
         }
     }
 }
 
+/// Read the metadata embedded into the policy. Policies in the WebAssembly
+/// text format have none, the ones whose metadata cannot be read are handled
+/// the same way
+fn read_metadata(contents: &[u8]) -> Option<Metadata> {
+    if !contents.starts_with(WASM_MAGIC) {
+        return None;
+    }
+
+    Metadata::from_contents(contents)
+        .inspect_err(|e| warn!(error = %e, "cannot read policy metadata, ignoring it"))
+        .ok()
+        .flatten()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use rstest::rstest;
+    use serde_json::json;
+
+    use crate::evaluation_context::EvaluationContext;
+    use crate::policy_evaluator::PolicySettings;
+
+    /// Embed the given metadata into the policy, as `kwctl annotate` does
+    fn annotate(policy: &[u8], metadata: &Metadata) -> Vec<u8> {
+        add_metadata_section(policy, &serde_json::to_vec(metadata).unwrap())
+    }
+
+    /// Add a Kubewarden metadata section with the given contents to the policy
+    fn add_metadata_section(policy: &[u8], data: &[u8]) -> Vec<u8> {
+        fn leb128(mut value: usize, out: &mut Vec<u8>) {
+            loop {
+                let byte = (value & 0x7f) as u8;
+                value >>= 7;
+                if value == 0 {
+                    out.push(byte);
+                    return;
+                }
+                out.push(byte | 0x80);
+            }
+        }
+
+        let name = crate::constants::KUBEWARDEN_CUSTOM_SECTION_METADATA.as_bytes();
+        let mut section = Vec::new();
+        leb128(name.len(), &mut section);
+        section.extend_from_slice(name);
+        section.extend_from_slice(data);
+
+        let mut annotated = policy.to_vec();
+        annotated.push(0);
+        leb128(section.len(), &mut annotated);
+        annotated.extend_from_slice(&section);
+        annotated
+    }
+
+    fn settings(settings: serde_json::Value) -> PolicySettings {
+        PolicySettings::try_from(&settings).unwrap()
+    }
+
     #[test]
     fn build_policy_evaluator_pre() {
         let engine = wasmtime::Engine::default();
@@ -253,4 +355,131 @@ mod tests {
 
         _ = policy_evaluator_builder.build_pre().unwrap();
     }
+
+    #[test]
+    fn build_policy_evaluator_pre_with_invalid_settings_schema() {
+        let wat = include_bytes!("../../tests/data/endless_wasm/wapc_endless_loop.wat");
+
+        let policy_evaluator_builder = PolicyEvaluatorBuilder::new()
+            .execution_mode(PolicyExecutionMode::KubewardenWapc)
+            .policy_contents(wat)
+            .settings_schema(serde_json::json!({"type": 42}));
+
+        assert!(matches!(
+            policy_evaluator_builder.build_pre(),
+            Err(PolicyEvaluatorBuilderError::InvalidSettingsSchema(_))
+        ));
+    }
+
+    #[rstest]
+    #[case::valid(json!({"replicas": 3}), true)]
+    #[case::invalid(json!({"replicas": "three"}), false)]
+    fn validate_settings_with_schema_from_metadata(
+        #[case] policy_settings: serde_json::Value,
+        #[case] valid: bool,
+    ) {
+        let metadata = Metadata {
+            execution_mode: PolicyExecutionMode::OpaGatekeeper,
+            settings_schema: Some(json!({
+                "type": "object",
+                "properties": { "replicas": { "type": "integer" } }
+            })),
+            ..Default::default()
+        };
+        let policy = annotate(
+            include_bytes!("../../tests/data/gatekeeper_always_happy_policy.wasm"),
+            &metadata,
+        );
+
+        let mut evaluator = PolicyEvaluatorBuilder::new()
+            .execution_mode(PolicyExecutionMode::OpaGatekeeper)
+            .policy_contents(&policy)
+            .build_pre()
+            .unwrap()
+            .rehydrate(&EvaluationContext::default())
+            .unwrap();
+
+        let response = evaluator.validate_settings(&settings(policy_settings));
+        assert_eq!(response.valid, valid, "{response:?}");
+    }
+
+    #[test]
+    fn explicit_settings_schema_takes_precedence_over_metadata() {
+        let metadata = Metadata {
+            execution_mode: PolicyExecutionMode::OpaGatekeeper,
+            settings_schema: Some(json!({"type": "object", "required": ["name"]})),
+            ..Default::default()
+        };
+        let policy = annotate(
+            include_bytes!("../../tests/data/gatekeeper_always_happy_policy.wasm"),
+            &metadata,
+        );
+
+        let mut evaluator = PolicyEvaluatorBuilder::new()
+            .execution_mode(PolicyExecutionMode::OpaGatekeeper)
+            .policy_contents(&policy)
+            .settings_schema(json!({"type": "object"}))
+            .build_pre()
+            .unwrap()
+            .rehydrate(&EvaluationContext::default())
+            .unwrap();
+
+        assert!(evaluator.validate_settings(&settings(json!({}))).valid);
+    }
+
+    #[test]
+    fn read_settings_schema_from_policy_file() {
+        let metadata = Metadata {
+            execution_mode: PolicyExecutionMode::OpaGatekeeper,
+            settings_schema: Some(json!({"type": "object", "required": ["name"]})),
+            ..Default::default()
+        };
+        let policy = annotate(
+            include_bytes!("../../tests/data/gatekeeper_always_happy_policy.wasm"),
+            &metadata,
+        );
+        let policy_file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(policy_file.path(), policy).unwrap();
+
+        let mut evaluator = PolicyEvaluatorBuilder::new()
+            .execution_mode(PolicyExecutionMode::OpaGatekeeper)
+            .policy_file(policy_file.path())
+            .unwrap()
+            .build_pre()
+            .unwrap()
+            .rehydrate(&EvaluationContext::default())
+            .unwrap();
+
+        assert!(!evaluator.validate_settings(&settings(json!({}))).valid);
+    }
+
+    #[rstest]
+    #[case::no_settings_schema(None, true)]
+    #[case::explicit_settings_schema(Some(json!({"type": "object", "required": ["name"]})), false)]
+    fn build_policy_with_unreadable_metadata(
+        #[case] settings_schema: Option<serde_json::Value>,
+        #[case] valid: bool,
+    ) {
+        let policy = add_metadata_section(
+            include_bytes!("../../tests/data/gatekeeper_always_happy_policy.wasm"),
+            b"not a JSON document",
+        );
+
+        let mut builder = PolicyEvaluatorBuilder::new()
+            .execution_mode(PolicyExecutionMode::OpaGatekeeper)
+            .policy_contents(&policy);
+        if let Some(schema) = settings_schema {
+            builder = builder.settings_schema(schema);
+        }
+        let mut evaluator = builder
+            .build_pre()
+            .expect("unreadable metadata must not prevent the policy from being built")
+            .rehydrate(&EvaluationContext::default())
+            .unwrap();
+
+        assert_eq!(
+            evaluator.validate_settings(&settings(json!({}))).valid,
+            valid
+        );
+    }
 }
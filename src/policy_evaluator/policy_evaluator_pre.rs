@@ -1,8 +1,12 @@
+use std::collections::BTreeSet;
 use std::result::Result;
 
 use crate::errors::PolicyEvaluatorPreError;
 use crate::evaluation_context::EvaluationContext;
-use crate::policy_evaluator::{PolicyEvaluator, stack_pre::StackPre};
+use crate::policy_evaluator::{
+    PolicyEvaluator, settings_schema::SettingsSchema, stack_pre::StackPre,
+};
+use crate::policy_metadata::HostCapability;
 use crate::runtimes::{Runtime, rego, wapc, wasi_cli};
 
 /// This struct provides a way to quickly allocate a `PolicyEvaluator`
@@ -12,11 +16,22 @@ use crate::runtimes::{Runtime, rego, wapc, wasi_cli};
 #[derive(Clone)]
 pub struct PolicyEvaluatorPre {
     stack_pre: StackPre,
+    settings_schema: Option<SettingsSchema>,
+    /// The host capabilities declared inside of the policy metadata, if any
+    host_capabilities: Option<BTreeSet<HostCapability>>,
 }
 
 impl PolicyEvaluatorPre {
-    pub(crate) fn new(stack_pre: StackPre) -> Self {
-        PolicyEvaluatorPre { stack_pre }
+    pub(crate) fn new(
+        stack_pre: StackPre,
+        settings_schema: Option<SettingsSchema>,
+        host_capabilities: Option<BTreeSet<HostCapability>>,
+    ) -> Self {
+        PolicyEvaluatorPre {
+            stack_pre,
+            settings_schema,
+            host_capabilities,
+        }
     }
 
     /// Create a `PolicyEvaluator` instance. The creation of the instance is achieved by
@@ -26,10 +41,27 @@ impl PolicyEvaluatorPre {
     /// Warning: the Rego stack cannot make use of these low level primitives, but its
     /// instantiation times are negligible. More details inside of the
     /// documentation of [`rego::StackPre`](crate::runtimes::rego::StackPre).
+    ///
+    /// When the policy metadata declares the host capabilities used by the
+    /// policy, the evaluator cannot use any other one, regardless of the
+    /// host capabilities granted by `eval_ctx`.
+    ///
+    /// Each evaluator tracks its own request, deadline and captured logs:
+    /// evaluators rehydrated from the same `eval_ctx` can be used concurrently.
     pub fn rehydrate(
         &self,
         eval_ctx: &EvaluationContext,
     ) -> Result<PolicyEvaluator, PolicyEvaluatorPreError> {
+        let eval_ctx = &EvaluationContext {
+            host_capabilities_allow_list: restrict_host_capabilities(
+                eval_ctx.host_capabilities_allow_list.as_ref(),
+                self.host_capabilities.as_ref(),
+            ),
+            current_request: Default::default(),
+            deadline: Default::default(),
+            policy_logs: Default::default(),
+            ..eval_ctx.clone()
+        };
         let runtime = match &self.stack_pre {
             StackPre::Wapc(stack_pre) => {
                 let wapc_stack = wapc::WapcStack::new_from_pre(stack_pre, eval_ctx)
@@ -47,6 +79,122 @@ impl PolicyEvaluatorPre {
             }
         };
 
-        Ok(PolicyEvaluator::new(runtime, eval_ctx))
+        Ok(PolicyEvaluator::new(
+            runtime,
+            eval_ctx,
+            self.settings_schema.clone(),
+        ))
+    }
+}
+
+/// The host capabilities that are both granted to the policy and declared by
+/// its metadata. A missing list doesn't restrict anything
+fn restrict_host_capabilities(
+    granted: Option<&BTreeSet<HostCapability>>,
+    declared: Option<&BTreeSet<HostCapability>>,
+) -> Option<BTreeSet<HostCapability>> {
+    match (granted, declared) {
+        (Some(granted), Some(declared)) => Some(granted.intersection(declared).copied().collect()),
+        (granted, declared) => granted.or(declared).cloned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::{Duration, Instant};
+
+    use rstest::rstest;
+    use serde_json::json;
+
+    use crate::policy_evaluator::{
+        PolicyExecutionMode, ValidateRequest, policy_evaluator_builder::PolicyEvaluatorBuilder,
+    };
+    use crate::policy_tracing::{PolicyLogLevel, PolicyLogRuntime};
+
+    #[rstest]
+    #[case::unrestricted(None, None, None)]
+    #[case::granted_only(
+        Some(BTreeSet::from([HostCapability::NetCidr])),
+        None,
+        Some(BTreeSet::from([HostCapability::NetCidr]))
+    )]
+    #[case::declared_only(
+        None,
+        Some(BTreeSet::from([HostCapability::OciManifest])),
+        Some(BTreeSet::from([HostCapability::OciManifest]))
+    )]
+    #[case::both(
+        Some(BTreeSet::from([HostCapability::NetCidr, HostCapability::OciManifest])),
+        Some(BTreeSet::from([HostCapability::OciManifest, HostCapability::NetHttpGet])),
+        Some(BTreeSet::from([HostCapability::OciManifest]))
+    )]
+    #[case::nothing_in_common(
+        Some(BTreeSet::from([HostCapability::NetCidr])),
+        Some(BTreeSet::from([HostCapability::OciManifest])),
+        Some(BTreeSet::new())
+    )]
+    fn host_capabilities_restricted_by_metadata(
+        #[case] granted: Option<BTreeSet<HostCapability>>,
+        #[case] declared: Option<BTreeSet<HostCapability>>,
+        #[case] expected: Option<BTreeSet<HostCapability>>,
+    ) {
+        assert_eq!(
+            restrict_host_capabilities(granted.as_ref(), declared.as_ref()),
+            expected
+        );
+    }
+
+    #[test]
+    fn rehydrated_evaluators_do_not_share_the_evaluation_state() {
+        let policy_evaluator_pre = PolicyEvaluatorBuilder::new()
+            .execution_mode(PolicyExecutionMode::OpaGatekeeper)
+            .policy_contents(include_bytes!(
+                "../../tests/data/gatekeeper_always_happy_policy.wasm"
+            ))
+            .build_pre()
+            .unwrap();
+        let eval_ctx = EvaluationContext::default();
+        let deadline = Instant::now() + Duration::from_secs(60);
+        eval_ctx.current_request.set_uid(Some("uid-1".to_owned()));
+        eval_ctx.deadline.set(Some(deadline));
+
+        let mut evaluator = policy_evaluator_pre.rehydrate(&eval_ctx).unwrap();
+        evaluator.validate(
+            ValidateRequest::Raw(json!({"uid": "uid-2"})),
+            &Default::default(),
+        );
+
+        assert_eq!(eval_ctx.current_request.uid(), Some("uid-1".to_owned()));
+        assert_eq!(eval_ctx.deadline.get(), Some(deadline));
+    }
+
+    #[test]
+    fn rehydrated_evaluators_do_not_share_the_captured_logs() {
+        let policy_evaluator_pre = PolicyEvaluatorBuilder::new()
+            .execution_mode(PolicyExecutionMode::OpaGatekeeper)
+            .policy_contents(include_bytes!(
+                "../../tests/data/gatekeeper_always_happy_policy.wasm"
+            ))
+            .build_pre()
+            .unwrap();
+        let eval_ctx = EvaluationContext::default();
+        eval_ctx.policy_logs.start();
+
+        let mut evaluator = policy_evaluator_pre.rehydrate(&eval_ctx).unwrap();
+        evaluator.validate_with_logs(
+            ValidateRequest::Raw(json!({"uid": "uid-1"})),
+            &Default::default(),
+        );
+
+        // the capture of `eval_ctx` has not been stopped by the evaluator
+        eval_ctx.emit_policy_log(
+            PolicyLogRuntime::Rego,
+            PolicyLogLevel::Info,
+            "still captured".to_owned(),
+            Default::default(),
+        );
+        assert_eq!(eval_ctx.policy_logs.finish().len(), 1);
     }
 }
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use itertools::Itertools;
+use kubewarden_policy_sdk::settings::SettingsValidationResponse;
+
+use crate::policy_evaluator::PolicySettings;
+
+/// A compiled JSON Schema used to validate the settings of a policy before
+/// they are sent to the WebAssembly guest.
+///
+/// The validation is done on the host, hence it works the same way for
+/// all the execution modes.
+#[derive(Clone, Debug)]
+pub(crate) struct SettingsSchema {
+    validator: Arc<jsonschema::Validator>,
+}
+
+impl SettingsSchema {
+    pub fn new(schema: &serde_json::Value) -> Result<Self, String> {
+        let validator = jsonschema::validator_for(schema).map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            validator: Arc::new(validator),
+        })
+    }
+
+    /// Validate the settings against the schema. Each error reports the
+    /// JSON pointer of the offending value
+    pub fn validate(&self, settings: &PolicySettings) -> SettingsValidationResponse {
+        let settings = serde_json::Value::Object(settings.0.clone());
+
+        let errors = self
+            .validator
+            .iter_errors(&settings)
+            .map(|error| {
+                let path = error.instance_path().as_str();
+                let path = if path.is_empty() { "/" } else { path };
+                format!("{path}: {error}")
+            })
+            .collect::<Vec<String>>();
+
+        if errors.is_empty() {
+            SettingsValidationResponse {
+                valid: true,
+                message: None,
+            }
+        } else {
+            SettingsValidationResponse {
+                valid: false,
+                message: Some(format!(
+                    "settings do not match the schema of the policy: {}",
+                    errors.iter().join("; ")
+                )),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+    use serde_json::json;
+
+    #[rstest]
+    #[case::valid(json!({"replicas": 3, "name": "test"}), None)]
+    #[case::missing_required(
+        json!({"replicas": 3}),
+        Some("settings do not match the schema of the policy: /: \"name\" is a required property")
+    )]
+    #[case::wrong_type(
+        json!({"replicas": "3", "name": "test"}),
+        Some("settings do not match the schema of the policy: /replicas: \"3\" is not of type \"integer\"")
+    )]
+    fn validate_settings(#[case] settings: serde_json::Value, #[case] expected: Option<&str>) {
+        let schema = SettingsSchema::new(&json!({
+            "type": "object",
+            "required": ["name"],
+            "properties": {
+                "replicas": { "type": "integer" },
+                "name": { "type": "string" }
+            }
+        }))
+        .expect("cannot build schema");
+
+        let settings = PolicySettings::try_from(&settings).expect("cannot build settings");
+        let response = schema.validate(&settings);
+
+        assert_eq!(response.valid, expected.is_none());
+        assert_eq!(response.message.as_deref(), expected);
+    }
+
+    #[test]
+    fn invalid_schema() {
+        assert!(SettingsSchema::new(&json!({"type": 42})).is_err());
+    }
+}
@@ -9,8 +9,11 @@ pub mod errors;
 pub mod evaluator;
 
 use crate::{
-    admission_response::AdmissionResponse, policy_evaluator::PolicySettings,
-    policy_metadata::ContextAwareResource,
+    admission_response::AdmissionResponse,
+    evaluation_context::HttpAllowedEndpoint,
+    policy_evaluator::PolicySettings,
+    policy_metadata::{ContextAwareResource, HostCapability},
+    policy_tracing::PolicyLogLevel,
 };
 
 /// The settings of a policy group member
@@ -22,6 +25,14 @@ pub struct PolicyGroupMemberSettings {
     pub ctx_aware_resources_allow_list: BTreeSet<ContextAwareResource>,
     /// The epoch deadlines to be used when executing this policy member
     pub epoch_deadline: Option<u64>,
+    /// The HTTP endpoints the policy member can fetch via the `net/v1/http_get`
+    /// host capability
+    pub http_get_allow_list: Vec<HttpAllowedEndpoint>,
+    /// The host capabilities the policy member is granted access to. When not
+    /// set, the policy member can use all of them
+    pub host_capabilities_allow_list: Option<BTreeSet<HostCapability>>,
+    /// Optional minimum level of the logs emitted by the policy member
+    pub log_level: Option<PolicyLogLevel>,
 }
 
 /// This holds the a summary of the evaluation results of a policy group member
@@ -74,6 +85,9 @@ impl TryFrom<&PolicyGroupMemberWithContext> for PolicyGroupMemberSettings {
                 .timeout_eval_seconds
                 .as_ref()
                 .map(|t| Into::<i32>::into(t) as u64),
+            http_get_allow_list: Vec::new(),
+            host_capabilities_allow_list: None,
+            log_level: None,
         })
     }
 }
@@ -91,6 +105,9 @@ impl TryFrom<&PolicyGroupMember> for PolicyGroupMemberSettings {
                 .timeout_eval_seconds
                 .as_ref()
                 .map(|t| Into::<i32>::into(t) as u64),
+            http_get_allow_list: Vec::new(),
+            host_capabilities_allow_list: None,
+            log_level: None,
         })
     }
 }
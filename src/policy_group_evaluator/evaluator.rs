@@ -2,6 +2,7 @@ use std::{
     collections::HashMap,
     fmt,
     sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
 use kubewarden_policy_sdk::settings::SettingsValidationResponse;
@@ -10,13 +11,15 @@ use tokio::sync::mpsc;
 use tracing::debug;
 
 use crate::admission_response::{self, AdmissionResponse, AdmissionResponseStatus};
+use crate::audit::AuditSink;
 use crate::callback_requests::CallbackRequest;
 use crate::evaluation_context::EvaluationContext;
-use crate::policy_evaluator::{PolicyEvaluatorPre, ValidateRequest};
+use crate::policy_evaluator::{PolicyEvaluatorPre, ValidateRequest, ValidationReport};
 use crate::policy_group_evaluator::{
     PolicyGroupMemberEvaluationResult, PolicyGroupMemberSettings,
     errors::{EvaluationError, Result},
 };
+use crate::policy_tracing::PolicyLogRecord;
 
 /// PolicyGroupEvaluator is an evaluator that can evaluate a group of policies
 ///
@@ -60,6 +63,14 @@ pub struct PolicyGroupEvaluator {
     /// to request the computation of code that can only be run inside of an
     /// asynchronous block
     callback_channel: Option<mpsc::Sender<CallbackRequest>>,
+
+    /// Optional wall-clock limit of the evaluation of the whole group. The
+    /// host capabilities invoked by the policies are cancelled once it's reached
+    evaluation_timeout: Option<Duration>,
+
+    /// Optional sink receiving an event for each host capability invoked by
+    /// the policies of the group
+    audit_sink: Option<Arc<dyn AuditSink>>,
 }
 
 impl fmt::Debug for PolicyGroupEvaluator {
@@ -89,9 +100,25 @@ impl PolicyGroupEvaluator {
             policy_members: HashMap::new(),
             policy_members_settings: HashMap::new(),
             callback_channel,
+            evaluation_timeout: None,
+            audit_sink: None,
         }
     }
 
+    /// Limit the wall-clock duration of the evaluation of the whole group.
+    /// All the policies of the group share the same deadline
+    pub fn evaluation_timeout(mut self, timeout: Duration) -> Self {
+        self.evaluation_timeout = Some(timeout);
+        self
+    }
+
+    /// Report the host capabilities invoked by the policies of the group to
+    /// the given sink. The events carry the name of the policy member
+    pub fn audit_sink(mut self, audit_sink: Arc<dyn AuditSink>) -> Self {
+        self.audit_sink = Some(audit_sink);
+        self
+    }
+
     /// Add a policy to the group
     pub fn add_policy_member(
         &mut self,
@@ -111,6 +138,28 @@ impl PolicyGroupEvaluator {
     /// requires `+send` and `+sync`.
     #[tracing::instrument(skip(request))]
     pub fn validate(self: Arc<Self>, request: &ValidateRequest) -> AdmissionResponse {
+        self.evaluate(request, None)
+    }
+
+    /// Validate the request against the group of policies, capturing the logs
+    /// emitted by the policies that have been evaluated. The logs are still
+    /// forwarded to `tracing`
+    #[tracing::instrument(skip(request))]
+    pub fn validate_with_logs(self: Arc<Self>, request: &ValidateRequest) -> ValidationReport {
+        let policy_logs = Arc::new(Mutex::new(Vec::new()));
+        let response = self.evaluate(request, Some(policy_logs.clone()));
+        let logs = std::mem::take(&mut *policy_logs.lock().unwrap());
+
+        ValidationReport { response, logs }
+    }
+
+    /// Evaluate the expression of the group. When `policy_logs` is given, the
+    /// logs emitted by the policies are appended to it
+    fn evaluate(
+        self: Arc<Self>,
+        request: &ValidateRequest,
+        policy_logs: Option<Arc<Mutex<Vec<PolicyLogRecord>>>>,
+    ) -> AdmissionResponse {
         // We create a RAW engine, which has a really limited set of built-ins available
         let mut rhai_engine = rhai::Engine::new_raw();
 
@@ -119,6 +168,11 @@ impl PolicyGroupEvaluator {
             Mutex<HashMap<String, PolicyGroupMemberEvaluationResult>>,
         > = Arc::new(Mutex::new(HashMap::new()));
 
+        // all the policies evaluated by the expression share the same deadline
+        let deadline = self
+            .evaluation_timeout
+            .map(|timeout| Instant::now() + timeout);
+
         let policy_ids = self.policy_members.keys().cloned().collect::<Vec<String>>();
         for sub_policy_name in policy_ids {
             let rhai_eval_env = self.clone();
@@ -126,6 +180,7 @@ impl PolicyGroupEvaluator {
             let evaluation_results = policies_evaluation_results.clone();
 
             let validate_request = request.clone();
+            let policy_logs = policy_logs.clone();
             rhai_engine.register_fn(
                 sub_policy_name.clone().as_str(),
                 move || -> std::result::Result<bool, Box<EvalAltResult>> {
@@ -133,6 +188,8 @@ impl PolicyGroupEvaluator {
                         rhai_eval_env.clone(),
                         &sub_policy_name,
                         &validate_request,
+                        deadline,
+                        policy_logs.as_deref(),
                     )
                     .map_err(|e| {
                         EvalAltResult::ErrorSystem(
@@ -243,6 +300,8 @@ impl PolicyGroupEvaluator {
         self: Arc<Self>,
         policy_id: &str,
         req: &ValidateRequest,
+        deadline: Option<Instant>,
+        policy_logs: Option<&Mutex<Vec<PolicyLogRecord>>>,
     ) -> Result<AdmissionResponse> {
         debug!(?policy_id, "validate policy");
 
@@ -255,16 +314,16 @@ impl PolicyGroupEvaluator {
             .get(policy_id)
             .ok_or_else(|| EvaluationError::SettingsNotFound(policy_id.to_owned()))?;
 
-        let eval_ctx = EvaluationContext {
-            policy_id: policy_id.to_owned(),
-            callback_channel: self.callback_channel.clone(),
-            ctx_aware_resources_allow_list: settings.ctx_aware_resources_allow_list.clone(),
-            epoch_deadline: settings.epoch_deadline,
-        };
+        let eval_ctx = self.member_evaluation_context(policy_id, settings);
         let mut evaluator = evaluator_pre.rehydrate(&eval_ctx).map_err(|e| {
             EvaluationError::CannotRehydratePolicyGroupMember(policy_id.to_owned(), e)
         })?;
-        Ok(evaluator.validate(req.clone(), &settings.settings))
+        let Some(policy_logs) = policy_logs else {
+            return Ok(evaluator.validate_until(req.clone(), &settings.settings, deadline));
+        };
+        let report = evaluator.validate_with_logs_until(req.clone(), &settings.settings, deadline);
+        policy_logs.lock().unwrap().extend(report.logs);
+        Ok(report.response)
     }
 
     /// Validate the settings of the group of policies
@@ -316,6 +375,28 @@ impl PolicyGroupEvaluator {
         }
     }
 
+    /// The evaluation context of a policy member, built from its settings
+    fn member_evaluation_context(
+        &self,
+        policy_id: &str,
+        settings: &PolicyGroupMemberSettings,
+    ) -> EvaluationContext {
+        EvaluationContext {
+            policy_id: policy_id.to_owned(),
+            callback_channel: self.callback_channel.clone(),
+            ctx_aware_resources_allow_list: settings.ctx_aware_resources_allow_list.clone(),
+            http_get_allow_list: settings.http_get_allow_list.clone(),
+            host_capabilities_allow_list: settings.host_capabilities_allow_list.clone(),
+            epoch_deadline: settings.epoch_deadline,
+            evaluation_timeout: None,
+            deadline: Default::default(),
+            audit_sink: self.audit_sink.clone(),
+            current_request: Default::default(),
+            log_level: settings.log_level,
+            policy_logs: Default::default(),
+        }
+    }
+
     /// Validate the settings of a single policy
     fn validate_policy_settings(&self, policy_id: &str) -> Result<()> {
         debug!(?policy_id, "validate policy settings");
@@ -329,12 +410,7 @@ impl PolicyGroupEvaluator {
             .get(policy_id)
             .ok_or_else(|| EvaluationError::SettingsNotFound(policy_id.to_owned()))?;
 
-        let eval_ctx = EvaluationContext {
-            policy_id: policy_id.to_owned(),
-            callback_channel: self.callback_channel.clone(),
-            ctx_aware_resources_allow_list: settings.ctx_aware_resources_allow_list.clone(),
-            epoch_deadline: settings.epoch_deadline,
-        };
+        let eval_ctx = self.member_evaluation_context(policy_id, settings);
         let mut evaluator = evaluator_pre.rehydrate(&eval_ctx).map_err(|e| {
             EvaluationError::CannotRehydratePolicyGroupMember(policy_id.to_owned(), e)
         })?;
@@ -363,13 +439,17 @@ impl PolicyGroupEvaluator {
 mod tests {
     use super::*;
 
+    use std::collections::BTreeSet;
+
     use lazy_static::lazy_static;
     use rstest::*;
     use wasmtime::Engine;
 
     use crate::{
-        admission_request::AdmissionRequest,
+        admission_request::AdmissionRequest, audit::TracingAuditSink,
+        evaluation_context::HttpAllowedEndpoint,
         policy_evaluator::policy_evaluator_builder::PolicyEvaluatorBuilder,
+        policy_metadata::HostCapability, policy_tracing::PolicyLogLevel,
     };
 
     lazy_static! {
@@ -473,6 +553,9 @@ mod tests {
                     settings: Default::default(),
                     ctx_aware_resources_allow_list: Default::default(),
                     epoch_deadline: None,
+                    http_get_allow_list: Vec::new(),
+                    host_capabilities_allow_list: None,
+                    log_level: None,
                 },
             );
         }
@@ -551,6 +634,9 @@ mod tests {
                     settings: Default::default(),
                     ctx_aware_resources_allow_list: Default::default(),
                     epoch_deadline: None,
+                    http_get_allow_list: Vec::new(),
+                    host_capabilities_allow_list: None,
+                    log_level: None,
                 },
             );
         }
@@ -558,4 +644,78 @@ mod tests {
 
         assert_eq!(expression_is_valid, validation_result.valid);
     }
+
+    #[test]
+    fn validate_with_logs() {
+        let mut policy_group_evaluator = PolicyGroupEvaluator::new(
+            "group_policy",
+            "something went wrong",
+            "happy_policy_1() && unhappy_policy_1()",
+            None,
+        );
+        for (policy_id, policy_pre) in [
+            ("happy_policy_1", POLICY_ALWAYS_HAPPY.clone()),
+            ("unhappy_policy_1", POLICY_ALWAYS_UNHAPPY.clone()),
+        ] {
+            policy_group_evaluator.add_policy_member(
+                policy_id,
+                Arc::new(policy_pre),
+                PolicyGroupMemberSettings {
+                    settings: Default::default(),
+                    ctx_aware_resources_allow_list: Default::default(),
+                    epoch_deadline: None,
+                    http_get_allow_list: Vec::new(),
+                    host_capabilities_allow_list: None,
+                    log_level: None,
+                },
+            );
+        }
+        let policy_group_evaluator = Arc::new(policy_group_evaluator);
+        let validate_request = build_validate_request();
+
+        let report = policy_group_evaluator
+            .clone()
+            .validate_with_logs(&validate_request);
+
+        assert_eq!(
+            report.response,
+            policy_group_evaluator.validate(&validate_request)
+        );
+        assert!(report.logs.iter().all(|record| {
+            record.policy_id == "happy_policy_1" || record.policy_id == "unhappy_policy_1"
+        }));
+    }
+
+    #[test]
+    fn member_evaluation_context_from_settings() {
+        let policy_group_evaluator =
+            PolicyGroupEvaluator::new("group_policy", "something went wrong", "true", None)
+                .audit_sink(Arc::new(TracingAuditSink));
+        let http_get_allow_list = vec![HttpAllowedEndpoint {
+            scheme: "https".to_owned(),
+            host: "registry.example.com".to_owned(),
+            port: None,
+            path_prefix: "/v2".to_owned(),
+        }];
+        let settings = PolicyGroupMemberSettings {
+            settings: Default::default(),
+            ctx_aware_resources_allow_list: Default::default(),
+            epoch_deadline: Some(5),
+            http_get_allow_list: http_get_allow_list.clone(),
+            host_capabilities_allow_list: Some(BTreeSet::from([HostCapability::NetCidr])),
+            log_level: Some(PolicyLogLevel::Warning),
+        };
+
+        let eval_ctx = policy_group_evaluator.member_evaluation_context("member", &settings);
+
+        assert_eq!(eval_ctx.policy_id, "member");
+        assert_eq!(eval_ctx.epoch_deadline, Some(5));
+        assert_eq!(eval_ctx.http_get_allow_list, http_get_allow_list);
+        assert_eq!(
+            eval_ctx.host_capabilities_allow_list,
+            Some(BTreeSet::from([HostCapability::NetCidr]))
+        );
+        assert!(eval_ctx.audit_sink.is_some());
+        assert_eq!(eval_ctx.log_level, Some(PolicyLogLevel::Warning));
+    }
 }
@@ -4,7 +4,7 @@ use std::{
     path::Path,
 };
 
-use k8s_openapi::api::admissionregistration::v1::NamedRuleWithOperations;
+use k8s_openapi::api::admissionregistration::v1::{NamedRuleWithOperations, RuleWithOperations};
 use kubewarden_policy_sdk::metadata::ProtocolVersion;
 use semver::Version;
 use serde::{Deserialize, Serialize};
@@ -27,6 +27,18 @@ pub enum Operation {
     All,
 }
 
+impl From<&Operation> for String {
+    fn from(op: &Operation) -> String {
+        match op {
+            Operation::Create => String::from("CREATE"),
+            Operation::Update => String::from("UPDATE"),
+            Operation::Delete => String::from("DELETE"),
+            Operation::Connect => String::from("CONNECT"),
+            Operation::All => String::from("*"),
+        }
+    }
+}
+
 impl TryFrom<&str> for Operation {
     type Error = &'static str;
 
@@ -159,6 +171,18 @@ impl TryFrom<&NamedRuleWithOperations> for Rule {
     }
 }
 
+impl From<&Rule> for RuleWithOperations {
+    fn from(rule: &Rule) -> Self {
+        RuleWithOperations {
+            api_groups: Some(rule.api_groups.clone()),
+            api_versions: Some(rule.api_versions.clone()),
+            resources: Some(rule.resources.clone()),
+            operations: Some(rule.operations.iter().map(String::from).collect()),
+            scope: None,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, Validate, PartialEq, Hash, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "camelCase")]
 pub struct ContextAwareResource {
@@ -179,6 +203,65 @@ impl From<&kubewarden_policy_sdk::crd::policies::common::ContextAwareResource>
     }
 }
 
+/// A host capability that can be used by a policy
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Hash, Eq, PartialOrd, Ord)]
+pub enum HostCapability {
+    /// Digest of the manifests of the images
+    #[serde(rename = "oci.manifest_digest")]
+    OciManifestDigest,
+    /// Manifests and configurations of the images
+    #[serde(rename = "oci.manifest")]
+    OciManifest,
+    /// Listing of the tags of the images
+    #[serde(rename = "oci.list_tags")]
+    OciListTags,
+    /// Listing of the artifacts referring to the images
+    #[serde(rename = "oci.referrers")]
+    OciReferrers,
+    /// Verification of Sigstore signatures and attestations
+    #[serde(rename = "sigstore.verify")]
+    SigstoreVerify,
+    /// Verification of Notation signatures
+    #[serde(rename = "notation.verify")]
+    NotationVerify,
+    /// All the kinds of DNS lookups
+    #[serde(rename = "net.dns_lookup")]
+    NetDnsLookup,
+    /// HTTP GET requests to the endpoints allowed to the policy
+    #[serde(rename = "net.http_get")]
+    NetHttpGet,
+    /// Classification of IP addresses and CIDR ranges
+    #[serde(rename = "net.cidr")]
+    NetCidr,
+    /// Parsing of certificates and certificate signing requests
+    #[serde(rename = "crypto.parse_certificate")]
+    CryptoParseCertificate,
+    /// Verification of certificates against the given chain
+    #[serde(rename = "crypto.verify_certificate")]
+    CryptoVerifyCertificate,
+    /// Listing of Kubernetes resources, including the context of Rego policies
+    #[serde(rename = "kubernetes.list_resources")]
+    KubernetesListResources,
+    /// Retrieval of a single Kubernetes resource
+    #[serde(rename = "kubernetes.get_resource")]
+    KubernetesGetResource,
+    /// Authorization checks, like the ones of `kubectl auth can-i`
+    #[serde(rename = "kubernetes.can_i")]
+    KubernetesCanI,
+    /// Any capability this version of the host doesn't know about, e.g. one
+    /// declared by a policy targeting a newer host. No operation requires it,
+    /// hence declaring it doesn't grant access to anything
+    #[serde(rename = "unknown", other)]
+    Unknown,
+}
+
+impl Display for HostCapability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_string(self).map_err(|_| fmt::Error {})?;
+        write!(f, "{}", json.replace('"', ""))
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
 pub enum PolicyType {
     #[default]
@@ -215,8 +298,18 @@ pub struct Metadata {
     #[serde(default)]
     #[validate(nested)]
     pub context_aware_resources: BTreeSet<ContextAwareResource>,
+    /// The host capabilities used by the policy. When provided, the policy
+    /// cannot use any other host capability. When not provided, all the host
+    /// capabilities can be used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_capabilities: Option<BTreeSet<HostCapability>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub minimum_kubewarden_version: Option<Version>,
+    /// Optional JSON Schema describing the settings accepted by the policy.
+    /// When provided, the settings are validated against it before being
+    /// sent to the policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settings_schema: Option<serde_json::Value>,
 }
 
 const fn _default_true() -> bool {
@@ -234,7 +327,9 @@ impl Default for Metadata {
             execution_mode: PolicyExecutionMode::KubewardenWapc,
             policy_type: PolicyType::Kubernetes,
             context_aware_resources: BTreeSet::new(),
+            host_capabilities: None,
             minimum_kubewarden_version: None,
+            settings_schema: None,
         }
     }
 }
@@ -269,6 +364,13 @@ fn validate_metadata(metadata: &Metadata) -> Result<(), ValidationError> {
             "Must specify a valid protocol version",
         ));
     }
+    if let Some(schema) = &metadata.settings_schema
+        && jsonschema::validator_for(schema).is_err()
+    {
+        return Err(ValidationError::new(
+            "settingsSchema must be a valid JSON Schema",
+        ));
+    }
     Ok(())
 }
 
@@ -405,6 +507,33 @@ mod tests {
         }
     }
 
+    #[test]
+    fn metadata_with_settings_schema() {
+        let json_metadata = json!({
+            "protocolVersion": "v1",
+            "rules": [ ],
+            "mutating": false,
+            "settingsSchema": {
+                "type": "object",
+                "properties": {
+                    "replicas": { "type": "integer" }
+                }
+            }
+        });
+
+        let metadata: Metadata =
+            serde_json::from_value(json_metadata).expect("cannot deserialize Metadata");
+        assert!(metadata.settings_schema.is_some());
+        assert!(metadata.validate().is_ok());
+
+        let metadata = Metadata {
+            protocol_version: Some(ProtocolVersion::V1),
+            settings_schema: Some(json!({ "type": "not-a-type" })),
+            ..Default::default()
+        };
+        assert!(metadata.validate().is_err());
+    }
+
     #[test]
     fn metadata_without_rules() -> Result<(), ()> {
         let metadata = Metadata {
@@ -713,4 +842,64 @@ mod tests {
 
         assert!(metadata.validate().is_err());
     }
+
+    #[test]
+    fn metadata_with_host_capabilities() {
+        let json_metadata = json!({
+            "protocolVersion": "v1",
+            "rules": [ ],
+            "mutating": false,
+            "hostCapabilities": ["oci.manifest_digest", "sigstore.verify"],
+        });
+
+        let metadata: Metadata =
+            serde_json::from_value(json_metadata).expect("cannot deserialize Metadata");
+
+        assert_eq!(
+            metadata.host_capabilities,
+            Some(BTreeSet::from([
+                HostCapability::OciManifestDigest,
+                HostCapability::SigstoreVerify,
+            ]))
+        );
+        assert_eq!(
+            HostCapability::SigstoreVerify.to_string(),
+            "sigstore.verify"
+        );
+    }
+
+    #[test]
+    fn metadata_with_unknown_host_capabilities() {
+        let json_metadata = json!({
+            "protocolVersion": "v1",
+            "rules": [ ],
+            "mutating": false,
+            "hostCapabilities": ["oci.manifest", "quantum.entangle"],
+        });
+
+        let metadata: Metadata =
+            serde_json::from_value(json_metadata).expect("cannot deserialize Metadata");
+
+        assert_eq!(
+            metadata.host_capabilities,
+            Some(BTreeSet::from([
+                HostCapability::OciManifest,
+                HostCapability::Unknown,
+            ]))
+        );
+    }
+
+    #[test]
+    fn metadata_without_host_capabilities() {
+        let json_metadata = json!({
+            "protocolVersion": "v1",
+            "rules": [ ],
+            "mutating": false,
+        });
+
+        let metadata: Metadata =
+            serde_json::from_value(json_metadata).expect("cannot deserialize Metadata");
+
+        assert!(metadata.host_capabilities.is_none());
+    }
 }
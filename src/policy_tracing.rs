@@ -1,11 +1,16 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
 use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
 use tracing::{Level, event};
 
 use crate::evaluation_context::EvaluationContext;
 
-#[derive(Debug, Serialize)]
-enum PolicyLogEntryLevel {
+/// The severity of a log entry emitted by a policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyLogLevel {
     Trace,
     Debug,
     Info,
@@ -13,65 +18,311 @@ enum PolicyLogEntryLevel {
     Error,
 }
 
-impl<'de> Deserialize<'de> for PolicyLogEntryLevel {
+impl<'de> Deserialize<'de> for PolicyLogLevel {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::de::Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
         match s.to_uppercase().as_str() {
-            "TRACE" => Ok(PolicyLogEntryLevel::Trace),
-            "DEBUG" => Ok(PolicyLogEntryLevel::Debug),
-            "INFO" => Ok(PolicyLogEntryLevel::Info),
-            "WARNING" => Ok(PolicyLogEntryLevel::Warning),
-            "ERROR" => Ok(PolicyLogEntryLevel::Error),
+            "TRACE" => Ok(PolicyLogLevel::Trace),
+            "DEBUG" => Ok(PolicyLogLevel::Debug),
+            "INFO" => Ok(PolicyLogLevel::Info),
+            "WARNING" => Ok(PolicyLogLevel::Warning),
+            "ERROR" => Ok(PolicyLogLevel::Error),
             _ => Err(anyhow!("unknown log level {}", s)).map_err(serde::de::Error::custom),
         }
     }
 }
 
+/// The runtime of the policy that emitted a log entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyLogRuntime {
+    /// Logs sent via the waPC `tracing/log` host capability
+    Wapc,
+    /// Lines written to the standard error of WASI policies
+    Wasi,
+    /// Messages printed, or aborts raised, by Rego policies
+    Rego,
+}
+
+impl fmt::Display for PolicyLogRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let runtime = match self {
+            PolicyLogRuntime::Wapc => "wapc",
+            PolicyLogRuntime::Wasi => "wasi",
+            PolicyLogRuntime::Rego => "rego",
+        };
+        write!(f, "{runtime}")
+    }
+}
+
+/// A log entry emitted by a policy, regardless of its runtime
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PolicyLogRecord {
+    pub policy_id: String,
+    /// The uid of the admission request being evaluated, if any
+    pub request_uid: Option<String>,
+    pub runtime: PolicyLogRuntime,
+    pub level: PolicyLogLevel,
+    pub message: String,
+    /// The structured data attached to the entry by the policy
+    pub data: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Collects the logs emitted by a policy while an evaluation is in progress.
+/// The buffer is shared by all the copies of the evaluation context, including
+/// the ones owned by the runtimes
+#[derive(Clone, Debug, Default)]
+pub struct PolicyLogCapture(Arc<Mutex<Option<Vec<PolicyLogRecord>>>>);
+
+impl PolicyLogCapture {
+    /// Start collecting the logs, discarding the ones collected previously
+    pub(crate) fn start(&self) {
+        *self.0.lock().expect("cannot lock policy log capture") = Some(Vec::new());
+    }
+
+    /// Stop collecting the logs, returning the ones collected so far
+    pub(crate) fn finish(&self) -> Vec<PolicyLogRecord> {
+        self.0
+            .lock()
+            .expect("cannot lock policy log capture")
+            .take()
+            .unwrap_or_default()
+    }
+
+    fn push(&self, record: &PolicyLogRecord) {
+        if let Some(records) = self
+            .0
+            .lock()
+            .expect("cannot lock policy log capture")
+            .as_mut()
+        {
+            records.push(record.clone());
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct PolicyLogEntry {
-    level: PolicyLogEntryLevel,
+    level: PolicyLogLevel,
     message: Option<String>,
     #[serde(flatten)]
-    data: Option<serde_json::Map<String, serde_json::Value>>,
+    data: serde_json::Map<String, serde_json::Value>,
 }
 
 impl EvaluationContext {
+    /// Handle the logs sent by waPC policies via the `tracing/log` host capability
     #[tracing::instrument(name = "policy_log", skip(contents))]
     pub(crate) fn log(&self, contents: &[u8]) -> Result<()> {
         let log_entry: PolicyLogEntry = serde_json::from_slice(contents)?;
+        self.emit_policy_log(
+            PolicyLogRuntime::Wapc,
+            log_entry.level,
+            log_entry.message.unwrap_or_default(),
+            log_entry.data,
+        );
+
+        Ok(())
+    }
+
+    /// Handle what WASI policies wrote to their standard error, each line
+    /// becomes a log entry
+    pub(crate) fn log_stderr(&self, operation: &str, stderr: &str) {
+        for line in stderr.lines().filter(|line| !line.trim().is_empty()) {
+            self.emit_policy_log(
+                PolicyLogRuntime::Wasi,
+                PolicyLogLevel::Warning,
+                line.to_owned(),
+                serde_json::Map::from_iter([("operation".to_owned(), operation.into())]),
+            );
+        }
+    }
+
+    /// Forward a log entry of the policy to `tracing`, using the `policy_log`
+    /// target, and to the capture buffer of the current evaluation. Entries
+    /// below the log level of the policy are discarded
+    pub(crate) fn emit_policy_log(
+        &self,
+        runtime: PolicyLogRuntime,
+        level: PolicyLogLevel,
+        message: String,
+        data: serde_json::Map<String, serde_json::Value>,
+    ) {
+        if self.log_level.is_some_and(|min_level| level < min_level) {
+            return;
+        }
+
+        let record = PolicyLogRecord {
+            policy_id: self.policy_id.clone(),
+            request_uid: self.current_request.uid(),
+            runtime,
+            level,
+            message,
+            data,
+        };
         macro_rules! log {
             ($level:path) => {
                 event!(
                     target: "policy_log",
                     $level,
-                    data = %&serde_json::to_string(&log_entry.data.clone().unwrap())?.as_str(),
+                    policy_id = record.policy_id,
+                    request_uid = record.request_uid,
+                    runtime = %record.runtime,
+                    data = %serde_json::Value::Object(record.data.clone()),
                     "{}",
-                    log_entry.message.clone().unwrap_or_default(),
+                    record.message,
                 );
             };
         }
 
-        match log_entry.level {
-            PolicyLogEntryLevel::Trace => {
+        match record.level {
+            PolicyLogLevel::Trace => {
                 log!(Level::TRACE);
             }
-            PolicyLogEntryLevel::Debug => {
+            PolicyLogLevel::Debug => {
                 log!(Level::DEBUG);
             }
-            PolicyLogEntryLevel::Info => {
+            PolicyLogLevel::Info => {
                 log!(Level::INFO);
             }
-            PolicyLogEntryLevel::Warning => {
+            PolicyLogLevel::Warning => {
                 log!(Level::WARN);
             }
-            PolicyLogEntryLevel::Error => {
+            PolicyLogLevel::Error => {
                 log!(Level::ERROR);
             }
         };
 
-        Ok(())
+        self.policy_logs.push(&record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+    use serde_json::json;
+
+    fn captured_logs(eval_ctx: &EvaluationContext, emit: impl FnOnce()) -> Vec<PolicyLogRecord> {
+        eval_ctx.policy_logs.start();
+        emit();
+        eval_ctx.policy_logs.finish()
+    }
+
+    #[test]
+    fn log_wapc_entry() {
+        let eval_ctx = EvaluationContext {
+            policy_id: "pod-privileged".to_owned(),
+            ..Default::default()
+        };
+        eval_ctx.current_request.set_uid(Some("uid-1".to_owned()));
+
+        let logs = captured_logs(&eval_ctx, || {
+            eval_ctx
+                .log(br#"{"level": "warning", "message": "privileged pod", "pod": "nginx"}"#)
+                .unwrap();
+        });
+
+        assert_eq!(
+            logs,
+            vec![PolicyLogRecord {
+                policy_id: "pod-privileged".to_owned(),
+                request_uid: Some("uid-1".to_owned()),
+                runtime: PolicyLogRuntime::Wapc,
+                level: PolicyLogLevel::Warning,
+                message: "privileged pod".to_owned(),
+                data: serde_json::Map::from_iter([("pod".to_owned(), json!("nginx"))]),
+            }]
+        );
+    }
+
+    #[test]
+    fn log_invalid_wapc_entry() {
+        let eval_ctx = EvaluationContext::default();
+
+        assert!(eval_ctx.log(br#"{"level": "fatal"}"#).is_err());
+    }
+
+    #[test]
+    fn log_wasi_stderr() {
+        let eval_ctx = EvaluationContext::default();
+
+        let logs = captured_logs(&eval_ctx, || {
+            eval_ctx.log_stderr("validate", "first line\n\nsecond line\n");
+        });
+
+        assert_eq!(
+            logs.iter()
+                .map(|record| (record.runtime, record.message.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (PolicyLogRuntime::Wasi, "first line"),
+                (PolicyLogRuntime::Wasi, "second line"),
+            ]
+        );
+        assert_eq!(
+            logs[0].data,
+            json!({"operation": "validate"})
+                .as_object()
+                .unwrap()
+                .clone()
+        );
+    }
+
+    #[rstest]
+    #[case::no_filter(None, 5)]
+    #[case::info(Some(PolicyLogLevel::Info), 3)]
+    #[case::error(Some(PolicyLogLevel::Error), 1)]
+    fn log_level_filter(#[case] log_level: Option<PolicyLogLevel>, #[case] expected: usize) {
+        let eval_ctx = EvaluationContext {
+            log_level,
+            ..Default::default()
+        };
+
+        let logs = captured_logs(&eval_ctx, || {
+            for level in [
+                PolicyLogLevel::Trace,
+                PolicyLogLevel::Debug,
+                PolicyLogLevel::Info,
+                PolicyLogLevel::Warning,
+                PolicyLogLevel::Error,
+            ] {
+                eval_ctx.emit_policy_log(
+                    PolicyLogRuntime::Rego,
+                    level,
+                    "message".to_owned(),
+                    serde_json::Map::new(),
+                );
+            }
+        });
+
+        assert_eq!(logs.len(), expected);
+    }
+
+    #[test]
+    fn logs_are_not_captured_outside_of_evaluations() {
+        let eval_ctx = EvaluationContext::default();
+
+        eval_ctx.emit_policy_log(
+            PolicyLogRuntime::Rego,
+            PolicyLogLevel::Info,
+            "message".to_owned(),
+            serde_json::Map::new(),
+        );
+
+        assert!(eval_ctx.policy_logs.finish().is_empty());
+    }
+
+    #[rstest]
+    #[case::lowercase("\"warning\"", PolicyLogLevel::Warning)]
+    #[case::uppercase("\"ERROR\"", PolicyLogLevel::Error)]
+    fn deserialize_level(#[case] level: &str, #[case] expected: PolicyLogLevel) {
+        assert_eq!(
+            serde_json::from_str::<PolicyLogLevel>(level).unwrap(),
+            expected
+        );
     }
 }
@@ -0,0 +1,144 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use k8s_openapi::{
+    api::rbac::v1::{ClusterRole, PolicyRule},
+    apimachinery::pkg::apis::meta::v1::ObjectMeta,
+};
+
+use crate::discovery::DiscoverySnapshot;
+use crate::errors::RbacError;
+use crate::policy_metadata::ContextAwareResource;
+
+/// The verbs required by a policy to read context aware resources
+const READ_VERBS: [&str; 3] = ["get", "list", "watch"];
+
+/// Build the least privileged `ClusterRole` that grants read access to the
+/// given context aware resources.
+///
+/// The plural names of the resources are resolved using the provided
+/// [`DiscoverySnapshot`], hence no interaction with a Kubernetes API server is required.
+/// A rule is generated for each API group, sorted by group name.
+pub fn cluster_role_for_context_aware_resources(
+    name: &str,
+    resources: &BTreeSet<ContextAwareResource>,
+    discovery: &DiscoverySnapshot,
+) -> Result<ClusterRole, RbacError> {
+    let mut resources_by_group: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+
+    for resource in resources {
+        let discovered = discovery
+            .get(&resource.api_version, &resource.kind)
+            .ok_or_else(|| RbacError::UnknownResource {
+                api_version: resource.api_version.clone(),
+                kind: resource.kind.clone(),
+            })?;
+        resources_by_group
+            .entry(discovered.group.clone())
+            .or_default()
+            .insert(discovered.plural.clone());
+    }
+
+    let rules = resources_by_group
+        .into_iter()
+        .map(|(group, plurals)| PolicyRule {
+            api_groups: Some(vec![group]),
+            resources: Some(plurals.into_iter().collect()),
+            verbs: READ_VERBS.iter().map(|v| v.to_string()).collect(),
+            ..Default::default()
+        })
+        .collect();
+
+    Ok(ClusterRole {
+        metadata: ObjectMeta {
+            name: Some(name.to_owned()),
+            ..Default::default()
+        },
+        rules: Some(rules),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::discovery::DiscoveredResource;
+
+    fn discovery() -> DiscoverySnapshot {
+        let mut snapshot = DiscoverySnapshot::default();
+        for (group, version, kind, plural) in [
+            ("", "v1", "Pod", "pods"),
+            ("", "v1", "Namespace", "namespaces"),
+            ("apps", "v1", "Deployment", "deployments"),
+            ("apps", "v1", "StatefulSet", "statefulsets"),
+        ] {
+            snapshot.insert(DiscoveredResource {
+                group: group.to_string(),
+                version: version.to_string(),
+                api_version: if group.is_empty() {
+                    version.to_string()
+                } else {
+                    format!("{group}/{version}")
+                },
+                kind: kind.to_string(),
+                plural: plural.to_string(),
+                namespaced: kind != "Namespace",
+            });
+        }
+        snapshot
+    }
+
+    fn context_aware_resource(api_version: &str, kind: &str) -> ContextAwareResource {
+        ContextAwareResource {
+            api_version: api_version.to_string(),
+            kind: kind.to_string(),
+        }
+    }
+
+    #[test]
+    fn build_cluster_role() {
+        let resources = BTreeSet::from([
+            context_aware_resource("apps/v1", "StatefulSet"),
+            context_aware_resource("v1", "Pod"),
+            context_aware_resource("apps/v1", "Deployment"),
+            context_aware_resource("v1", "Namespace"),
+        ]);
+
+        let cluster_role =
+            cluster_role_for_context_aware_resources("my-policy", &resources, &discovery())
+                .expect("cannot build cluster role");
+
+        assert_eq!(cluster_role.metadata.name.as_deref(), Some("my-policy"));
+        let verbs: Vec<String> = READ_VERBS.iter().map(|v| v.to_string()).collect();
+        assert_eq!(
+            cluster_role.rules,
+            Some(vec![
+                PolicyRule {
+                    api_groups: Some(vec!["".to_string()]),
+                    resources: Some(vec!["namespaces".to_string(), "pods".to_string()]),
+                    verbs: verbs.clone(),
+                    ..Default::default()
+                },
+                PolicyRule {
+                    api_groups: Some(vec!["apps".to_string()]),
+                    resources: Some(vec!["deployments".to_string(), "statefulsets".to_string()]),
+                    verbs,
+                    ..Default::default()
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn build_cluster_role_with_unknown_resource() {
+        let resources = BTreeSet::from([context_aware_resource("v1", "Secret")]);
+
+        let result =
+            cluster_role_for_context_aware_resources("my-policy", &resources, &discovery());
+
+        assert!(matches!(
+            result,
+            Err(RbacError::UnknownResource { api_version, kind }) if api_version == "v1" && kind == "Secret"
+        ));
+    }
+}
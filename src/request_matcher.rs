@@ -0,0 +1,463 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+use serde::{Deserialize, Serialize};
+
+pub mod errors;
+pub(crate) mod label_selector;
+
+use crate::admission_request::AdmissionRequest;
+use crate::policy_evaluator::ValidateRequest;
+use crate::policy_metadata::{Operation, Rule};
+use crate::request_matcher::errors::{RequestMatcherError, Result};
+
+/// The operator used by a [`MatchCondition`]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum MatchConditionOperator {
+    /// The value found at the given path must be equal to the provided one
+    Equals(serde_json::Value),
+    /// The value found at the given path must be different from the provided one
+    NotEquals(serde_json::Value),
+    /// The value found at the given path must be one of the provided ones
+    In(Vec<serde_json::Value>),
+    /// The value found at the given path must not be any of the provided ones
+    NotIn(Vec<serde_json::Value>),
+    /// The given path must exist
+    Exists,
+    /// The given path must not exist
+    DoesNotExist,
+}
+
+/// A condition that must be satisfied by a request for the policy to be evaluated.
+///
+/// This is a CEL-free version of the Kubernetes `matchConditions`: the
+/// condition looks up the value found at `path` (a JSON pointer into the
+/// request) and evaluates it with the given operator.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchCondition {
+    /// The name of the condition, used when reporting errors
+    pub name: String,
+    /// A JSON pointer (RFC 6901) into the request, e.g. `/userInfo/username`
+    pub path: String,
+    /// The operator used to evaluate the value found at `path`
+    pub operator: MatchConditionOperator,
+}
+
+impl MatchCondition {
+    fn matches(&self, request: &serde_json::Value) -> Result<bool> {
+        if !self.path.is_empty() && !self.path.starts_with('/') {
+            return Err(RequestMatcherError::InvalidMatchConditionPointer {
+                name: self.name.clone(),
+                pointer: self.path.clone(),
+            });
+        }
+        let value = request.pointer(&self.path);
+
+        Ok(match &self.operator {
+            MatchConditionOperator::Equals(expected) => value == Some(expected),
+            MatchConditionOperator::NotEquals(expected) => value != Some(expected),
+            MatchConditionOperator::In(expected) => value.is_some_and(|v| expected.contains(v)),
+            MatchConditionOperator::NotIn(expected) => value.is_none_or(|v| !expected.contains(v)),
+            MatchConditionOperator::Exists => value.is_some(),
+            MatchConditionOperator::DoesNotExist => value.is_none(),
+        })
+    }
+}
+
+/// Decides whether a [`ValidateRequest`] has to be evaluated by a policy.
+///
+/// Inside of a Kubernetes cluster this is done by the API server, using the
+/// configuration of the webhook. Offline consumers (like audit scanners and
+/// CLI testers) can use this struct to avoid evaluating requests that would
+/// never reach the policy.
+///
+/// The following checks are performed, in this order:
+///
+/// * `rules`: the resource and the operation of the request must match one of
+///   the rules. An empty list of rules matches every request
+/// * `namespace_selector`: the labels of the namespace of the request must
+///   match the selector. Cluster wide resources always match, with the exception
+///   of `Namespace` objects, which are matched using their own labels
+/// * `object_selector`: the labels of either the object or the old object must
+///   match the selector
+/// * `match_conditions`: all the conditions must be satisfied
+///
+/// Rules and selectors are not applied to raw requests, only the match conditions are.
+#[derive(Debug, Clone, Default)]
+pub struct RequestMatcher {
+    rules: Vec<Rule>,
+    namespace_selector: Option<LabelSelector>,
+    object_selector: Option<LabelSelector>,
+    match_conditions: Vec<MatchCondition>,
+}
+
+impl RequestMatcher {
+    /// Create a new matcher that uses the given rules. These are usually
+    /// taken from the [`Metadata`](crate::policy_metadata::Metadata) of the policy
+    pub fn new(rules: &[Rule]) -> Self {
+        RequestMatcher {
+            rules: rules.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    /// Only match requests targeting namespaces whose labels match the given selector
+    #[must_use]
+    pub fn namespace_selector(mut self, selector: LabelSelector) -> Self {
+        self.namespace_selector = Some(selector);
+        self
+    }
+
+    /// Only match requests whose object labels match the given selector
+    #[must_use]
+    pub fn object_selector(mut self, selector: LabelSelector) -> Self {
+        self.object_selector = Some(selector);
+        self
+    }
+
+    /// Only match requests that satisfy all the given conditions
+    #[must_use]
+    pub fn match_conditions(mut self, conditions: Vec<MatchCondition>) -> Self {
+        self.match_conditions = conditions;
+        self
+    }
+
+    /// Returns true when the policy has to evaluate the given request.
+    ///
+    /// `namespace_labels` are the labels of the namespace the request belongs to. They
+    /// are used only when a namespace selector is defined, when not provided the
+    /// namespace is considered to have no labels.
+    pub fn matches(
+        &self,
+        request: &ValidateRequest,
+        namespace_labels: Option<&BTreeMap<String, String>>,
+    ) -> Result<bool> {
+        if let ValidateRequest::AdmissionRequest(adm_req) = request {
+            if !self.rules_match(adm_req) {
+                return Ok(false);
+            }
+            if !self.namespace_selector_matches(adm_req, namespace_labels)? {
+                return Ok(false);
+            }
+            if !self.object_selector_matches(adm_req)? {
+                return Ok(false);
+            }
+        }
+
+        if self.match_conditions.is_empty() {
+            return Ok(true);
+        }
+
+        let request_value = match request {
+            ValidateRequest::Raw(raw) => raw.clone(),
+            ValidateRequest::AdmissionRequest(adm_req) => serde_json::to_value(adm_req)
+                .map_err(|e| RequestMatcherError::SerializeRequest(e.to_string()))?,
+        };
+        for condition in &self.match_conditions {
+            if !condition.matches(&request_value)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn rules_match(&self, request: &AdmissionRequest) -> bool {
+        if self.rules.is_empty() {
+            return true;
+        }
+
+        self.rules.iter().any(|rule| rule_matches(rule, request))
+    }
+
+    fn namespace_selector_matches(
+        &self,
+        request: &AdmissionRequest,
+        namespace_labels: Option<&BTreeMap<String, String>>,
+    ) -> Result<bool> {
+        let selector = match &self.namespace_selector {
+            Some(selector) => selector,
+            None => return Ok(true),
+        };
+
+        if request.kind.group.is_empty() && request.kind.kind == "Namespace" {
+            // DELETE requests have only the old object
+            let namespace = request.object.as_ref().or(request.old_object.as_ref());
+            let labels = object_labels(namespace.map(|o| &o.0))?;
+            return label_selector::matches(selector, &labels);
+        }
+
+        if request.namespace.as_deref().unwrap_or_default().is_empty() {
+            // cluster wide resources are not affected by the namespace selector
+            return Ok(true);
+        }
+
+        let empty_labels = BTreeMap::new();
+        label_selector::matches(selector, namespace_labels.unwrap_or(&empty_labels))
+    }
+
+    fn object_selector_matches(&self, request: &AdmissionRequest) -> Result<bool> {
+        let selector = match &self.object_selector {
+            Some(selector) => selector,
+            None => return Ok(true),
+        };
+
+        let object_labels = object_labels(request.object.as_ref().map(|o| &o.0))?;
+        if label_selector::matches(selector, &object_labels)? {
+            return Ok(true);
+        }
+
+        let old_object_labels = object_labels(request.old_object.as_ref().map(|o| &o.0))?;
+        label_selector::matches(selector, &old_object_labels)
+    }
+}
+
+fn rule_matches(rule: &Rule, request: &AdmissionRequest) -> bool {
+    let operation_matches = Operation::try_from(request.operation.as_str())
+        .map(|operation| {
+            rule.operations
+                .iter()
+                .any(|op| op == &Operation::All || op == &operation)
+        })
+        .unwrap_or_default();
+
+    operation_matches
+        && matches_or_wildcard(&rule.api_groups, &request.resource.group)
+        && matches_or_wildcard(&rule.api_versions, &request.resource.version)
+        && rule.resources.iter().any(|resource| {
+            resource_matches(
+                resource,
+                &request.resource.resource,
+                request.sub_resource.as_deref().unwrap_or_default(),
+            )
+        })
+}
+
+fn matches_or_wildcard(allowed: &[String], value: &str) -> bool {
+    allowed.iter().any(|a| a == "*" || a == value)
+}
+
+// This is a transposition of the check done by Kubernetes, see
+// https://github.com/kubernetes/kubernetes/blob/v1.30.0/staging/src/k8s.io/apiserver/pkg/admission/plugin/webhook/predicates/rules/rules.go
+fn resource_matches(rule_resource: &str, resource: &str, sub_resource: &str) -> bool {
+    let (rule_res, rule_sub) = rule_resource.split_once('/').unwrap_or((rule_resource, ""));
+
+    (rule_res == "*" || rule_res == resource) && (rule_sub == "*" || rule_sub == sub_resource)
+}
+
+fn object_labels(object: Option<&serde_json::Value>) -> Result<BTreeMap<String, String>> {
+    match object.and_then(|o| o.pointer("/metadata/labels")) {
+        Some(labels) => serde_json::from_value(labels.clone())
+            .map_err(|e| RequestMatcherError::ObjectLabels(e.to_string())),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelectorRequirement;
+    use rstest::rstest;
+    use serde_json::json;
+
+    fn pod_request(operation: &str, sub_resource: Option<&str>) -> ValidateRequest {
+        let mut request = json!({
+            "uid": "uid",
+            "kind": { "group": "", "version": "v1", "kind": "Pod" },
+            "resource": { "group": "", "version": "v1", "resource": "pods" },
+            "namespace": "default",
+            "operation": operation,
+            "userInfo": { "username": "alice" },
+            "object": {
+                "apiVersion": "v1",
+                "kind": "Pod",
+                "metadata": { "name": "nginx", "labels": { "env": "prod" } }
+            }
+        });
+        if let Some(sub_resource) = sub_resource {
+            request["subResource"] = json!(sub_resource);
+        }
+
+        ValidateRequest::AdmissionRequest(Box::new(
+            serde_json::from_value(request).expect("cannot deserialize request"),
+        ))
+    }
+
+    fn rule(api_groups: &[&str], resources: &[&str], operations: Vec<Operation>) -> Rule {
+        Rule {
+            api_groups: api_groups.iter().map(|s| s.to_string()).collect(),
+            api_versions: vec!["v1".to_string()],
+            resources: resources.iter().map(|s| s.to_string()).collect(),
+            operations,
+        }
+    }
+
+    fn env_selector(operator: &str, values: &[&str]) -> LabelSelector {
+        LabelSelector {
+            match_expressions: Some(vec![LabelSelectorRequirement {
+                key: "env".to_string(),
+                operator: operator.to_string(),
+                values: Some(values.iter().map(|s| s.to_string()).collect()),
+            }]),
+            ..Default::default()
+        }
+    }
+
+    #[rstest]
+    #[case::no_rules(vec![], "CREATE", None, true)]
+    #[case::exact_match(vec![rule(&[""], &["pods"], vec![Operation::Create])], "CREATE", None, true)]
+    #[case::wrong_operation(vec![rule(&[""], &["pods"], vec![Operation::Create])], "UPDATE", None, false)]
+    #[case::all_operations(vec![rule(&[""], &["pods"], vec![Operation::All])], "DELETE", None, true)]
+    #[case::wrong_group(vec![rule(&["apps"], &["pods"], vec![Operation::All])], "CREATE", None, false)]
+    #[case::wildcard_group(vec![rule(&["*"], &["*"], vec![Operation::All])], "CREATE", None, true)]
+    #[case::resource_does_not_match_subresource(
+        vec![rule(&[""], &["pods"], vec![Operation::All])],
+        "UPDATE",
+        Some("status"),
+        false
+    )]
+    #[case::subresource(
+        vec![rule(&[""], &["pods/status"], vec![Operation::All])],
+        "UPDATE",
+        Some("status"),
+        true
+    )]
+    #[case::wildcard_subresource(
+        vec![rule(&[""], &["*/*"], vec![Operation::All])],
+        "UPDATE",
+        Some("status"),
+        true
+    )]
+    #[case::second_rule_matches(
+        vec![
+            rule(&["apps"], &["deployments"], vec![Operation::All]),
+            rule(&[""], &["pods"], vec![Operation::Create]),
+        ],
+        "CREATE",
+        None,
+        true
+    )]
+    fn match_rules(
+        #[case] rules: Vec<Rule>,
+        #[case] operation: &str,
+        #[case] sub_resource: Option<&str>,
+        #[case] expected: bool,
+    ) {
+        let matcher = RequestMatcher::new(&rules);
+        let request = pod_request(operation, sub_resource);
+
+        assert_eq!(matcher.matches(&request, None), Ok(expected));
+    }
+
+    #[rstest]
+    #[case::matching_labels(Some(BTreeMap::from([("env".to_string(), "prod".to_string())])), true)]
+    #[case::not_matching_labels(Some(BTreeMap::from([("env".to_string(), "dev".to_string())])), false)]
+    #[case::no_labels(None, false)]
+    fn match_namespace_selector(
+        #[case] namespace_labels: Option<BTreeMap<String, String>>,
+        #[case] expected: bool,
+    ) {
+        let matcher = RequestMatcher::new(&[]).namespace_selector(env_selector("In", &["prod"]));
+        let request = pod_request("CREATE", None);
+
+        assert_eq!(
+            matcher.matches(&request, namespace_labels.as_ref()),
+            Ok(expected)
+        );
+    }
+
+    #[rstest]
+    #[case::create("CREATE", "object", true)]
+    #[case::delete("DELETE", "oldObject", true)]
+    #[case::no_object("DELETE", "unknownField", false)]
+    fn match_namespace_selector_on_namespaces(
+        #[case] operation: &str,
+        #[case] object_field: &str,
+        #[case] expected: bool,
+    ) {
+        let mut request = json!({
+            "uid": "uid",
+            "kind": { "group": "", "version": "v1", "kind": "Namespace" },
+            "resource": { "group": "", "version": "v1", "resource": "namespaces" },
+            "name": "production",
+            "operation": operation,
+            "userInfo": { "username": "alice" },
+        });
+        request[object_field] = json!({
+            "apiVersion": "v1",
+            "kind": "Namespace",
+            "metadata": { "name": "production", "labels": { "env": "prod" } }
+        });
+        let request = ValidateRequest::AdmissionRequest(Box::new(
+            serde_json::from_value(request).expect("cannot deserialize request"),
+        ));
+        let matcher = RequestMatcher::new(&[]).namespace_selector(env_selector("In", &["prod"]));
+
+        assert_eq!(matcher.matches(&request, None), Ok(expected));
+    }
+
+    #[rstest]
+    #[case::matching_object(env_selector("In", &["prod"]), true)]
+    #[case::not_matching_object(env_selector("NotIn", &["prod"]), false)]
+    fn match_object_selector(#[case] selector: LabelSelector, #[case] expected: bool) {
+        let matcher = RequestMatcher::new(&[]).object_selector(selector);
+        let request = pod_request("CREATE", None);
+
+        assert_eq!(matcher.matches(&request, None), Ok(expected));
+    }
+
+    #[rstest]
+    #[case::equals(MatchConditionOperator::Equals(json!("alice")), true)]
+    #[case::not_equals(MatchConditionOperator::NotEquals(json!("alice")), false)]
+    #[case::in_values(MatchConditionOperator::In(vec![json!("bob"), json!("alice")]), true)]
+    #[case::not_in_values(MatchConditionOperator::NotIn(vec![json!("bob")]), true)]
+    #[case::exists(MatchConditionOperator::Exists, true)]
+    #[case::does_not_exist(MatchConditionOperator::DoesNotExist, false)]
+    fn match_conditions(#[case] operator: MatchConditionOperator, #[case] expected: bool) {
+        let matcher = RequestMatcher::new(&[]).match_conditions(vec![MatchCondition {
+            name: "username".to_string(),
+            path: "/userInfo/username".to_string(),
+            operator,
+        }]);
+        let request = pod_request("CREATE", None);
+
+        assert_eq!(matcher.matches(&request, None), Ok(expected));
+    }
+
+    #[test]
+    fn match_conditions_on_raw_request() {
+        let matcher = RequestMatcher::new(&[rule(&["apps"], &["deployments"], vec![])])
+            .match_conditions(vec![MatchCondition {
+                name: "user".to_string(),
+                path: "/request/user".to_string(),
+                operator: MatchConditionOperator::Equals(json!("tonio")),
+            }]);
+
+        let request = ValidateRequest::Raw(json!({"request": {"user": "tonio"}}));
+        assert_eq!(matcher.matches(&request, None), Ok(true));
+
+        let request = ValidateRequest::Raw(json!({"request": {"user": "wanda"}}));
+        assert_eq!(matcher.matches(&request, None), Ok(false));
+    }
+
+    #[test]
+    fn match_condition_with_invalid_pointer() {
+        let matcher = RequestMatcher::new(&[]).match_conditions(vec![MatchCondition {
+            name: "broken".to_string(),
+            path: "userInfo".to_string(),
+            operator: MatchConditionOperator::Exists,
+        }]);
+        let request = pod_request("CREATE", None);
+
+        assert_eq!(
+            matcher.matches(&request, None),
+            Err(RequestMatcherError::InvalidMatchConditionPointer {
+                name: "broken".to_string(),
+                pointer: "userInfo".to_string(),
+            })
+        );
+    }
+}
@@ -0,0 +1,24 @@
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, RequestMatcherError>;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RequestMatcherError {
+    #[error("invalid label selector operator '{0}'")]
+    InvalidLabelSelectorOperator(String),
+
+    #[error("label selector operator '{operator}' on key '{key}' requires at least one value")]
+    MissingLabelSelectorValues { key: String, operator: String },
+
+    #[error("label selector operator '{operator}' on key '{key}' must not have values")]
+    UnexpectedLabelSelectorValues { key: String, operator: String },
+
+    #[error("match condition '{name}' uses an invalid JSON pointer: '{pointer}'")]
+    InvalidMatchConditionPointer { name: String, pointer: String },
+
+    #[error("cannot serialize request: {0}")]
+    SerializeRequest(String),
+
+    #[error("cannot read labels of the request object: {0}")]
+    ObjectLabels(String),
+}
@@ -0,0 +1,157 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, LabelSelectorRequirement};
+
+use crate::callback_handler::kubernetes::selector::{
+    LabelRequirement, LabelSelector as ParsedLabelSelector,
+};
+use crate::request_matcher::errors::{RequestMatcherError, Result};
+
+/// Evaluates a Kubernetes `LabelSelector` against a set of labels.
+///
+/// The semantics are the same of the Kubernetes API server: all the `matchLabels`
+/// and all the `matchExpressions` must be satisfied. An empty selector matches
+/// everything.
+pub(crate) fn matches(selector: &LabelSelector, labels: &BTreeMap<String, String>) -> Result<bool> {
+    let match_labels = selector.match_labels.iter().flatten().map(|(key, value)| {
+        Ok(LabelRequirement::Equals {
+            key: key.clone(),
+            value: value.clone(),
+        })
+    });
+    let requirements = match_labels
+        .chain(selector.match_expressions.iter().flatten().map(requirement))
+        .collect::<Result<BTreeSet<_>>>()?;
+
+    Ok(ParsedLabelSelector::from_requirements(requirements).matches(labels))
+}
+
+/// Convert a `matchExpressions` entry into the requirement evaluated by the
+/// label selectors of the context aware host capabilities
+fn requirement(requirement: &LabelSelectorRequirement) -> Result<LabelRequirement> {
+    let key = requirement.key.clone();
+    let values = requirement
+        .values
+        .iter()
+        .flatten()
+        .cloned()
+        .collect::<BTreeSet<String>>();
+
+    match requirement.operator.as_str() {
+        "In" | "NotIn" if values.is_empty() => {
+            Err(RequestMatcherError::MissingLabelSelectorValues {
+                key,
+                operator: requirement.operator.clone(),
+            })
+        }
+        "Exists" | "DoesNotExist" if !values.is_empty() => {
+            Err(RequestMatcherError::UnexpectedLabelSelectorValues {
+                key,
+                operator: requirement.operator.clone(),
+            })
+        }
+        "In" => Ok(LabelRequirement::In { key, values }),
+        "NotIn" => Ok(LabelRequirement::NotIn { key, values }),
+        "Exists" => Ok(LabelRequirement::Exists { key }),
+        "DoesNotExist" => Ok(LabelRequirement::DoesNotExist { key }),
+        operator => Err(RequestMatcherError::InvalidLabelSelectorOperator(
+            operator.to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    fn requirement(key: &str, operator: &str, values: &[&str]) -> LabelSelectorRequirement {
+        LabelSelectorRequirement {
+            key: key.to_string(),
+            operator: operator.to_string(),
+            values: if values.is_empty() {
+                None
+            } else {
+                Some(values.iter().map(|v| v.to_string()).collect())
+            },
+        }
+    }
+
+    #[rstest]
+    #[case::empty_selector(LabelSelector::default(), Ok(true))]
+    #[case::match_labels(
+        LabelSelector {
+            match_labels: Some(BTreeMap::from([("env".to_string(), "prod".to_string())])),
+            ..Default::default()
+        },
+        Ok(true),
+    )]
+    #[case::match_labels_mismatch(
+        LabelSelector {
+            match_labels: Some(BTreeMap::from([("env".to_string(), "dev".to_string())])),
+            ..Default::default()
+        },
+        Ok(false),
+    )]
+    #[case::in_operator(
+        LabelSelector {
+            match_expressions: Some(vec![requirement("env", "In", &["prod", "staging"])]),
+            ..Default::default()
+        },
+        Ok(true),
+    )]
+    #[case::not_in_operator(
+        LabelSelector {
+            match_expressions: Some(vec![requirement("env", "NotIn", &["prod"])]),
+            ..Default::default()
+        },
+        Ok(false),
+    )]
+    #[case::not_in_operator_missing_label(
+        LabelSelector {
+            match_expressions: Some(vec![requirement("tier", "NotIn", &["frontend"])]),
+            ..Default::default()
+        },
+        Ok(true),
+    )]
+    #[case::exists_operator(
+        LabelSelector {
+            match_expressions: Some(vec![requirement("team", "Exists", &[])]),
+            ..Default::default()
+        },
+        Ok(true),
+    )]
+    #[case::does_not_exist_operator(
+        LabelSelector {
+            match_expressions: Some(vec![requirement("team", "DoesNotExist", &[])]),
+            ..Default::default()
+        },
+        Ok(false),
+    )]
+    #[case::in_without_values(
+        LabelSelector {
+            match_expressions: Some(vec![requirement("env", "In", &[])]),
+            ..Default::default()
+        },
+        Err(RequestMatcherError::MissingLabelSelectorValues {
+            key: "env".to_string(),
+            operator: "In".to_string(),
+        }),
+    )]
+    #[case::unknown_operator(
+        LabelSelector {
+            match_expressions: Some(vec![requirement("env", "Gt", &["1"])]),
+            ..Default::default()
+        },
+        Err(RequestMatcherError::InvalidLabelSelectorOperator("Gt".to_string())),
+    )]
+    fn evaluate_label_selector(#[case] selector: LabelSelector, #[case] expected: Result<bool>) {
+        let labels = BTreeMap::from([
+            ("env".to_string(), "prod".to_string()),
+            ("team".to_string(), "kubewarden".to_string()),
+        ]);
+
+        assert_eq!(matches(&selector, &labels), expected);
+    }
+}
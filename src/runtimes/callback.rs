@@ -1,18 +1,38 @@
-use std::sync::Arc;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
 
 use anyhow::{Result, anyhow};
 use kubewarden_policy_sdk::host_capabilities::{
     SigstoreVerificationInputV1, SigstoreVerificationInputV2,
+    crypto::Certificate,
     crypto_v1::CertificateVerificationRequest,
     kubernetes::{
         CanIRequest, GetResourceRequest, ListAllResourcesRequest, ListResourcesByNamespaceRequest,
     },
 };
-use tokio::sync::{mpsc, oneshot, oneshot::Receiver};
+use tokio::{
+    sync::{mpsc, oneshot, oneshot::Receiver},
+    time::Instant,
+};
 use tracing::{debug, error, warn};
 
-use crate::callback_requests::{CallbackRequest, CallbackRequestType, CallbackResponse};
+use crate::audit::{self, HostCapabilityOutcome};
+use crate::callback_requests::{
+    CallbackRequest, CallbackRequestType, CallbackResponse, CanIBatchRequest,
+    CertificateVerificationV2Request, DnsLookupRequest, DnsReverseLookupRequest, HttpGetRequest,
+    NotationVerifyRequest, OciListTagsRequest, OciManifestAndConfigForPlatformRequest,
+    OciReferrersRequest, SigstoreAttestationVerifyRequest,
+};
+use crate::errors::HostCapabilityError;
 use crate::evaluation_context::EvaluationContext;
+use crate::policy_metadata::HostCapability;
+
+mod certificates;
+mod cidr;
 
 fn unknown_operation(
     namespace: &str,
@@ -27,13 +47,142 @@ fn unknown_namespace(namespace: &str) -> Result<Vec<u8>, Box<dyn std::error::Err
     Err(format!("unknown namespace: {}", namespace).into())
 }
 
-/// The callback function used by waPC and Wasi policies to use host capabilities
+/// The access required to perform an operation requested by a policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RequiredAccess {
+    /// The operation doesn't use any host capability, like logging. The
+    /// unknown bindings fall here too, they are rejected later on
+    Unrestricted,
+    /// The operation uses the given host capability
+    HostCapability(HostCapability),
+    /// The `kubewarden` operation is not mapped to any host capability, hence
+    /// it's denied
+    Unmapped,
+}
+
+/// The access required to perform the given operation. The `kubewarden`
+/// operations not mapped to a host capability are denied by default
+fn required_host_capability(binding: &str, namespace: &str, operation: &str) -> RequiredAccess {
+    let capability = match (binding, namespace, operation) {
+        ("kubewarden", "tracing", "log") => return RequiredAccess::Unrestricted,
+        ("kubewarden", "oci", "v1/verify" | "v2/verify" | "v1/verify_attestation") => {
+            HostCapability::SigstoreVerify
+        }
+        ("kubewarden", "oci", "v1/verify_notation") => HostCapability::NotationVerify,
+        ("kubewarden", "oci", "v1/manifest_digest") => HostCapability::OciManifestDigest,
+        (
+            "kubewarden",
+            "oci",
+            "v1/oci_manifest" | "v1/oci_manifest_config" | "v1/oci_manifest_config_for_platform",
+        ) => HostCapability::OciManifest,
+        ("kubewarden", "oci", "v1/list_tags") => HostCapability::OciListTags,
+        ("kubewarden", "oci", "v1/referrers") => HostCapability::OciReferrers,
+        ("kubewarden", "net", "v1/dns_lookup_host" | "v1/dns_lookup" | "v1/dns_reverse_lookup") => {
+            HostCapability::NetDnsLookup
+        }
+        ("kubewarden", "net", "v1/http_get") => HostCapability::NetHttpGet,
+        ("kubewarden", "net", "v1/cidr") => HostCapability::NetCidr,
+        ("kubewarden", "crypto", "v1/parse_certificate" | "v1/parse_csr") => {
+            HostCapability::CryptoParseCertificate
+        }
+        ("kubewarden", "crypto", "v1/is_certificate_trusted" | "v2/is_certificate_trusted") => {
+            HostCapability::CryptoVerifyCertificate
+        }
+        ("kubewarden", "kubernetes", "list_resources_by_namespace" | "list_resources_all")
+        | ("kubernetes", _, _) => HostCapability::KubernetesListResources,
+        ("kubewarden", "kubernetes", "get_resource") => HostCapability::KubernetesGetResource,
+        ("kubewarden", "kubernetes", "can_i" | "can_i_batch") => HostCapability::KubernetesCanI,
+        ("kubewarden", _, _) => return RequiredAccess::Unmapped,
+        _ => return RequiredAccess::Unrestricted,
+    };
+    RequiredAccess::HostCapability(capability)
+}
+
+/// The callback function used by waPC and Wasi policies to use host capabilities.
+///
+/// The access to the host capabilities is checked against the evaluation
+/// context, and each invocation is reported to its audit sink
 pub(crate) fn host_callback(
     binding: &str,
     namespace: &str,
     operation: &str,
     payload: &[u8],
     eval_ctx: &Arc<EvaluationContext>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    let capability = match required_host_capability(binding, namespace, operation) {
+        RequiredAccess::Unrestricted => {
+            return invoke_host_capability(
+                binding, namespace, operation, payload, eval_ctx, &mut None,
+            );
+        }
+        RequiredAccess::HostCapability(capability) => capability,
+        RequiredAccess::Unmapped => {
+            error!(
+                policy = eval_ctx.policy_id,
+                binding, namespace, operation, "Policy tried to use an unknown host capability"
+            );
+            return Err(format!(
+                "Policy cannot use {binding}/{namespace}/{operation}, it's not a known host capability"
+            )
+            .into());
+        }
+    };
+
+    let audit = |outcome: HostCapabilityOutcome, cached: Option<bool>| {
+        eval_ctx.audit(
+            capability,
+            &format!("{binding}/{namespace}/{operation}"),
+            || audit::redacted_arguments(payload),
+            outcome,
+            cached,
+        );
+    };
+
+    if !eval_ctx.can_use_host_capability(capability) {
+        error!(
+            policy = eval_ctx.policy_id,
+            capability_requested = %capability,
+            capabilities_allowed = ?eval_ctx.host_capabilities_allow_list,
+            "Policy tried to use a host capability it doesn't have access to"
+        );
+        audit(HostCapabilityOutcome::Denied, None);
+        return Err(format!(
+            "Policy has not been granted access to the {capability} host capability. The violation has been reported."
+        )
+        .into());
+    }
+
+    let mut cached = None;
+    let result = invoke_host_capability(
+        binding,
+        namespace,
+        operation,
+        payload,
+        eval_ctx,
+        &mut cached,
+    );
+    match &result {
+        Ok(_) => audit(HostCapabilityOutcome::Success, cached),
+        Err(e) => audit(
+            HostCapabilityOutcome::Failure {
+                error: e.to_string(),
+            },
+            None,
+        ),
+    }
+    result
+}
+
+/// Perform the operation requested by the policy. When the operation is
+/// performed by the callback handler, `cached` tells whether its result has
+/// been served from the cache
+fn invoke_host_capability(
+    binding: &str,
+    namespace: &str,
+    operation: &str,
+    payload: &[u8],
+    eval_ctx: &Arc<EvaluationContext>,
+    cached: &mut Option<bool>,
 ) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
     match binding {
         "kubewarden" => match namespace {
@@ -58,6 +207,7 @@ pub(crate) fn host_callback(
                     let req = CallbackRequest {
                         request: req_type,
                         response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
                     };
 
                     send_request_and_wait_for_response(
@@ -67,6 +217,7 @@ pub(crate) fn host_callback(
                         req,
                         rx,
                         eval_ctx,
+                        cached,
                     )
                 }
                 "v2/verify" => {
@@ -76,6 +227,7 @@ pub(crate) fn host_callback(
                     let req = CallbackRequest {
                         request: req_type,
                         response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
                     };
 
                     send_request_and_wait_for_response(
@@ -85,6 +237,7 @@ pub(crate) fn host_callback(
                         req,
                         rx,
                         eval_ctx,
+                        cached,
                     )
                 }
                 "v1/manifest_digest" => {
@@ -97,6 +250,7 @@ pub(crate) fn host_callback(
                     let req = CallbackRequest {
                         request: CallbackRequestType::OciManifestDigest { image },
                         response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
                     };
                     send_request_and_wait_for_response(
                         &eval_ctx.policy_id,
@@ -105,6 +259,7 @@ pub(crate) fn host_callback(
                         req,
                         rx,
                         eval_ctx,
+                        cached,
                     )
                 }
                 "v1/oci_manifest" => {
@@ -117,6 +272,7 @@ pub(crate) fn host_callback(
                     let req = CallbackRequest {
                         request: CallbackRequestType::OciManifest { image },
                         response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
                     };
                     send_request_and_wait_for_response(
                         &eval_ctx.policy_id,
@@ -125,6 +281,7 @@ pub(crate) fn host_callback(
                         req,
                         rx,
                         eval_ctx,
+                        cached,
                     )
                 }
                 "v1/oci_manifest_config" => {
@@ -137,6 +294,108 @@ pub(crate) fn host_callback(
                     let req = CallbackRequest {
                         request: CallbackRequestType::OciManifestAndConfig { image },
                         response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
+                    };
+                    send_request_and_wait_for_response(
+                        &eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        req,
+                        rx,
+                        eval_ctx,
+                        cached,
+                    )
+                }
+                "v1/verify_attestation" => {
+                    let req: SigstoreAttestationVerifyRequest = serde_json::from_slice(payload)?;
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        image = req.image,
+                        predicate_type = req.predicate_type,
+                        "Sending request via callback channel"
+                    );
+                    let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
+                    let req = CallbackRequest {
+                        request: CallbackRequestType::from(req),
+                        response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
+                    };
+                    send_request_and_wait_for_response(
+                        &eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        req,
+                        rx,
+                        eval_ctx,
+                        cached,
+                    )
+                }
+                "v1/verify_notation" => {
+                    let req: NotationVerifyRequest = serde_json::from_slice(payload)?;
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        image = req.image,
+                        "Sending request via callback channel"
+                    );
+                    let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
+                    let req = CallbackRequest {
+                        request: CallbackRequestType::from(req),
+                        response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
+                    };
+                    send_request_and_wait_for_response(
+                        &eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        req,
+                        rx,
+                        eval_ctx,
+                        cached,
+                    )
+                }
+                "v1/list_tags" => {
+                    let req: OciListTagsRequest = serde_json::from_slice(payload)?;
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        ?req,
+                        "Sending request via callback channel"
+                    );
+                    let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
+                    let req = CallbackRequest {
+                        request: CallbackRequestType::from(req),
+                        response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
+                    };
+                    send_request_and_wait_for_response(
+                        &eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        req,
+                        rx,
+                        eval_ctx,
+                        cached,
+                    )
+                }
+                "v1/referrers" => {
+                    let req: OciReferrersRequest = serde_json::from_slice(payload)?;
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        ?req,
+                        "Sending request via callback channel"
+                    );
+                    let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
+                    let req = CallbackRequest {
+                        request: CallbackRequestType::from(req),
+                        response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
                     };
                     send_request_and_wait_for_response(
                         &eval_ctx.policy_id,
@@ -145,6 +404,33 @@ pub(crate) fn host_callback(
                         req,
                         rx,
                         eval_ctx,
+                        cached,
+                    )
+                }
+                "v1/oci_manifest_config_for_platform" => {
+                    let req: OciManifestAndConfigForPlatformRequest =
+                        serde_json::from_slice(payload)?;
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        ?req,
+                        "Sending request via callback channel"
+                    );
+                    let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
+                    let req = CallbackRequest {
+                        request: CallbackRequestType::from(req),
+                        response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
+                    };
+                    send_request_and_wait_for_response(
+                        &eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        req,
+                        rx,
+                        eval_ctx,
+                        cached,
                     )
                 }
                 _ => unknown_operation(namespace, operation),
@@ -160,6 +446,7 @@ pub(crate) fn host_callback(
                     let req = CallbackRequest {
                         request: CallbackRequestType::DNSLookupHost { host },
                         response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
                     };
                     send_request_and_wait_for_response(
                         &eval_ctx.policy_id,
@@ -168,11 +455,130 @@ pub(crate) fn host_callback(
                         req,
                         rx,
                         eval_ctx,
+                        cached,
                     )
                 }
+                "v1/dns_lookup" => {
+                    let req: DnsLookupRequest = serde_json::from_slice(payload)?;
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        ?req,
+                        "Sending request via callback channel"
+                    );
+                    let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
+                    let req = CallbackRequest {
+                        request: CallbackRequestType::from(req),
+                        response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
+                    };
+                    send_request_and_wait_for_response(
+                        &eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        req,
+                        rx,
+                        eval_ctx,
+                        cached,
+                    )
+                }
+                "v1/dns_reverse_lookup" => {
+                    let req: DnsReverseLookupRequest = serde_json::from_slice(payload)?;
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        ?req,
+                        "Sending request via callback channel"
+                    );
+                    let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
+                    let req = CallbackRequest {
+                        request: CallbackRequestType::from(req),
+                        response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
+                    };
+                    send_request_and_wait_for_response(
+                        &eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        req,
+                        rx,
+                        eval_ctx,
+                        cached,
+                    )
+                }
+                "v1/http_get" => {
+                    let req: HttpGetRequest = serde_json::from_slice(payload)?;
+
+                    if !eval_ctx.can_fetch_url(&req.url) {
+                        error!(
+                            policy = eval_ctx.policy_id,
+                            url_requested = req.url.as_str(),
+                            endpoints_allowed = ?eval_ctx.http_get_allow_list,
+                            "Policy tried to fetch a URL it doesn't have access to");
+                        return Err(format!(
+                                "Policy has not been granted access to {}. The violation has been reported.",
+                                req.url).into());
+                    }
+
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        ?req,
+                        "Sending request via callback channel"
+                    );
+                    let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
+                    let req = CallbackRequest {
+                        request: CallbackRequestType::from(req),
+                        response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
+                    };
+                    send_request_and_wait_for_response(
+                        &eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        req,
+                        rx,
+                        eval_ctx,
+                        cached,
+                    )
+                }
+                "v1/cidr" => {
+                    let req: cidr::CidrRequest = serde_json::from_slice(payload)?;
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        ?req,
+                        "Evaluating CIDR operation"
+                    );
+                    let response = cidr::evaluate(&req)?;
+                    Ok(serde_json::to_vec(&response)?)
+                }
                 _ => unknown_operation(namespace, operation),
             },
             "crypto" => match operation {
+                "v1/parse_certificate" => {
+                    let req: Certificate = serde_json::from_slice(payload)?;
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding, operation, "Parsing certificate"
+                    );
+                    let parsed = certificates::parse_certificate(&req)?;
+                    Ok(serde_json::to_vec(&parsed)?)
+                }
+                "v1/parse_csr" => {
+                    let req: certificates::CertificateSigningRequest =
+                        serde_json::from_slice(payload)?;
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding, operation, "Parsing certificate signing request"
+                    );
+                    let parsed = certificates::parse_csr(&req)?;
+                    Ok(serde_json::to_vec(&parsed)?)
+                }
                 "v1/is_certificate_trusted" => {
                     let req: CertificateVerificationRequest = serde_json::from_slice(payload)?;
 
@@ -187,6 +593,7 @@ pub(crate) fn host_callback(
                     let req = CallbackRequest {
                         request: CallbackRequestType::from(req),
                         response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
                     };
                     send_request_and_wait_for_response(
                         &eval_ctx.policy_id,
@@ -195,6 +602,33 @@ pub(crate) fn host_callback(
                         req,
                         rx,
                         eval_ctx,
+                        cached,
+                    )
+                }
+                "v2/is_certificate_trusted" => {
+                    let req: CertificateVerificationV2Request = serde_json::from_slice(payload)?;
+
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        ?req,
+                        "Sending request via callback channel"
+                    );
+                    let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
+                    let req = CallbackRequest {
+                        request: CallbackRequestType::from(req),
+                        response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
+                    };
+                    send_request_and_wait_for_response(
+                        &eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        req,
+                        rx,
+                        eval_ctx,
+                        cached,
                     )
                 }
                 _ => unknown_operation(namespace, operation),
@@ -226,6 +660,7 @@ pub(crate) fn host_callback(
                     let req = CallbackRequest {
                         request: CallbackRequestType::from(req),
                         response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
                     };
                     send_request_and_wait_for_response(
                         &eval_ctx.policy_id,
@@ -234,6 +669,7 @@ pub(crate) fn host_callback(
                         req,
                         rx,
                         eval_ctx,
+                        cached,
                     )
                 }
                 "list_resources_all" => {
@@ -261,6 +697,7 @@ pub(crate) fn host_callback(
                     let req = CallbackRequest {
                         request: CallbackRequestType::from(req),
                         response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
                     };
                     send_request_and_wait_for_response(
                         &eval_ctx.policy_id,
@@ -269,6 +706,7 @@ pub(crate) fn host_callback(
                         req,
                         rx,
                         eval_ctx,
+                        cached,
                     )
                 }
                 "get_resource" => {
@@ -296,6 +734,7 @@ pub(crate) fn host_callback(
                     let req = CallbackRequest {
                         request: CallbackRequestType::from(req),
                         response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
                     };
                     send_request_and_wait_for_response(
                         &eval_ctx.policy_id,
@@ -304,6 +743,7 @@ pub(crate) fn host_callback(
                         req,
                         rx,
                         eval_ctx,
+                        cached,
                     )
                 }
                 "can_i" => {
@@ -321,6 +761,7 @@ pub(crate) fn host_callback(
                     let req = CallbackRequest {
                         request: CallbackRequestType::from(req),
                         response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
                     };
                     send_request_and_wait_for_response(
                         &eval_ctx.policy_id,
@@ -329,6 +770,34 @@ pub(crate) fn host_callback(
                         req,
                         rx,
                         eval_ctx,
+                        cached,
+                    )
+                }
+                "can_i_batch" => {
+                    let req: CanIBatchRequest = serde_json::from_slice(payload)?;
+
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding,
+                        namespace,
+                        operation,
+                        ?req,
+                        "Sending request via callback channel"
+                    );
+                    let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
+                    let req = CallbackRequest {
+                        request: CallbackRequestType::from(req),
+                        response_channel: tx,
+                        deadline: eval_ctx.deadline.get().map(Instant::from_std),
+                    };
+                    send_request_and_wait_for_response(
+                        &eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        req,
+                        rx,
+                        eval_ctx,
+                        cached,
                     )
                 }
                 _ => unknown_operation(namespace, operation),
@@ -353,6 +822,7 @@ pub(crate) fn host_callback(
                 let req = CallbackRequest {
                     request: req,
                     response_channel: tx,
+                    deadline: eval_ctx.deadline.get().map(Instant::from_std),
                 };
                 send_request_and_wait_for_response(
                     &eval_ctx.policy_id,
@@ -361,6 +831,7 @@ pub(crate) fn host_callback(
                     req,
                     rx,
                     eval_ctx,
+                    cached,
                 )
             }
             "namespaces" => {
@@ -380,6 +851,7 @@ pub(crate) fn host_callback(
                 let req = CallbackRequest {
                     request: req,
                     response_channel: tx,
+                    deadline: eval_ctx.deadline.get().map(Instant::from_std),
                 };
                 send_request_and_wait_for_response(
                     &eval_ctx.policy_id,
@@ -388,6 +860,7 @@ pub(crate) fn host_callback(
                     req,
                     rx,
                     eval_ctx,
+                    cached,
                 )
             }
             "services" => {
@@ -407,6 +880,7 @@ pub(crate) fn host_callback(
                 let req = CallbackRequest {
                     request: req,
                     response_channel: tx,
+                    deadline: eval_ctx.deadline.get().map(Instant::from_std),
                 };
                 send_request_and_wait_for_response(
                     &eval_ctx.policy_id,
@@ -415,6 +889,7 @@ pub(crate) fn host_callback(
                     req,
                     rx,
                     eval_ctx,
+                    cached,
                 )
             }
             _ => unknown_namespace(namespace),
@@ -433,6 +908,7 @@ fn send_request_and_wait_for_response(
     req: CallbackRequest,
     rx: Receiver<Result<CallbackResponse>>,
     eval_ctx: &EvaluationContext,
+    cached: &mut Option<bool>,
 ) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
     let cb_channel: mpsc::Sender<CallbackRequest> = if let Some(c) =
         eval_ctx.callback_channel.clone()
@@ -448,15 +924,44 @@ fn send_request_and_wait_for_response(
         ))
     }?;
 
+    let deadline = eval_ctx.deadline.get();
+    if deadline.is_some_and(|deadline| deadline <= std::time::Instant::now()) {
+        warn!(
+            policy_id,
+            binding, operation, "evaluation deadline reached, host capability not invoked"
+        );
+        return Err(HostCapabilityError::Timeout.into());
+    }
+
     let send_result = cb_channel.try_send(req);
     if let Err(e) = send_result {
         return Err(format!("Error sending request over callback channel: {e:?}").into());
     }
 
     // wait for the response
-    match rx.blocking_recv() {
+    let response = match deadline {
+        Some(deadline) => match blocking_recv_until(rx, deadline) {
+            Some(response) => response,
+            None => {
+                warn!(
+                    policy_id,
+                    binding, operation, "evaluation deadline reached, host capability timed out"
+                );
+                return Err(HostCapabilityError::Timeout.into());
+            }
+        },
+        None => rx.blocking_recv(),
+    };
+    match response {
         Ok(msg) => match msg {
-            Ok(resp) => Ok(resp.payload),
+            Ok(resp) => {
+                *cached = Some(resp.cached);
+                Ok(resp.payload)
+            }
+            Err(e) if e.is::<HostCapabilityError>() => {
+                warn!(policy_id, binding, operation, error = %e, "callback evaluation failed");
+                Err(e.into())
+            }
             Err(e) => {
                 error!(
                     policy_id,
@@ -480,3 +985,288 @@ fn send_request_and_wait_for_response(
         }
     }
 }
+
+/// Wakes up the thread that is blocked waiting for a callback response
+struct ThreadWaker(std::thread::Thread);
+
+impl futures::task::ArcWake for ThreadWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.0.unpark();
+    }
+}
+
+/// Like `Receiver::blocking_recv`, but gives up once the deadline is reached.
+/// Returns `None` in that case
+pub(crate) fn blocking_recv_until<T>(
+    mut rx: Receiver<T>,
+    deadline: std::time::Instant,
+) -> Option<Result<T, oneshot::error::RecvError>> {
+    let waker = futures::task::waker(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(response) = Pin::new(&mut rx).poll(&mut cx) {
+            return Some(response);
+        }
+        let now = std::time::Instant::now();
+        if now >= deadline {
+            return None;
+        }
+        std::thread::park_timeout(deadline - now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+    use std::collections::BTreeSet;
+
+    use crate::audit::{AuditSink, HostCapabilityEvent};
+    use crate::callback_requests::OciPlatform;
+    use std::time::Duration;
+
+    #[rstest]
+    #[case::response_before_deadline(Duration::from_millis(10), Some(42))]
+    #[case::deadline_reached(Duration::from_secs(5), None)]
+    fn wait_for_response_until_deadline(
+        #[case] response_delay: Duration,
+        #[case] expected: Option<u32>,
+    ) {
+        let (tx, rx) = oneshot::channel::<u32>();
+        let deadline = std::time::Instant::now() + Duration::from_millis(200);
+
+        let sender = std::thread::spawn(move || {
+            std::thread::sleep(response_delay);
+            let _ = tx.send(42);
+        });
+
+        let response = blocking_recv_until(rx, deadline).map(|r| r.expect("sender dropped"));
+        assert_eq!(response, expected);
+        assert!(std::time::Instant::now() < deadline + Duration::from_secs(1));
+
+        if expected.is_some() {
+            sender.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn host_capability_times_out_at_evaluation_deadline() {
+        // the requests are never answered
+        let (callback_tx, _callback_rx) = mpsc::channel::<CallbackRequest>(10);
+        let eval_ctx = Arc::new(EvaluationContext {
+            policy_id: "test".to_owned(),
+            callback_channel: Some(callback_tx),
+            ..Default::default()
+        });
+        eval_ctx
+            .deadline
+            .set(Some(std::time::Instant::now() + Duration::from_millis(100)));
+
+        let err = host_callback(
+            "kubewarden",
+            "oci",
+            "v1/manifest_digest",
+            br#""busybox""#,
+            &eval_ctx,
+        )
+        .expect_err("the host capability should time out");
+
+        assert!(err.is::<HostCapabilityError>());
+    }
+
+    #[rstest]
+    #[case::list_tags(
+        "v1/list_tags",
+        r#"{"image": "busybox", "page_size": 10, "last": "1.36"}"#,
+        CallbackRequestType::OciListTags {
+            image: "busybox".to_owned(),
+            page_size: Some(10),
+            last: Some("1.36".to_owned()),
+        }
+    )]
+    #[case::referrers(
+        "v1/referrers",
+        r#"{"image": "busybox:1.36", "artifact_type": "application/spdx+json"}"#,
+        CallbackRequestType::OciReferrers {
+            image: "busybox:1.36".to_owned(),
+            artifact_type: Some("application/spdx+json".to_owned()),
+        }
+    )]
+    #[case::manifest_config_for_platform(
+        "v1/oci_manifest_config_for_platform",
+        r#"{"image": "busybox:1.36", "platform": {"os": "linux", "architecture": "arm64", "variant": "v8"}}"#,
+        CallbackRequestType::OciManifestAndConfigForPlatform {
+            image: "busybox:1.36".to_owned(),
+            platform: OciPlatform {
+                os: "linux".to_owned(),
+                architecture: "arm64".to_owned(),
+                variant: Some("v8".to_owned()),
+            },
+        }
+    )]
+    fn oci_requests_sent_over_callback_channel(
+        #[case] operation: &str,
+        #[case] payload: &str,
+        #[case] expected: CallbackRequestType,
+    ) {
+        let (callback_tx, mut callback_rx) = mpsc::channel::<CallbackRequest>(10);
+        let eval_ctx = Arc::new(EvaluationContext {
+            policy_id: "test".to_owned(),
+            callback_channel: Some(callback_tx),
+            ..Default::default()
+        });
+
+        let responder = std::thread::spawn(move || {
+            let req = callback_rx.blocking_recv().expect("no request received");
+            req.response_channel
+                .send(Ok(CallbackResponse {
+                    payload: b"response".to_vec(),
+                    cached: false,
+                }))
+                .expect("cannot send response");
+            req.request
+        });
+
+        let response = host_callback(
+            "kubewarden",
+            "oci",
+            operation,
+            payload.as_bytes(),
+            &eval_ctx,
+        )
+        .unwrap();
+
+        assert_eq!(response, b"response");
+        assert_eq!(responder.join().unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case::sigstore(
+        "kubewarden",
+        "oci",
+        "v2/verify",
+        RequiredAccess::HostCapability(HostCapability::SigstoreVerify)
+    )]
+    #[case::dns(
+        "kubewarden",
+        "net",
+        "v1/dns_lookup",
+        RequiredAccess::HostCapability(HostCapability::NetDnsLookup)
+    )]
+    #[case::kubernetes_get(
+        "kubewarden",
+        "kubernetes",
+        "get_resource",
+        RequiredAccess::HostCapability(HostCapability::KubernetesGetResource)
+    )]
+    #[case::deprecated_cluster_context(
+        "kubernetes",
+        "ingresses",
+        "",
+        RequiredAccess::HostCapability(HostCapability::KubernetesListResources)
+    )]
+    #[case::logging("kubewarden", "tracing", "log", RequiredAccess::Unrestricted)]
+    #[case::unknown_operation("kubewarden", "oci", "v9/unknown", RequiredAccess::Unmapped)]
+    #[case::unknown_namespace("kubewarden", "unknown", "v1/unknown", RequiredAccess::Unmapped)]
+    #[case::unknown_binding("unknown", "oci", "v1/verify", RequiredAccess::Unrestricted)]
+    fn required_host_capabilities(
+        #[case] binding: &str,
+        #[case] namespace: &str,
+        #[case] operation: &str,
+        #[case] expected: RequiredAccess,
+    ) {
+        assert_eq!(
+            required_host_capability(binding, namespace, operation),
+            expected
+        );
+    }
+
+    #[test]
+    fn deny_not_granted_host_capability() {
+        let eval_ctx = Arc::new(EvaluationContext {
+            policy_id: "test".to_owned(),
+            host_capabilities_allow_list: Some(BTreeSet::from([HostCapability::OciManifest])),
+            ..Default::default()
+        });
+
+        let err = host_callback("kubewarden", "net", "v1/cidr", b"{}", &eval_ctx)
+            .expect_err("the host capability is not granted");
+
+        assert!(err.to_string().contains("net.cidr"));
+    }
+
+    #[test]
+    fn deny_unmapped_operation() {
+        let eval_ctx = Arc::new(EvaluationContext {
+            policy_id: "test".to_owned(),
+            ..Default::default()
+        });
+
+        let err = host_callback("kubewarden", "oci", "v9/unknown", b"{}", &eval_ctx)
+            .expect_err("the operation is not mapped to a host capability");
+
+        assert!(err.to_string().contains("not a known host capability"));
+    }
+
+    #[derive(Default)]
+    struct RecordingAuditSink {
+        events: std::sync::Mutex<Vec<HostCapabilityEvent>>,
+    }
+
+    impl AuditSink for RecordingAuditSink {
+        fn record(&self, event: &HostCapabilityEvent) {
+            self.events.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn audit_host_capability_invocations() {
+        let audit_sink = Arc::new(RecordingAuditSink::default());
+        let eval_ctx = Arc::new(EvaluationContext {
+            policy_id: "test".to_owned(),
+            host_capabilities_allow_list: Some(BTreeSet::from([HostCapability::NetCidr])),
+            audit_sink: Some(audit_sink.clone()),
+            ..Default::default()
+        });
+        eval_ctx.current_request.set_uid(Some("uid-1".to_owned()));
+
+        let classify = br#"{"operation": "classify", "address": "10.0.0.1"}"#;
+        host_callback("kubewarden", "net", "v1/cidr", classify, &eval_ctx).unwrap();
+        host_callback(
+            "kubewarden",
+            "net",
+            "v1/dns_lookup_host",
+            br#""example.com""#,
+            &eval_ctx,
+        )
+        .unwrap_err();
+        host_callback("kubewarden", "tracing", "log", b"{}", &eval_ctx).unwrap();
+
+        let events = audit_sink.events.lock().unwrap();
+        assert_eq!(
+            *events,
+            vec![
+                HostCapabilityEvent {
+                    policy_id: "test".to_owned(),
+                    request_uid: Some("uid-1".to_owned()),
+                    capability: HostCapability::NetCidr,
+                    operation: "kubewarden/net/v1/cidr".to_owned(),
+                    arguments: serde_json::json!({"operation": "classify", "address": "10.0.0.1"}),
+                    outcome: HostCapabilityOutcome::Success,
+                    cached: None,
+                },
+                HostCapabilityEvent {
+                    policy_id: "test".to_owned(),
+                    request_uid: Some("uid-1".to_owned()),
+                    capability: HostCapability::NetDnsLookup,
+                    operation: "kubewarden/net/v1/dns_lookup_host".to_owned(),
+                    arguments: serde_json::json!("example.com"),
+                    outcome: HostCapabilityOutcome::Denied,
+                    cached: None,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,506 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use anyhow::{Result, anyhow};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use kubewarden_policy_sdk::host_capabilities::crypto::{Certificate, CertificateEncoding};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use x509_cert::{
+    der::{
+        Decode, DecodePem, Encode, Reader, SliceReader,
+        asn1::UintRef,
+        oid::{AssociatedOid, ObjectIdentifier},
+    },
+    ext::{Extension, pkix},
+    request::{CertReq, ExtensionReq},
+    spki::SubjectPublicKeyInfoOwned,
+    time::Time,
+};
+
+use crate::callback_requests::{ExtendedKeyUsage, KeyUsage};
+
+const RSA_ENCRYPTION: &str = "1.2.840.113549.1.1.1";
+const EC_PUBLIC_KEY: &str = "1.2.840.10045.2.1";
+const ED25519: &str = "1.3.101.112";
+const ED448: &str = "1.3.101.113";
+
+/// Names of the most common signature algorithms, the other ones are reported
+/// using their object identifier
+const SIGNATURE_ALGORITHMS: &[(&str, &str)] = &[
+    ("1.2.840.113549.1.1.5", "sha1WithRSAEncryption"),
+    ("1.2.840.113549.1.1.10", "RSASSA-PSS"),
+    ("1.2.840.113549.1.1.11", "sha256WithRSAEncryption"),
+    ("1.2.840.113549.1.1.12", "sha384WithRSAEncryption"),
+    ("1.2.840.113549.1.1.13", "sha512WithRSAEncryption"),
+    ("1.2.840.10045.4.3.2", "ecdsa-with-SHA256"),
+    ("1.2.840.10045.4.3.3", "ecdsa-with-SHA384"),
+    ("1.2.840.10045.4.3.4", "ecdsa-with-SHA512"),
+    ("1.3.101.112", "Ed25519"),
+    ("1.3.101.113", "Ed448"),
+];
+
+/// Named elliptic curves and their size, in bits
+const CURVES: &[(&str, &str, usize)] = &[
+    ("1.2.840.10045.3.1.7", "P-256", 256),
+    ("1.3.132.0.34", "P-384", 384),
+    ("1.3.132.0.35", "P-521", 521),
+];
+
+/// Names of the most common extensions, the other ones are reported using
+/// only their object identifier
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("2.5.29.14", "subjectKeyIdentifier"),
+    ("2.5.29.15", "keyUsage"),
+    ("2.5.29.17", "subjectAltName"),
+    ("2.5.29.19", "basicConstraints"),
+    ("2.5.29.30", "nameConstraints"),
+    ("2.5.29.31", "cRLDistributionPoints"),
+    ("2.5.29.32", "certificatePolicies"),
+    ("2.5.29.35", "authorityKeyIdentifier"),
+    ("2.5.29.37", "extKeyUsage"),
+    ("1.3.6.1.5.5.7.1.1", "authorityInfoAccess"),
+    ("1.3.6.1.4.1.11129.2.4.2", "signedCertificateTimestampList"),
+];
+
+/// Payload of the `crypto/v1/parse_csr` operation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CertificateSigningRequest {
+    /// Which encoding is used by the CSR
+    pub encoding: CertificateEncoding,
+    /// Actual CSR
+    pub data: Vec<u8>,
+}
+
+/// The content of a X.509 certificate
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ParsedCertificate {
+    /// The version of the certificate: 1, 2 or 3
+    pub version: u8,
+    /// Hex encoded serial number
+    pub serial_number: String,
+    /// RFC 4514 representation of the subject
+    pub subject: String,
+    /// RFC 4514 representation of the issuer
+    pub issuer: String,
+    pub subject_alternative_names: SubjectAlternativeNames,
+    /// RFC 3339 start of the validity period
+    pub not_before: String,
+    /// RFC 3339 end of the validity period
+    pub not_after: String,
+    pub public_key: PublicKey,
+    /// The name of the signature algorithm, or its object identifier when not known
+    pub signature_algorithm: String,
+    /// Whether the certificate belongs to a CA, according to its Basic Constraints
+    pub is_ca: bool,
+    pub key_usages: Vec<KeyUsage>,
+    /// The extended key usages, the unknown ones are listed only among the extensions
+    pub extended_key_usages: Vec<ExtendedKeyUsage>,
+    pub extensions: Vec<ParsedExtension>,
+    pub fingerprints: Fingerprints,
+}
+
+/// The content of a PKCS#10 certificate signing request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ParsedCertificateSigningRequest {
+    /// RFC 4514 representation of the subject
+    pub subject: String,
+    /// The subject alternative names of the requested extensions
+    pub subject_alternative_names: SubjectAlternativeNames,
+    pub public_key: PublicKey,
+    /// The name of the signature algorithm, or its object identifier when not known
+    pub signature_algorithm: String,
+    /// The requested extensions
+    pub extensions: Vec<ParsedExtension>,
+    pub fingerprints: Fingerprints,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SubjectAlternativeNames {
+    pub dns_names: Vec<String>,
+    pub email_addresses: Vec<String>,
+    pub ip_addresses: Vec<String>,
+    pub uris: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct PublicKey {
+    /// The type of the key: `RSA`, `EC`, `Ed25519`, `Ed448`, or the object
+    /// identifier of its algorithm when not known
+    pub key_type: String,
+    /// The size of the key, in bits. Not set when not known
+    pub size: Option<usize>,
+    /// The name of the curve of EC keys, or its object identifier when not known
+    pub curve: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ParsedExtension {
+    pub oid: String,
+    /// The name of the extension, when known
+    pub name: Option<String>,
+    pub critical: bool,
+    /// Base64 encoded DER value of the extension
+    pub value: String,
+}
+
+/// Hex encoded fingerprints of the DER encoding
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Fingerprints {
+    pub sha1: String,
+    pub sha256: String,
+}
+
+/// Parse a PEM or DER encoded X.509 certificate
+pub(crate) fn parse_certificate(cert: &Certificate) -> Result<ParsedCertificate> {
+    let certificate = match cert.encoding {
+        CertificateEncoding::Pem => x509_cert::Certificate::from_pem(&cert.data),
+        CertificateEncoding::Der => x509_cert::Certificate::from_der(&cert.data),
+    }
+    .map_err(|e| anyhow!("Certificate is not valid: {}", e))?;
+    let tbs_certificate = &certificate.tbs_certificate;
+    let extensions = tbs_certificate.extensions.as_deref().unwrap_or_default();
+
+    let key_usages = match extension::<pkix::KeyUsage>(extensions)? {
+        Some(key_usage) => KeyUsage::ALL
+            .into_iter()
+            .filter(|usage| usage.is_set(&key_usage))
+            .collect(),
+        None => vec![],
+    };
+    let extended_key_usages = extension::<pkix::ExtendedKeyUsage>(extensions)?
+        .map(|extended_key_usage| {
+            extended_key_usage
+                .0
+                .iter()
+                .filter_map(ExtendedKeyUsage::from_oid)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(ParsedCertificate {
+        version: tbs_certificate.version as u8 + 1,
+        serial_number: hex(tbs_certificate.serial_number.as_bytes()),
+        subject: tbs_certificate.subject.to_string(),
+        issuer: tbs_certificate.issuer.to_string(),
+        subject_alternative_names: subject_alternative_names(extensions)?,
+        not_before: rfc3339(&tbs_certificate.validity.not_before)?,
+        not_after: rfc3339(&tbs_certificate.validity.not_after)?,
+        public_key: public_key(&tbs_certificate.subject_public_key_info)?,
+        signature_algorithm: signature_algorithm(&certificate.signature_algorithm.oid),
+        is_ca: extension::<pkix::BasicConstraints>(extensions)?
+            .is_some_and(|basic_constraints| basic_constraints.ca),
+        key_usages,
+        extended_key_usages,
+        extensions: extensions.iter().map(parsed_extension).collect(),
+        fingerprints: fingerprints(&certificate.to_der()?),
+    })
+}
+
+/// Parse a PEM or DER encoded PKCS#10 certificate signing request
+pub(crate) fn parse_csr(
+    csr: &CertificateSigningRequest,
+) -> Result<ParsedCertificateSigningRequest> {
+    let request = match csr.encoding {
+        CertificateEncoding::Pem => CertReq::from_pem(&csr.data),
+        CertificateEncoding::Der => CertReq::from_der(&csr.data),
+    }
+    .map_err(|e| anyhow!("Certificate signing request is not valid: {}", e))?;
+
+    let mut extensions = vec![];
+    for attribute in request
+        .info
+        .attributes
+        .iter()
+        .filter(|attribute| attribute.oid == ExtensionReq::OID)
+    {
+        for value in attribute.values.iter() {
+            let requested = value
+                .decode_as::<ExtensionReq>()
+                .map_err(|e| anyhow!("Requested extensions are not valid: {}", e))?;
+            extensions.extend(requested.0);
+        }
+    }
+
+    Ok(ParsedCertificateSigningRequest {
+        subject: request.info.subject.to_string(),
+        subject_alternative_names: subject_alternative_names(&extensions)?,
+        public_key: public_key(&request.info.public_key)?,
+        signature_algorithm: signature_algorithm(&request.algorithm.oid),
+        extensions: extensions.iter().map(parsed_extension).collect(),
+        fingerprints: fingerprints(&request.to_der()?),
+    })
+}
+
+/// Decode the extension of the given type, if present
+fn extension<'a, T>(extensions: &'a [Extension]) -> Result<Option<T>>
+where
+    T: Decode<'a> + AssociatedOid,
+{
+    extensions
+        .iter()
+        .find(|extension| extension.extn_id == T::OID)
+        .map(|extension| {
+            T::from_der(extension.extn_value.as_bytes())
+                .map_err(|e| anyhow!("Extension {} is not valid: {}", T::OID, e))
+        })
+        .transpose()
+}
+
+fn subject_alternative_names(extensions: &[Extension]) -> Result<SubjectAlternativeNames> {
+    let mut names = SubjectAlternativeNames::default();
+    let Some(subject_alt_name) = extension::<pkix::SubjectAltName>(extensions)? else {
+        return Ok(names);
+    };
+
+    for name in subject_alt_name.0 {
+        match name {
+            pkix::name::GeneralName::DnsName(dns_name) => {
+                names.dns_names.push(dns_name.to_string())
+            }
+            pkix::name::GeneralName::Rfc822Name(email) => {
+                names.email_addresses.push(email.to_string())
+            }
+            pkix::name::GeneralName::UniformResourceIdentifier(uri) => {
+                names.uris.push(uri.to_string())
+            }
+            pkix::name::GeneralName::IpAddress(ip) => {
+                let ip = match ip.as_bytes() {
+                    bytes if bytes.len() == 4 => {
+                        IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(bytes)?))
+                    }
+                    bytes if bytes.len() == 16 => {
+                        IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(bytes)?))
+                    }
+                    _ => return Err(anyhow!("Subject alternative name IP address is not valid")),
+                };
+                names.ip_addresses.push(ip.to_string());
+            }
+            _ => {}
+        }
+    }
+    Ok(names)
+}
+
+fn public_key(spki: &SubjectPublicKeyInfoOwned) -> Result<PublicKey> {
+    let algorithm = spki.algorithm.oid.to_string();
+    let public_key = match algorithm.as_str() {
+        RSA_ENCRYPTION => PublicKey {
+            key_type: "RSA".to_owned(),
+            size: Some(rsa_modulus_size(spki.subject_public_key.raw_bytes())?),
+            curve: None,
+        },
+        EC_PUBLIC_KEY => {
+            let curve = spki
+                .algorithm
+                .parameters
+                .as_ref()
+                .and_then(|parameters| parameters.decode_as::<ObjectIdentifier>().ok())
+                .ok_or_else(|| anyhow!("EC public key has no named curve"))?;
+            let known_curve = CURVES.iter().find(|(oid, _, _)| *oid == curve.to_string());
+            PublicKey {
+                key_type: "EC".to_owned(),
+                size: known_curve.map(|(_, _, size)| *size),
+                curve: Some(
+                    known_curve
+                        .map(|(_, name, _)| name.to_string())
+                        .unwrap_or_else(|| curve.to_string()),
+                ),
+            }
+        }
+        ED25519 => PublicKey {
+            key_type: "Ed25519".to_owned(),
+            size: Some(256),
+            curve: None,
+        },
+        ED448 => PublicKey {
+            key_type: "Ed448".to_owned(),
+            size: Some(456),
+            curve: None,
+        },
+        _ => PublicKey {
+            key_type: algorithm,
+            size: None,
+            curve: None,
+        },
+    };
+    Ok(public_key)
+}
+
+/// The size, in bits, of the modulus of a PKCS#1 RSA public key
+fn rsa_modulus_size(public_key: &[u8]) -> Result<usize> {
+    let mut reader = SliceReader::new(public_key)?;
+    let modulus = reader
+        .sequence(|reader| {
+            let modulus = UintRef::decode(reader)?;
+            let _exponent = UintRef::decode(reader)?;
+            Ok(modulus)
+        })
+        .map_err(|e| anyhow!("RSA public key is not valid: {}", e))?;
+
+    let bytes = modulus.as_bytes();
+    Ok(bytes
+        .first()
+        .map_or(0, |first| bytes.len() * 8 - first.leading_zeros() as usize))
+}
+
+fn signature_algorithm(oid: &ObjectIdentifier) -> String {
+    let oid = oid.to_string();
+    SIGNATURE_ALGORITHMS
+        .iter()
+        .find(|(known, _)| *known == oid)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or(oid)
+}
+
+fn parsed_extension(extension: &Extension) -> ParsedExtension {
+    let oid = extension.extn_id.to_string();
+    ParsedExtension {
+        name: EXTENSIONS
+            .iter()
+            .find(|(known, _)| *known == oid)
+            .map(|(_, name)| name.to_string()),
+        oid,
+        critical: extension.critical,
+        value: BASE64.encode(extension.extn_value.as_bytes()),
+    }
+}
+
+fn rfc3339(time: &Time) -> Result<String> {
+    let time = chrono::DateTime::from_timestamp(time.to_unix_duration().as_secs() as i64, 0)
+        .ok_or_else(|| anyhow!("Certificate validity is out of range"))?;
+    Ok(time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+}
+
+fn fingerprints(der: &[u8]) -> Fingerprints {
+    Fingerprints {
+        sha1: hex(&Sha1::digest(der)),
+        sha256: hex(&Sha256::digest(der)),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rcgen::{
+        CertificateParams, DistinguishedName, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+        KeyUsagePurpose, SanType,
+    };
+
+    fn certificate_params() -> CertificateParams {
+        let mut params = CertificateParams::new(vec!["kubewarden.io".to_owned()]).unwrap();
+        params
+            .subject_alt_names
+            .push(SanType::IpAddress("10.0.0.1".parse().unwrap()));
+        let mut distinguished_name = DistinguishedName::new();
+        distinguished_name.push(DnType::CommonName, "kubewarden.io");
+        distinguished_name.push(DnType::OrganizationName, "Kubewarden");
+        params.distinguished_name = distinguished_name;
+        params
+    }
+
+    #[test]
+    fn parse_pem_certificate() {
+        let key_pair = KeyPair::generate().unwrap();
+        let mut params = certificate_params();
+        params.is_ca = IsCa::ExplicitNoCa;
+        params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        params.extended_key_usages = vec![
+            ExtendedKeyUsagePurpose::ServerAuth,
+            ExtendedKeyUsagePurpose::Other(vec![1, 2, 3, 4]),
+        ];
+        let cert = params.self_signed(&key_pair).unwrap();
+
+        let parsed = parse_certificate(&Certificate {
+            encoding: CertificateEncoding::Pem,
+            data: cert.pem().into_bytes(),
+        })
+        .unwrap();
+
+        assert_eq!(parsed.version, 3);
+        assert_eq!(parsed.subject, "O=Kubewarden,CN=kubewarden.io");
+        assert_eq!(parsed.issuer, parsed.subject);
+        assert_eq!(
+            parsed.subject_alternative_names,
+            SubjectAlternativeNames {
+                dns_names: vec!["kubewarden.io".to_owned()],
+                ip_addresses: vec!["10.0.0.1".to_owned()],
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            parsed.public_key,
+            PublicKey {
+                key_type: "EC".to_owned(),
+                size: Some(256),
+                curve: Some("P-256".to_owned()),
+            }
+        );
+        assert_eq!(parsed.signature_algorithm, "ecdsa-with-SHA256");
+        assert!(!parsed.is_ca);
+        assert_eq!(parsed.key_usages, vec![KeyUsage::DigitalSignature]);
+        assert_eq!(
+            parsed.extended_key_usages,
+            vec![ExtendedKeyUsage::ServerAuth]
+        );
+        assert!(
+            parsed
+                .extensions
+                .iter()
+                .any(|extension| extension.name.as_deref() == Some("subjectAltName"))
+        );
+        assert_eq!(parsed.fingerprints.sha256, hex(&Sha256::digest(cert.der())));
+    }
+
+    #[test]
+    fn parse_der_certificate() {
+        let key_pair = KeyPair::generate().unwrap();
+        let cert = certificate_params().self_signed(&key_pair).unwrap();
+
+        let parsed = parse_certificate(&Certificate {
+            encoding: CertificateEncoding::Der,
+            data: cert.der().to_vec(),
+        })
+        .unwrap();
+
+        assert_eq!(parsed.subject, "O=Kubewarden,CN=kubewarden.io");
+        assert!(parsed.key_usages.is_empty());
+        assert!(parsed.extended_key_usages.is_empty());
+    }
+
+    #[test]
+    fn parse_pem_csr() {
+        let key_pair = KeyPair::generate().unwrap();
+        let csr = certificate_params().serialize_request(&key_pair).unwrap();
+
+        let parsed = parse_csr(&CertificateSigningRequest {
+            encoding: CertificateEncoding::Pem,
+            data: csr.pem().unwrap().into_bytes(),
+        })
+        .unwrap();
+
+        assert_eq!(parsed.subject, "O=Kubewarden,CN=kubewarden.io");
+        assert_eq!(
+            parsed.subject_alternative_names.dns_names,
+            vec!["kubewarden.io".to_owned()]
+        );
+        assert_eq!(parsed.public_key.key_type, "EC");
+        assert_eq!(parsed.signature_algorithm, "ecdsa-with-SHA256");
+    }
+
+    #[test]
+    fn reject_invalid_certificate() {
+        assert!(
+            parse_certificate(&Certificate {
+                encoding: CertificateEncoding::Der,
+                data: b"not a certificate".to_vec(),
+            })
+            .is_err()
+        );
+    }
+}
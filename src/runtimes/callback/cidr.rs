@@ -0,0 +1,218 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+use anyhow::{Result, anyhow};
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use serde::{Deserialize, Serialize};
+
+/// Payload of the `v1/cidr` net host capability
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+pub(crate) enum CidrRequest {
+    /// Check if `cidr` contains `address`, which can be an IP address or a
+    /// network in CIDR notation
+    Contains { cidr: String, address: String },
+    /// Check if the two networks have at least one address in common
+    Overlaps { cidr: String, other: String },
+    /// Classify the IP address, or the network in CIDR notation
+    Classify { address: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub(crate) enum CidrResponse {
+    Contains { contains: bool },
+    Overlaps { overlaps: bool },
+    Classify { classification: IpClassification },
+}
+
+/// The kind of addresses contained in a network
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum IpClassification {
+    Unspecified,
+    Loopback,
+    /// Private networks (RFC 1918), shared address space (RFC 6598) and
+    /// unique local addresses (RFC 4193)
+    Private,
+    LinkLocal,
+    Multicast,
+    Public,
+    /// The network contains addresses of different kinds
+    Mixed,
+}
+
+/// The special purpose networks, checked in order
+const SPECIAL_NETWORKS: [(&str, IpClassification); 13] = [
+    ("0.0.0.0/8", IpClassification::Unspecified),
+    ("::/128", IpClassification::Unspecified),
+    ("127.0.0.0/8", IpClassification::Loopback),
+    ("::1/128", IpClassification::Loopback),
+    ("10.0.0.0/8", IpClassification::Private),
+    ("100.64.0.0/10", IpClassification::Private),
+    ("172.16.0.0/12", IpClassification::Private),
+    ("192.168.0.0/16", IpClassification::Private),
+    ("fc00::/7", IpClassification::Private),
+    ("169.254.0.0/16", IpClassification::LinkLocal),
+    ("fe80::/10", IpClassification::LinkLocal),
+    ("224.0.0.0/4", IpClassification::Multicast),
+    ("ff00::/8", IpClassification::Multicast),
+];
+
+/// The IPv6 networks embedding IPv4 addresses inside of their last 32 bits:
+/// the IPv4-mapped addresses (RFC 4291) and the NAT64 well-known prefix (RFC 6052)
+const IPV4_EMBEDDING_NETWORKS: [&str; 2] = ["::ffff:0:0/96", "64:ff9b::/96"];
+
+pub(crate) fn evaluate(request: &CidrRequest) -> Result<CidrResponse> {
+    let response = match request {
+        CidrRequest::Contains { cidr, address } => CidrResponse::Contains {
+            contains: parse_network(cidr)?.contains(&parse_network(address)?),
+        },
+        CidrRequest::Overlaps { cidr, other } => CidrResponse::Overlaps {
+            overlaps: overlaps(&parse_network(cidr)?, &parse_network(other)?),
+        },
+        CidrRequest::Classify { address } => CidrResponse::Classify {
+            classification: classify(&parse_network(address)?),
+        },
+    };
+    Ok(response)
+}
+
+/// Parse a network in CIDR notation. A plain IP address is a network made
+/// only by itself
+fn parse_network(value: &str) -> Result<IpNet> {
+    if let Ok(ip) = value.parse::<IpAddr>() {
+        return Ok(IpNet::from(ip));
+    }
+    value
+        .parse::<IpNet>()
+        .map(|network| network.trunc())
+        .map_err(|_| anyhow!("'{value}' is neither an IP address nor a CIDR"))
+}
+
+/// Networks are either disjoint or one contains the other
+fn overlaps(a: &IpNet, b: &IpNet) -> bool {
+    a.contains(b) || b.contains(a)
+}
+
+/// Classify the network. The IPv6 networks embedding IPv4 addresses are
+/// classified like the IPv4 network they embed, e.g. `::ffff:10.0.0.1` is private
+fn classify(network: &IpNet) -> IpClassification {
+    if let IpNet::V6(network) = network {
+        for embedding in IPV4_EMBEDDING_NETWORKS {
+            let embedding: Ipv6Net = embedding.parse().expect("valid embedding network");
+            if embedding.contains(network) {
+                return classify(&IpNet::V4(embedded_ipv4(network)));
+            }
+            if network.contains(&embedding) {
+                return IpClassification::Mixed;
+            }
+        }
+    }
+
+    for (special, classification) in SPECIAL_NETWORKS {
+        let special: IpNet = special.parse().expect("valid special network");
+        if special.contains(network) {
+            return classification;
+        }
+        if network.contains(&special) {
+            return IpClassification::Mixed;
+        }
+    }
+    IpClassification::Public
+}
+
+/// The IPv4 network stored inside of the last 32 bits of an IPv6 network
+/// whose prefix is at least 96 bits long
+fn embedded_ipv4(network: &Ipv6Net) -> Ipv4Net {
+    let [.., a, b, c, d] = network.addr().octets();
+    Ipv4Net::new(Ipv4Addr::new(a, b, c, d), network.prefix_len() - 96)
+        .expect("valid embedded IPv4 network")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::address("10.0.0.0/8", "10.1.2.3", true)]
+    #[case::network("10.0.0.0/8", "10.1.0.0/16", true)]
+    #[case::larger_network("10.1.0.0/16", "10.0.0.0/8", false)]
+    #[case::other_address("10.0.0.0/8", "192.168.1.1", false)]
+    #[case::ipv6("2001:db8::/32", "2001:db8::1", true)]
+    #[case::different_families("10.0.0.0/8", "::1", false)]
+    #[case::not_truncated("10.1.2.3/8", "10.200.0.1", true)]
+    fn contains(#[case] cidr: &str, #[case] address: &str, #[case] expected: bool) {
+        let request = CidrRequest::Contains {
+            cidr: cidr.to_owned(),
+            address: address.to_owned(),
+        };
+
+        assert_eq!(
+            evaluate(&request).unwrap(),
+            CidrResponse::Contains { contains: expected }
+        );
+    }
+
+    #[rstest]
+    #[case::nested("10.0.0.0/8", "10.1.0.0/16", true)]
+    #[case::nested_reversed("10.1.0.0/16", "10.0.0.0/8", true)]
+    #[case::disjoint("10.0.0.0/16", "10.1.0.0/16", false)]
+    #[case::different_families("0.0.0.0/0", "::/0", false)]
+    fn overlaps(#[case] cidr: &str, #[case] other: &str, #[case] expected: bool) {
+        let request = CidrRequest::Overlaps {
+            cidr: cidr.to_owned(),
+            other: other.to_owned(),
+        };
+
+        assert_eq!(
+            evaluate(&request).unwrap(),
+            CidrResponse::Overlaps { overlaps: expected }
+        );
+    }
+
+    #[rstest]
+    #[case::private("192.168.1.10", IpClassification::Private)]
+    #[case::private_network("172.20.0.0/16", IpClassification::Private)]
+    #[case::shared("100.64.1.1", IpClassification::Private)]
+    #[case::unique_local("fd00::1", IpClassification::Private)]
+    #[case::loopback("127.0.0.1", IpClassification::Loopback)]
+    #[case::loopback_ipv6("::1", IpClassification::Loopback)]
+    #[case::link_local("169.254.169.254", IpClassification::LinkLocal)]
+    #[case::multicast("ff02::1", IpClassification::Multicast)]
+    #[case::unspecified("0.0.0.0", IpClassification::Unspecified)]
+    #[case::public("8.8.8.8", IpClassification::Public)]
+    #[case::public_ipv6("2001:4860:4860::8888", IpClassification::Public)]
+    #[case::mixed("0.0.0.0/0", IpClassification::Mixed)]
+    #[case::ipv4_mapped_private("::ffff:10.0.0.1", IpClassification::Private)]
+    #[case::ipv4_mapped_loopback("::ffff:127.0.0.1", IpClassification::Loopback)]
+    #[case::ipv4_mapped_link_local("::ffff:169.254.169.254", IpClassification::LinkLocal)]
+    #[case::ipv4_mapped_public("::ffff:8.8.8.8", IpClassification::Public)]
+    #[case::ipv4_mapped_network("::ffff:10.0.0.0/104", IpClassification::Private)]
+    #[case::ipv4_mapped_addresses("::ffff:0:0/96", IpClassification::Mixed)]
+    #[case::nat64_private("64:ff9b::10.0.0.1", IpClassification::Private)]
+    #[case::nat64_public("64:ff9b::8.8.8.8", IpClassification::Public)]
+    #[case::nat64_network("64:ff9b::/64", IpClassification::Mixed)]
+    fn classify(#[case] address: &str, #[case] expected: IpClassification) {
+        let request = CidrRequest::Classify {
+            address: address.to_owned(),
+        };
+
+        assert_eq!(
+            evaluate(&request).unwrap(),
+            CidrResponse::Classify {
+                classification: expected
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_network() {
+        let request = CidrRequest::Classify {
+            address: "not-an-ip".to_owned(),
+        };
+
+        assert!(evaluate(&request).is_err());
+    }
+}
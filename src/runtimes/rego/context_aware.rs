@@ -1,13 +1,18 @@
 use kube::api::ObjectList;
 use std::collections::{BTreeMap, BTreeSet};
+use std::time::Instant;
 use tokio::sync::{mpsc, oneshot};
 
 use crate::{
     callback_requests::{CallbackRequest, CallbackRequestType, CallbackResponse},
+    errors::HostCapabilityError,
     policy_metadata::ContextAwareResource,
-    runtimes::rego::{
-        errors::{RegoRuntimeError, Result},
-        opa_inventory::OpaInventory,
+    runtimes::{
+        callback::blocking_recv_until,
+        rego::{
+            errors::{RegoRuntimeError, Result},
+            opa_inventory::OpaInventory,
+        },
     },
 };
 
@@ -26,13 +31,14 @@ pub(crate) enum KubernetesContext {
 /// used by the runtime.
 pub(crate) fn get_allowed_resources(
     callback_channel: &mpsc::Sender<CallbackRequest>,
+    deadline: Option<Instant>,
     allowed_resources: &BTreeSet<ContextAwareResource>,
 ) -> Result<BTreeMap<ContextAwareResource, ObjectList<kube::core::DynamicObject>>> {
     let mut kube_resources: BTreeMap<ContextAwareResource, ObjectList<kube::core::DynamicObject>> =
         BTreeMap::new();
 
     for resource in allowed_resources {
-        let resource_list = get_all_resources_by_type(callback_channel, resource)?;
+        let resource_list = get_all_resources_by_type(callback_channel, deadline, resource)?;
         kube_resources.insert(resource.to_owned(), resource_list);
     }
 
@@ -41,6 +47,7 @@ pub(crate) fn get_allowed_resources(
 
 fn get_all_resources_by_type(
     callback_channel: &mpsc::Sender<CallbackRequest>,
+    deadline: Option<Instant>,
     resource_type: &ContextAwareResource,
 ) -> Result<ObjectList<kube::core::DynamicObject>> {
     let req_type = CallbackRequestType::KubernetesListResourceAll {
@@ -50,7 +57,7 @@ fn get_all_resources_by_type(
         field_selector: None,
     };
 
-    let response = make_request_via_callback_channel(req_type, callback_channel)?;
+    let response = make_request_via_callback_channel(req_type, callback_channel, deadline)?;
     serde_json::from_slice::<ObjectList<kube::core::DynamicObject>>(&response.payload)
         .map_err(RegoRuntimeError::CallbackConvertList)
 }
@@ -58,11 +65,12 @@ fn get_all_resources_by_type(
 /// For each allowed resource, check if the "list all resources" result changed since the given instant
 pub(crate) fn have_allowed_resources_changed_since_instant(
     callback_channel: &mpsc::Sender<CallbackRequest>,
+    deadline: Option<Instant>,
     allowed_resources: &BTreeSet<ContextAwareResource>,
     since: tokio::time::Instant,
 ) -> Result<bool> {
     for resource in allowed_resources {
-        if has_resource_changed_since(callback_channel, resource, since)? {
+        if has_resource_changed_since(callback_channel, deadline, resource, since)? {
             return Ok(true);
         }
     }
@@ -71,22 +79,19 @@ pub(crate) fn have_allowed_resources_changed_since_instant(
 }
 
 /// Check if the "list all resources" result changed since the given instant
-/// Note: this function doesn't take label_selector and field_selector into account because
-/// it's used only by gatekeeper policies, which don't use these selectors.
 fn has_resource_changed_since(
     callback_channel: &mpsc::Sender<CallbackRequest>,
+    deadline: Option<Instant>,
     resource_type: &ContextAwareResource,
     since: tokio::time::Instant,
 ) -> Result<bool> {
     let req_type = CallbackRequestType::HasKubernetesListResourceAllResultChangedSinceInstant {
         api_version: resource_type.api_version.to_owned(),
         kind: resource_type.kind.to_owned(),
-        label_selector: None,
-        field_selector: None,
         since,
     };
 
-    let response = make_request_via_callback_channel(req_type, callback_channel)?;
+    let response = make_request_via_callback_channel(req_type, callback_channel, deadline)?;
     serde_json::from_slice::<bool>(&response.payload).map_err(RegoRuntimeError::CallbackConvertBool)
 }
 
@@ -95,6 +100,7 @@ fn has_resource_changed_since(
 /// The map is built by making request via the given callback channel.
 pub(crate) fn get_plural_names(
     callback_channel: &mpsc::Sender<CallbackRequest>,
+    deadline: Option<Instant>,
     allowed_resources: &BTreeSet<ContextAwareResource>,
 ) -> Result<BTreeMap<ContextAwareResource, String>> {
     let mut plural_names_by_resource: BTreeMap<ContextAwareResource, String> = BTreeMap::new();
@@ -105,7 +111,7 @@ pub(crate) fn get_plural_names(
             kind: resource.kind.to_owned(),
         };
 
-        let response = make_request_via_callback_channel(req_type, callback_channel)?;
+        let response = make_request_via_callback_channel(req_type, callback_channel, deadline)?;
         let plural_name = serde_json::from_slice::<String>(&response.payload)
             .map_err(RegoRuntimeError::CallbackGetPluralName)?;
 
@@ -120,17 +126,31 @@ pub(crate) fn get_plural_names(
 fn make_request_via_callback_channel(
     request_type: CallbackRequestType,
     callback_channel: &mpsc::Sender<CallbackRequest>,
+    deadline: Option<Instant>,
 ) -> Result<CallbackResponse> {
+    if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+        return Err(RegoRuntimeError::CallbackRequest(
+            HostCapabilityError::Timeout.into(),
+        ));
+    }
+
     let (tx, rx) = oneshot::channel::<std::result::Result<CallbackResponse, wasmtime::Error>>();
     let req = CallbackRequest {
         request: request_type,
         response_channel: tx,
+        deadline: deadline.map(tokio::time::Instant::from_std),
     };
     callback_channel
         .try_send(req)
         .map_err(|e| RegoRuntimeError::CallbackSend(e.to_string()))?;
 
-    match rx.blocking_recv() {
+    let response = match deadline {
+        Some(deadline) => blocking_recv_until(rx, deadline).ok_or_else(|| {
+            RegoRuntimeError::CallbackRequest(HostCapabilityError::Timeout.into())
+        })?,
+        None => rx.blocking_recv(),
+    };
+    match response {
         Ok(msg) => msg.map_err(RegoRuntimeError::CallbackRequest),
         Err(e) => Err(RegoRuntimeError::CallbackResponse(e.to_string())),
     }
@@ -144,6 +164,31 @@ pub(crate) mod tests {
     use rstest::rstest;
     use std::collections::HashMap;
     use std::path::Path;
+    use std::time::Duration;
+
+    #[rstest]
+    #[case::deadline_reached_while_waiting(Duration::from_millis(100))]
+    #[case::deadline_already_reached(Duration::ZERO)]
+    fn callback_request_times_out_at_deadline(#[case] timeout: Duration) {
+        // the requests are never answered
+        let (callback_tx, _callback_rx) = mpsc::channel::<CallbackRequest>(10);
+        let deadline = Instant::now() + timeout;
+
+        let err = make_request_via_callback_channel(
+            CallbackRequestType::KubernetesGetResourcePluralName {
+                api_version: "v1".to_owned(),
+                kind: "Pod".to_owned(),
+            },
+            &callback_tx,
+            Some(deadline),
+        )
+        .expect_err("the request should time out");
+
+        assert!(matches!(
+            err,
+            RegoRuntimeError::CallbackRequest(e) if e.is::<HostCapabilityError>()
+        ));
+    }
 
     pub fn dynamic_object_from_fixture(
         resource_type: &str,
@@ -199,8 +244,6 @@ pub(crate) mod tests {
                 } => {
                     assert_eq!(api_version, expected_resource.api_version);
                     assert_eq!(kind, expected_resource.kind);
-                    assert!(label_selector.is_none());
-                    assert!(field_selector.is_none());
                 }
                 _ => {
                     panic!("not the expected request type");
@@ -210,13 +253,14 @@ pub(crate) mod tests {
             let services_list = object_list_from_dynamic_objects(&services).unwrap();
             let callback_response = CallbackResponse {
                 payload: serde_json::to_vec(&services_list).unwrap(),
+                cached: false,
             };
 
             req.response_channel.send(Ok(callback_response)).unwrap();
         });
 
         tokio::task::spawn_blocking(move || {
-            let actual = get_all_resources_by_type(&callback_tx, &resource).unwrap();
+            let actual = get_all_resources_by_type(&callback_tx, None, &resource).unwrap();
             let actual_json = serde_json::to_value(actual).unwrap();
             let expected_json = serde_json::to_value(services_list).unwrap();
             assert_json_eq!(actual_json, expected_json);
@@ -259,13 +303,14 @@ pub(crate) mod tests {
 
             let callback_response = CallbackResponse {
                 payload: serde_json::to_vec(&plural_name).unwrap(),
+                cached: false,
             };
 
             req.response_channel.send(Ok(callback_response)).unwrap();
         });
 
         tokio::task::spawn_blocking(move || {
-            let actual = get_plural_names(&callback_tx, &resources).unwrap();
+            let actual = get_plural_names(&callback_tx, None, &resources).unwrap();
             assert_eq!(actual, expected_names);
         })
         .await
@@ -313,16 +358,12 @@ pub(crate) mod tests {
                 CallbackRequestType::HasKubernetesListResourceAllResultChangedSinceInstant {
                     api_version,
                     kind,
-                    label_selector,
-                    field_selector,
                     since: _,
                 } => {
                     let resource = ContextAwareResource {
                         api_version: api_version.clone(),
                         kind: kind.clone(),
                     };
-                    assert!(label_selector.is_none());
-                    assert!(field_selector.is_none());
 
                     expected_resources_with_change_status
                         .get(&resource)
@@ -335,6 +376,7 @@ pub(crate) mod tests {
 
                 let callback_response = CallbackResponse {
                     payload: serde_json::to_vec(&changed).unwrap(),
+                    cached: false,
                 };
 
                 req.response_channel.send(Ok(callback_response)).unwrap();
@@ -344,7 +386,7 @@ pub(crate) mod tests {
         tokio::task::spawn_blocking(move || {
             let resources = resources_with_change_status.keys().cloned().collect();
             let actual =
-                have_allowed_resources_changed_since_instant(&callback_tx, &resources, since)
+                have_allowed_resources_changed_since_instant(&callback_tx, None, &resources, since)
                     .unwrap();
             assert_json_eq!(expected, actual);
         })
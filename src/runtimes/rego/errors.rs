@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::policy_metadata::HostCapability;
+
 pub type Result<T> = std::result::Result<T, RegoRuntimeError>;
 
 #[derive(Error, Debug)]
@@ -7,6 +9,11 @@ pub enum RegoRuntimeError {
     #[error("cannot build Rego context aware data: callback channel is not set")]
     CallbackChannelNotSet,
 
+    #[error(
+        "Policy has not been granted access to the {0} host capability. The violation has been reported."
+    )]
+    HostCapabilityNotGranted(HostCapability),
+
     #[error("cannot convert callback response into a list of kubernetes objects: {0}")]
     CallbackConvertList(#[source] serde_json::Error),
 
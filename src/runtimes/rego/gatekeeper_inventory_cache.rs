@@ -73,6 +73,7 @@ impl GateKeeperInventoryCache {
     pub fn get_inventory(
         &self,
         callback_channel: &mpsc::Sender<CallbackRequest>,
+        deadline: Option<std::time::Instant>,
         ctx_aware_resources: &BTreeSet<ContextAwareResource>,
     ) -> Result<Vec<u8>> {
         let inventory = {
@@ -80,14 +81,21 @@ impl GateKeeperInventoryCache {
             inventories.get(ctx_aware_resources).cloned()
         };
         let inventory = match inventory {
-            None => self.create_and_register_inventory(ctx_aware_resources, callback_channel),
+            None => {
+                self.create_and_register_inventory(ctx_aware_resources, callback_channel, deadline)
+            }
             Some(cached_inventory) => {
                 if have_allowed_resources_changed_since_instant(
                     callback_channel,
+                    deadline,
                     ctx_aware_resources,
                     cached_inventory.cache_time,
                 )? {
-                    self.create_and_register_inventory(ctx_aware_resources, callback_channel)
+                    self.create_and_register_inventory(
+                        ctx_aware_resources,
+                        callback_channel,
+                        deadline,
+                    )
                 } else {
                     Ok(cached_inventory)
                 }
@@ -102,9 +110,11 @@ impl GateKeeperInventoryCache {
         &self,
         ctx_aware_resources: &BTreeSet<ContextAwareResource>,
         callback_channel: &mpsc::Sender<CallbackRequest>,
+        deadline: Option<std::time::Instant>,
     ) -> Result<Arc<CachedInventory>> {
         let now = Instant::now();
-        let cluster_resources = get_allowed_resources(callback_channel, ctx_aware_resources)?;
+        let cluster_resources =
+            get_allowed_resources(callback_channel, deadline, ctx_aware_resources)?;
         let inventory = GatekeeperInput {
             inventory: GatekeeperInventory::new(&cluster_resources)?,
         };
@@ -165,10 +175,9 @@ pub(crate) mod tests {
                     } => {
                         assert_eq!(api_version, expected_resource.api_version);
                         assert_eq!(kind, expected_resource.kind);
-                        assert!(label_selector.is_none());
-                        assert!(field_selector.is_none());
                         CallbackResponse {
                             payload: serde_json::to_vec(&services_list).unwrap(),
+                            cached: false,
                         }
                     }
                     _ => {
@@ -190,7 +199,7 @@ pub(crate) mod tests {
             let resources: BTreeSet<ContextAwareResource> = BTreeSet::from([resource]);
 
             let cached_inventory = GATEKEEPER_INVENTORY_CACHE
-                .get_inventory(&callback_tx, &resources)
+                .get_inventory(&callback_tx, None, &resources)
                 .unwrap();
             assert!(!cached_inventory.is_empty());
 
@@ -244,17 +253,14 @@ pub(crate) mod tests {
                     CallbackRequestType::HasKubernetesListResourceAllResultChangedSinceInstant {
                         api_version,
                         kind,
-                        label_selector,
-                        field_selector,
                         since: _,
                     } => {
                         assert_eq!(api_version, expected_resource.api_version);
                         assert_eq!(kind, expected_resource.kind);
-                        assert!(label_selector.is_none());
-                        assert!(field_selector.is_none());
 
                         CallbackResponse {
                             payload: serde_json::to_vec(&false).unwrap(),
+                            cached: false,
                         }
                     }
                     _ => {
@@ -268,7 +274,7 @@ pub(crate) mod tests {
 
         tokio::task::spawn_blocking(move || {
             let actual = GATEKEEPER_INVENTORY_CACHE
-                .get_inventory(&callback_tx, &resources)
+                .get_inventory(&callback_tx, None, &resources)
                 .unwrap();
             assert_eq!(expected_cached_inventory.data, actual);
         })
@@ -324,26 +330,22 @@ pub(crate) mod tests {
                     } => {
                         assert_eq!(api_version, expected_resource.api_version);
                         assert_eq!(kind, expected_resource.kind);
-                        assert!(label_selector.is_none());
-                        assert!(field_selector.is_none());
                         CallbackResponse {
                             payload: serde_json::to_vec(&services_list).unwrap(),
+                            cached: false,
                         }
                     }
                     CallbackRequestType::HasKubernetesListResourceAllResultChangedSinceInstant {
                         api_version,
                         kind,
-                        label_selector,
-                        field_selector,
                         since: _,
                     } => {
                         assert_eq!(api_version, expected_resource.api_version);
                         assert_eq!(kind, expected_resource.kind);
-                        assert!(label_selector.is_none());
-                        assert!(field_selector.is_none());
 
                         CallbackResponse {
                             payload: serde_json::to_vec(&true).unwrap(),
+                            cached: false,
                         }
                     }
                     _ => {
@@ -357,7 +359,7 @@ pub(crate) mod tests {
 
         tokio::task::spawn_blocking(move || {
             let actual = GATEKEEPER_INVENTORY_CACHE
-                .get_inventory(&callback_tx, &resources)
+                .get_inventory(&callback_tx, None, &resources)
                 .unwrap();
             assert!(actual != stale_cached_inventory.data);
             let actual_inventory = serde_json::from_slice::<GatekeeperInput>(&actual).unwrap();
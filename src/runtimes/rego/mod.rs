@@ -7,20 +7,33 @@ mod runtime;
 mod stack;
 mod stack_pre;
 
+use std::sync::Arc;
+
 use burrego::host_callbacks::HostCallbacks;
 pub(crate) use runtime::Runtime;
 pub(crate) use stack::Stack;
 pub(crate) use stack_pre::StackPre;
 
-#[tracing::instrument(level = "error")]
-fn opa_abort(msg: &str) {}
+use crate::evaluation_context::EvaluationContext;
+use crate::policy_tracing::{PolicyLogLevel, PolicyLogRuntime};
 
-#[tracing::instrument(level = "info")]
-fn opa_println(msg: &str) {}
+/// Build the OPA host callbacks, which forward the messages of the policy
+/// to its log pipeline
+pub(crate) fn new_host_callbacks(eval_ctx: &EvaluationContext) -> HostCallbacks {
+    let host_callback = |level| {
+        let eval_ctx = eval_ctx.clone();
+        Arc::new(move |msg: &str| {
+            eval_ctx.emit_policy_log(
+                PolicyLogRuntime::Rego,
+                level,
+                msg.to_owned(),
+                serde_json::Map::new(),
+            );
+        })
+    };
 
-pub(crate) fn new_host_callbacks() -> HostCallbacks {
     HostCallbacks {
-        opa_abort,
-        opa_println,
+        opa_abort: host_callback(PolicyLogLevel::Error),
+        opa_println: host_callback(PolicyLogLevel::Info),
     }
 }
@@ -1,12 +1,11 @@
-use std::collections::BTreeSet;
-
-use tokio::sync::mpsc;
+use serde_json::json;
+use tracing::error;
 
 use crate::{
-    callback_requests::CallbackRequest,
+    audit::HostCapabilityOutcome,
     evaluation_context::EvaluationContext,
     policy_evaluator::RegoPolicyExecutionMode,
-    policy_metadata::ContextAwareResource,
+    policy_metadata::HostCapability,
     runtimes::rego::{
         context_aware,
         errors::{RegoRuntimeError, Result},
@@ -26,7 +25,7 @@ impl Stack {
     /// Create a new `Stack` using a `StackPre` object
     pub fn new_from_pre(stack_pre: &StackPre, eval_ctx: &EvaluationContext) -> Result<Self> {
         let evaluator = stack_pre
-            .rehydrate(eval_ctx.epoch_deadline)
+            .rehydrate(eval_ctx)
             .map_err(|e| RegoRuntimeError::EvaluatorError(e.to_string()))?;
         Ok(Self {
             evaluator,
@@ -37,28 +36,74 @@ impl Stack {
 
     pub fn build_kubernetes_context(
         &self,
-        callback_channel: Option<&mpsc::Sender<CallbackRequest>>,
-        ctx_aware_resources_allow_list: &BTreeSet<ContextAwareResource>,
+        eval_ctx: &EvaluationContext,
     ) -> Result<context_aware::KubernetesContext> {
+        let ctx_aware_resources_allow_list = &eval_ctx.ctx_aware_resources_allow_list;
         if ctx_aware_resources_allow_list.is_empty() {
             return Ok(context_aware::KubernetesContext::Empty);
         }
 
-        match callback_channel {
+        let capability = HostCapability::KubernetesListResources;
+        let audit = |outcome| {
+            eval_ctx.audit(
+                capability,
+                "rego/kubernetes_context",
+                || json!({ "resources": ctx_aware_resources_allow_list }),
+                outcome,
+                None,
+            );
+        };
+        if !eval_ctx.can_use_host_capability(capability) {
+            error!(
+                policy = eval_ctx.policy_id,
+                capability_requested = %capability,
+                capabilities_allowed = ?eval_ctx.host_capabilities_allow_list,
+                "Policy tried to use a host capability it doesn't have access to"
+            );
+            audit(HostCapabilityOutcome::Denied);
+            return Err(RegoRuntimeError::HostCapabilityNotGranted(capability));
+        }
+
+        let kubernetes_context = self.fetch_kubernetes_context(eval_ctx);
+        match &kubernetes_context {
+            Ok(_) => audit(HostCapabilityOutcome::Success),
+            Err(e) => audit(HostCapabilityOutcome::Failure {
+                error: e.to_string(),
+            }),
+        }
+        kubernetes_context
+    }
+
+    fn fetch_kubernetes_context(
+        &self,
+        eval_ctx: &EvaluationContext,
+    ) -> Result<context_aware::KubernetesContext> {
+        let ctx_aware_resources_allow_list = &eval_ctx.ctx_aware_resources_allow_list;
+        let deadline = eval_ctx.deadline.get();
+        match eval_ctx.callback_channel.as_ref() {
             None => Err(RegoRuntimeError::CallbackChannelNotSet),
             Some(chan) => match self.policy_execution_mode {
                 RegoPolicyExecutionMode::Opa => {
-                    let cluster_resources =
-                        context_aware::get_allowed_resources(chan, ctx_aware_resources_allow_list)?;
-                    let plural_names_by_resource =
-                        context_aware::get_plural_names(chan, ctx_aware_resources_allow_list)?;
+                    let cluster_resources = context_aware::get_allowed_resources(
+                        chan,
+                        deadline,
+                        ctx_aware_resources_allow_list,
+                    )?;
+                    let plural_names_by_resource = context_aware::get_plural_names(
+                        chan,
+                        deadline,
+                        ctx_aware_resources_allow_list,
+                    )?;
                     let inventory =
                         OpaInventory::new(&cluster_resources, &plural_names_by_resource)?;
                     Ok(context_aware::KubernetesContext::Opa(inventory))
                 }
                 RegoPolicyExecutionMode::Gatekeeper => {
-                    let cached_inventory = GATEKEEPER_INVENTORY_CACHE
-                        .get_inventory(chan, ctx_aware_resources_allow_list)?;
+                    let cached_inventory = GATEKEEPER_INVENTORY_CACHE.get_inventory(
+                        chan,
+                        deadline,
+                        ctx_aware_resources_allow_list,
+                    )?;
                     Ok(context_aware::KubernetesContext::Gatekeeper(
                         cached_inventory,
                     ))
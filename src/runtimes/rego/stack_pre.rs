@@ -1,4 +1,5 @@
 use crate::{
+    evaluation_context::EvaluationContext,
     policy_evaluator::RegoPolicyExecutionMode,
     runtimes::rego::errors::{RegoRuntimeError, Result},
 };
@@ -38,13 +39,13 @@ impl StackPre {
     }
 
     /// Create a fresh `burrego::Evaluator`
-    pub(crate) fn rehydrate(&self, epoch_deadline: Option<u64>) -> Result<burrego::Evaluator> {
+    pub(crate) fn rehydrate(&self, eval_ctx: &EvaluationContext) -> Result<burrego::Evaluator> {
         let mut builder = burrego::EvaluatorBuilder::default()
             .engine(&self.engine)
             .module(self.module.clone())
-            .host_callbacks(crate::runtimes::rego::new_host_callbacks());
+            .host_callbacks(crate::runtimes::rego::new_host_callbacks(eval_ctx));
 
-        if let Some(deadline) = epoch_deadline {
+        if let Some(deadline) = eval_ctx.epoch_deadline {
             builder = builder.enable_epoch_interruptions(deadline);
         }
         let evaluator = builder
@@ -206,7 +206,15 @@ mod tests {
             policy_id: "wapc_endless_loop".to_string(),
             callback_channel: None,
             ctx_aware_resources_allow_list: Default::default(),
+            http_get_allow_list: Vec::new(),
+            host_capabilities_allow_list: None,
             epoch_deadline: Some(epoch_deadline),
+            evaluation_timeout: None,
+            deadline: Default::default(),
+            audit_sink: None,
+            current_request: Default::default(),
+            log_level: None,
+            policy_logs: Default::default(),
         };
 
         let eval_ctx = Arc::new(eval_ctx);
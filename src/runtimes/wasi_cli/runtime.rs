@@ -1,7 +1,7 @@
 use kubewarden_policy_sdk::response::ValidationResponse as PolicyValidationResponse;
 use kubewarden_policy_sdk::settings::SettingsValidationResponse;
 use serde_json::json;
-use tracing::{error, warn};
+use tracing::error;
 
 use crate::admission_response::AdmissionResponse;
 use crate::policy_evaluator::{PolicySettings, ValidateRequest};
@@ -37,14 +37,7 @@ impl Runtime<'_> {
 
         match self.0.run(&input, &args) {
             Ok(RunResult { stdout, stderr }) => {
-                if !stderr.is_empty() {
-                    warn!(
-                        request = request.uid().to_string(),
-                        operation = "validate",
-                        "stderr: {:?}",
-                        stderr
-                    )
-                }
+                self.0.eval_ctx().log_stderr("validate", &stderr);
                 match serde_json::from_slice::<PolicyValidationResponse>(stdout.as_bytes()) {
                     Ok(pvr) => {
                         let req_json_value = serde_json::to_value(request)
@@ -81,9 +74,7 @@ impl Runtime<'_> {
 
         match self.0.run(settings.as_bytes(), &args) {
             Ok(RunResult { stdout, stderr }) => {
-                if !stderr.is_empty() {
-                    warn!(operation = "validate-settings", "stderr: {:?}", stderr)
-                }
+                self.0.eval_ctx().log_stderr("validate-settings", &stderr);
                 serde_json::from_slice::<SettingsValidationResponse>(stdout.as_bytes())
                     .unwrap_or_else(|e| SettingsValidationResponse {
                         valid: false,
@@ -41,6 +41,10 @@ impl Stack {
         }
     }
 
+    pub(crate) fn eval_ctx(&self) -> &EvaluationContext {
+        &self.eval_ctx
+    }
+
     /// Run a WASI program with the given input and args
     pub(crate) fn run(
         &self,
@@ -0,0 +1,344 @@
+use k8s_openapi::{
+    ByteString,
+    api::admissionregistration::v1::{
+        MutatingWebhook, MutatingWebhookConfiguration, RuleWithOperations, ServiceReference,
+        ValidatingWebhook, ValidatingWebhookConfiguration, WebhookClientConfig,
+    },
+    apimachinery::pkg::apis::meta::v1::ObjectMeta,
+};
+
+use crate::errors::WebhookConfigurationError;
+use crate::policy_metadata::{Metadata, PolicyType};
+
+/// The maximum timeout allowed by Kubernetes for an admission webhook, in seconds
+const MAX_WEBHOOK_TIMEOUT_SECONDS: u64 = 30;
+
+/// The version of the `AdmissionReview` objects sent to the policies
+const ADMISSION_REVIEW_VERSION: &str = "v1";
+
+/// How unrecognized errors from the webhook are handled by the Kubernetes API server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// The error is ignored and the API request is allowed to continue
+    Ignore,
+    /// The API request is rejected
+    #[default]
+    Fail,
+}
+
+impl From<FailurePolicy> for String {
+    fn from(failure_policy: FailurePolicy) -> String {
+        match failure_policy {
+            FailurePolicy::Ignore => String::from("Ignore"),
+            FailurePolicy::Fail => String::from("Fail"),
+        }
+    }
+}
+
+/// Whether the webhook has side effects on the cluster
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SideEffects {
+    /// Calling the webhook has no side effects
+    #[default]
+    None,
+    /// Calling the webhook has side effects, unless the request is a dry run one
+    NoneOnDryRun,
+}
+
+impl From<SideEffects> for String {
+    fn from(side_effects: SideEffects) -> String {
+        match side_effects {
+            SideEffects::None => String::from("None"),
+            SideEffects::NoneOnDryRun => String::from("NoneOnDryRun"),
+        }
+    }
+}
+
+/// Helper struct that renders the Kubernetes webhook configuration of a policy,
+/// starting from its [`Metadata`].
+///
+/// ```rust,ignore
+/// let webhook_configuration = WebhookConfigurationBuilder::new(
+///         "privileged-pods.kubewarden.admission",
+///         ServiceReference {
+///             name: "policy-server-default".to_string(),
+///             namespace: "kubewarden".to_string(),
+///             path: Some("/validate/privileged-pods".to_string()),
+///             port: Some(8443),
+///         },
+///     )
+///     .ca_bundle(ca_bundle)
+///     .epoch_deadline(Some(2))
+///     .build_validating(&metadata)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct WebhookConfigurationBuilder {
+    name: String,
+    service: ServiceReference,
+    ca_bundle: Option<Vec<u8>>,
+    failure_policy: FailurePolicy,
+    epoch_deadline: Option<u64>,
+    side_effects: SideEffects,
+}
+
+impl WebhookConfigurationBuilder {
+    /// Create a new builder.
+    ///
+    /// `name` is used both as the name of the configuration object and of the
+    /// webhook. Kubernetes requires it to be a fully qualified domain name.
+    /// `service` is the Service exposing the Policy Server that hosts the policy.
+    pub fn new(name: &str, service: ServiceReference) -> Self {
+        WebhookConfigurationBuilder {
+            name: name.to_owned(),
+            service,
+            ca_bundle: None,
+            failure_policy: FailurePolicy::default(),
+            epoch_deadline: None,
+            side_effects: SideEffects::default(),
+        }
+    }
+
+    /// PEM encoded CA bundle used by the API server to validate the
+    /// certificate of the webhook
+    #[must_use]
+    pub fn ca_bundle(mut self, ca_bundle: &[u8]) -> Self {
+        self.ca_bundle = Some(ca_bundle.to_owned());
+        self
+    }
+
+    /// Set the failure policy of the webhook. Defaults to [`FailurePolicy::Fail`]
+    #[must_use]
+    pub fn failure_policy(mut self, failure_policy: FailurePolicy) -> Self {
+        self.failure_policy = failure_policy;
+        self
+    }
+
+    /// The epoch deadline of the policy, used to compute the timeout of the webhook.
+    ///
+    /// Like Policy Server does, each epoch tick is assumed to last one second.
+    /// The timeout is capped to the maximum value allowed by Kubernetes (30 seconds).
+    /// When not set, the Kubernetes default is used.
+    #[must_use]
+    pub fn epoch_deadline(mut self, epoch_deadline: Option<u64>) -> Self {
+        self.epoch_deadline = epoch_deadline;
+        self
+    }
+
+    /// Set the side effects of the webhook. Defaults to [`SideEffects::None`]
+    #[must_use]
+    pub fn side_effects(mut self, side_effects: SideEffects) -> Self {
+        self.side_effects = side_effects;
+        self
+    }
+
+    /// Render the `ValidatingWebhookConfiguration` of the policy
+    pub fn build_validating(
+        &self,
+        metadata: &Metadata,
+    ) -> Result<ValidatingWebhookConfiguration, WebhookConfigurationError> {
+        ensure_kubernetes_policy(metadata)?;
+
+        Ok(ValidatingWebhookConfiguration {
+            metadata: self.object_meta(),
+            webhooks: Some(vec![ValidatingWebhook {
+                name: self.name.clone(),
+                admission_review_versions: vec![ADMISSION_REVIEW_VERSION.to_string()],
+                client_config: self.client_config(),
+                failure_policy: Some(self.failure_policy.into()),
+                rules: Some(rules(metadata)),
+                side_effects: self.side_effects.into(),
+                timeout_seconds: self.timeout_seconds(),
+                ..Default::default()
+            }]),
+        })
+    }
+
+    /// Render the `MutatingWebhookConfiguration` of the policy.
+    ///
+    /// This fails when the policy is not a mutating one.
+    pub fn build_mutating(
+        &self,
+        metadata: &Metadata,
+    ) -> Result<MutatingWebhookConfiguration, WebhookConfigurationError> {
+        ensure_kubernetes_policy(metadata)?;
+        if !metadata.mutating {
+            return Err(WebhookConfigurationError::NotMutating);
+        }
+
+        Ok(MutatingWebhookConfiguration {
+            metadata: self.object_meta(),
+            webhooks: Some(vec![MutatingWebhook {
+                name: self.name.clone(),
+                admission_review_versions: vec![ADMISSION_REVIEW_VERSION.to_string()],
+                client_config: self.client_config(),
+                failure_policy: Some(self.failure_policy.into()),
+                rules: Some(rules(metadata)),
+                side_effects: self.side_effects.into(),
+                timeout_seconds: self.timeout_seconds(),
+                ..Default::default()
+            }]),
+        })
+    }
+
+    fn object_meta(&self) -> ObjectMeta {
+        ObjectMeta {
+            name: Some(self.name.clone()),
+            ..Default::default()
+        }
+    }
+
+    fn client_config(&self) -> WebhookClientConfig {
+        WebhookClientConfig {
+            ca_bundle: self.ca_bundle.clone().map(ByteString),
+            service: Some(self.service.clone()),
+            url: None,
+        }
+    }
+
+    fn timeout_seconds(&self) -> Option<i32> {
+        self.epoch_deadline
+            .map(|deadline| deadline.clamp(1, MAX_WEBHOOK_TIMEOUT_SECONDS) as i32)
+    }
+}
+
+fn ensure_kubernetes_policy(metadata: &Metadata) -> Result<(), WebhookConfigurationError> {
+    if metadata.policy_type != PolicyType::Kubernetes {
+        return Err(WebhookConfigurationError::NotKubernetesPolicy(
+            metadata.policy_type.to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn rules(metadata: &Metadata) -> Vec<RuleWithOperations> {
+    metadata
+        .rules
+        .iter()
+        .map(RuleWithOperations::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use kubewarden_policy_sdk::metadata::ProtocolVersion;
+    use rstest::rstest;
+
+    use crate::policy_metadata::{Operation, Rule};
+
+    fn metadata(mutating: bool) -> Metadata {
+        Metadata {
+            protocol_version: Some(ProtocolVersion::V1),
+            rules: vec![Rule {
+                api_groups: vec![String::from("")],
+                api_versions: vec![String::from("v1")],
+                resources: vec![String::from("pods")],
+                operations: vec![Operation::Create, Operation::Update],
+            }],
+            mutating,
+            ..Default::default()
+        }
+    }
+
+    fn builder() -> WebhookConfigurationBuilder {
+        WebhookConfigurationBuilder::new(
+            "privileged-pods.kubewarden.admission",
+            ServiceReference {
+                name: "policy-server-default".to_string(),
+                namespace: "kubewarden".to_string(),
+                path: Some("/validate/privileged-pods".to_string()),
+                port: Some(8443),
+            },
+        )
+    }
+
+    #[test]
+    fn build_validating_webhook_configuration() {
+        let configuration = builder()
+            .ca_bundle(b"ca bundle")
+            .failure_policy(FailurePolicy::Ignore)
+            .side_effects(SideEffects::NoneOnDryRun)
+            .epoch_deadline(Some(2))
+            .build_validating(&metadata(false))
+            .expect("cannot build validating webhook configuration");
+
+        assert_eq!(
+            configuration.metadata.name.as_deref(),
+            Some("privileged-pods.kubewarden.admission")
+        );
+        let webhooks = configuration.webhooks.expect("no webhooks");
+        assert_eq!(webhooks.len(), 1);
+
+        let webhook = &webhooks[0];
+        assert_eq!(webhook.admission_review_versions, vec!["v1".to_string()]);
+        assert_eq!(webhook.failure_policy.as_deref(), Some("Ignore"));
+        assert_eq!(webhook.side_effects, "NoneOnDryRun");
+        assert_eq!(webhook.timeout_seconds, Some(2));
+        assert_eq!(
+            webhook.client_config.ca_bundle,
+            Some(ByteString(b"ca bundle".to_vec()))
+        );
+        assert_eq!(
+            webhook.rules,
+            Some(vec![RuleWithOperations {
+                api_groups: Some(vec![String::from("")]),
+                api_versions: Some(vec![String::from("v1")]),
+                resources: Some(vec![String::from("pods")]),
+                operations: Some(vec![String::from("CREATE"), String::from("UPDATE")]),
+                scope: None,
+            }])
+        );
+    }
+
+    #[test]
+    fn build_mutating_webhook_configuration() {
+        let configuration = builder()
+            .build_mutating(&metadata(true))
+            .expect("cannot build mutating webhook configuration");
+
+        let webhook = &configuration.webhooks.expect("no webhooks")[0];
+        assert_eq!(webhook.failure_policy.as_deref(), Some("Fail"));
+        assert_eq!(webhook.side_effects, "None");
+        assert_eq!(webhook.timeout_seconds, None);
+    }
+
+    #[test]
+    fn build_mutating_webhook_configuration_of_validating_policy() {
+        let result = builder().build_mutating(&metadata(false));
+
+        assert!(matches!(
+            result,
+            Err(WebhookConfigurationError::NotMutating)
+        ));
+    }
+
+    #[test]
+    fn build_webhook_configuration_of_raw_policy() {
+        let metadata = Metadata {
+            policy_type: PolicyType::Raw,
+            ..metadata(false)
+        };
+
+        let result = builder().build_validating(&metadata);
+
+        assert!(matches!(
+            result,
+            Err(WebhookConfigurationError::NotKubernetesPolicy(_))
+        ));
+    }
+
+    #[rstest]
+    #[case::no_deadline(None, None)]
+    #[case::zero(Some(0), Some(1))]
+    #[case::within_limits(Some(10), Some(10))]
+    #[case::over_limits(Some(60), Some(30))]
+    fn timeout_from_epoch_deadline(
+        #[case] epoch_deadline: Option<u64>,
+        #[case] expected: Option<i32>,
+    ) {
+        let builder = builder().epoch_deadline(epoch_deadline);
+
+        assert_eq!(builder.timeout_seconds(), expected);
+    }
+}
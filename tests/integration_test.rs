@@ -202,7 +202,15 @@ async fn test_policy_evaluator(
         policy_id: "test".to_owned(),
         callback_channel: None,
         ctx_aware_resources_allow_list: Default::default(),
+        http_get_allow_list: Vec::new(),
+        host_capabilities_allow_list: None,
         epoch_deadline: None,
+        evaluation_timeout: None,
+        deadline: Default::default(),
+        audit_sink: None,
+        current_request: Default::default(),
+        log_level: None,
+        policy_logs: Default::default(),
     };
 
     let mut policy_evaluator = build_policy_evaluator(execution_mode, &policy, &eval_ctx);
@@ -315,7 +323,15 @@ async fn test_runtime_context_aware<F, Fut>(
                 kind: "Service".to_owned(),
             },
         ]),
+        http_get_allow_list: Vec::new(),
+        host_capabilities_allow_list: None,
         epoch_deadline: Some(2),
+        evaluation_timeout: None,
+        deadline: Default::default(),
+        audit_sink: None,
+        current_request: Default::default(),
+        log_level: None,
+        policy_logs: Default::default(),
     };
 
     let request_data = load_request_data(request_file_path);
@@ -356,13 +372,22 @@ async fn test_oci_manifest_capability(
             image: policy_uri.to_owned(),
         },
         response_channel: tx,
+        deadline: None,
     };
 
     let eval_ctx = EvaluationContext {
         policy_id: "test".to_owned(),
         callback_channel: Some(callback_handler_channel),
         ctx_aware_resources_allow_list: Default::default(),
+        http_get_allow_list: Vec::new(),
+        host_capabilities_allow_list: None,
         epoch_deadline: None,
+        evaluation_timeout: None,
+        deadline: Default::default(),
+        audit_sink: None,
+        current_request: Default::default(),
+        log_level: None,
+        policy_logs: Default::default(),
     };
 
     let cb_channel: mpsc::Sender<CallbackRequest> = eval_ctx
@@ -447,13 +472,22 @@ async fn test_oci_manifest_and_config_capability(
             image: policy_uri.to_owned(),
         },
         response_channel: tx,
+        deadline: None,
     };
 
     let eval_ctx = EvaluationContext {
         policy_id: "test".to_owned(),
         callback_channel: Some(callback_handler_channel),
         ctx_aware_resources_allow_list: Default::default(),
+        http_get_allow_list: Vec::new(),
+        host_capabilities_allow_list: None,
         epoch_deadline: None,
+        evaluation_timeout: None,
+        deadline: Default::default(),
+        audit_sink: None,
+        current_request: Default::default(),
+        log_level: None,
+        policy_logs: Default::default(),
     };
 
     let cb_channel: mpsc::Sender<CallbackRequest> = eval_ctx
@@ -515,13 +549,22 @@ async fn test_oci_digest_capability() {
             image: "ghcr.io/kubewarden/tests/policy-server:v1.13.0".to_owned(),
         },
         response_channel: tx,
+        deadline: None,
     };
 
     let eval_ctx = EvaluationContext {
         policy_id: "test".to_owned(),
         callback_channel: Some(callback_handler_channel),
         ctx_aware_resources_allow_list: Default::default(),
+        http_get_allow_list: Vec::new(),
+        host_capabilities_allow_list: None,
         epoch_deadline: None,
+        evaluation_timeout: None,
+        deadline: Default::default(),
+        audit_sink: None,
+        current_request: Default::default(),
+        log_level: None,
+        policy_logs: Default::default(),
     };
 
     let cb_channel: mpsc::Sender<CallbackRequest> = eval_ctx
@@ -35,10 +35,11 @@ pub(crate) async fn wapc_and_wasi_scenario(handle: Handle<Request<Body>, Respons
                 (&http::Method::GET, "/apis/apps/v1", None, false) => {
                     send_response(send, fixtures::apps_v1_resource_list());
                 }
-                (&http::Method::GET, "/api/v1/namespaces", Some("customer-id=1"), false) => {
+                // the label selectors are evaluated locally, over the objects of the reflector
+                (&http::Method::GET, "/api/v1/namespaces", None, false) => {
                     send_response(send, fixtures::namespaces());
                 }
-                (&http::Method::GET, "/api/v1/namespaces", Some("customer-id=1"), true) => {
+                (&http::Method::GET, "/api/v1/namespaces", None, true) => {
                     send_response(
                         send,
                         fixtures::namespaces_watch_bookmark(watch_resource_version.unwrap()),